@@ -0,0 +1,44 @@
+
+/* # Application layout */
+/* Appended to link.x with the `application-layout` feature. The application starts
+   `_bootloader_size` bytes into FLASH, after the bootloader, with an image header in front of its
+   code, see the `image` module. With `_slot_size` there's room for two applications, slot A right
+   after the bootloader and slot B after that, and `_application_slot` (0 for A, 1 for B) picks
+   the one the application is linked for. */
+PROVIDE(_slot_size = 0);
+PROVIDE(_application_slot = 0);
+
+_sslot_a = ORIGIN(REGION_BOOT) + _bootloader_size;
+_sslot_b = _sslot_a + _slot_size;
+_sapplication = _application_slot == 0 ? _sslot_a : _sslot_b;
+
+/* # Slot selection */
+/* Returns the slot to boot, see `image::select_slot`. */
+PROVIDE(__select_slot = default_select_slot);
+
+SECTIONS
+{
+  .image_header _sapplication :
+  {
+    LONG(0x47414d49);  /* "IMAG" */
+    LONG(_start);
+    LONG(_start_trap);
+    LONG(_eintegrity - _sapplication);
+    LONG(0xffffffff);  /* CRC-32 of the rest of the image, filled in after linking */
+    /* replaces the default of link.x, the code follows the header */
+    _stext = .;
+  } > REGION_BOOT
+}
+INSERT BEFORE .boot;
+
+/* the header is part of the image */
+_simage = _sapplication;
+
+ASSERT(_bootloader_size % _flash_sector_size == 0,
+       "_bootloader_size has to be a multiple of _flash_sector_size");
+ASSERT(_slot_size % _flash_sector_size == 0,
+       "_slot_size has to be a multiple of _flash_sector_size");
+ASSERT(_application_slot == 0 || (_application_slot == 1 && _slot_size != 0),
+       "_application_slot has to be 0, or 1 with _slot_size defined");
+ASSERT(_slot_size == 0 || _eimage <= _sapplication + _slot_size,
+       "the application doesn't fit in _slot_size");
@@ -8,13 +8,14 @@ crate=picorv32-rt
 rm -f bin/*.a
 
 for arch_features in ic i; do
-	for cpu_features in RV32RT_BARE RV32RT_INTERRUPTS RV32RT_INTERRUPTS_QREGS; do
-		riscv64-unknown-elf-gcc -c -mabi=ilp32 -march=rv32$arch_features -D$cpu_features asm.S -o bin/$crate.o
-		ar crs bin/riscv32$arch_features-unknown-none-elf_$cpu_features.a bin/$crate.o
+	case $arch_features in
+		ic) mattr=+c,+relax ;;
+		i) mattr=+relax ;;
+	esac
+	for cpu_features in RV32RT_BARE RV32RT_INTERRUPTS RV32RT_INTERRUPTS_QREGS RV32RT_INTERRUPTS_FULL_FRAME RV32RT_INTERRUPTS_QREGS_FULL_FRAME RV32RT_INTERRUPTS_QREGS_SPILL RV32RT_INTERRUPTS_QREGS_SPILL_FULL_FRAME; do
+		cpp -P -D$cpu_features asm.S | llvm-mc -triple=riscv32 -mattr=$mattr -filetype=obj -o bin/$crate.o
+		llvm-ar crs bin/riscv32$arch_features-unknown-none-elf_$cpu_features.a bin/$crate.o
 	done
 done
 
-# riscv64-unknown-elf-gcc -c -mabi=ilp32 -march=rv32i asm.S -o bin/$crate.o
-# ar crs bin/riscv32i-unknown-none-elf.a bin/$crate.o
-
 rm bin/$crate.o
@@ -0,0 +1,22 @@
+
+/* # Bootloader layout */
+/* Appended to link.x with the `bootloader-layout` feature. The bootloader occupies the first
+   `_bootloader_size` bytes of FLASH, defined in memory.x as a multiple of `_flash_sector_size`,
+   and the applications start right after it, see the `image` module. */
+PROVIDE(_slot_size = 0);
+PROVIDE(_application_slot = 0);
+
+_sslot_a = ORIGIN(REGION_BOOT) + _bootloader_size;
+_sslot_b = _sslot_a + _slot_size;
+_sapplication = _sslot_a;
+
+/* # Slot selection */
+/* Returns the slot to boot, see `image::select_slot`. */
+PROVIDE(__select_slot = default_select_slot);
+
+ASSERT(_bootloader_size % _flash_sector_size == 0,
+       "_bootloader_size has to be a multiple of _flash_sector_size");
+ASSERT(_slot_size % _flash_sector_size == 0,
+       "_slot_size has to be a multiple of _flash_sector_size");
+ASSERT(_eimage <= _sslot_a, "the bootloader doesn't fit in _bootloader_size");
+ASSERT(_sslot_b + _slot_size <= _sconfig_sectors, "the slots overlap the configuration block");
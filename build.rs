@@ -12,18 +12,48 @@ fn main() {
     let feature_compressed_isa = env::var("CARGO_FEATURE_COMPRESSED_ISA").is_ok();
     let feature_interrupts = env::var("CARGO_FEATURE_INTERRUPTS").is_ok();
     let feature_interrupts_qregs = env::var("CARGO_FEATURE_INTERRUPTS_QREGS").is_ok();
+    let feature_interrupts_qregs_spill = env::var("CARGO_FEATURE_INTERRUPTS_QREGS_SPILL").is_ok();
+    let feature_full_trap_frame = env::var("CARGO_FEATURE_FULL_TRAP_FRAME").is_ok();
+
+    check_features();
+    emit_cfg_aliases();
 
     if target.starts_with("riscv") {
-        let arch_features = if feature_compressed_isa { "ic" } else { "i" };
-        let cpu_features = if feature_interrupts_qregs {
+        // the target spells out the extensions, `compressed-isa` is for custom targets that don't
+        let compressed = match target_extensions(&target) {
+            Some(extensions) => {
+                check_extensions(&target, extensions);
+                if feature_compressed_isa && !extensions.contains('c') {
+                    panic!(
+                        "the `compressed-isa` feature needs a target with the C extension, which \
+                         `{}` doesn't have, disable the default features of picorv32-rt",
+                        target
+                    )
+                }
+                extensions.contains('c')
+            }
+            None => feature_compressed_isa,
+        };
+        let arch_features = if compressed { "ic" } else { "i" };
+        let cpu_features = if feature_interrupts_qregs_spill {
+            "RV32RT_INTERRUPTS_QREGS_SPILL"
+        } else if feature_interrupts_qregs {
             "RV32RT_INTERRUPTS_QREGS"
         } else if feature_interrupts {
             "RV32RT_INTERRUPTS"
         } else {
             "RV32RT_BARE"
         };
+        let frame_features = if feature_full_trap_frame && feature_interrupts {
+            "_FULL_FRAME"
+        } else {
+            ""
+        };
 
-        let lib_name = format!("riscv32{}-unknown-none-elf_{}", arch_features, cpu_features);
+        let lib_name = format!(
+            "riscv32{}-unknown-none-elf_{}{}",
+            arch_features, cpu_features, frame_features
+        );
 
         fs::copy(
             format!("bin/{}.a", lib_name),
@@ -33,15 +63,686 @@ fn main() {
 
         println!("cargo:rustc-link-lib=static={}", name);
         println!("cargo:rustc-link-search={}", out_dir.display());
+
+        // the trap path runs where IRQ1 can't be taken, so its own M instructions can't be emulated
+        if env::var("CARGO_FEATURE_EMULATE_M").is_ok()
+            && target_extensions(&target).map_or(false, |extensions| extensions.contains('m'))
+        {
+            println!(
+                "cargo:warning=the `emulate-m` feature can't emulate M instructions inside trap \
+                 handlers, and the `{}` target emits them there too, build for riscv32ic or \
+                 riscv32i to keep the trap path free of them",
+                target
+            );
+        }
+
+        // without qregs the core puts the return address of a trap in gp, so code relaxed to
+        // `gp`-relative accesses reads and writes random addresses once interrupted
+        if feature_interrupts && !feature_interrupts_qregs && rustflags().contains("--relax-gp") {
+            panic!(
+                "`--relax-gp` makes accesses to small data relative to gp, which the `interrupts` \
+                 feature doesn't preserve across traps, enable `interrupts-qregs` or drop \
+                 `--relax-gp` from RUSTFLAGS"
+            )
+        }
+
+        // backtraces follow the frame pointer chain, which only exists with frame pointers
+        if env::var("CARGO_FEATURE_BACKTRACE").is_ok() && !frame_pointers_forced() {
+            println!(
+                "cargo:warning=the `backtrace` feature needs `-C force-frame-pointers=yes` in \
+                 RUSTFLAGS, backtraces will be cut short"
+            );
+        }
     }
 
     // Put the linker script somewhere the linker can find it
-    fs::File::create(out_dir.join("link.x"))
-        .unwrap()
-        .write_all(include_bytes!("link.x"))
+    let link_x = include_str!("link.x");
+    let link_x_version = link_x_version(link_x);
+    let mut linker_script = fs::File::create(out_dir.join("link.x")).unwrap();
+    linker_script.write_all(link_x.as_bytes()).unwrap();
+    if env::var("CARGO_FEATURE_DEVICE").is_ok() {
+        // the device crate puts device.x somewhere the linker can find it
+        writeln!(
+            linker_script,
+            "\n/* Provided by the device crate */\nINCLUDE device.x"
+        )
         .unwrap();
+    }
+    match (
+        env::var("CARGO_FEATURE_BOOTLOADER_LAYOUT").is_ok(),
+        env::var("CARGO_FEATURE_APPLICATION_LAYOUT").is_ok(),
+    ) {
+        (true, false) => linker_script
+            .write_all(include_bytes!("bootloader-layout.x"))
+            .unwrap(),
+        (false, true) => linker_script
+            .write_all(include_bytes!("application-layout.x"))
+            .unwrap(),
+        _ => {}
+    }
+    if env::var("CARGO_FEATURE_COMPRESSED_DATA").is_ok() {
+        linker_script
+            .write_all(include_bytes!("compressed-data.x"))
+            .unwrap();
+    }
+    if env::var("CARGO_FEATURE_RISCV_RT_COMPAT").is_ok() {
+        linker_script
+            .write_all(include_bytes!("riscv-rt-compat.x"))
+            .unwrap();
+    }
+    // the stack goes below the statics with `stack-first`, `_start` tests RAM with `ram-test` and
+    // clears it with `zero-ram`
+    let flag = |feature: &str| env::var(format!("CARGO_FEATURE_{}", feature)).is_ok() as u8;
+    let custom_regions = env::var("CARGO_FEATURE_CUSTOM_REGIONS").is_ok();
+    let ram_only = env::var("CARGO_FEATURE_RAM_ONLY").is_ok();
+    let mut regions = if custom_regions {
+        custom_region_checks()
+    } else {
+        default_regions(ram_only)
+    };
+    regions.push_str(&format!(
+        "_stack_first = {};\n_ram_test = {};\n_zero_ram = {};\n",
+        flag("STACK_FIRST"),
+        flag("RAM_TEST"),
+        flag("ZERO_RAM"),
+    ));
+    fs::write(out_dir.join("regions.x"), regions).unwrap();
+    // PROGADDR_IRQ jumps to the trap entry in `.ramfunc` with `ram-trap`
+    let (trap_vector, trap_ram) = if env::var("CARGO_FEATURE_RAM_TRAP").is_ok() {
+        (RAM_TRAP_VECTOR, RAM_TRAP_ENTRY)
+    } else {
+        (FLASH_TRAP_ENTRY, "")
+    };
+    fs::write(out_dir.join("trap-vector.x"), trap_vector).unwrap();
+    fs::write(out_dir.join("trap-ram.x"), trap_ram).unwrap();
     println!("cargo:rustc-link-search={}", out_dir.display());
 
+    // memory.x comes from the application, it can only be checked when it's found on the way
+    if let Some(memory_x) = find_memory_x() {
+        let text = fs::read_to_string(&memory_x).unwrap_or_default();
+        let problem = if custom_regions {
+            check_region_aliases(&text)
+        } else {
+            check_memory_x(
+                &text,
+                if ram_only {
+                    &["RAM"]
+                } else {
+                    &["FLASH", "RAM"]
+                },
+            )
+        };
+        if let Err(problem) = problem {
+            println!("cargo:warning={}: {}", memory_x.display(), problem);
+        }
+        println!("cargo:rerun-if-changed={}", memory_x.display());
+    }
+
+    // C header describing the symbol interface, for C/asm components and host tools
+    let header = c_header(
+        link_x_version,
+        feature_interrupts,
+        feature_interrupts_qregs,
+        feature_interrupts_qregs_spill,
+        feature_full_trap_frame,
+    );
+    fs::write(out_dir.join("picorv32_rt.h"), header).unwrap();
+    println!("cargo:include={}", out_dir.display());
+    println!("cargo:link_x_version={}", link_x_version);
+
     println!("cargo:rerun-if-changed=build.rs");
     println!("cargo:rerun-if-changed=link.x");
+    println!("cargo:rerun-if-changed=bootloader-layout.x");
+    println!("cargo:rerun-if-changed=application-layout.x");
+    println!("cargo:rerun-if-changed=compressed-data.x");
+    println!("cargo:rerun-if-changed=riscv-rt-compat.x");
+}
+
+/// Features that need another one, which Cargo.toml enables, but build systems passing the
+/// features to rustc themselves may not
+const REQUIRED_FEATURES: &[(&str, &str)] = &[
+    ("context-switch", "full-trap-frame"),
+    ("emulate-m", "full-trap-frame"),
+    ("emulate-unaligned", "full-trap-frame"),
+    ("full-trap-frame", "interrupts"),
+    ("gdbstub", "full-trap-frame"),
+    ("interrupts-qregs", "interrupts"),
+    ("interrupts-qregs-spill", "interrupts-qregs"),
+    ("protect", "full-trap-frame"),
+];
+
+/// Features that can't be enabled together, with what to do instead
+const EXCLUSIVE_FEATURES: &[(&str, &str, &str)] = &[
+    (
+        "bootloader-layout",
+        "application-layout",
+        "an image is either the bootloader or the application",
+    ),
+    (
+        "compressed-data",
+        "integrity-check",
+        "`integrity-check` can't check a compressed `.data` load image",
+    ),
+    (
+        "custom-regions",
+        "copy-to-ram",
+        "alias REGION_TEXT to a RAM region in memory.x instead of enabling `copy-to-ram`",
+    ),
+    // all of these need a separate load image or a RAM region of their own
+    (
+        "ram-only",
+        "compressed-data",
+        "there's no load image to decompress",
+    ),
+    ("ram-only", "copy-to-ram", "the code already runs from RAM"),
+    (
+        "ram-only",
+        "custom-regions",
+        "`ram-only` places everything in RAM",
+    ),
+    (
+        "ram-only",
+        "ram-test",
+        "the test would overwrite the loaded image",
+    ),
+    ("ram-only", "ram2", "`ram-only` places everything in RAM"),
+    (
+        "ram-only",
+        "zero-ram",
+        "clearing RAM would overwrite the loaded image",
+    ),
+];
+
+fn feature_enabled(feature: &str) -> bool {
+    env::var(format!(
+        "CARGO_FEATURE_{}",
+        feature.to_uppercase().replace('-', "_")
+    ))
+    .is_ok()
+}
+
+/// Fails the build on a feature combination the runtime can't be built with
+fn check_features() {
+    for &(feature, required) in REQUIRED_FEATURES {
+        if feature_enabled(feature) && !feature_enabled(required) {
+            panic!(
+                "the `{}` feature needs `{}`, add it to the features of picorv32-rt",
+                feature, required
+            )
+        }
+    }
+    for &(a, b, hint) in EXCLUSIVE_FEATURES {
+        if feature_enabled(a) && feature_enabled(b) {
+            panic!(
+                "the `{}` and `{}` features are exclusive, remove one of them: {}",
+                a, b, hint
+            )
+        }
+    }
+}
+
+/// Emits cfgs for feature combinations `src` checks in several places
+fn emit_cfg_aliases() {
+    let aliases = [
+        // the image is one of the two slots of a bootloader setup
+        (
+            "slot_layout",
+            feature_enabled("bootloader-layout") || feature_enabled("application-layout"),
+        ),
+        // the default `trap_handler` dispatches through `__EXTERNAL_INTERRUPTS`
+        (
+            "interrupt_table",
+            feature_enabled("device") || feature_enabled("riscv-rt-compat"),
+        ),
+    ];
+    for &(alias, enabled) in &aliases {
+        println!("cargo:rustc-check-cfg=cfg({})", alias);
+        if enabled {
+            println!("cargo:rustc-cfg={}", alias);
+        }
+    }
+}
+
+/// ISA extensions of a `riscv32*` target, e.g. `imc` for `riscv32imc-unknown-none-elf`
+///
+/// `None` if the target name doesn't spell them out, e.g. a custom target specification.
+fn target_extensions(target: &str) -> Option<&str> {
+    if !target.starts_with("riscv32") {
+        return None;
+    }
+    let extensions = target["riscv32".len()..].split('-').next().unwrap();
+    if (extensions.starts_with('i') || extensions.starts_with('e'))
+        && extensions.chars().all(|c| c.is_ascii_lowercase())
+    {
+        Some(extensions)
+    } else {
+        None
+    }
+}
+
+/// Fails the build if `target` needs more than PicoRV32 and the startup code support
+fn check_extensions(target: &str, extensions: &str) {
+    if extensions.starts_with('e') {
+        panic!(
+            "the `{}` target is RV32E, the trap handler of picorv32-rt saves x16..x31, build for \
+             riscv32i, riscv32im or riscv32imc",
+            target
+        )
+    }
+    for extension in extensions[1..].chars() {
+        match extension {
+            'm' | 'c' => {}
+            'a' => panic!(
+                "the `{}` target uses the A extension, which PicoRV32 doesn't implement, build \
+                 for riscv32imc and use the `atomic-emulation` feature for atomics",
+                target
+            ),
+            _ => panic!(
+                "the `{}` target uses the `{}` extension, which PicoRV32 doesn't implement, build \
+                 for riscv32i, riscv32im or riscv32imc",
+                target, extension
+            ),
+        }
+    }
+}
+
+/// Version of `link.x`, from the `__picorv32_rt_link_x_v<N>` symbol it defines
+///
+/// `start_rust` references the same symbol, both have to be bumped together.
+fn link_x_version(link_x: &str) -> u32 {
+    const PREFIX: &str = "__picorv32_rt_link_x_v";
+
+    link_x
+        .match_indices(PREFIX)
+        .filter_map(|(start, _)| {
+            link_x[start + PREFIX.len()..]
+                .split(|c: char| !c.is_ascii_digit())
+                .next()
+                .and_then(|version| version.parse().ok())
+        })
+        .next()
+        .expect("link.x doesn't define its version")
+}
+
+/// Region aliases of `link.x` mapped to the `FLASH`, `RAM` and `RAM2` regions of `memory.x`, or
+/// all to `RAM` with the `ram-only` feature
+///
+/// The linker quotes the line of an alias to a missing region, the comment on it tells what to fix.
+fn default_regions(ram_only: bool) -> String {
+    // a RAM-only image is loaded where it runs, e.g. by a debugger or a simulator
+    let boot = if ram_only { "RAM" } else { "FLASH" };
+    // `.text` and `.rodata` are copied to RAM by `_start` with `copy-to-ram`
+    let text = if ram_only || env::var("CARGO_FEATURE_COPY_TO_RAM").is_ok() {
+        "RAM"
+    } else {
+        "FLASH"
+    };
+    // `.data_RAM2` and `.bss_RAM2` fall back to RAM without `ram2`
+    let ram2 = if env::var("CARGO_FEATURE_RAM2").is_ok() {
+        "RAM2"
+    } else {
+        "RAM"
+    };
+    let missing = |region: &str| {
+        format!(
+            "memory.x doesn't define a {} region in its MEMORY block",
+            region
+        )
+    };
+
+    format!(
+        "REGION_ALIAS(\"REGION_BOOT\", {}); /* {} */\n\
+         REGION_ALIAS(\"REGION_TEXT\", {}); /* {} */\n\
+         REGION_ALIAS(\"REGION_DATA\", RAM); /* {} */\n\
+         REGION_ALIAS(\"REGION_STACK\", RAM);\n\
+         REGION_ALIAS(\"REGION_RAM2\", {}); /* {} */\n",
+        boot,
+        missing(boot),
+        text,
+        missing(text),
+        missing("RAM"),
+        ram2,
+        missing(ram2),
+    )
+}
+
+/// Region aliases `memory.x` defines with the `custom-regions` feature
+const CUSTOM_REGIONS: [&str; 5] = [
+    "REGION_BOOT",
+    "REGION_TEXT",
+    "REGION_DATA",
+    "REGION_STACK",
+    "REGION_RAM2",
+];
+
+/// Checks that `memory.x` aliases all of [`CUSTOM_REGIONS`], at link time
+fn custom_region_checks() -> String {
+    CUSTOM_REGIONS
+        .iter()
+        .map(|alias| {
+            format!(
+                "REGION_ALIAS(\"__picorv32_rt_{}\", {}); /* memory.x doesn't alias {} to a region, \
+                 which the `custom-regions` feature needs */\n",
+                alias.to_lowercase(),
+                alias,
+                alias,
+            )
+        })
+        .collect()
 }
+
+/// Checks that `memory.x` aliases all of [`CUSTOM_REGIONS`], at build time
+fn check_region_aliases(memory_x: &str) -> Result<(), String> {
+    let missing = CUSTOM_REGIONS
+        .iter()
+        .filter(|alias| !memory_x.contains(&format!("\"{}\"", alias)))
+        .cloned()
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "the `custom-regions` feature needs REGION_ALIAS for {}",
+            missing.join(", ")
+        ))
+    }
+}
+
+/// `memory.x` in the directory cargo was run from or in a `-L` directory of the rustc flags
+///
+/// The application's build script usually adds the directory with its `memory.x` to the linker
+/// search path, which isn't visible here, so it's fine not to find one.
+fn find_memory_x() -> Option<PathBuf> {
+    let flags = env::var("CARGO_ENCODED_RUSTFLAGS")
+        .map(|flags| flags.replace('\x1f', " "))
+        .or_else(|_| env::var("RUSTFLAGS"))
+        .unwrap_or_default();
+    let mut flags = flags.split_whitespace();
+    let mut dirs = Vec::new();
+    while let Some(flag) = flags.next() {
+        let dir = match flag {
+            "-L" => flags.next(),
+            _ if flag.starts_with("-L") => Some(&flag[2..]),
+            _ => None,
+        };
+        if let Some(dir) = dir {
+            dirs.push(PathBuf::from(dir.rsplit('=').next().unwrap()));
+        }
+    }
+    dirs.extend(env::var("PWD").ok().map(PathBuf::from));
+
+    dirs.into_iter()
+        .map(|dir| dir.join("memory.x"))
+        .find(|path| path.is_file())
+}
+
+/// Checks that `memory.x` has a `MEMORY` block defining the given regions
+fn check_memory_x(memory_x: &str, regions: &[&str]) -> Result<(), String> {
+    // comments could mention the regions
+    let mut text = String::new();
+    let mut rest = memory_x;
+    while let Some(start) = rest.find("/*") {
+        text.push_str(&rest[..start]);
+        rest = rest[start..]
+            .find("*/")
+            .map_or("", |end| &rest[start + end + 2..]);
+    }
+    text.push_str(rest);
+
+    let block = text
+        .find("MEMORY")
+        .map(|start| &text[start + "MEMORY".len()..])
+        .and_then(|block| block.find('}').map(|end| &block[..end]))
+        .ok_or_else(|| {
+            format!(
+                "no MEMORY block, it has to define {}",
+                regions.join(" and ")
+            )
+        })?;
+    let defines = |region: &str| {
+        block.match_indices(region).any(|(start, _)| {
+            let before = block[..start].chars().next_back();
+            let after = block[start + region.len()..].trim_start().chars().next();
+            !before.map_or(false, |c| c.is_alphanumeric() || c == '_')
+                && (after == Some(':') || after == Some('('))
+        })
+    };
+    let missing = regions
+        .iter()
+        .filter(|region| !defines(region))
+        .cloned()
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "the MEMORY block doesn't define the {} region{}",
+            missing.join(" and "),
+            if missing.len() > 1 { "s" } else { "" }
+        ))
+    }
+}
+
+/// RUSTFLAGS of the build, separated by spaces
+fn rustflags() -> String {
+    env::var("CARGO_ENCODED_RUSTFLAGS")
+        .map(|flags| flags.replace('\x1f', " "))
+        .or_else(|_| env::var("RUSTFLAGS"))
+        .unwrap_or_default()
+}
+
+/// Whether the flags passed to rustc keep frame pointers, `-C force-frame-pointers[=yes]`
+fn frame_pointers_forced() -> bool {
+    let flags = rustflags();
+
+    flags
+        .match_indices("force-frame-pointers")
+        .map(|(start, _)| &flags[start + "force-frame-pointers".len()..])
+        .any(|rest| {
+            !["=no", "=n", "=off", "=false"]
+                .iter()
+                .any(|value| rest.starts_with(value))
+        })
+}
+
+/// Generates `picorv32_rt.h` for the enabled features
+fn c_header(
+    link_x_version: u32,
+    interrupts: bool,
+    qregs: bool,
+    qregs_spill: bool,
+    full_trap_frame: bool,
+) -> String {
+    let mut h = String::new();
+    let flag = |enabled: bool| if enabled { 1 } else { 0 };
+
+    h.push_str("/* picorv32-rt symbol interface, generated by its build script. Do not edit. */\n");
+    h.push_str("#ifndef PICORV32_RT_H\n#define PICORV32_RT_H\n\n");
+
+    h.push_str("/* Version of the linker script the runtime was built for */\n");
+    h.push_str(&format!(
+        "#define PICORV32_RT_LINK_X_VERSION {}\n\n",
+        link_x_version
+    ));
+
+    h.push_str("/* Features the runtime was built with */\n");
+    h.push_str(&format!(
+        "#define PICORV32_RT_INTERRUPTS {}\n",
+        flag(interrupts)
+    ));
+    h.push_str(&format!(
+        "#define PICORV32_RT_INTERRUPTS_QREGS {}\n",
+        flag(qregs)
+    ));
+    h.push_str(&format!(
+        "#define PICORV32_RT_INTERRUPTS_QREGS_SPILL {}\n",
+        flag(qregs_spill)
+    ));
+    h.push_str(&format!(
+        "#define PICORV32_RT_FULL_TRAP_FRAME {}\n\n",
+        flag(full_trap_frame)
+    ));
+
+    h.push_str("/* IRQ lines used by the core */\n");
+    h.push_str("#define PICORV32_RT_IRQ_TIMER 0\n");
+    h.push_str("#define PICORV32_RT_IRQ_EXCEPTION 1\n");
+    h.push_str("#define PICORV32_RT_IRQ_BUS_ERROR 2\n\n");
+
+    // must match `PicoRV32StoredRegisters`
+    let mut frame = vec![3];
+    if !qregs {
+        frame.extend(&[1, 2]);
+    }
+    frame.extend(&[5, 6, 7]);
+    if !qregs_spill {
+        frame.push(10);
+    }
+    frame.extend(&[11, 12, 13, 14, 15, 16, 17, 28, 29, 30, 31]);
+    if full_trap_frame {
+        frame.extend(&[8, 9, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27]);
+    }
+
+    h.push_str("/* Trap frame passed to trap_handler, byte offsets of the saved registers */\n");
+    for (i, reg) in frame.iter().enumerate() {
+        h.push_str(&format!("#define PICORV32_RT_FRAME_X{} {}\n", reg, i * 4));
+    }
+    h.push_str(&format!(
+        "#define PICORV32_RT_FRAME_SIZE {}\n\n",
+        frame.len() * 4
+    ));
+
+    h.push_str(if qregs { FAST_IRQ } else { NO_FAST_IRQ });
+
+    h.push_str("#ifndef __ASSEMBLER__\n\n#include <stdint.h>\n\n");
+
+    h.push_str("typedef struct {\n");
+    for reg in &frame {
+        h.push_str(&format!("    uint32_t x{};\n", reg));
+    }
+    h.push_str("} picorv32_rt_trap_frame_t;\n\n");
+
+    h.push_str("/* Linker script symbols, only their addresses are meaningful */\n");
+    for symbol in &[
+        "_stext",
+        "_reset_vector_address",
+        "_irq_vector_address",
+        "_sramtext",
+        "_eramtext",
+        "_sitext",
+        "_sbss",
+        "_ebss",
+        "_sdata",
+        "_edata",
+        "_sidata",
+        "_sbss_ram2",
+        "_ebss_ram2",
+        "_sdata_ram2",
+        "_edata_ram2",
+        "_sidata_ram2",
+        "_sramfunc",
+        "_eramfunc",
+        "_siramfunc",
+        "_suninit",
+        "_euninit",
+        "_sheap",
+        "_eheap",
+        "_heap_size",
+        "_sheap2",
+        "_eheap2",
+        "_heap2_size",
+        "_stack_start",
+        "_sconfig",
+        "_config_size",
+        "_flash_sector_size",
+        "_simage",
+        "_eimage",
+        "_sconfig_sectors",
+        "_econfig_sectors",
+        "_irq_unconnected",
+    ] {
+        h.push_str(&format!("extern uint8_t {}[];\n", symbol));
+    }
+    h.push('\n');
+
+    h.push_str("/* Can be defined in C instead of with picorv32_interrupts! */\n");
+    h.push_str("void trap_handler(picorv32_rt_trap_frame_t *regs, uint32_t irqs);\n\n");
+
+    h.push_str("/* Runtime entry points */\n");
+    h.push_str("void _start(void);\n");
+    h.push_str("uint32_t __picorv32_rt_rdcycle(void);\n");
+    h.push_str(
+        "uint32_t __picorv32_rt_ecall(uint32_t a0, uint32_t a1, uint32_t a2, uint32_t a3, \
+         uint32_t a4, uint32_t a5, uint32_t nr);\n\n",
+    );
+
+    h.push_str("#endif /* __ASSEMBLER__ */\n\n#endif /* PICORV32_RT_H */\n");
+    h
+}
+
+/// Assembler macro emitting the fast IRQ prologue, see the `Fast IRQ` section of the crate
+/// documentation
+///
+/// The q register instructions are encoded by hand, as in `custom_ops.S`. Compressed instructions
+/// are disabled so the prologue stays a multiple of 4 bytes long and falls through to
+/// `_start_trap` without padding.
+const FAST_IRQ: &str = "\
+/* Fast IRQ prologue, for assembly files. Jumps to `handler` if `line` is the only pending IRQ,
+   otherwise the trap is dispatched as usual. */
+#ifdef __ASSEMBLER__
+#define PICORV32_RT_FAST_IRQ(line, handler) \\
+    .pushsection .trap.fast, \"ax\"; \\
+    .option push; \\
+    .option norvc; \\
+    .word 0x0200a10b; /* setq q2, x1 */ \\
+    .word 0x0201218b; /* setq q3, x2 */ \\
+    .word 0x0000c08b; /* getq x1, q1 */ \\
+    li x2, 1 << (line); \\
+    bne x1, x2, 1f; \\
+    .word 0x0001408b; /* getq x1, q2 */ \\
+    .word 0x0001c10b; /* getq x2, q3 */ \\
+    j handler; \\
+1:  .word 0x0001408b; /* getq x1, q2 */ \\
+    .word 0x0001c10b; /* getq x2, q3 */ \\
+    .option pop; \\
+    .popsection
+#endif
+
+";
+
+/// Stands in for `FAST_IRQ` without `interrupts-qregs`, so using it fails the build
+const NO_FAST_IRQ: &str = "\
+/* Fast IRQ prologue, only available with the `interrupts-qregs` feature */
+#ifdef __ASSEMBLER__
+#define PICORV32_RT_FAST_IRQ(line, handler) \\
+    .error \"PICORV32_RT_FAST_IRQ needs the interrupts-qregs feature of picorv32-rt\"
+#endif
+
+";
+
+/// Trap entry at `PROGADDR_IRQ`, in `.boot`
+const FLASH_TRAP_ENTRY: &str = "\
+KEEP(*(.trap.fast));
+KEEP(*(.trap));
+";
+
+/// Jump at `PROGADDR_IRQ` to the trap entry in RAM, with the `ram-trap` feature
+///
+/// Every register is live at this point and `_start_trap_ram` may be out of reach of `jal`, so
+/// `t0` is saved right below the stack pointer and the absolute address is built in it. The
+/// instructions are encoded by hand, the address is only known to the linker.
+const RAM_TRAP_VECTOR: &str = "\
+LONG(0xfe512e23); /* sw t0, -4(sp) */
+LONG(((_start_trap_ram + 0x800) & 0xfffff000) | 0x2b7); /* lui t0, %hi(_start_trap_ram) */
+LONG(((_start_trap_ram & 0xfff) << 20) | 0x28067); /* jalr zero, %lo(_start_trap_ram)(t0) */
+";
+
+/// Trap entry in `.ramfunc`, with the `ram-trap` feature
+///
+/// Restores `t0`, saved by `RAM_TRAP_VECTOR`, and falls through
+/// to the `PICORV32_RT_FAST_IRQ` prologues and `_start_trap`.
+const RAM_TRAP_ENTRY: &str = "\
+_start_trap_ram = .;
+LONG(0xffc12283); /* lw t0, -4(sp) */
+KEEP(*(.trap.fast));
+KEEP(*(.trap));
+";
@@ -6,14 +6,14 @@ set -euxo pipefail
 
 for lib in $(ls bin/*.a); do
     filename=$(basename $lib)
-    riscv64-unknown-elf-objdump -Cd $lib > bin/${filename%.a}.before
+    llvm-objdump -Cd $lib > bin/${filename%.a}.before
 done
 
 ./assemble.sh
 
 for lib in $(ls bin/*.a); do
     filename=$(basename $lib)
-    riscv64-unknown-elf-objdump -Cd $lib > bin/${filename%.a}.after
+    llvm-objdump -Cd $lib > bin/${filename%.a}.after
 done
 
 for cksum in $(ls bin/*.after); do
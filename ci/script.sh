@@ -9,6 +9,10 @@ main() {
 
     if [ $TARGET = x86_64-unknown-linux-gnu ]; then
         ./check-blobs.sh
+        cargo test --features "event-log,flash-config,host-tools,stack-canary,stack-paint,$FEATURES"
+    else
+        RUSTFLAGS="-C link-arg=-Tlink.x -L ci/sim" \
+            cargo build --target $TARGET --features "$FEATURES" --example sim_smoke
     fi
 }
 
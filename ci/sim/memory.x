@@ -0,0 +1,6 @@
+/* 128K of memory of the picorv32 testbench, used to link examples/sim_smoke.rs */
+MEMORY
+{
+  FLASH : ORIGIN = 0x00000000, LENGTH = 64K
+  RAM : ORIGIN = 0x00010000, LENGTH = 64K
+}
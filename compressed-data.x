@@ -0,0 +1,14 @@
+
+/* # Compressed `.data` */
+/* Appended to link.x with the `compressed-data` feature. A trailer after the `.data` load image
+   tells `host::compress_data` where it starts, the trailer is dropped with the image encoded, see
+   the `rle` module. */
+SECTIONS
+{
+  .data_trailer LOADADDR(.data) + SIZEOF(.data) :
+  {
+    LONG(0x41544144);  /* "DATA" */
+    LONG(SIZEOF(.data));
+  } > REGION_BOOT
+}
+INSERT AFTER .data;
@@ -0,0 +1,49 @@
+//! Smoke test for the picorv32 testbench
+//!
+//! Boots through the startup code, checks that `.data` and `.bss` have been initialized and
+//! reports the result the way the firmware of the picorv32 testbench does: characters written to
+//! `0x1000_0000` are printed, and writing `123456789` to `0x2000_0000` ends the simulation
+//! successfully. Built for the target by `ci/script.sh` with `ci/sim/memory.x`.
+
+#![cfg_attr(target_arch = "riscv32", no_std)]
+#![cfg_attr(target_arch = "riscv32", no_main)]
+
+#[cfg(target_arch = "riscv32")]
+mod sim {
+    use core::panic::PanicInfo;
+    use core::ptr;
+
+    const OUTPUT: *mut u32 = 0x1000_0000 as *mut u32;
+    const EXIT: *mut u32 = 0x2000_0000 as *mut u32;
+
+    static mut DATA: u32 = 0xcafe_f00d;
+    static mut BSS: u32 = 0;
+
+    fn print(s: &str) {
+        for byte in s.bytes() {
+            unsafe { ptr::write_volatile(OUTPUT, u32::from(byte)) };
+        }
+    }
+
+    #[picorv32_rt::entry]
+    fn main() -> ! {
+        let ok =
+            unsafe { ptr::read_volatile(&DATA) == 0xcafe_f00d && ptr::read_volatile(&BSS) == 0 };
+        if ok {
+            print("OK\n");
+            unsafe { ptr::write_volatile(EXIT, 123_456_789) };
+        } else {
+            print("FAIL\n");
+        }
+        loop {}
+    }
+
+    #[panic_handler]
+    fn panic(_info: &PanicInfo) -> ! {
+        print("PANIC\n");
+        loop {}
+    }
+}
+
+#[cfg(not(target_arch = "riscv32"))]
+fn main() {}
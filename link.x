@@ -1,75 +1,347 @@
 /* NOTE: Adapted from cortex-m/link.x */
-INCLUDE memory.x
 
-PROVIDE(_stack_start = ORIGIN(RAM) + LENGTH(RAM));
+/* # memory.x */
+/* Supplied by the application, see the crate docs. The linker quotes the offending line when it's
+   missing, so the comment there is the error message. */
+INCLUDE memory.x /* not found: put a memory.x describing the FLASH and RAM regions of the board where the linker searches, e.g. OUT_DIR of the build script */
+
+/* # Region aliases */
+/* Written by the build script, or by memory.x with the `custom-regions` feature, and checked to be
+   defined, again with the error in a comment on the offending line.
+   `REGION_BOOT` holds the reset vector, the trap entry, `_start` and the load image of everything
+   else: FLASH, or RAM with the `ram-only` feature, which places everything in RAM.
+   `REGION_TEXT` is where `.text` and `.rodata` run from: FLASH, or RAM with the `copy-to-ram`
+   feature, in which case `_start` copies them there from `REGION_BOOT`, and with `ram-only`.
+   `REGION_DATA` holds the statics, `.ramfunc` and the heap: RAM by default.
+   `REGION_STACK` holds the stack: RAM by default.
+   `REGION_RAM2` holds `.data_RAM2` and `.bss_RAM2`: RAM2 with the `ram2` feature, RAM otherwise.
+   `_stack_first` is 1 with the `stack-first` feature, which puts the stack at the bottom of
+   `REGION_STACK`.
+   `_ram_test` is 1 with the `ram-test` feature, `_zero_ram` with the `zero-ram` feature. */
+INCLUDE regions.x
+
+/* # Version of this script */
+/* Bumped whenever the layout changes in a way the runtime depends on. The runtime references this
+   symbol, so linking with a stale copy of this file fails with an undefined
+   `__picorv32_rt_link_x_v<N>`, naming the version the runtime expects. */
+__picorv32_rt_link_x_v1 = 1;
+
+/* # Stack size */
+/* Reserved below `_stack_start`, and at the bottom of RAM below the statics with the `stack-first`
+   feature. 0 leaves the stack whatever RAM the statics and the heap don't use. */
+PROVIDE(_stack_size = 0);
+
+PROVIDE(_stack_start = _stack_first ? ORIGIN(REGION_STACK) + _stack_size
+                                    : ORIGIN(REGION_STACK) + LENGTH(REGION_STACK));
+
+/* # Board configuration block */
+/* Reserved at the end of FLASH unless `_sconfig` is defined in memory.x, see the `flash_config`
+   module. Empty by default. */
+PROVIDE(_config_size = 0);
+PROVIDE(_sconfig = ORIGIN(REGION_BOOT) + LENGTH(REGION_BOOT) - _config_size);
+
+/* # Flash sectors */
+/* Erase granularity of the flash, a power of two. The updatable image is padded to whole sectors,
+   see the `flash` module. */
+PROVIDE(_flash_sector_size = 4);
+
+/* # Console */
+/* TX register of the `console` module, set in memory.x. */
+_has_console_tx = DEFINED(_console_tx_addr) ? 1 : 0;
+PROVIDE(_console_tx_addr = 0);
+
+/* # Host calls */
+/* Register window of the `hostcall` module, set in memory.x. */
+_has_hostcall = DEFINED(_hostcall_addr) ? 1 : 0;
+PROVIDE(_hostcall_addr = 0);
+
+/* # Simulator exit */
+/* Address a testbench watches to end the simulation, set in memory.x, see the `sim` module. */
+_has_tohost = DEFINED(_tohost) ? 1 : 0;
+PROVIDE(_tohost = 0);
 
 PROVIDE(trap_handler = default_trap_handler);
+PROVIDE(DebugTrapHandler = default_debug_trap_handler);
+PROVIDE(CustomInstructionHandler = default_custom_instruction_handler);
+PROVIDE(IllegalInstructionHandler = default_illegal_instruction_handler);
+PROVIDE(syscall_handler = default_syscall_handler);
+PROVIDE(ProtectionFaultHandler = default_protection_fault_handler);
+PROVIDE(__protect_configure = default_protect_configure);
+
+/* # Unconnected IRQ lines */
+/* Set in device.x, see the `_irq_handled` check at the end of this file. */
+PROVIDE(_irq_unconnected = 0);
+
+/* # External interrupt table */
+/* `__EXTERNAL_INTERRUPTS` and the number of its entries are provided by the device crate, see the
+   `device` module. */
+PROVIDE(__EXTERNAL_INTERRUPTS = 0);
+PROVIDE(_external_interrupts_len = 0);
+PROVIDE(DefaultHandler = default_handler);
+
+/* # Pre-start hook */
+/* Jumped to at the very top of `_start`, before `gp` and `sp` are set up, with the return address
+   in `t0`. */
+PROVIDE(__pre_start = default_pre_start);
+
+/* # RAM test failure hook */
+/* Jumped to from `_start` when the `ram-test` feature finds a bad word, with its address in `a0`
+   and the value read from it in `a1`. */
+PROVIDE(RamTestFailed = default_ram_test_failed);
 
 /* # Pre-initialization function */
 /* If the user overrides this using the `#[pre_init]` attribute or by creating a `__pre_init` function,
    then the function this points to will be called before the RAM is initialized. */
 PROVIDE(__pre_init = default_pre_init);
 
+/* # Memory setup function */
+/* Called after `__pre_init` and before RAM is initialized, e.g. to bring up an SDRAM controller
+   when `.data`/`.bss` live in external memory. */
+PROVIDE(__memory_setup = default_memory_setup);
+
+/* # Integrity failure hook */
+/* Called instead of initializing RAM if the image is corrupted, with the `integrity-check`
+   feature. */
+PROVIDE(__integrity_failure = default_integrity_failure);
+
+/* # Stack overflow hook */
+/* Called when the canary at the bottom of the stack is gone, with the `stack-canary` feature. */
+PROVIDE(StackOverflow = default_stack_overflow);
+
+/* # IRQs masked in `main` */
+/* Defined as 1 by `#[entry]` if `main` takes a `CriticalSection`. */
+PROVIDE(__picorv32_rt_main_masks_irqs = default_main_masks_irqs);
+
+/* # Runtime configuration */
+/* Defined by `runtime_config!`. */
+PROVIDE(__picorv32_rt_config = default_runtime_config);
+
+/* # Exit hook */
+/* Called when an entry point declared as `fn main()` returns. */
+PROVIDE(__on_main_exit = default_on_main_exit);
+
+/* # Flush hook of `runtime::park` */
+PROVIDE(__park_flush = default_park_flush);
+
+/* # Quiesce hook of `runtime::soft_reset` */
+PROVIDE(__on_soft_reset = default_on_soft_reset);
+
+/* # Boot progress hook */
+/* Called with a `runtime::BootStage` at every boot milestone, e.g. to blink progress codes. */
+PROVIDE(__boot_beacon = default_boot_beacon);
+
+/* # Trap trace hooks */
+/* Called with the pending IRQs before and after every trap is dispatched, e.g. for profiling or
+   to kick a watchdog. */
+PROVIDE(__trap_enter = default_trap_enter);
+PROVIDE(__trap_exit = default_trap_exit);
+
+/* # Out-of-memory hook */
+/* Called with the layout of an allocation that doesn't fit, see the `heap` module. */
+PROVIDE(__alloc_error = default_alloc_error);
+
+/* # Panic output hook */
+/* Called with the text of the panic report of the `panic-handler` feature. */
+PROVIDE(__panic_sink = default_panic_sink);
+
 SECTIONS
 {
-  PROVIDE(_stext = ORIGIN(FLASH));
+  PROVIDE(_stext = ORIGIN(REGION_BOOT));
+
+  /* # Reset and IRQ vectors */
+  /* `PROGADDR_RESET` and `PROGADDR_IRQ` of the core, defined in memory.x if the SoC doesn't use
+     the defaults: the start of the code, and the next multiple of 16 after the reset jump. The
+     IRQ vector has to follow the reset vector. */
+  PROVIDE(_reset_vector_address = ALIGN(_stext, 4));
+  PROVIDE(_irq_vector_address = ALIGN(_reset_vector_address + 4, 0x10));
 
-  .text ALIGN(_stext,4) :
+  /* runs from FLASH, also with the `copy-to-ram` feature */
+  .boot _reset_vector_address :
   {
-    /* Put reset handler first in .text section so it ends up as the entry */
-    /* point of the program. */
+    /* Put reset handler first so it ends up as the entry point of the */
+    /* program. */
     KEEP(*(.initjmp));
-    . = ALIGN(0x10);
-    KEEP(*(.trap));
+    . += _irq_vector_address - ABSOLUTE(.);
+    _strap_vector = .;
+    /* the trap entry, `_start_trap` preceded by the `PICORV32_RT_FAST_IRQ` prologues, which fall
+       through to it, or with the `ram-trap` feature a jump to its copy in `.ramfunc`, written by
+       the build script */
+    INCLUDE trap-vector.x
     KEEP(*(.init));
+  } > REGION_BOOT
+
+  /* the stack with the `stack-first` feature, an overflow runs off the bottom of RAM instead of
+     into the statics */
+  .stack_first (NOLOAD) :
+  {
+    . += _stack_first ? _stack_size : 0;
+  } > REGION_STACK
+
+  .text : ALIGN(4)
+  {
+    /* copied from `_sitext` to `_sramtext`..`_eramtext` by `_start` unless it's already there */
+    _sramtext = .;
     KEEP(*(.init.rust));
     KEEP(*(.trap.rust));
 
     *(.text .text.*);
-  } > FLASH
+  } > REGION_TEXT AT > REGION_BOOT
 
-  .rodata ALIGN(4) :
+  .rodata : ALIGN(4)
   {
+    *(.srodata .srodata.*);
     *(.rodata .rodata.*);
-  } > FLASH
 
-  .bss :
+    /* registered with `#[picorv32_test]` */
+    . = ALIGN(4);
+    __spicorv32_tests = .;
+    KEEP(*(.picorv32_tests));
+    __epicorv32_tests = .;
+
+    /* static constructors, called by `start_rust` before `main` */
+    . = ALIGN(4);
+    __preinit_array_start = .;
+    KEEP(*(.preinit_array));
+    __preinit_array_end = .;
+    __init_array_start = .;
+    KEEP(*(SORT_BY_INIT_PRIORITY(.init_array.*)));
+    KEEP(*(.init_array));
+    __init_array_end = .;
+    . = ALIGN(4);
+    _eramtext = .;
+  } > REGION_TEXT AT > REGION_BOOT
+
+  _sitext = LOADADDR(.text);
+
+  /* statics placed in a second RAM region, e.g. a large external SRAM next to a small TCM */
+  .bss_RAM2 : ALIGN(4)
   {
-    _sbss = .;
-    *(.bss .bss.*);
+    _sbss_ram2 = .;
+    *(.bss_RAM2 .bss_RAM2.*);
     . = ALIGN(4);
-    _ebss = .;
-  } > RAM
+    _ebss_ram2 = .;
+  } > REGION_RAM2
+
+  /* functions run from RAM, see `#[ram_func]`, copied from FLASH by `start_rust` */
+  .ramfunc : AT(LOADADDR(.rodata) + SIZEOF(.rodata)) ALIGN(4)
+  {
+    _siramfunc = LOADADDR(.ramfunc);
+    _sramfunc = .;
+    /* the trap entry with the `ram-trap` feature, nothing otherwise */
+    INCLUDE trap-ram.x
+    *(.ramfunc .ramfunc.*);
+    . = ALIGN(4);
+    _eramfunc = .;
+  } > REGION_DATA
+
+  /* initialized statics in the second RAM region */
+  .data_RAM2 : AT(LOADADDR(.ramfunc) + SIZEOF(.ramfunc)) ALIGN(4)
+  {
+    _sidata_ram2 = LOADADDR(.data_RAM2);
+    _sdata_ram2 = .;
+    *(.data_RAM2 .data_RAM2.*);
+    . = ALIGN(4);
+    _edata_ram2 = .;
+  } > REGION_RAM2
 
-  .data : AT(LOADADDR(.rodata) + SIZEOF(.rodata))
+  .data : AT(LOADADDR(.data_RAM2) + SIZEOF(.data_RAM2))
   {
     _sidata = LOADADDR(.data);
     _sdata = .;
-    /* Must be called __global_pointer$ for linker relaxations to work. */
-    PROVIDE(__global_pointer$ = . + 0x800);
+    /* registered with `background_task!` */
+    . = ALIGN(4);
+    __sbackground_tasks = .;
+    KEEP(*(.background_tasks));
+    __ebackground_tasks = .;
     *(.data .data.*);
+    /* small data, accessed relative to `gp` once the linker relaxes the accesses, `.sbss`
+       follows at the start of `.bss` */
+    . = ALIGN(4);
+    __ssdata = .;
+    *(.sdata .sdata.* .sdata2 .sdata2.*);
     . = ALIGN(4);
     _edata = .;
-  } > RAM
+  } > REGION_DATA
+
+  /* Must be called __global_pointer$ for linker relaxations to work. The accesses within 2K of
+     it, to the small data in `.sdata` and `.sbss`, become single `gp`-relative instructions.
+     Relaxing them breaks with the `interrupts` feature without `interrupts-qregs`, which keeps
+     the return address of a trap in gp. */
+  PROVIDE(__global_pointer$ = __ssdata + 0x800);
+
+  .bss :
+  {
+    _sbss = .;
+    /* small data, next to `.sdata` and reachable from `__global_pointer$` */
+    *(.sbss .sbss.*);
+    *(.bss .bss.*);
+    . = ALIGN(4);
+    _ebss = .;
+  } > REGION_DATA
+
+  /* expected CRC-32 of the image with the `integrity-check` feature, see the `integrity` module,
+     right after the `.data` load image so it's the last word of a binary image */
+  .integrity LOADADDR(.data) + SIZEOF(.data) :
+  {
+    _sintegrity = .;
+    KEEP(*(.integrity));
+    _eintegrity = .;
+  } > REGION_BOOT
+
+  /* left alone by the startup code, survives resets that don't clear RAM, see `#[no_init]` */
+  .uninit (NOLOAD) :
+  {
+    . = ALIGN(4);
+    _suninit = .;
+    *(.uninit .uninit.*);
+    /* the name used by other toolchains */
+    *(.noinit .noinit.*);
+    . = ALIGN(4);
+    _euninit = .;
+  } > REGION_DATA
 
   PROVIDE(_heap_size = 0);
 
-  /* fictitious region that represents the memory available for the heap */
-  .heap (INFO) :
+  /* memory available for the heap, reserved after the statics */
+  .heap (NOLOAD) :
   {
     _sheap = .;
     . += _heap_size;
     . = ALIGN(4);
     _eheap = .;
-  } > RAM
+  } > REGION_DATA
+
+  PROVIDE(_heap2_size = 0);
 
-  /* fictitious region that represents the memory available for the stack */
-  .stack (INFO) :
+  /* a second heap after the statics in `REGION_RAM2`, e.g. in a large external SRAM, right after
+     the first one without the `ram2` feature */
+  .heap_RAM2 (NOLOAD) :
   {
-    _estack = .;
-    . = _stack_start;
-    _sstack = .;
-  } > RAM
+    _sheap2 = .;
+    . += _heap2_size;
+    . = ALIGN(4);
+    _eheap2 = .;
+  } > REGION_RAM2
+
+  /* end of what's allocated in the region of the stack, unless it's reserved separately */
+  _eheaps = ORIGIN(REGION_RAM2) == ORIGIN(REGION_DATA) ? _eheap2 : _eheap;
+
+  /* memory available for the stack, `_stack_size` bytes below `_stack_start`, or everything
+     between the heaps and `_stack_start` without `_stack_size` */
+  _estack = _stack_size != 0 ? _stack_start - _stack_size : _eheaps;
+  _sstack = _stack_start;
+
+  /* board configuration block, provisioned separately from the firmware image */
+  .config _sconfig (NOLOAD) :
+  {
+    . += _config_size;
+  } > REGION_BOOT
+
+  /* marker symbols of the IRQ lines that have a handler, see `picorv32_interrupts!` */
+  .irq_markers (INFO) :
+  {
+    KEEP(*(.irq_markers));
+  }
 
   /* fake output .got section */
   /* Dynamic relocations are unsupported. This section is only used to detect
@@ -87,7 +359,141 @@ SECTIONS
   }
 }
 
+/* # IRQ lines with a handler */
+_irq_handled =
+    (DEFINED(__picorv32_rt_irq0) ? 1 << 0 : 0)
+  | (DEFINED(__picorv32_rt_irq1) ? 1 << 1 : 0)
+  | (DEFINED(__picorv32_rt_irq2) ? 1 << 2 : 0)
+  | (DEFINED(__picorv32_rt_irq3) ? 1 << 3 : 0)
+  | (DEFINED(__picorv32_rt_irq4) ? 1 << 4 : 0)
+  | (DEFINED(__picorv32_rt_irq5) ? 1 << 5 : 0)
+  | (DEFINED(__picorv32_rt_irq6) ? 1 << 6 : 0)
+  | (DEFINED(__picorv32_rt_irq7) ? 1 << 7 : 0)
+  | (DEFINED(__picorv32_rt_irq8) ? 1 << 8 : 0)
+  | (DEFINED(__picorv32_rt_irq9) ? 1 << 9 : 0)
+  | (DEFINED(__picorv32_rt_irq10) ? 1 << 10 : 0)
+  | (DEFINED(__picorv32_rt_irq11) ? 1 << 11 : 0)
+  | (DEFINED(__picorv32_rt_irq12) ? 1 << 12 : 0)
+  | (DEFINED(__picorv32_rt_irq13) ? 1 << 13 : 0)
+  | (DEFINED(__picorv32_rt_irq14) ? 1 << 14 : 0)
+  | (DEFINED(__picorv32_rt_irq15) ? 1 << 15 : 0)
+  | (DEFINED(__picorv32_rt_irq16) ? 1 << 16 : 0)
+  | (DEFINED(__picorv32_rt_irq17) ? 1 << 17 : 0)
+  | (DEFINED(__picorv32_rt_irq18) ? 1 << 18 : 0)
+  | (DEFINED(__picorv32_rt_irq19) ? 1 << 19 : 0)
+  | (DEFINED(__picorv32_rt_irq20) ? 1 << 20 : 0)
+  | (DEFINED(__picorv32_rt_irq21) ? 1 << 21 : 0)
+  | (DEFINED(__picorv32_rt_irq22) ? 1 << 22 : 0)
+  | (DEFINED(__picorv32_rt_irq23) ? 1 << 23 : 0)
+  | (DEFINED(__picorv32_rt_irq24) ? 1 << 24 : 0)
+  | (DEFINED(__picorv32_rt_irq25) ? 1 << 25 : 0)
+  | (DEFINED(__picorv32_rt_irq26) ? 1 << 26 : 0)
+  | (DEFINED(__picorv32_rt_irq27) ? 1 << 27 : 0)
+  | (DEFINED(__picorv32_rt_irq28) ? 1 << 28 : 0)
+  | (DEFINED(__picorv32_rt_irq29) ? 1 << 29 : 0)
+  | (DEFINED(__picorv32_rt_irq30) ? 1 << 30 : 0)
+  | (DEFINED(__picorv32_rt_irq31) ? 1 << 31 : 0);
+
+/* # RAM tested by `_start` */
+/* All of RAM but `.uninit` with the `ram-test` feature, before anything is stored there. */
+_sramtest = ORIGIN(REGION_DATA);
+_eramtest = _ram_test ? ORIGIN(REGION_DATA) + LENGTH(REGION_DATA) : ORIGIN(REGION_DATA);
+
+/* # RAM cleared by `_start` */
+/* All of RAM but `.uninit` with the `zero-ram` feature, before `.data` is initialized. */
+_szeroed = ORIGIN(REGION_DATA);
+_ezeroed = _zero_ram ? ORIGIN(REGION_DATA) + LENGTH(REGION_DATA) : ORIGIN(REGION_DATA);
+
+/* # External interrupt table */
+ASSERT(_external_interrupts_len <= 32, "
+ERROR(picorv32-rt): PicoRV32 has 32 IRQ lines, `_external_interrupts_len` can't exceed 32");
+
+/* # Sector groups */
+/* Whole sectors covered by the firmware image and by the configuration block, what has to be
+   erased to update either of them. */
+_simage = ADDR(.boot) & ~(_flash_sector_size - 1);
+_eimage = ALIGN(_eintegrity, _flash_sector_size);
+_sconfig_sectors = _sconfig & ~(_flash_sector_size - 1);
+_econfig_sectors = ALIGN(_sconfig + _config_size, _flash_sector_size);
+
 /* Do not exceed this mark in the error messages below                | */
+
+ASSERT(ORIGIN(REGION_BOOT) >= ORIGIN(REGION_DATA) + LENGTH(REGION_DATA)
+       || ORIGIN(REGION_DATA) >= ORIGIN(REGION_BOOT) + LENGTH(REGION_BOOT)
+       || ORIGIN(REGION_BOOT) == ORIGIN(REGION_DATA) && LENGTH(REGION_BOOT) == LENGTH(REGION_DATA)
+          && !_ram_test && !_zero_ram, "
+FLASH and RAM overlap. Check their ORIGIN and LENGTH in the MEMORY block of
+memory.x. A single memory for both is aliased to `REGION_BOOT` and
+`REGION_DATA` with the `custom-regions` feature, the `ram-test` and
+`zero-ram` features would overwrite the code there.");
+
+ASSERT(_sidata >= ORIGIN(REGION_BOOT) && _eintegrity <= ORIGIN(REGION_BOOT) + LENGTH(REGION_BOOT), "
+The firmware image doesn't fit in FLASH, or `REGION_BOOT`, the load image of
+.data ends past it. Increase its LENGTH in memory.x if the part is bigger, or
+reduce the code and the initialized statics.");
+
+ASSERT(_stack_start > ORIGIN(REGION_STACK)
+          && _stack_start <= ORIGIN(REGION_STACK) + LENGTH(REGION_STACK)
+       || _stack_start > ORIGIN(REGION_RAM2)
+          && _stack_start <= ORIGIN(REGION_RAM2) + LENGTH(REGION_RAM2), "
+`_stack_start` isn't inside RAM, or `REGION_STACK`, or RAM2. The stack grows
+down from it, so it has to be at most the end of the region, e.g.
+`_stack_start = ORIGIN(RAM) + LENGTH(RAM);` in memory.x.");
+
+ASSERT(_irq_vector_address >= _reset_vector_address + 4, "
+`_irq_vector_address` has to be at least 4 bytes after `_reset_vector_address`,
+the reset vector holds a jump to `_start` and the code follows the IRQ vector.
+Check both against PROGADDR_RESET and PROGADDR_IRQ of the core.");
+
+ASSERT(ADDR(.boot) == _reset_vector_address && _strap_vector == _irq_vector_address, "
+The reset jump or the trap entry didn't end up at `_reset_vector_address` or
+`_irq_vector_address`. Check that they are multiples of 4 and inside FLASH, or
+`REGION_BOOT`, in memory.x.");
+
+ASSERT(_stack_start % 4 == 0 && _stack_size % 4 == 0 && _heap_size % 4 == 0
+       && _heap2_size % 4 == 0, "
+`_stack_start`, `_stack_size`, `_heap_size` and `_heap2_size` must be
+multiples of 4, the startup code works with whole words. Round them in
+memory.x.");
+
+ASSERT(_sdata % 4 == 0 && _edata % 4 == 0 && _sidata % 4 == 0 && _sbss % 4 == 0 && _ebss % 4 == 0
+       && _sramtext % 4 == 0 && _sitext % 4 == 0 && _sheap % 4 == 0, "
+A section boundary the startup code copies or clears word by word isn't
+4-byte aligned. Check that the ORIGIN of FLASH and RAM in memory.x, and
+`_stack_size` with the `stack-first` feature, are multiples of 4.");
+
+ASSERT(_config_size == 0 || LOADADDR(.data) + SIZEOF(.data) <= _sconfig, "
+The firmware image overlaps the configuration block. Move `_sconfig` or
+reduce `_config_size` in memory.x.");
+
+ASSERT(_config_size == 0 || _eimage <= _sconfig_sectors, "
+The last flash sector of the firmware image is shared with the configuration
+block, updating either would erase the other. Align `_sconfig` in memory.x to
+`_flash_sector_size`.");
+
+ASSERT(LOADADDR(.rodata) - LOADADDR(.text) == ADDR(.rodata) - ADDR(.text), "
+`.rodata` doesn't follow `.text` the same way in FLASH and in RAM, which the
+`copy-to-ram` feature relies on. Reduce the alignment of `.rodata`.");
+
+ASSERT(_stack_first || _stack_start <= ORIGIN(REGION_DATA)
+       || _stack_start > ORIGIN(REGION_DATA) + LENGTH(REGION_DATA) || _eheaps <= _estack, "
+.data, .bss and the heap don't fit in RAM next to the `_stack_size` bytes
+reserved for the stack below `_stack_start`. The linker map shows where they
+end, `_eheaps`, and where the stack starts, `_estack`. Reduce `_heap_size` or
+`_stack_size`, or the statics.");
+
+ASSERT(!_stack_first || _stack_size > 0, "
+The `stack-first` feature reserves `_stack_size` bytes at the bottom of RAM
+for the stack, define it in memory.x.");
+
+ASSERT((_flash_sector_size & (_flash_sector_size - 1)) == 0, "
+`_flash_sector_size` must be a power of two.");
+
+ASSERT((_irq_handled & _irq_unconnected) == 0, "
+picorv32_interrupts! registers a handler for an IRQ line that is marked as
+unconnected in `_irq_unconnected`. Check the IRQ numbers, `_irq_handled` in
+the linker map lists the lines that have a handler.");
+
 ASSERT(SIZEOF(.got) == 0, "
 .got section detected in the input files. Dynamic relocations are not
 supported. If you are linking to C code compiled using the `gcc` crate
@@ -12,9 +12,15 @@ extern crate syn;
 use proc_macro2::Span;
 use rand::Rng;
 use rand::SeedableRng;
+use std::collections::HashSet;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
-use syn::{parse, spanned::Spanned, Ident, ItemFn, ReturnType, Type, Visibility};
+use syn::{
+    parse::{self, Parse, ParseStream},
+    spanned::Spanned,
+    Attribute, Expr, FnArg, Ident, Item, ItemFn, ItemStatic, Lit, LitInt, LitStr, ReturnType, Stmt,
+    Type, Visibility,
+};
 
 static CALL_COUNT: AtomicUsize = AtomicUsize::new(0);
 
@@ -30,13 +36,23 @@ use proc_macro::TokenStream;
 /// The specified function will be called by the reset handler *after* RAM has been initialized.
 /// If present, the FPU will also be enabled before the function is called.
 ///
-/// The type of the specified function must be `[unsafe] fn() -> !` (never ending function)
+/// The type of the specified function must be `[unsafe] fn() -> !` (never ending function) or
+/// `[unsafe] fn()`. The latter may return, the runtime then calls the `__on_main_exit` hook and
+/// sleeps forever.
 ///
 /// # Properties
 ///
 /// The entry point will be called by the reset handler. The program can't reference to the entry
 /// point, much less invoke it.
 ///
+/// If the entry point takes a `CriticalSection` it's called with all IRQ lines still masked, and
+/// they stay masked until the token is given up with `picorv32_rt::interrupt::enable`. This makes
+/// initialization that must not be interrupted safe, e.g. of statics shared with IRQ handlers.
+///
+/// `static mut` variables declared at the beginning of the entry point will be safely initialized
+/// to their initial values, and replaced by `&'static mut` references to them. As the entry point
+/// runs only once these references are unique, so no `unsafe` is needed to use them.
+///
 /// # Examples
 ///
 /// - Simple entry point
@@ -51,30 +67,217 @@ use proc_macro::TokenStream;
 ///     }
 /// }
 /// ```
+///
+/// - Entry point of test firmware, which returns
+///
+/// ``` no_run
+/// # #![no_main]
+/// # use picorv32_rt_macros::entry;
+/// #[entry]
+/// fn main() {
+///     assert_eq!(2 + 2, 4);
+/// }
+/// ```
+///
+/// - Entry point that initializes with IRQs masked
+///
+/// ``` ignore
+/// use picorv32_rt::interrupt::{self, CriticalSection};
+///
+/// #[entry]
+/// fn main(cs: CriticalSection) -> ! {
+///     // set up the peripherals and the state shared with IRQ handlers
+///     interrupt::enable(cs);
+///
+///     loop {
+///         /* .. */
+///     }
+/// }
+/// ```
+///
+/// - `static mut` variables local to the entry point are safe to modify.
+///
+/// ``` no_run
+/// # #![no_main]
+/// # use picorv32_rt_macros::entry;
+/// #[entry]
+/// fn main() -> ! {
+///     static mut FOO: u32 = 0;
+///
+///     let foo: &'static mut u32 = FOO;
+///     assert_eq!(*foo, 0);
+///     *foo = 1;
+///     assert_eq!(*foo, 1);
+///
+///     loop {
+///         /* .. */
+///     }
+/// }
+/// ```
 #[proc_macro_attribute]
 pub fn entry(args: TokenStream, input: TokenStream) -> TokenStream {
     let f = parse_macro_input!(input as ItemFn);
 
     // check the function signature
-    let valid_signature = f.constness.is_none()
-        && f.vis == Visibility::Inherited
-        && f.abi.is_none()
-        && f.decl.inputs.is_empty()
-        && f.decl.generics.params.is_empty()
-        && f.decl.generics.where_clause.is_none()
-        && f.decl.variadic.is_none()
-        && match f.decl.output {
-            ReturnType::Default => false,
-            ReturnType::Type(_, ref ty) => match **ty {
-                Type::Never(_) => true,
-                _ => false,
-            },
+    let signature = "`#[entry]` function must have signature `[unsafe] fn() -> !`, \
+                     `[unsafe] fn(CriticalSection) -> !` or `[unsafe] fn()`";
+    if let Err(e) = check_signature(&f, signature) {
+        return e.to_compile_error().into();
+    }
+    if f.vis != Visibility::Inherited {
+        return parse::Error::new(f.vis.span(), signature)
+            .to_compile_error()
+            .into();
+    }
+    if !(returns_never(&f.decl.output) || returns_unit(&f.decl.output)) {
+        return parse::Error::new(f.decl.output.span(), signature)
+            .to_compile_error()
+            .into();
+    }
+
+    let mut inputs = f.decl.inputs.iter();
+    let cs = match (inputs.next(), inputs.next()) {
+        (None, _) => None,
+        (Some(FnArg::Captured(arg)), None) if is_type(&arg.ty, "CriticalSection") => Some(arg),
+        (Some(FnArg::Captured(arg)), None) => {
+            return parse::Error::new(arg.ty.span(), signature)
+                .to_compile_error()
+                .into();
+        }
+        (Some(arg), None) | (_, Some(arg)) => {
+            return parse::Error::new(arg.span(), signature)
+                .to_compile_error()
+                .into();
+        }
+    };
+
+    if !args.is_empty() {
+        return parse::Error::new(Span::call_site(), "This attribute accepts no arguments")
+            .to_compile_error()
+            .into();
+    }
+
+    // with a `CriticalSection` argument the runtime leaves the IRQs masked, it reads the flag
+    // before calling `main`
+    let critical_section = cs.map(|arg| {
+        let pat = &arg.pat;
+        let ty = &arg.ty;
+
+        quote!(
+            #[export_name = "__picorv32_rt_main_masks_irqs"]
+            static MAIN_MASKS_IRQS: u8 = 1;
+
+            let #pat: #ty = unsafe { <#ty>::new() };
+        )
+    });
+
+    // XXX should we blacklist other attributes?
+    let attrs = f.attrs;
+    let unsafety = f.unsafety;
+    let output = f.decl.output;
+    let hash = random_ident();
+    let (statics, stmts) = match extract_static_muts(f.block.stmts) {
+        Err(e) => return e.to_compile_error().into(),
+        Ok(x) => x,
+    };
+
+    let vars = statics
+        .into_iter()
+        .map(|var| {
+            let attrs = var.attrs;
+            let ident = var.ident;
+            let ty = var.ty;
+            let expr = var.expr;
+
+            quote!(
+                #[allow(non_snake_case)]
+                let #ident: &'static mut #ty = unsafe {
+                    #(#attrs)*
+                    static mut #ident: #ty = #expr;
+
+                    &mut #ident
+                };
+            )
+        })
+        .collect::<Vec<_>>();
+
+    quote!(
+        #[export_name = "main"]
+        #(#attrs)*
+        pub #unsafety fn #hash() #output {
+            #critical_section
+
+            #(#vars)*
+
+            #(#stmts)*
+        }
+    )
+    .into()
+}
+
+/// Attribute to declare an interrupt handler
+///
+/// The function can then be registered with `picorv32_interrupts!`, which is the only code that
+/// may call it. Its type must be `fn(&PicoRV32StoredRegisters)` or
+/// `fn(&mut PicoRV32StoredRegisters)`, and the attribute turns it into an `unsafe fn` so it can't
+/// be called from safe code.
+///
+/// # Properties
+///
+/// `static mut` variables declared at the beginning of the handler will be safely initialized to
+/// their initial values, and replaced by `&mut` references to them. Trap handlers can't be
+/// preempted, so these references are unique while the handler runs and no `unsafe` is needed to
+/// use them. Unlike the ones of `#[entry]`, the references aren't `'static`.
+///
+/// # Examples
+///
+/// - Counting the IRQs of a line
+///
+/// ``` ignore
+/// #[interrupt]
+/// fn irq5(_regs: &picorv32_rt::PicoRV32StoredRegisters) {
+///     static mut COUNT: u32 = 0;
+///
+///     *COUNT += 1;
+/// }
+///
+/// picorv32_interrupts!(5: irq5);
+/// ```
+#[proc_macro_attribute]
+pub fn interrupt(args: TokenStream, input: TokenStream) -> TokenStream {
+    let f = parse_macro_input!(input as ItemFn);
+
+    // check the function signature
+    let signature =
+        "`#[interrupt]` handlers must have signature `fn(&[mut] PicoRV32StoredRegisters)`";
+    if let Err(e) = check_signature(&f, signature) {
+        return e.to_compile_error().into();
+    }
+    if let Some(ref unsafety) = f.unsafety {
+        return parse::Error::new(unsafety.span(), signature)
+            .to_compile_error()
+            .into();
+    }
+    if f.decl.inputs.len() != 1 {
+        let span = if f.decl.inputs.is_empty() {
+            f.decl.paren_token.span
+        } else {
+            f.decl.inputs.span()
         };
+        return parse::Error::new(span, signature).to_compile_error().into();
+    }
+    if !returns_unit(&f.decl.output) {
+        return parse::Error::new(f.decl.output.span(), signature)
+            .to_compile_error()
+            .into();
+    }
 
-    if !valid_signature {
+    if args.to_string() == "fast" {
         return parse::Error::new(
-            f.span(),
-            "`#[entry]` function must have signature `[unsafe] fn() -> !`",
+            Span::call_site(),
+            "`#[interrupt(fast)]` isn't supported, fast IRQ handlers must be written in assembly \
+             with `PICORV32_RT_FAST_IRQ` from `picorv32_rt.h`, which needs the \
+             `interrupts-qregs` feature",
         )
         .to_compile_error()
         .into();
@@ -86,17 +289,58 @@ pub fn entry(args: TokenStream, input: TokenStream) -> TokenStream {
             .into();
     }
 
-    // XXX should we blacklist other attributes?
+    let regs_ty = match f.decl.inputs.first().map(|arg| arg.into_value()) {
+        Some(FnArg::Captured(arg)) => arg.ty.clone(),
+        _ => {
+            return parse::Error::new(f.decl.inputs.span(), signature)
+                .to_compile_error()
+                .into();
+        }
+    };
+
     let attrs = f.attrs;
-    let unsafety = f.unsafety;
+    let vis = f.vis;
+    let ident = f.ident;
+    let inputs = f.decl.inputs;
     let hash = random_ident();
-    let stmts = f.block.stmts;
+    let (statics, stmts) = match extract_static_muts(f.block.stmts) {
+        Err(e) => return e.to_compile_error().into(),
+        Ok(x) => x,
+    };
+
+    let vars = statics
+        .into_iter()
+        .map(|var| {
+            let attrs = var.attrs;
+            let ident = var.ident;
+            let ty = var.ty;
+            let expr = var.expr;
 
+            quote!(
+                #[allow(non_snake_case)]
+                let #ident: &mut #ty = unsafe {
+                    #(#attrs)*
+                    static mut #ident: #ty = #expr;
+
+                    &mut #ident
+                };
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // every call hands out the `static mut` references again, so only `picorv32_interrupts!`
+    // may call the handler: it's `unsafe`, and the body can't be named to call it recursively
     quote!(
-        #[export_name = "main"]
         #(#attrs)*
-        pub #unsafety fn #hash() -> ! {
-            #(#stmts)*
+        #vis unsafe fn #ident(regs: #regs_ty) {
+            #[inline(always)]
+            fn #hash(#inputs) {
+                #(#vars)*
+
+                #(#stmts)*
+            }
+
+            #hash(regs)
         }
     )
     .into()
@@ -110,11 +354,15 @@ pub fn entry(args: TokenStream, input: TokenStream) -> TokenStream {
 /// crate you'll be fine. This reachability restriction doesn't apply to Rust 1.31 and newer
 /// releases.
 ///
-/// The function must have the signature of `unsafe fn()`.
+/// The function must have the signature of `unsafe fn()` or `fn(PreInit)`.
 ///
 /// The function passed will be called before static variables are initialized. Any access of static
 /// variables will result in undefined behavior.
 ///
+/// Taking a `picorv32_rt::runtime::PreInit` token makes the function safe: the token offers
+/// volatile accesses to MMIO registers, enough to disable a watchdog or enable external RAM
+/// without any `unsafe`. Statics with interior mutability, e.g. atomics, still must not be used.
+///
 /// # Examples
 ///
 /// ```
@@ -126,35 +374,66 @@ pub fn entry(args: TokenStream, input: TokenStream) -> TokenStream {
 ///
 /// # fn main() {}
 /// ```
+///
+/// - Without `unsafe`
+///
+/// ``` ignore
+/// use picorv32_rt::runtime::PreInit;
+///
+/// #[pre_init]
+/// fn disable_watchdog(p: PreInit) {
+///     p.write(0x0300_0010, 0);
+/// }
+/// ```
 #[proc_macro_attribute]
 pub fn pre_init(args: TokenStream, input: TokenStream) -> TokenStream {
     let f = parse_macro_input!(input as ItemFn);
 
     // check the function signature
-    let valid_signature = f.constness.is_none()
-        && f.vis == Visibility::Inherited
-        && f.unsafety.is_some()
-        && f.abi.is_none()
-        && f.decl.inputs.is_empty()
-        && f.decl.generics.params.is_empty()
-        && f.decl.generics.where_clause.is_none()
-        && f.decl.variadic.is_none()
-        && match f.decl.output {
-            ReturnType::Default => true,
-            ReturnType::Type(_, ref ty) => match **ty {
-                Type::Tuple(ref tuple) => tuple.elems.is_empty(),
-                _ => false,
-            },
-        };
-
-    if !valid_signature {
-        return parse::Error::new(
-            f.span(),
-            "`#[pre_init]` function must have signature `unsafe fn()`",
-        )
-        .to_compile_error()
-        .into();
+    let signature = "`#[pre_init]` function must have signature `unsafe fn()` or `fn(PreInit)`";
+    if let Err(e) = check_signature(&f, signature) {
+        return e.to_compile_error().into();
+    }
+    if f.vis != Visibility::Inherited {
+        return parse::Error::new(f.vis.span(), signature)
+            .to_compile_error()
+            .into();
     }
+    if !returns_unit(&f.decl.output) {
+        return parse::Error::new(f.decl.output.span(), signature)
+            .to_compile_error()
+            .into();
+    }
+
+    let mut inputs = f.decl.inputs.iter();
+    let token = match (inputs.next(), inputs.next()) {
+        (None, _) if f.unsafety.is_some() => None,
+        (None, _) => {
+            return parse::Error::new(f.decl.fn_token.span(), signature)
+                .to_compile_error()
+                .into();
+        }
+        (Some(FnArg::Captured(arg)), None)
+            if f.unsafety.is_none() && is_type(&arg.ty, "PreInit") =>
+        {
+            Some(arg)
+        }
+        (Some(_), None) if f.unsafety.is_some() => {
+            return parse::Error::new(f.unsafety.span(), signature)
+                .to_compile_error()
+                .into();
+        }
+        (Some(FnArg::Captured(arg)), None) => {
+            return parse::Error::new(arg.ty.span(), signature)
+                .to_compile_error()
+                .into();
+        }
+        (Some(arg), None) | (_, Some(arg)) => {
+            return parse::Error::new(arg.span(), signature)
+                .to_compile_error()
+                .into();
+        }
+    };
 
     if !args.is_empty() {
         return parse::Error::new(Span::call_site(), "This attribute accepts no arguments")
@@ -167,14 +446,433 @@ pub fn pre_init(args: TokenStream, input: TokenStream) -> TokenStream {
     let ident = f.ident;
     let block = f.block;
 
+    match token {
+        None => quote!(
+            #[export_name = "__pre_init"]
+            #(#attrs)*
+            pub unsafe fn #ident() #block
+        ),
+        // the body stays in a safe function, only the token is created in an unsafe context
+        Some(arg) => {
+            let pat = &arg.pat;
+            let ty = &arg.ty;
+
+            quote!(
+                #[export_name = "__pre_init"]
+                pub unsafe fn #ident() {
+                    #(#attrs)*
+                    #[inline(always)]
+                    fn #ident(#pat: #ty) #block
+
+                    #ident(<#ty>::new())
+                }
+            )
+        }
+    }
+    .into()
+}
+
+/// Attribute to place a static in the `.uninit` section, which the startup code leaves alone
+///
+/// The static is neither zeroed nor initialized at startup, so it keeps its contents across soft
+/// restarts and doesn't cost any boot time, e.g. for large DMA buffers. Its initializer can be
+/// left out if its type is a `MaybeUninit`, which is what it should be unless every bit pattern
+/// is a valid value of the type. Statics with an initializer must be `static mut`, the compiler
+/// would assume immutable ones keep the initial value.
+///
+/// # Examples
+///
+/// ``` ignore
+/// use core::mem::MaybeUninit;
+///
+/// #[no_init]
+/// static mut RX_BUFFER: MaybeUninit<[u8; 4096]> = MaybeUninit::uninit();
+///
+/// #[no_init]
+/// static mut BOOT_COUNT: u32 = 0;
+/// ```
+#[proc_macro_attribute]
+pub fn no_init(args: TokenStream, input: TokenStream) -> TokenStream {
+    let var = parse_macro_input!(input as NoInitStatic);
+
+    if !args.is_empty() {
+        return parse::Error::new(Span::call_site(), "This attribute accepts no arguments")
+            .to_compile_error()
+            .into();
+    }
+
+    let attrs = var.attrs;
+    let vis = var.vis;
+    let mutability = var.mutability;
+    let ident = var.ident;
+    let ty = var.ty;
+    let expr = match var.expr {
+        Some(expr) => {
+            if mutability.is_none() {
+                return parse::Error::new(
+                    expr.span(),
+                    "`#[no_init]` statics with an initializer must be `static mut`",
+                )
+                .to_compile_error()
+                .into();
+            }
+            quote!(#expr)
+        }
+        None => quote!(<#ty>::uninit()),
+    };
+    let section = format!(".uninit.{}", ident);
+
     quote!(
-        #[export_name = "__pre_init"]
         #(#attrs)*
-        pub unsafe fn #ident() #block
+        #[link_section = #section]
+        #vis static #mutability #ident: #ty = #expr;
+    )
+    .into()
+}
+
+/// Attribute to place a function in the `.ramfunc` section, which runs from RAM
+///
+/// The startup code copies the section from FLASH to RAM along with `.data`, so the function runs
+/// at the same speed no matter how slow the flash is, and keeps running while the flash is being
+/// programmed. It must not be called before RAM is initialized, e.g. from `#[pre_init]`.
+///
+/// The function is never inlined, an inlined copy would run from wherever its caller does. The
+/// functions it calls still run from their own sections, so code that must not touch the flash
+/// at all should only call other `#[ram_func]` functions.
+///
+/// # Examples
+///
+/// ``` ignore
+/// #[ram_func]
+/// fn checksum(data: &[u8]) -> u32 {
+///     data.iter().fold(0, |sum, &byte| sum.rotate_left(1) ^ u32::from(byte))
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn ram_func(args: TokenStream, input: TokenStream) -> TokenStream {
+    let f = parse_macro_input!(input as ItemFn);
+
+    if !args.is_empty() {
+        return parse::Error::new(Span::call_site(), "This attribute accepts no arguments")
+            .to_compile_error()
+            .into();
+    }
+
+    let section = format!(".ramfunc.{}", f.ident);
+
+    quote!(
+        #[link_section = #section]
+        #[inline(never)]
+        #f
+    )
+    .into()
+}
+
+/// Attribute to place a static in a memory region other than `RAM`
+///
+/// The static goes to the `.data_<REGION>` section of the region, or to its `.bss_<REGION>`
+/// section if the initializer is made of zeros only, e.g. `0` or `[0; 4096]`, so that it doesn't
+/// take up any flash. Either way it's initialized by the startup code before `main`. The region
+/// has to be enabled in the runtime, e.g. `RAM2` by its `ram2` feature, compilation fails
+/// otherwise. `RAM` is accepted as well and places the static in `.data`/`.bss`.
+///
+/// # Examples
+///
+/// ``` ignore
+/// #[in_region("RAM2")]
+/// static mut FRAME_BUFFER: [u32; 64 * 1024] = [0; 64 * 1024];
+///
+/// #[in_region("RAM2")]
+/// static GAMMA: [u8; 4] = [0, 64, 128, 255];
+/// ```
+#[proc_macro_attribute]
+pub fn in_region(args: TokenStream, input: TokenStream) -> TokenStream {
+    let region = parse_macro_input!(args as LitStr);
+    let var = parse_macro_input!(input as ItemStatic);
+
+    // regions other than `RAM` need a feature of the runtime, `picorv32_rt::region` has a marker
+    // type for each of the enabled ones
+    let (suffix, marker) = match &*region.value() {
+        "RAM" => (String::new(), None),
+        "RAM2" => ("_RAM2".to_string(), Some(Ident::new("RAM2", region.span()))),
+        name => {
+            return parse::Error::new(
+                region.span(),
+                format!("unknown memory region `{}`, expected `RAM` or `RAM2`", name),
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+
+    let kind = if is_zeros(&var.expr) { "bss" } else { "data" };
+    let section = format!(".{}{}.{}", kind, suffix, var.ident);
+    let check = marker.map(|marker| {
+        let check = Ident::new(
+            &format!("__PICORV32_RT_IN_REGION_{}", var.ident),
+            Span::call_site(),
+        );
+
+        quote!(
+            #[allow(dead_code, non_upper_case_globals)]
+            const #check: picorv32_rt::region::#marker = picorv32_rt::region::#marker;
+        )
+    });
+
+    quote!(
+        #check
+
+        #[link_section = #section]
+        #var
     )
     .into()
 }
 
+/// Whether `expr` is made of zero literals only, so its value is all zero bits
+fn is_zeros(expr: &Expr) -> bool {
+    match expr {
+        Expr::Lit(lit) => match &lit.lit {
+            Lit::Int(int) => int.value() == 0,
+            Lit::Float(float) => float.value() == 0.0,
+            Lit::Bool(boolean) => !boolean.value,
+            _ => false,
+        },
+        Expr::Repeat(repeat) => is_zeros(&repeat.expr),
+        Expr::Array(array) => array.elems.iter().all(is_zeros),
+        Expr::Tuple(tuple) => tuple.elems.iter().all(is_zeros),
+        Expr::Paren(paren) => is_zeros(&paren.expr),
+        _ => false,
+    }
+}
+
+/// A static with an optional initializer, see `no_init`
+struct NoInitStatic {
+    attrs: Vec<Attribute>,
+    vis: Visibility,
+    mutability: Option<Token![mut]>,
+    ident: Ident,
+    ty: Type,
+    expr: Option<Expr>,
+}
+
+impl Parse for NoInitStatic {
+    fn parse(input: ParseStream) -> parse::Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        input.parse::<Token![static]>()?;
+        let mutability = input.parse()?;
+        let ident = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let ty = input.parse()?;
+        let expr = if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+        input.parse::<Token![;]>()?;
+
+        Ok(NoInitStatic {
+            attrs,
+            vis,
+            mutability,
+            ident,
+            ty,
+            expr,
+        })
+    }
+}
+
+/// Attribute to declare an on-target test, run by `picorv32_rt::test::run`
+///
+/// The function must have signature `fn()`. A test passes if it returns and fails if it panics.
+/// It's registered in a table in flash, so it's only linked into the firmware with the
+/// `test-harness` feature of the runtime, which provides the table.
+///
+/// # Examples
+///
+/// ``` ignore
+/// use picorv32_rt::picorv32_test;
+///
+/// #[picorv32_test]
+/// fn checksum_of_empty_buffer() {
+///     assert_eq!(picorv32_rt::crc::crc32(&[]), 0);
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn picorv32_test(args: TokenStream, input: TokenStream) -> TokenStream {
+    let f = parse_macro_input!(input as ItemFn);
+
+    // check the function signature
+    let signature = "`#[picorv32_test]` function must have signature `fn()`";
+    if let Err(e) = check_signature(&f, signature) {
+        return e.to_compile_error().into();
+    }
+    if let Some(ref unsafety) = f.unsafety {
+        return parse::Error::new(unsafety.span(), signature)
+            .to_compile_error()
+            .into();
+    }
+    if let Some(arg) = f.decl.inputs.iter().next() {
+        return parse::Error::new(arg.span(), signature)
+            .to_compile_error()
+            .into();
+    }
+    if !returns_unit(&f.decl.output) {
+        return parse::Error::new(f.decl.output.span(), signature)
+            .to_compile_error()
+            .into();
+    }
+
+    if !args.is_empty() {
+        return parse::Error::new(Span::call_site(), "This attribute accepts no arguments")
+            .to_compile_error()
+            .into();
+    }
+
+    let ident = &f.ident;
+    let entry = Ident::new(
+        &format!("__PICORV32_RT_TEST_{}", f.ident),
+        Span::call_site(),
+    );
+
+    quote!(
+        #f
+
+        #[doc(hidden)]
+        #[allow(non_upper_case_globals)]
+        #[link_section = ".picorv32_tests"]
+        #[used]
+        static #entry: picorv32_rt::test::Test = picorv32_rt::test::Test {
+            name: concat!(module_path!(), "::", stringify!(#ident)),
+            f: #ident,
+        };
+    )
+    .into()
+}
+
+/// Emits the marker symbol of an IRQ line that has a handler, used by `picorv32_interrupts!`
+///
+/// The linker script checks the markers against the lines `device.x` marks as unconnected.
+#[doc(hidden)]
+#[proc_macro]
+pub fn irq_marker(input: TokenStream) -> TokenStream {
+    let irq = parse_macro_input!(input as LitInt);
+
+    if irq.value() >= 32 {
+        return parse::Error::new(irq.span(), "IRQ number must be in the range 0..32")
+            .to_compile_error()
+            .into();
+    }
+
+    let ident = Ident::new(
+        &format!("__picorv32_rt_irq{}", irq.value()),
+        Span::call_site(),
+    );
+    let value = irq.value() as u8;
+
+    quote!(
+        #[doc(hidden)]
+        #[link_section = ".irq_markers"]
+        #[no_mangle]
+        #[used]
+        pub static #ident: u8 = #value;
+    )
+    .into()
+}
+
+/// Checks the parts of the signature of `f` that no attribute accepts, the error points at the
+/// offending part
+fn check_signature(f: &ItemFn, signature: &str) -> Result<(), parse::Error> {
+    let span = if let Some(ref constness) = f.constness {
+        constness.span()
+    } else if let Some(ref asyncness) = f.asyncness {
+        asyncness.span()
+    } else if let Some(ref abi) = f.abi {
+        abi.span()
+    } else if !f.decl.generics.params.is_empty() {
+        f.decl.generics.params.span()
+    } else if let Some(ref where_clause) = f.decl.generics.where_clause {
+        where_clause.span()
+    } else if let Some(ref variadic) = f.decl.variadic {
+        variadic.span()
+    } else {
+        return Ok(());
+    };
+
+    Err(parse::Error::new(span, signature))
+}
+
+fn returns_unit(output: &ReturnType) -> bool {
+    match *output {
+        ReturnType::Default => true,
+        ReturnType::Type(_, ref ty) => match **ty {
+            Type::Tuple(ref tuple) => tuple.elems.is_empty(),
+            _ => false,
+        },
+    }
+}
+
+fn returns_never(output: &ReturnType) -> bool {
+    match *output {
+        ReturnType::Default => false,
+        ReturnType::Type(_, ref ty) => matches!(**ty, Type::Never(_)),
+    }
+}
+
+/// Whether `ty` names the type `name`, e.g. `picorv32_rt::interrupt::CriticalSection` for
+/// `CriticalSection`
+fn is_type(ty: &Type, name: &str) -> bool {
+    match *ty {
+        Type::Path(ref path) => {
+            path.qself.is_none()
+                && path
+                    .path
+                    .segments
+                    .iter()
+                    .last()
+                    .map_or(false, |segment| segment.ident == name)
+        }
+        _ => false,
+    }
+}
+
+/// Extracts the `static mut` variables declared at the beginning of a function body
+fn extract_static_muts(stmts: Vec<Stmt>) -> Result<(Vec<ItemStatic>, Vec<Stmt>), parse::Error> {
+    let mut istmts = stmts.into_iter();
+
+    let mut seen = HashSet::new();
+    let mut statics = vec![];
+    let mut stmts = vec![];
+    for stmt in istmts.by_ref() {
+        match stmt {
+            Stmt::Item(Item::Static(var)) => {
+                if var.mutability.is_some() {
+                    if seen.contains(&var.ident) {
+                        return Err(parse::Error::new(
+                            var.ident.span(),
+                            format!("the name `{}` is defined multiple times", var.ident),
+                        ));
+                    }
+
+                    seen.insert(var.ident.clone());
+                    statics.push(var);
+                } else {
+                    stmts.push(Stmt::Item(Item::Static(var)));
+                }
+            }
+            _ => {
+                stmts.push(stmt);
+                break;
+            }
+        }
+    }
+
+    stmts.extend(istmts);
+
+    Ok((statics, stmts))
+}
+
 // Creates a random identifier
 fn random_ident() -> Ident {
     let secs = SystemTime::now()
@@ -198,9 +896,9 @@ fn random_ident() -> Ident {
         &(0..16)
             .map(|i| {
                 if i == 0 || rng.gen() {
-                    ('a' as u8 + rng.gen::<u8>() % 25) as char
+                    (b'a' + rng.gen::<u8>() % 25) as char
                 } else {
-                    ('0' as u8 + rng.gen::<u8>() % 10) as char
+                    (b'0' + rng.gen::<u8>() % 10) as char
                 }
             })
             .collect::<String>(),
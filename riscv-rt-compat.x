@@ -0,0 +1,9 @@
+
+/* # riscv-rt compatibility */
+/* Appended to link.x with the `riscv-rt-compat` feature, the symbols HALs and PACs written for
+   riscv-rt expect, see the `riscv_rt` module. */
+PROVIDE(ExceptionHandler = default_riscv_rt_exception_handler);
+PROVIDE(MachineTimer = DefaultHandler);
+PROVIDE(_setup_interrupts = default_setup_interrupts);
+PROVIDE(_mp_hook = default_mp_hook);
+
@@ -0,0 +1,123 @@
+//! Background tasks for superloop firmware
+//!
+//! With the `background` feature poll functions can be registered statically with
+//! [`background_task!`](../macro.background_task.html), each with a budget in cycles.
+//! [`poll_registry`](fn.poll_registry.html) runs them round robin, usually from the idle loop.
+//!
+//! Tasks can't be preempted, so budgets are enforced after the fact: every call is timed with the
+//! cycle counter (the core has to be built with `ENABLE_COUNTERS`), and a task that overruns its
+//! budget is skipped in later rounds until the excess is paid off. [`stats`](fn.stats.html) shows
+//! how each task behaves.
+//!
+//! ```
+//! use picorv32_rt::{background, background_task};
+//!
+//! fn poll_uart() {
+//!     // ...
+//! }
+//!
+//! fn poll_sensors() {
+//!     // ...
+//! }
+//!
+//! background_task!(poll_uart, 2_000);
+//! background_task!(poll_sensors, 10_000);
+//!
+//! fn main_loop() -> ! {
+//!     loop {
+//!         background::poll_registry();
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+
+use core::slice;
+
+use crate::runtime;
+
+/// A registered task, created by `background_task!`
+#[doc(hidden)]
+#[repr(C)]
+pub struct Task {
+    pub name: &'static str,
+    pub f: fn(),
+    pub budget: u32,
+    pub stats: TaskStats,
+    /// Cycles over budget not paid off yet
+    pub debt: u32,
+}
+
+/// Statistics of a background task
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TaskStats {
+    /// Number of calls, wrapping
+    pub runs: u32,
+    /// Number of calls that exceeded the budget, wrapping
+    pub overruns: u32,
+    /// Number of rounds the task was skipped in, wrapping
+    pub skipped: u32,
+    /// Duration of the longest call in cycles
+    pub max_cycles: u32,
+}
+
+#[doc(hidden)]
+pub const NO_STATS: TaskStats = TaskStats {
+    runs: 0,
+    overruns: 0,
+    skipped: 0,
+    max_cycles: 0,
+};
+
+fn tasks() -> &'static mut [Task] {
+    extern "C" {
+        // Boundaries of the .background_tasks input sections
+        static mut __sbackground_tasks: u32;
+        static mut __ebackground_tasks: u32;
+    }
+
+    unsafe {
+        let start = &mut __sbackground_tasks as *mut u32 as *mut Task;
+        let end = &mut __ebackground_tasks as *mut u32 as *mut Task;
+        let len = (end as usize - start as usize) / core::mem::size_of::<Task>();
+        slice::from_raw_parts_mut(start, len)
+    }
+}
+
+/// Runs one round of the registered tasks, returns how many ran
+///
+/// Must not be called from a trap handler or from a task.
+pub fn poll_registry() -> usize {
+    let mut count = 0;
+    for task in tasks().iter_mut() {
+        if task.debt != 0 {
+            task.debt = task.debt.saturating_sub(task.budget);
+            task.stats.skipped = task.stats.skipped.wrapping_add(1);
+            continue;
+        }
+
+        let start = runtime::cycles();
+        (task.f)();
+        let cycles = runtime::cycles().wrapping_sub(start);
+
+        task.stats.runs = task.stats.runs.wrapping_add(1);
+        if cycles > task.stats.max_cycles {
+            task.stats.max_cycles = cycles;
+        }
+        if cycles > task.budget {
+            task.stats.overruns = task.stats.overruns.wrapping_add(1);
+            task.debt = cycles - task.budget;
+        }
+        count += 1;
+    }
+    count
+}
+
+/// Number of registered tasks
+pub fn task_count() -> usize {
+    tasks().len()
+}
+
+/// Name and statistics of the task with the given index, in link order
+pub fn stats(index: usize) -> Option<(&'static str, TaskStats)> {
+    tasks().get(index).map(|task| (task.name, task.stats))
+}
@@ -0,0 +1,89 @@
+//! Frame-pointer based backtraces
+//!
+//! `_start` points `s0` at the top of the stack, and code built with frame pointers keeps it
+//! pointing at the top of the current frame, with the return address and the `s0` of the caller
+//! saved right below it. With the `backtrace` feature this module follows that chain, e.g. to
+//! report where a panic or a fault came from.
+//!
+//! The chain only exists if the firmware is built with `-C force-frame-pointers=yes`, which the
+//! build script warns about. Frames of code built without it, e.g. C libraries, end the walk
+//! early or are skipped. A walk never leaves the stack, between the `_estack` and `_sstack`
+//! symbols, and stops after [`MAX_DEPTH`](constant.MAX_DEPTH.html) frames.
+//!
+//! ```
+//! use picorv32_rt::backtrace;
+//!
+//! fn report(sink: &mut impl core::fmt::Write) -> core::fmt::Result {
+//!     for (depth, return_address) in backtrace::backtrace().enumerate() {
+//!         writeln!(sink, "#{:<2} {:08x}", depth, return_address)?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use core::ptr;
+
+#[cfg(feature = "full-trap-frame")]
+use crate::PicoRV32StoredRegisters;
+
+/// Frames walked at most
+pub const MAX_DEPTH: usize = 32;
+
+extern "C" {
+    fn __picorv32_rt_frame_pointer() -> u32;
+
+    static _estack: u8;
+    static _sstack: u8;
+}
+
+/// Return addresses of the frames on the stack, innermost first
+#[derive(Clone, Debug)]
+pub struct Backtrace {
+    fp: u32,
+    depth: usize,
+}
+
+/// Backtrace of the caller, starting with the address this returns to
+#[inline(never)]
+pub fn backtrace() -> Backtrace {
+    from_frame_pointer(unsafe { __picorv32_rt_frame_pointer() })
+}
+
+/// Backtrace of the frames starting with the one `fp` points to, e.g. a saved `s0`
+pub fn from_frame_pointer(fp: u32) -> Backtrace {
+    Backtrace { fp, depth: 0 }
+}
+
+/// Backtrace of the code interrupted by a trap, e.g. in a fault handler
+///
+/// The return address of the trap itself isn't included, it's
+/// [`q0`](../struct.PicoRV32StoredRegisters.html#method.q0).
+#[cfg(feature = "full-trap-frame")]
+pub fn from_trap_frame(regs: &PicoRV32StoredRegisters) -> Backtrace {
+    from_frame_pointer(regs.x8())
+}
+
+impl Iterator for Backtrace {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        let (bottom, top) = unsafe { (&_estack as *const u8 as u32, &_sstack as *const u8 as u32) };
+
+        // the frame of `_start_rust` is at the top, it has no caller
+        let fp = self.fp;
+        if self.depth == MAX_DEPTH || fp & 3 != 0 || fp < bottom + 8 || fp >= top {
+            return None;
+        }
+
+        let (ra, next) = unsafe {
+            (
+                ptr::read_volatile((fp - 4) as *const u32),
+                ptr::read_volatile((fp - 8) as *const u32),
+            )
+        };
+        // callers' frames are further up, anything else ends the walk after this one
+        self.fp = if next > fp { next } else { 0 };
+        self.depth += 1;
+        Some(ra)
+    }
+}
@@ -0,0 +1,72 @@
+//! Converts a firmware ELF into memory images for FPGA flows
+//!
+//! Writes `<elf>.hex`, a word per line for `$readmemh`, and `<elf>.ihex`, Intel HEX, next to the
+//! ELF. The `$readmemh` image starts at the origin of a `memory.x` region, FLASH by default, so it
+//! can initialize the BRAM or ROM the region describes. As a cargo runner, e.g. in
+//! `.cargo/config`:
+//!
+//! ``` text
+//! [target.riscv32imc-unknown-none-elf]
+//! runner = "picorv32-hex --memory-x memory.x --region FLASH"
+//! ```
+//!
+//! `cargo run --release` then regenerates the images the bitstream build picks up. Installed with
+//! `cargo install picorv32-rt --features host-tools --bin picorv32-hex`.
+
+use std::env;
+use std::fs;
+use std::process;
+
+use picorv32_rt::host;
+
+const USAGE: &str = "usage: picorv32-hex [--memory-x <memory.x>] [--region <name>] <elf>";
+
+fn main() {
+    let mut memory_x = String::from("memory.x");
+    let mut region = String::from("FLASH");
+    let mut elf = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--memory-x" => memory_x = args.next().unwrap_or_else(|| fail(USAGE)),
+            "--region" => region = args.next().unwrap_or_else(|| fail(USAGE)),
+            _ if arg.starts_with('-') => fail(USAGE),
+            _ => elf = Some(arg),
+        }
+    }
+    let elf = elf.unwrap_or_else(|| fail(USAGE));
+
+    let memory = fs::read_to_string(&memory_x)
+        .unwrap_or_else(|e| fail(&format!("can't read {}: {}", memory_x, e)));
+    let (origin, length) = host::memory_region(&memory, &region)
+        .unwrap_or_else(|| fail(&format!("{} has no {} region", memory_x, region)));
+    let segments = fs::read(&elf)
+        .ok()
+        .and_then(|elf| host::elf_segments(&elf))
+        .unwrap_or_else(|| fail(&format!("{} isn't a readable ELF32 file", elf)));
+
+    // the `$readmemh` image only covers the region, Intel HEX has the addresses of everything
+    let in_region: Vec<_> = segments
+        .iter()
+        .filter(|s| {
+            s.address >= origin && u64::from(s.address) < u64::from(origin) + u64::from(length)
+        })
+        .cloned()
+        .collect();
+    let image = host::memory_image(&in_region, origin, length)
+        .unwrap_or_else(|| fail(&format!("the image doesn't fit in {}", region)));
+
+    write(&format!("{}.hex", elf), &host::readmemh(&image));
+    write(&format!("{}.ihex", elf), &host::intel_hex(&segments));
+}
+
+fn write(path: &str, contents: &str) {
+    fs::write(path, contents).unwrap_or_else(|e| fail(&format!("can't write {}: {}", path, e)));
+    eprintln!("wrote {}", path);
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("picorv32-hex: {}", message);
+    process::exit(1)
+}
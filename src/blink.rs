@@ -0,0 +1,108 @@
+//! Fault blink codes
+//!
+//! A last-resort diagnostic channel for boards with nothing but an LED. With the `fault-blink`
+//! feature every [crash record](../crash/index.html) is also shown as a blink code before the
+//! system stops or restarts: a number of long blinks for the kind of crash, followed by a number
+//! of short blinks for a detail.
+//!
+//! The LED is driven through the `__boot_beacon` hook with
+//! [`BootStage::FaultLedOn`](../runtime/enum.BootStage.html) and `BootStage::FaultLedOff`.
+//! Timing uses spin loops, as the cycle counter may not be available, so the length of a blink
+//! should be calibrated for the clock with [`set_unit`](fn.set_unit.html).
+//!
+//! | Crash         | Long | Short                             |
+//! |---------------|------|-----------------------------------|
+//! | `Assertion`   | 2    | line number modulo 8, plus one    |
+//! | `Panic`       | 3    | line number modulo 8, plus one    |
+//! | `OutOfMemory` | 4    | log2 of the alignment, plus one   |
+//!
+//! ```
+//! use picorv32_rt::blink;
+//!
+//! fn init() {
+//!     // a short blink of about 200ms at 12MHz
+//!     blink::set_unit(600_000);
+//! }
+//! ```
+
+use core::ptr;
+use core::sync::atomic::{self, Ordering};
+
+use crate::crash::Crash;
+use crate::runtime::{self, BootStage};
+
+/// Spin loop iterations of a short blink
+static mut UNIT: u32 = 1_000_000;
+
+/// A blink code
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BlinkCode {
+    /// Number of long blinks
+    pub long: u8,
+    /// Number of short blinks
+    pub short: u8,
+}
+
+impl BlinkCode {
+    /// Blink code of `crash`, see the table in the [module documentation](index.html)
+    pub fn from_crash(crash: Crash) -> BlinkCode {
+        // no division here, the core may not have the M extension
+        match crash {
+            Crash::Assertion { location } => BlinkCode {
+                long: 2,
+                short: (location & 7) as u8 + 1,
+            },
+            Crash::Panic { location } => BlinkCode {
+                long: 3,
+                short: (location & 7) as u8 + 1,
+            },
+            Crash::OutOfMemory { align, .. } => BlinkCode {
+                long: 4,
+                short: align.trailing_zeros() as u8 + 1,
+            },
+        }
+    }
+}
+
+/// Sets the length of a short blink in spin loop iterations
+///
+/// Long blinks and the pauses are multiples of it.
+pub fn set_unit(spins: u32) {
+    unsafe { ptr::write_volatile(&mut UNIT, spins) };
+}
+
+/// Shows `code` once
+pub fn show(code: BlinkCode) {
+    for _ in 0..code.long {
+        pulse(3);
+    }
+    pause(2);
+    for _ in 0..code.short {
+        pulse(1);
+    }
+    pause(6);
+}
+
+/// Shows `code` over and over
+pub fn blink_forever(code: BlinkCode) -> ! {
+    loop {
+        show(code);
+    }
+}
+
+/// LED on for `units`, then off for one unit
+fn pulse(units: u32) {
+    runtime::beacon(BootStage::FaultLedOn);
+    pause(units);
+    runtime::beacon(BootStage::FaultLedOff);
+    pause(1);
+}
+
+fn pause(units: u32) {
+    let unit = unsafe { ptr::read_volatile(&UNIT) };
+    for _ in 0..units {
+        for _ in 0..unit {
+            atomic::compiler_fence(Ordering::SeqCst);
+        }
+    }
+}
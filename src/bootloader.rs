@@ -0,0 +1,104 @@
+//! Starting an application image from a bootloader
+//!
+//! With the `bootloader` feature a firmware built with this runtime can hand over to another
+//! image, e.g. after checking or updating it. [`jump_to_application`](fn.jump_to_application.html)
+//! undoes what the runtime has set up, so the application starts in the same state as after a
+//! reset:
+//!
+//! 1. masks all IRQs and stops the timer
+//! 2. zeroes [`Launch::clear_ram`](struct.Launch.html#structfield.clear_ram), e.g. so keys of the
+//!    bootloader don't leak into the application, even if it holds the stack of the caller
+//! 3. sets `sp` and `gp` for images that expect them set, picorv32-rt applications set both in
+//!    their own `_start`
+//! 4. jumps to the entry point with a pointer to the boot information in `a0`, 0 without one
+//!
+//! The boot information is any `'static` value the bootloader and the application agree on, its
+//! type is checked at compile time on the bootloader side. The application finds its address in
+//! `a0` at its entry point, in `__pre_start` for picorv32-rt applications.
+//!
+//! ```no_run
+//! use picorv32_rt::bootloader::{self, Launch};
+//!
+//! #[repr(C)]
+//! struct BootInfo {
+//!     magic: u32,
+//!     slot: u32,
+//! }
+//!
+//! static BOOT_INFO: BootInfo = BootInfo { magic: 0xb007_1a7e, slot: 1 };
+//!
+//! fn start_application() -> ! {
+//!     let launch = Launch {
+//!         clear_ram: Some(0x0001_0000..0x0002_0000),
+//!         ..Launch::default()
+//!     };
+//!     unsafe { bootloader::jump_to_application_with(0x0010_0000, &launch, &BOOT_INFO) }
+//! }
+//! ```
+
+use core::ops::Range;
+
+use crate::interrupt;
+
+extern "C" {
+    fn __picorv32_rt_jump(
+        entry: u32,
+        sp: u32,
+        gp: u32,
+        boot_info: u32,
+        clear_start: u32,
+        clear_end: u32,
+    ) -> !;
+}
+
+/// How an application image is started
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Launch {
+    /// Stack pointer the image expects, `None` to leave `sp` alone
+    pub stack_pointer: Option<u32>,
+    /// Global pointer the image expects, `None` to leave `gp` alone
+    pub global_pointer: Option<u32>,
+    /// Word-aligned RAM zeroed right before the jump, `None` to keep everything
+    pub clear_ram: Option<Range<u32>>,
+}
+
+/// Starts the application at `entry` as described by `launch`, with 0 in `a0`
+///
+/// # Safety
+///
+/// `entry` has to be the entry point of an image that can run with the RAM left as it is, apart
+/// from the cleared range. Nothing of the current firmware runs anymore afterwards.
+pub unsafe fn jump_to_application(entry: u32, launch: &Launch) -> ! {
+    jump(entry, launch, 0)
+}
+
+/// Starts the application at `entry` as described by `launch`, with the address of `boot_info`
+/// in `a0`
+///
+/// # Safety
+///
+/// As for [`jump_to_application`](fn.jump_to_application.html), and `boot_info` must not be in
+/// the cleared range.
+pub unsafe fn jump_to_application_with<T: Sync>(
+    entry: u32,
+    launch: &Launch,
+    boot_info: &'static T,
+) -> ! {
+    jump(entry, launch, boot_info as *const T as usize as u32)
+}
+
+unsafe fn jump(entry: u32, launch: &Launch, boot_info: u32) -> ! {
+    interrupt::disable();
+    #[cfg(feature = "interrupts")]
+    picorv32::asm::timer(0);
+
+    let clear = launch.clear_ram.clone().unwrap_or(0..0);
+    __picorv32_rt_jump(
+        entry,
+        launch.stack_pointer.unwrap_or(0),
+        launch.global_pointer.unwrap_or(0),
+        boot_info,
+        clear.start,
+        clear.end,
+    )
+}
@@ -0,0 +1,530 @@
+//! Generating `memory.x` from a build script
+//!
+//! With the `build-helper` feature this crate can be used as a build dependency, to describe the
+//! memory layout in Rust instead of copying a `memory.x` into the linker search path by hand.
+//! [`MemoryLayout`](struct.MemoryLayout.html) checks the layout before writing it, so a region
+//! overlapping another or a misaligned size fails the build with a message saying which, rather
+//! than the link or the firmware at run time. Like `host-tools` the feature needs `std` and is
+//! meant for host builds only.
+//!
+//! ``` text
+//! [build-dependencies]
+//! picorv32-rt = { version = "0.5", default-features = false, features = ["build-helper"] }
+//! ```
+//!
+//! ``` ignore
+//! use picorv32_rt::build::{MemoryLayout, KIB, MIB};
+//!
+//! fn main() {
+//!     MemoryLayout::new()
+//!         .flash(0x0010_0000, 4 * MIB)
+//!         .ram(0, 14 * KIB)
+//!         .stack_size(2 * KIB)
+//!         .write(std::env::var("OUT_DIR").unwrap());
+//! }
+//! ```
+//!
+//! # Device descriptions
+//!
+//! Projects with many board variants can keep the layout in a `picorv32.toml` next to
+//! `Cargo.toml`, or in the `[package.metadata.picorv32]` table of `Cargo.toml` itself, and load it
+//! with [`from_package`](struct.MemoryLayout.html#method.from_package). Sizes and addresses are
+//! integers, the regions tables with an `origin` and a `length`:
+//!
+//! ``` text
+//! stack-size = 0x800
+//! heap-size = 0
+//! flash-sector-size = 0x1000
+//! reset-vector-address = 0x0010_0000
+//! irq-vector-address = 0x0010_0010
+//!
+//! [flash]
+//! origin = 0x0010_0000
+//! length = 0x40_0000
+//!
+//! [ram]
+//! origin = 0
+//! length = 0x3800
+//! ```
+//!
+//! Besides `memory.x`, [`write`](struct.MemoryLayout.html#method.write) puts the same values as
+//! Rust constants in `memory_layout.rs`, for the firmware to include:
+//!
+//! ``` ignore
+//! include!(concat!(env!("OUT_DIR"), "/memory_layout.rs"));
+//!
+//! fn ram_size() -> u32 {
+//!     RAM_LENGTH
+//! }
+//! ```
+
+use std::env;
+use std::fmt::Write;
+use std::format;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::println;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// 1024 bytes
+pub const KIB: u32 = 1024;
+/// 1024 KiB
+pub const MIB: u32 = 1024 * KIB;
+
+/// A region of the `MEMORY` command
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Region {
+    name: &'static str,
+    origin: u32,
+    length: u32,
+}
+
+/// Memory layout of a board, written out as `memory.x`
+///
+/// `FLASH` and `RAM` are required, everything else is optional and keeps the default of
+/// `link.x` when left out. See the [`memory.x`](../index.html#memoryx) section of the crate
+/// documentation for what the symbols mean.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryLayout {
+    flash: Option<Region>,
+    ram: Option<Region>,
+    ram2: Option<Region>,
+    stack_size: Option<u32>,
+    heap_size: Option<u32>,
+    heap2_size: Option<u32>,
+    flash_sector_size: Option<u32>,
+    reset_vector_address: Option<u32>,
+    irq_vector_address: Option<u32>,
+}
+
+impl MemoryLayout {
+    /// A layout without any regions
+    pub fn new() -> Self {
+        MemoryLayout::default()
+    }
+
+    /// Sets the `FLASH` region
+    pub fn flash(mut self, origin: u32, length: u32) -> Self {
+        self.flash = Some(Region {
+            name: "FLASH",
+            origin,
+            length,
+        });
+        self
+    }
+
+    /// Sets the `RAM` region
+    pub fn ram(mut self, origin: u32, length: u32) -> Self {
+        self.ram = Some(Region {
+            name: "RAM",
+            origin,
+            length,
+        });
+        self
+    }
+
+    /// Sets the `RAM2` region, used with the `ram2` feature
+    pub fn ram2(mut self, origin: u32, length: u32) -> Self {
+        self.ram2 = Some(Region {
+            name: "RAM2",
+            origin,
+            length,
+        });
+        self
+    }
+
+    /// Sets `_stack_size`
+    pub fn stack_size(mut self, size: u32) -> Self {
+        self.stack_size = Some(size);
+        self
+    }
+
+    /// Sets `_heap_size`
+    pub fn heap_size(mut self, size: u32) -> Self {
+        self.heap_size = Some(size);
+        self
+    }
+
+    /// Sets `_heap2_size`
+    pub fn heap2_size(mut self, size: u32) -> Self {
+        self.heap2_size = Some(size);
+        self
+    }
+
+    /// Sets `_flash_sector_size`
+    pub fn flash_sector_size(mut self, size: u32) -> Self {
+        self.flash_sector_size = Some(size);
+        self
+    }
+
+    /// Sets `_reset_vector_address`, `PROGADDR_RESET` of the core
+    pub fn reset_vector_address(mut self, address: u32) -> Self {
+        self.reset_vector_address = Some(address);
+        self
+    }
+
+    /// Sets `_irq_vector_address`, `PROGADDR_IRQ` of the core
+    pub fn irq_vector_address(mut self, address: u32) -> Self {
+        self.irq_vector_address = Some(address);
+        self
+    }
+
+    /// Parses a device description, the contents of a `picorv32.toml`
+    ///
+    /// Only the subset of TOML the description needs is understood: tables, inline tables and
+    /// integers. Unknown keys are errors, so a typo doesn't silently keep a default.
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        parse(text, "")
+    }
+
+    /// Parses the `[package.metadata.picorv32]` table of a `Cargo.toml`
+    ///
+    /// The other tables are skipped, their contents don't have to be in the subset
+    /// [`from_toml`](#method.from_toml) understands.
+    pub fn from_manifest(text: &str) -> Result<Self, String> {
+        parse(text, "package.metadata.picorv32")
+    }
+
+    /// Loads the device description of the package a build script belongs to
+    ///
+    /// From `picorv32.toml` in the package directory if there is one, from `Cargo.toml`
+    /// otherwise, and tells `cargo` to rerun the build script when it changes.
+    ///
+    /// # Panics
+    ///
+    /// If the description can't be read or parsed.
+    pub fn from_package() -> Self {
+        let dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+        let toml = dir.join("picorv32.toml");
+        let (path, layout) = if toml.exists() {
+            let text = fs::read_to_string(&toml).unwrap();
+            (toml, MemoryLayout::from_toml(&text))
+        } else {
+            let manifest = dir.join("Cargo.toml");
+            let text = fs::read_to_string(&manifest).unwrap();
+            (manifest, MemoryLayout::from_manifest(&text))
+        };
+        println!("cargo:rerun-if-changed={}", path.display());
+        match layout {
+            Ok(layout) => layout,
+            Err(e) => panic!("{}: {}", path.display(), e),
+        }
+    }
+
+    /// Checks the layout, returns what's wrong with it otherwise
+    ///
+    /// The regions have to be non-empty, word aligned, fit in the address space and not overlap.
+    /// The sizes have to be multiples of 4, and the stack and the heaps have to fit in the RAM
+    /// regions they're placed in.
+    pub fn check(&self) -> Result<(), String> {
+        let flash = self.flash.ok_or("the FLASH region is missing")?;
+        let ram = self.ram.ok_or("the RAM region is missing")?;
+        let regions = [Some(flash), Some(ram), self.ram2];
+        let regions: Vec<Region> = regions.iter().filter_map(|&r| r).collect();
+
+        for r in &regions {
+            if r.length == 0 {
+                return Err(format!("the {} region is empty", r.name));
+            }
+            if r.origin % 4 != 0 || r.length % 4 != 0 {
+                return Err(format!(
+                    "the {} region at {:#010x}, {:#x} bytes long, isn't word aligned",
+                    r.name, r.origin, r.length
+                ));
+            }
+            if u64::from(r.origin) + u64::from(r.length) > 1 << 32 {
+                return Err(format!(
+                    "the {} region at {:#010x}, {:#x} bytes long, runs past the end of the \
+                     address space",
+                    r.name, r.origin, r.length
+                ));
+            }
+        }
+        for (i, a) in regions.iter().enumerate() {
+            for b in &regions[i + 1..] {
+                let a_end = u64::from(a.origin) + u64::from(a.length);
+                let b_end = u64::from(b.origin) + u64::from(b.length);
+                if u64::from(a.origin) < b_end && u64::from(b.origin) < a_end {
+                    return Err(format!("the {} and {} regions overlap", a.name, b.name));
+                }
+            }
+        }
+
+        let sizes = [
+            ("_stack_size", self.stack_size),
+            ("_heap_size", self.heap_size),
+            ("_heap2_size", self.heap2_size),
+        ];
+        for &(symbol, size) in &sizes {
+            if size.unwrap_or(0) % 4 != 0 {
+                return Err(format!("{} isn't a multiple of 4", symbol));
+            }
+        }
+        if let Some(size) = self.flash_sector_size {
+            if !size.is_power_of_two() || size < 4 {
+                return Err(String::from(
+                    "_flash_sector_size isn't a power of two of at least 4",
+                ));
+            }
+        }
+
+        if let Some(reset) = self.reset_vector_address {
+            let flash_end = u64::from(flash.origin) + u64::from(flash.length);
+            if reset % 4 != 0 || reset < flash.origin || u64::from(reset) + 4 > flash_end {
+                return Err(format!(
+                    "the reset vector at {:#010x} isn't a word in FLASH",
+                    reset
+                ));
+            }
+        }
+        if let Some(irq) = self.irq_vector_address {
+            let reset = self.reset_vector_address.unwrap_or(flash.origin);
+            if irq % 4 != 0 || u64::from(irq) < u64::from(reset) + 4 {
+                return Err(format!(
+                    "the IRQ vector at {:#010x} has to be word aligned and come after the reset \
+                     vector",
+                    irq
+                ));
+            }
+        }
+
+        // the second heap goes after the first one without a RAM2 region
+        let stack = u64::from(self.stack_size.unwrap_or(0));
+        let heap = u64::from(self.heap_size.unwrap_or(0));
+        let heap2 = u64::from(self.heap2_size.unwrap_or(0));
+        let in_ram = stack + heap + if self.ram2.is_none() { heap2 } else { 0 };
+        if in_ram > u64::from(ram.length) {
+            return Err(format!(
+                "the stack and the heap need {:#x} bytes, more than the {:#x} bytes of RAM",
+                in_ram, ram.length
+            ));
+        }
+        if let Some(ram2) = self.ram2 {
+            if heap2 > u64::from(ram2.length) {
+                return Err(format!(
+                    "the second heap needs {:#x} bytes, more than the {:#x} bytes of RAM2",
+                    heap2, ram2.length
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The contents of `memory.x`
+    ///
+    /// # Panics
+    ///
+    /// If the layout doesn't pass [`check`](#method.check).
+    pub fn to_memory_x(&self) -> String {
+        if let Err(e) = self.check() {
+            panic!("invalid memory layout: {}", e)
+        }
+
+        let mut memory_x = String::from("MEMORY\n{\n");
+        for r in [self.flash, self.ram, self.ram2].iter().filter_map(|&r| r) {
+            writeln!(
+                memory_x,
+                "  {} : ORIGIN = {:#010x}, LENGTH = {:#x}",
+                r.name, r.origin, r.length
+            )
+            .unwrap();
+        }
+        memory_x.push_str("}\n");
+
+        for &(symbol, _, value) in &self.symbols() {
+            if let Some(value) = value {
+                writeln!(memory_x, "{} = {:#x};", symbol, value).unwrap();
+            }
+        }
+        memory_x
+    }
+
+    /// The contents of `memory_layout.rs`, a constant for every region and symbol set
+    ///
+    /// # Panics
+    ///
+    /// If the layout doesn't pass [`check`](#method.check).
+    pub fn to_rust_constants(&self) -> String {
+        if let Err(e) = self.check() {
+            panic!("invalid memory layout: {}", e)
+        }
+
+        let mut constants = String::new();
+        for r in [self.flash, self.ram, self.ram2].iter().filter_map(|&r| r) {
+            writeln!(
+                constants,
+                "pub const {0}_ORIGIN: u32 = {1:#010x};\npub const {0}_LENGTH: u32 = {2:#x};",
+                r.name, r.origin, r.length
+            )
+            .unwrap();
+        }
+        for &(_, constant, value) in &self.symbols() {
+            if let Some(value) = value {
+                writeln!(constants, "pub const {}: u32 = {:#x};", constant, value).unwrap();
+            }
+        }
+        constants
+    }
+
+    /// The optional symbols, with the names of their constants
+    fn symbols(&self) -> [(&'static str, &'static str, Option<u32>); 6] {
+        [
+            ("_stack_size", "STACK_SIZE", self.stack_size),
+            ("_heap_size", "HEAP_SIZE", self.heap_size),
+            ("_heap2_size", "HEAP2_SIZE", self.heap2_size),
+            (
+                "_flash_sector_size",
+                "FLASH_SECTOR_SIZE",
+                self.flash_sector_size,
+            ),
+            (
+                "_reset_vector_address",
+                "RESET_VECTOR_ADDRESS",
+                self.reset_vector_address,
+            ),
+            (
+                "_irq_vector_address",
+                "IRQ_VECTOR_ADDRESS",
+                self.irq_vector_address,
+            ),
+        ]
+    }
+
+    /// Writes `memory.x` and `memory_layout.rs` to `out_dir` and adds it to the linker search path
+    ///
+    /// Meant to be called from a build script with its `OUT_DIR`.
+    ///
+    /// # Panics
+    ///
+    /// If the layout doesn't pass [`check`](#method.check) or the file can't be written.
+    pub fn write<P: AsRef<Path>>(&self, out_dir: P) {
+        let out_dir = out_dir.as_ref();
+        fs::write(out_dir.join("memory.x"), self.to_memory_x()).unwrap();
+        fs::write(out_dir.join("memory_layout.rs"), self.to_rust_constants()).unwrap();
+        println!("cargo:rustc-link-search={}", out_dir.display());
+        println!("cargo:rerun-if-changed=build.rs");
+    }
+}
+
+/// Parses the tables of a device description below `prefix`, skipping all others
+fn parse(text: &str, prefix: &str) -> Result<MemoryLayout, String> {
+    let mut layout = MemoryLayout::new();
+    // the table the following keys belong to, relative to `prefix`, `None` outside of it
+    let mut table = if prefix.is_empty() {
+        Some(String::new())
+    } else {
+        None
+    };
+
+    for (i, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let at_line = |e: String| format!("line {}: {}", i + 1, e);
+
+        if line.starts_with('[') {
+            let name = line.trim_matches(|c| c == '[' || c == ']').trim();
+            table = if name == prefix {
+                Some(String::new())
+            } else if prefix.is_empty() {
+                Some(name.to_string())
+            } else if name.starts_with(prefix) && name[prefix.len()..].starts_with('.') {
+                Some(name[prefix.len() + 1..].to_string())
+            } else {
+                None
+            };
+            continue;
+        }
+        let table = match table {
+            Some(ref table) => table.as_str(),
+            None => continue,
+        };
+
+        let (key, value) = split_key_value(line).map_err(at_line)?;
+        if value.starts_with('{') {
+            // an inline table, `flash = { origin = 0, length = 0x1000 }`
+            if !table.is_empty() || !value.ends_with('}') {
+                return Err(at_line(format!("unexpected inline table `{}`", key)));
+            }
+            for field in value[1..value.len() - 1].split(',') {
+                if field.trim().is_empty() {
+                    continue;
+                }
+                let (field, value) = split_key_value(field.trim()).map_err(at_line)?;
+                layout = set(layout, key, field, parse_integer(value).map_err(at_line)?)
+                    .map_err(at_line)?;
+            }
+        } else {
+            layout =
+                set(layout, table, key, parse_integer(value).map_err(at_line)?).map_err(at_line)?;
+        }
+    }
+    Ok(layout)
+}
+
+/// `line` without a `#` comment, none of the values understood are strings that could contain one
+fn strip_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(i) => &line[..i],
+        None => line,
+    }
+}
+
+fn split_key_value(line: &str) -> Result<(&str, &str), String> {
+    let i = line
+        .find('=')
+        .ok_or_else(|| format!("expected `key = value`, found `{}`", line))?;
+    let key = line[..i].trim().trim_matches('"');
+    Ok((key, line[i + 1..].trim()))
+}
+
+/// Parses a TOML integer, decimal or with a `0x`/`0o`/`0b` prefix, with optional underscores
+fn parse_integer(value: &str) -> Result<u32, String> {
+    let digits = value.replace('_', "");
+    let parsed = if digits.starts_with("0x") {
+        u32::from_str_radix(&digits[2..], 16)
+    } else if digits.starts_with("0o") {
+        u32::from_str_radix(&digits[2..], 8)
+    } else if digits.starts_with("0b") {
+        u32::from_str_radix(&digits[2..], 2)
+    } else {
+        digits.parse()
+    };
+    parsed.map_err(|_| format!("`{}` isn't a 32 bit unsigned integer", value))
+}
+
+/// Sets `key` of `table` in `layout`, the top level for an empty `table`
+fn set(layout: MemoryLayout, table: &str, key: &str, value: u32) -> Result<MemoryLayout, String> {
+    let region = |r: Option<Region>, name| {
+        let (origin, length) = r.map_or((None, None), |r| (Some(r.origin), Some(r.length)));
+        match key {
+            "origin" => Ok((value, length.unwrap_or(0))),
+            "length" => Ok((origin.unwrap_or(0), value)),
+            _ => Err(format!("unknown key `{}` of the {} region", key, name)),
+        }
+    };
+    Ok(match (table, key) {
+        ("", "stack-size") => layout.stack_size(value),
+        ("", "heap-size") => layout.heap_size(value),
+        ("", "heap2-size") => layout.heap2_size(value),
+        ("", "flash-sector-size") => layout.flash_sector_size(value),
+        ("", "reset-vector-address") => layout.reset_vector_address(value),
+        ("", "irq-vector-address") => layout.irq_vector_address(value),
+        ("flash", _) => {
+            let (origin, length) = region(layout.flash, "FLASH")?;
+            layout.flash(origin, length)
+        }
+        ("ram", _) => {
+            let (origin, length) = region(layout.ram, "RAM")?;
+            layout.ram(origin, length)
+        }
+        ("ram2", _) => {
+            let (origin, length) = region(layout.ram2, "RAM2")?;
+            layout.ram2(origin, length)
+        }
+        ("", _) => return Err(format!("unknown key `{}`", key)),
+        _ => return Err(format!("unknown table `{}`", table)),
+    })
+}
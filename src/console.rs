@@ -0,0 +1,87 @@
+//! Memory-mapped console
+//!
+//! Most PicoRV32 SoCs, and the testbenches of the PicoRV32 repository, have a register that
+//! sends every byte written to it to a UART or the simulator's stdout. With the `console` feature
+//! [`print!`](../macro.print.html) and [`println!`](../macro.println.html) write there. The
+//! address is `_console_tx_addr`, set in `memory.x`:
+//!
+//! ``` text
+//! _console_tx_addr = 0x10000000;
+//! ```
+//!
+//! Each byte is a 32-bit store of its value, so UARTs that have to be polled before sending take a
+//! sink installed with [`set_sink`](fn.set_sink.html) instead, which takes precedence over the
+//! register. Without either the output is dropped.
+//!
+//! Every `print!` is written with all IRQ lines masked, so it can be used from both `main` and
+//! trap handlers without the output getting interleaved.
+//!
+//! With the `panic-console` feature the [panic handler](../panic_handler/index.html) writes its
+//! report, the message, the registers and a backtrace, to the console too. Together with
+//! `panic: PanicPolicy::Halt` in the [runtime config](../runtime/index.html) it replaces
+//! `panic-halt`, with the panic out in the open.
+//!
+//! ```
+//! use picorv32_rt::{console, println};
+//!
+//! fn uart_write(byte: u8) {
+//!     // wait for the UART and send `byte`
+//! }
+//!
+//! fn init() {
+//!     console::set_sink(uart_write);
+//!     println!("booted, {} KiB of RAM", 64);
+//! }
+//! ```
+
+use core::fmt::{self, Write};
+use core::ptr;
+
+use crate::interrupt;
+
+/// Installed by `set_sink`
+static mut SINK: Option<fn(u8)> = None;
+
+/// Sends every byte written to the console sink or the TX register
+///
+/// Unlike `print!` it doesn't mask IRQs, wrap it in
+/// [`interrupt::free`](../interrupt/fn.free.html) to keep the output of several writes together.
+pub struct Console;
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            write_byte(byte);
+        }
+        Ok(())
+    }
+}
+
+/// Sends console output to `sink` instead of the `_console_tx_addr` register
+pub fn set_sink(sink: fn(u8)) {
+    unsafe { ptr::write_volatile(&mut SINK, Some(sink)) };
+}
+
+/// Sends `byte` to the console
+pub fn write_byte(byte: u8) {
+    extern "C" {
+        // Defined by link.x, 1 if memory.x sets `_console_tx_addr`
+        static _has_console_tx: u8;
+        static mut _console_tx_addr: u32;
+    }
+
+    unsafe {
+        if let Some(sink) = ptr::read_volatile(&SINK) {
+            sink(byte)
+        } else if &_has_console_tx as *const u8 as usize != 0 {
+            ptr::write_volatile(&mut _console_tx_addr, u32::from(byte));
+        }
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    interrupt::free(|_| {
+        let _ = Console.write_fmt(args);
+    });
+}
@@ -0,0 +1,186 @@
+//! Context switching for preemptive kernels
+//!
+//! The trap entry saves the registers of the interrupted code in a frame on its own stack, so a
+//! task that isn't running is described by the address of that frame, plus the registers that
+//! live in q registers with `interrupts-qregs`. With the `context-switch` feature this module
+//! exposes that knowledge to RTOS ports:
+//!
+//! - [`TaskContext`](struct.TaskContext.html), a suspended task
+//! - [`init_task_stack`](fn.init_task_stack.html), which fabricates the frame of a task that
+//!   hasn't run yet
+//! - [`context_switch_in_trap`](fn.context_switch_in_trap.html), which makes the current trap
+//!   return to another task and suspends the interrupted one
+//!
+//! The feature enables `full-trap-frame`, as a suspended task needs its callee-saved registers
+//! back as well. The `gp`/`tp` registers aren't switched, they belong to the whole program.
+//!
+//! This API is meant for kernel authors and isn't stable yet, it may change in minor releases.
+//!
+//! ```
+//! use picorv32_rt::context::{self, TaskContext};
+//! use picorv32_rt::PicoRV32StoredRegisters;
+//!
+//! static mut STACK: [u32; 256] = [0; 256];
+//! static mut SUSPENDED: Option<TaskContext> = None;
+//!
+//! extern "C" fn task() -> ! {
+//!     loop {}
+//! }
+//!
+//! fn init() {
+//!     unsafe { SUSPENDED = Some(context::init_task_stack(task, &mut STACK)) };
+//! }
+//!
+//! // round robin between `main` and `task`
+//! fn tick(regs: &mut PicoRV32StoredRegisters) {
+//!     unsafe {
+//!         if let Some(next) = SUSPENDED.take() {
+//!             SUSPENDED = Some(context::context_switch_in_trap(regs, &next));
+//!         }
+//!     }
+//! }
+//!
+//! picorv32_rt::picorv32_interrupts!(timer: tick);
+//! ```
+
+use core::mem;
+use core::ptr;
+
+#[cfg(feature = "interrupts-qregs")]
+use picorv32::asm;
+
+use crate::PicoRV32StoredRegisters;
+
+/// Frame the current trap returns through, null for the one it was entered with
+static mut NEXT: *mut PicoRV32StoredRegisters = ptr::null_mut();
+
+/// A suspended task
+///
+/// Resuming the same context twice makes two tasks share a stack, so it can't be copied.
+#[derive(Debug)]
+pub struct TaskContext {
+    frame: *mut PicoRV32StoredRegisters,
+    /// Return address of the trap
+    #[cfg(feature = "interrupts-qregs")]
+    q0: u32,
+    /// `x10`
+    #[cfg(feature = "interrupts-qregs-spill")]
+    q1: u32,
+    /// `x1`
+    #[cfg(feature = "interrupts-qregs")]
+    q2: u32,
+}
+
+impl TaskContext {
+    /// Stack pointer of the task once it's resumed
+    pub fn stack_pointer(&self) -> usize {
+        self.frame as usize + mem::size_of::<PicoRV32StoredRegisters>()
+    }
+
+    /// Lowest address of the stack the task has used so far, its saved frame
+    pub fn frame_address(&self) -> usize {
+        self.frame as usize
+    }
+}
+
+/// Prepares `stack` for a task that starts at `entry` once it's resumed
+///
+/// The task starts with all registers but the stack pointer zeroed, and its stack pointer at
+/// the top of `stack`, 16-byte aligned.
+///
+/// # Panics
+///
+/// If `stack` can't hold a trap frame
+pub fn init_task_stack(entry: extern "C" fn() -> !, stack: &'static mut [u32]) -> TaskContext {
+    let size = mem::size_of::<PicoRV32StoredRegisters>();
+    let bottom = stack.as_ptr() as usize;
+    let top = (bottom + stack.len() * 4) & !15;
+    assert!(top >= bottom + size, "task stack too small");
+
+    let frame = (top - size) as *mut PicoRV32StoredRegisters;
+    let regs = unsafe {
+        ptr::write_bytes(frame, 0, 1);
+        &mut *frame
+    };
+
+    #[cfg(feature = "interrupts-qregs")]
+    {
+        extern "C" {
+            #[link_name = "__global_pointer$"]
+            static GLOBAL_POINTER: u8;
+        }
+
+        regs.x3 = unsafe { &GLOBAL_POINTER as *const u8 as u32 };
+        TaskContext {
+            frame,
+            q0: entry as usize as u32,
+            #[cfg(feature = "interrupts-qregs-spill")]
+            q1: 0,
+            q2: 0,
+        }
+    }
+
+    #[cfg(not(feature = "interrupts-qregs"))]
+    {
+        // the return address of the trap is restored into `x3`, the stack pointer is restored
+        // first and then advanced past the frame
+        regs.x3 = entry as usize as u32;
+        regs.x2 = frame as u32;
+        TaskContext { frame }
+    }
+}
+
+/// Makes the current trap return to `next`, returns the context of the interrupted task
+///
+/// May be called more than once in a trap, the task returned by a call is the one resumed by
+/// the previous call then. Handlers that run later in the same trap still get the frame of the
+/// interrupted task.
+///
+/// # Safety
+///
+/// `frame` has to be the frame of a trap handler called by the hardware trap, not by
+/// [`priority::pend`](../priority/fn.pend.html) or IRQ replay. `next` has to be a suspended
+/// task that isn't resumed anywhere else, after this call it's running and must be dropped.
+pub unsafe fn context_switch_in_trap(
+    frame: &mut PicoRV32StoredRegisters,
+    next: &TaskContext,
+) -> TaskContext {
+    let current = if NEXT.is_null() {
+        frame as *mut PicoRV32StoredRegisters
+    } else {
+        NEXT
+    };
+    NEXT = next.frame;
+
+    #[cfg(feature = "interrupts-qregs")]
+    {
+        let suspended = TaskContext {
+            frame: current,
+            q0: asm::getq0(),
+            #[cfg(feature = "interrupts-qregs-spill")]
+            q1: asm::getq1(),
+            q2: asm::getq2(),
+        };
+        asm::setq0(next.q0);
+        #[cfg(feature = "interrupts-qregs-spill")]
+        asm::setq1(next.q1);
+        asm::setq2(next.q2);
+        asm::setq3(next.stack_pointer() as u32);
+        suspended
+    }
+
+    #[cfg(not(feature = "interrupts-qregs"))]
+    TaskContext { frame: current }
+}
+
+/// Frame the trap returns through, called at the end of the trap handler
+pub(crate) fn resume(frame: *mut u32) -> *mut u32 {
+    unsafe {
+        let next = mem::replace(&mut NEXT, ptr::null_mut());
+        if next.is_null() {
+            frame
+        } else {
+            next as *mut u32
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Persistent crash region
+//!
+//! A small record in `.uninit` RAM that fatal paths fill in right before the system stops or
+//! restarts. The startup code leaves `.uninit` alone, so the next boot can read the record back
+//! with [`take`](fn.take.html) and report it.
+//!
+//! ```
+//! use picorv32_rt::crash::{self, Crash};
+//!
+//! fn report_last_crash() {
+//!     match crash::take() {
+//!         Some(Crash::Assertion { location }) => { /* send `location` home */ }
+//!         Some(crash) => { /* ... */ }
+//!         None => {}
+//!     }
+//! }
+//! ```
+//!
+//! # Assertions
+//!
+//! [`rt_assert!`](../macro.rt_assert.html) and [`rt_debug_assert!`](../macro.rt_debug_assert.html)
+//! panic with the full message in debug builds. In release builds a failed assertion records
+//! [`Crash::Assertion`](enum.Crash.html) and restarts the firmware from `_start`, or halts it as
+//! the [`PanicPolicy`](../runtime/enum.PanicPolicy.html) says, without pulling in any formatting
+//! code.
+//!
+//! ```
+//! use picorv32_rt::rt_assert;
+//!
+//! fn set_divider(divider: u32) {
+//!     rt_assert!(divider != 0, "divider must not be zero");
+//!     // ...
+//! }
+//! ```
+
+use core::panic::PanicInfo;
+use core::ptr;
+
+use crate::crc;
+
+/// `"CRSH"`, marks a valid record
+const MAGIC: u32 = 0x4853_5243;
+
+/// `Crash::Assertion`
+const KIND_ASSERTION: u32 = 2;
+/// `Crash::Panic`
+const KIND_PANIC: u32 = 3;
+/// `Crash::OutOfMemory`
+const KIND_OUT_OF_MEMORY: u32 = 4;
+
+/// Magic, kind and two kind-specific words
+#[link_section = ".uninit.crash"]
+static mut RECORD: [u32; 4] = [0; 4];
+
+/// Reason the firmware stopped or restarted
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Crash {
+    /// An `rt_assert!` failed in a release build
+    Assertion {
+        /// See [`location_code`](fn.location_code.html)
+        location: u32,
+    },
+    /// A panic, see [`record_panic`](fn.record_panic.html)
+    Panic {
+        /// See [`location_code`](fn.location_code.html), 0 if unknown
+        location: u32,
+    },
+    /// An allocation failed, see the [`heap`](../heap/index.html) module
+    OutOfMemory {
+        /// Requested size in bytes
+        size: u32,
+        /// Requested alignment
+        align: u32,
+    },
+}
+
+/// Writes `crash` to the crash region, replacing any previous record
+///
+/// With the `event-log` feature it's also appended to the installed
+/// [event log](../event_log/index.html), with the `fault-blink` feature it's shown as a
+/// [blink code](../blink/index.html), unless the [`LogSinks`](../runtime/struct.LogSinks.html)
+/// of the runtime configuration turn them off.
+pub fn record(crash: Crash) {
+    unsafe { ptr::write_volatile(ptr::addr_of_mut!(RECORD), encode(crash)) };
+
+    #[cfg(feature = "event-log")]
+    {
+        if crate::runtime::config().log.event_log {
+            crate::event_log::record_crash(crash);
+        }
+    }
+
+    #[cfg(feature = "fault-blink")]
+    {
+        if crate::runtime::config().log.blink {
+            crate::blink::show(crate::blink::BlinkCode::from_crash(crash));
+        }
+    }
+}
+
+/// Records a [`Crash::Panic`](enum.Crash.html), meant to be called from the panic handler
+///
+/// Only the location is recorded, so this doesn't pull in any formatting code. The `__boot_beacon`
+/// hook is called with [`BootStage::Panic`](../runtime/enum.BootStage.html) afterwards.
+pub fn record_panic(info: &PanicInfo) {
+    let location = info
+        .location()
+        .map(|location| location_code(location.file(), location.line()))
+        .unwrap_or(0);
+    record(Crash::Panic { location });
+    crate::runtime::beacon(crate::runtime::BootStage::Panic);
+}
+
+/// Returns and clears the record left before the last restart, if any
+pub fn take() -> Option<Crash> {
+    unsafe {
+        let record = ptr::read_volatile(ptr::addr_of!(RECORD));
+        ptr::write_volatile(ptr::addr_of_mut!(RECORD[0]), 0);
+        decode(record)
+    }
+}
+
+/// Magic, kind and two kind-specific words
+pub(crate) fn encode(crash: Crash) -> [u32; 4] {
+    match crash {
+        Crash::Assertion { location } => [MAGIC, KIND_ASSERTION, location, 0],
+        Crash::Panic { location } => [MAGIC, KIND_PANIC, location, 0],
+        Crash::OutOfMemory { size, align } => [MAGIC, KIND_OUT_OF_MEMORY, size, align],
+    }
+}
+
+pub(crate) fn decode(record: [u32; 4]) -> Option<Crash> {
+    if record[0] != MAGIC {
+        return None;
+    }
+    match record[1] {
+        KIND_ASSERTION => Some(Crash::Assertion {
+            location: record[2],
+        }),
+        KIND_PANIC => Some(Crash::Panic {
+            location: record[2],
+        }),
+        KIND_OUT_OF_MEMORY => Some(Crash::OutOfMemory {
+            size: record[2],
+            align: record[3],
+        }),
+        _ => None,
+    }
+}
+
+/// Identifies a source location in 32 bits
+///
+/// The upper half is the CRC-32 of `file`, folded to 16 bits, the lower half is `line`. Host
+/// tools can map codes back by computing the same for the source files of the firmware.
+pub fn location_code(file: &str, line: u32) -> u32 {
+    let crc = crc::crc32(file.as_bytes());
+    (crc >> 16 ^ crc) << 16 | line & 0xffff
+}
+
+#[doc(hidden)]
+#[cold]
+#[inline(never)]
+pub fn assertion_failed(file: &'static str, line: u32) -> ! {
+    record(Crash::Assertion {
+        location: location_code(file, line),
+    });
+    crate::runtime::fatal()
+}
@@ -0,0 +1,28 @@
+//! CRC-32 (IEEE 802.3), as used by zlib and most host tools
+//!
+//! Computed bit by bit, trading speed for not needing a 1 KiB table in flash.
+
+/// Reversed polynomial
+const POLY: u32 = 0xedb8_8320;
+
+/// Computes the CRC-32 of `data`
+pub fn crc32(data: &[u8]) -> u32 {
+    !update(!0, data)
+}
+
+/// Feeds `data` into a running CRC-32, start with `!0` and invert the final value
+///
+/// Allows computing the checksum of data that isn't contiguous in memory.
+pub fn update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
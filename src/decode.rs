@@ -0,0 +1,658 @@
+//! RV32IMC instruction decoder
+//!
+//! Used by the emulation features and available to user fault handlers, e.g. together with
+//! [`exception::faulting_instruction`](../exception/fn.faulting_instruction.html):
+//!
+//! ```
+//! use picorv32_rt::decode::{self, DecodedInstr};
+//!
+//! let instr = 0x02b5_0533; // mul a0, a0, a1
+//! assert_eq!(decode::length(instr), 4);
+//! match decode::decode(instr) {
+//!     DecodedInstr::MulDiv { rd, rs1, rs2, .. } => assert_eq!((rd, rs1, rs2), (10, 10, 11)),
+//!     _ => unreachable!(),
+//! }
+//! ```
+//!
+//! Compressed instructions are expanded to their RV32I equivalents, so `c.addi sp, -16` decodes
+//! the same as `addi sp, sp, -16`. Register numbers are always full `x0`..`x31` numbers.
+
+/// Load/store access width
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Width {
+    /// 8 bits
+    Byte,
+    /// 16 bits
+    Half,
+    /// 32 bits
+    Word,
+}
+
+impl Width {
+    /// Width in bytes
+    pub fn bytes(self) -> u32 {
+        match self {
+            Width::Byte => 1,
+            Width::Half => 2,
+            Width::Word => 4,
+        }
+    }
+}
+
+/// Branch condition
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BranchOp {
+    /// `beq`
+    Eq,
+    /// `bne`
+    Ne,
+    /// `blt`
+    Lt,
+    /// `bge`
+    Ge,
+    /// `bltu`
+    Ltu,
+    /// `bgeu`
+    Geu,
+}
+
+/// Integer register-register and register-immediate operation
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AluOp {
+    /// `add`/`addi`
+    Add,
+    /// `sub`
+    Sub,
+    /// `sll`/`slli`
+    Sll,
+    /// `slt`/`slti`
+    Slt,
+    /// `sltu`/`sltiu`
+    Sltu,
+    /// `xor`/`xori`
+    Xor,
+    /// `srl`/`srli`
+    Srl,
+    /// `sra`/`srai`
+    Sra,
+    /// `or`/`ori`
+    Or,
+    /// `and`/`andi`
+    And,
+}
+
+/// M extension operation
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MulDivOp {
+    /// `mul`
+    Mul,
+    /// `mulh`
+    Mulh,
+    /// `mulhsu`
+    Mulhsu,
+    /// `mulhu`
+    Mulhu,
+    /// `div`
+    Div,
+    /// `divu`
+    Divu,
+    /// `rem`
+    Rem,
+    /// `remu`
+    Remu,
+}
+
+/// A decoded instruction
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecodedInstr {
+    /// `lui`, `imm` already shifted into place
+    Lui {
+        /// Destination register
+        rd: u32,
+        /// Upper immediate
+        imm: u32,
+    },
+    /// `auipc`, `imm` already shifted into place
+    Auipc {
+        /// Destination register
+        rd: u32,
+        /// Upper immediate
+        imm: u32,
+    },
+    /// `jal`
+    Jal {
+        /// Link register
+        rd: u32,
+        /// PC relative offset
+        offset: i32,
+    },
+    /// `jalr`
+    Jalr {
+        /// Link register
+        rd: u32,
+        /// Base register
+        rs1: u32,
+        /// Offset from `rs1`
+        offset: i32,
+    },
+    /// Conditional branch
+    Branch {
+        /// Condition
+        op: BranchOp,
+        /// First operand
+        rs1: u32,
+        /// Second operand
+        rs2: u32,
+        /// PC relative offset
+        offset: i32,
+    },
+    /// Load
+    Load {
+        /// Access width
+        width: Width,
+        /// Whether the loaded value is sign extended
+        signed: bool,
+        /// Destination register
+        rd: u32,
+        /// Base register
+        rs1: u32,
+        /// Offset from `rs1`
+        offset: i32,
+    },
+    /// Store
+    Store {
+        /// Access width
+        width: Width,
+        /// Base register
+        rs1: u32,
+        /// Source register
+        rs2: u32,
+        /// Offset from `rs1`
+        offset: i32,
+    },
+    /// Register-immediate operation, shift amounts are in `imm`
+    OpImm {
+        /// Operation
+        op: AluOp,
+        /// Destination register
+        rd: u32,
+        /// Source register
+        rs1: u32,
+        /// Immediate operand
+        imm: i32,
+    },
+    /// Register-register operation
+    Op {
+        /// Operation
+        op: AluOp,
+        /// Destination register
+        rd: u32,
+        /// First source register
+        rs1: u32,
+        /// Second source register
+        rs2: u32,
+    },
+    /// M extension operation
+    MulDiv {
+        /// Operation
+        op: MulDivOp,
+        /// Destination register
+        rd: u32,
+        /// First source register
+        rs1: u32,
+        /// Second source register
+        rs2: u32,
+    },
+    /// `fence`/`fence.i`
+    Fence,
+    /// `ecall`
+    Ecall,
+    /// `ebreak`/`c.ebreak`
+    Ebreak,
+    /// `csrr*` instructions, e.g. `rdcycle`
+    Csr {
+        /// `funct3`, selecting the CSR operation
+        funct3: u32,
+        /// Destination register
+        rd: u32,
+        /// Source register or immediate
+        rs1: u32,
+        /// CSR number
+        csr: u32,
+    },
+    /// An R-type instruction in the custom-0 or custom-1 opcode space
+    ///
+    /// The PicoRV32 IRQ instructions (`getq`, `setq`, `retirq`, `maskirq`, `waitirq`, `timer`) as
+    /// well as most PCPI coprocessor instructions live here.
+    Custom {
+        /// Major opcode, `0b000_1011` (custom-0) or `0b010_1011` (custom-1)
+        opcode: u32,
+        /// `funct3`
+        funct3: u32,
+        /// `funct7`
+        funct7: u32,
+        /// Destination register
+        rd: u32,
+        /// First source register
+        rs1: u32,
+        /// Second source register
+        rs2: u32,
+    },
+    /// Anything else
+    Unknown,
+}
+
+/// custom-0 major opcode
+pub const OPCODE_CUSTOM0: u32 = 0b000_1011;
+/// custom-1 major opcode
+pub const OPCODE_CUSTOM1: u32 = 0b010_1011;
+
+/// Length in bytes of the instruction starting with `instr`, 2 for compressed instructions
+///
+/// Only the lowest two bits are looked at.
+pub fn length(instr: u32) -> u32 {
+    if instr & 3 == 3 {
+        4
+    } else {
+        2
+    }
+}
+
+/// Major opcode of a 32 bit instruction
+pub fn opcode(instr: u32) -> u32 {
+    instr & 0x7f
+}
+
+/// `rd` field of a 32 bit instruction
+pub fn rd(instr: u32) -> u32 {
+    (instr >> 7) & 0x1f
+}
+
+/// `rs1` field of a 32 bit instruction
+pub fn rs1(instr: u32) -> u32 {
+    (instr >> 15) & 0x1f
+}
+
+/// `rs2` field of a 32 bit instruction
+pub fn rs2(instr: u32) -> u32 {
+    (instr >> 20) & 0x1f
+}
+
+/// `funct3` field of a 32 bit instruction
+pub fn funct3(instr: u32) -> u32 {
+    (instr >> 12) & 0x7
+}
+
+/// `funct7` field of a 32 bit instruction
+pub fn funct7(instr: u32) -> u32 {
+    instr >> 25
+}
+
+/// Decodes an instruction, compressed ones are taken from the lower 16 bits
+pub fn decode(instr: u32) -> DecodedInstr {
+    if length(instr) == 4 {
+        decode_32(instr)
+    } else {
+        decode_16(instr & 0xffff)
+    }
+}
+
+fn decode_32(instr: u32) -> DecodedInstr {
+    let rd = rd(instr);
+    let rs1 = rs1(instr);
+    let rs2 = rs2(instr);
+    let funct3 = funct3(instr);
+    let funct7 = funct7(instr);
+    let imm_i = instr as i32 >> 20;
+
+    match opcode(instr) {
+        0b011_0111 => DecodedInstr::Lui {
+            rd,
+            imm: instr & 0xffff_f000,
+        },
+        0b001_0111 => DecodedInstr::Auipc {
+            rd,
+            imm: instr & 0xffff_f000,
+        },
+        0b110_1111 => DecodedInstr::Jal {
+            rd,
+            offset: (instr as i32 >> 31) << 20
+                | (((instr >> 21) & 0x3ff) << 1) as i32
+                | (((instr >> 20) & 0x1) << 11) as i32
+                | (((instr >> 12) & 0xff) << 12) as i32,
+        },
+        0b110_0111 if funct3 == 0 => DecodedInstr::Jalr {
+            rd,
+            rs1,
+            offset: imm_i,
+        },
+        0b110_0011 => {
+            let op = match funct3 {
+                0 => BranchOp::Eq,
+                1 => BranchOp::Ne,
+                4 => BranchOp::Lt,
+                5 => BranchOp::Ge,
+                6 => BranchOp::Ltu,
+                7 => BranchOp::Geu,
+                _ => return DecodedInstr::Unknown,
+            };
+            DecodedInstr::Branch {
+                op,
+                rs1,
+                rs2,
+                offset: (instr as i32 >> 31) << 12
+                    | (((instr >> 25) & 0x3f) << 5) as i32
+                    | (((instr >> 8) & 0xf) << 1) as i32
+                    | (((instr >> 7) & 0x1) << 11) as i32,
+            }
+        }
+        0b000_0011 => {
+            let (width, signed) = match funct3 {
+                0 => (Width::Byte, true),
+                1 => (Width::Half, true),
+                2 => (Width::Word, true),
+                4 => (Width::Byte, false),
+                5 => (Width::Half, false),
+                _ => return DecodedInstr::Unknown,
+            };
+            DecodedInstr::Load {
+                width,
+                signed,
+                rd,
+                rs1,
+                offset: imm_i,
+            }
+        }
+        0b010_0011 => {
+            let width = match funct3 {
+                0 => Width::Byte,
+                1 => Width::Half,
+                2 => Width::Word,
+                _ => return DecodedInstr::Unknown,
+            };
+            DecodedInstr::Store {
+                width,
+                rs1,
+                rs2,
+                offset: (instr as i32 >> 25) << 5 | rd as i32,
+            }
+        }
+        0b001_0011 => {
+            let (op, imm) = match (funct3, funct7) {
+                (0, _) => (AluOp::Add, imm_i),
+                (1, 0) => (AluOp::Sll, rs2 as i32),
+                (2, _) => (AluOp::Slt, imm_i),
+                (3, _) => (AluOp::Sltu, imm_i),
+                (4, _) => (AluOp::Xor, imm_i),
+                (5, 0) => (AluOp::Srl, rs2 as i32),
+                (5, 0b010_0000) => (AluOp::Sra, rs2 as i32),
+                (6, _) => (AluOp::Or, imm_i),
+                (7, _) => (AluOp::And, imm_i),
+                _ => return DecodedInstr::Unknown,
+            };
+            DecodedInstr::OpImm { op, rd, rs1, imm }
+        }
+        0b011_0011 => {
+            if funct7 == 1 {
+                let op = match funct3 {
+                    0 => MulDivOp::Mul,
+                    1 => MulDivOp::Mulh,
+                    2 => MulDivOp::Mulhsu,
+                    3 => MulDivOp::Mulhu,
+                    4 => MulDivOp::Div,
+                    5 => MulDivOp::Divu,
+                    6 => MulDivOp::Rem,
+                    _ => MulDivOp::Remu,
+                };
+                return DecodedInstr::MulDiv { op, rd, rs1, rs2 };
+            }
+
+            let op = match (funct3, funct7) {
+                (0, 0) => AluOp::Add,
+                (0, 0b010_0000) => AluOp::Sub,
+                (1, 0) => AluOp::Sll,
+                (2, 0) => AluOp::Slt,
+                (3, 0) => AluOp::Sltu,
+                (4, 0) => AluOp::Xor,
+                (5, 0) => AluOp::Srl,
+                (5, 0b010_0000) => AluOp::Sra,
+                (6, 0) => AluOp::Or,
+                (7, 0) => AluOp::And,
+                _ => return DecodedInstr::Unknown,
+            };
+            DecodedInstr::Op { op, rd, rs1, rs2 }
+        }
+        0b000_1111 => DecodedInstr::Fence,
+        0b111_0011 => match (instr, funct3) {
+            (0x0000_0073, _) => DecodedInstr::Ecall,
+            (0x0010_0073, _) => DecodedInstr::Ebreak,
+            (_, 0) | (_, 4) => DecodedInstr::Unknown,
+            _ => DecodedInstr::Csr {
+                funct3,
+                rd,
+                rs1,
+                csr: instr >> 20,
+            },
+        },
+        opcode @ OPCODE_CUSTOM0 | opcode @ OPCODE_CUSTOM1 => DecodedInstr::Custom {
+            opcode,
+            funct3,
+            funct7,
+            rd,
+            rs1,
+            rs2,
+        },
+        _ => DecodedInstr::Unknown,
+    }
+}
+
+/// Sign extends the lowest `bits` bits of `value`
+fn sext(value: u32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    (value << shift) as i32 >> shift
+}
+
+fn decode_16(instr: u32) -> DecodedInstr {
+    let funct3 = instr >> 13;
+    // full register numbers, and the 3 bit ones of the CIW/CL/CS/CB formats
+    let rd = (instr >> 7) & 0x1f;
+    let rs2 = (instr >> 2) & 0x1f;
+    let rd_ = ((instr >> 7) & 0x7) + 8;
+    let rs2_ = ((instr >> 2) & 0x7) + 8;
+    // 6 bit immediate of the CI format
+    let imm6 = sext(((instr >> 12) & 0x1) << 5 | (instr >> 2) & 0x1f, 6);
+    let bit12 = (instr >> 12) & 0x1;
+
+    match (instr & 3, funct3) {
+        // C.ADDI4SPN
+        (0b00, 0b000) => {
+            let imm = ((instr >> 6) & 0x1) << 2
+                | ((instr >> 5) & 0x1) << 3
+                | ((instr >> 11) & 0x3) << 4
+                | ((instr >> 7) & 0xf) << 6;
+            if imm == 0 {
+                return DecodedInstr::Unknown;
+            }
+            DecodedInstr::OpImm {
+                op: AluOp::Add,
+                rd: rs2_,
+                rs1: 2,
+                imm: imm as i32,
+            }
+        }
+        // C.LW / C.SW
+        (0b00, 0b010) | (0b00, 0b110) => {
+            let offset = (((instr >> 6) & 0x1) << 2
+                | ((instr >> 10) & 0x7) << 3
+                | ((instr >> 5) & 0x1) << 6) as i32;
+            if funct3 == 0b010 {
+                DecodedInstr::Load {
+                    width: Width::Word,
+                    signed: true,
+                    rd: rs2_,
+                    rs1: rd_,
+                    offset,
+                }
+            } else {
+                DecodedInstr::Store {
+                    width: Width::Word,
+                    rs1: rd_,
+                    rs2: rs2_,
+                    offset,
+                }
+            }
+        }
+        // C.ADDI / C.NOP
+        (0b01, 0b000) => DecodedInstr::OpImm {
+            op: AluOp::Add,
+            rd,
+            rs1: rd,
+            imm: imm6,
+        },
+        // C.JAL / C.J
+        (0b01, 0b001) | (0b01, 0b101) => DecodedInstr::Jal {
+            rd: if funct3 == 0b001 { 1 } else { 0 },
+            offset: sext(
+                ((instr >> 12) & 0x1) << 11
+                    | ((instr >> 11) & 0x1) << 4
+                    | ((instr >> 9) & 0x3) << 8
+                    | ((instr >> 8) & 0x1) << 10
+                    | ((instr >> 7) & 0x1) << 6
+                    | ((instr >> 6) & 0x1) << 7
+                    | ((instr >> 3) & 0x7) << 1
+                    | ((instr >> 2) & 0x1) << 5,
+                12,
+            ),
+        },
+        // C.LI
+        (0b01, 0b010) => DecodedInstr::OpImm {
+            op: AluOp::Add,
+            rd,
+            rs1: 0,
+            imm: imm6,
+        },
+        // C.ADDI16SP
+        (0b01, 0b011) if rd == 2 => DecodedInstr::OpImm {
+            op: AluOp::Add,
+            rd: 2,
+            rs1: 2,
+            imm: sext(
+                bit12 << 9
+                    | ((instr >> 6) & 0x1) << 4
+                    | ((instr >> 5) & 0x1) << 6
+                    | ((instr >> 3) & 0x3) << 7
+                    | ((instr >> 2) & 0x1) << 5,
+                10,
+            ),
+        },
+        // C.LUI
+        (0b01, 0b011) => DecodedInstr::Lui {
+            rd,
+            imm: (imm6 << 12) as u32,
+        },
+        // C.SRLI / C.SRAI / C.ANDI / C.SUB / C.XOR / C.OR / C.AND
+        (0b01, 0b100) => match ((instr >> 10) & 0x3, bit12) {
+            (0b00, 0) => DecodedInstr::OpImm {
+                op: AluOp::Srl,
+                rd: rd_,
+                rs1: rd_,
+                imm: rs2 as i32,
+            },
+            (0b01, 0) => DecodedInstr::OpImm {
+                op: AluOp::Sra,
+                rd: rd_,
+                rs1: rd_,
+                imm: rs2 as i32,
+            },
+            (0b10, _) => DecodedInstr::OpImm {
+                op: AluOp::And,
+                rd: rd_,
+                rs1: rd_,
+                imm: imm6,
+            },
+            (0b11, 0) => {
+                let op = match (instr >> 5) & 0x3 {
+                    0b00 => AluOp::Sub,
+                    0b01 => AluOp::Xor,
+                    0b10 => AluOp::Or,
+                    _ => AluOp::And,
+                };
+                DecodedInstr::Op {
+                    op,
+                    rd: rd_,
+                    rs1: rd_,
+                    rs2: rs2_,
+                }
+            }
+            _ => DecodedInstr::Unknown,
+        },
+        // C.BEQZ / C.BNEZ
+        (0b01, 0b110) | (0b01, 0b111) => DecodedInstr::Branch {
+            op: if funct3 == 0b110 {
+                BranchOp::Eq
+            } else {
+                BranchOp::Ne
+            },
+            rs1: rd_,
+            rs2: 0,
+            offset: sext(
+                bit12 << 8
+                    | ((instr >> 10) & 0x3) << 3
+                    | ((instr >> 5) & 0x3) << 6
+                    | ((instr >> 3) & 0x3) << 1
+                    | ((instr >> 2) & 0x1) << 5,
+                9,
+            ),
+        },
+        // C.SLLI
+        (0b10, 0b000) if bit12 == 0 => DecodedInstr::OpImm {
+            op: AluOp::Sll,
+            rd,
+            rs1: rd,
+            imm: rs2 as i32,
+        },
+        // C.LWSP
+        (0b10, 0b010) if rd != 0 => DecodedInstr::Load {
+            width: Width::Word,
+            signed: true,
+            rd,
+            rs1: 2,
+            offset: (((instr >> 4) & 0x7) << 2 | bit12 << 5 | ((instr >> 2) & 0x3) << 6) as i32,
+        },
+        // C.JR / C.MV / C.EBREAK / C.JALR / C.ADD
+        (0b10, 0b100) => match (bit12, rd, rs2) {
+            (0, 0, 0) => DecodedInstr::Unknown,
+            (0, _, 0) => DecodedInstr::Jalr {
+                rd: 0,
+                rs1: rd,
+                offset: 0,
+            },
+            (0, _, _) => DecodedInstr::Op {
+                op: AluOp::Add,
+                rd,
+                rs1: 0,
+                rs2,
+            },
+            (_, 0, 0) => DecodedInstr::Ebreak,
+            (_, _, 0) => DecodedInstr::Jalr {
+                rd: 1,
+                rs1: rd,
+                offset: 0,
+            },
+            (_, _, _) => DecodedInstr::Op {
+                op: AluOp::Add,
+                rd,
+                rs1: rd,
+                rs2,
+            },
+        },
+        // C.SWSP
+        (0b10, 0b110) => DecodedInstr::Store {
+            width: Width::Word,
+            rs1: 2,
+            rs2,
+            offset: (((instr >> 9) & 0xf) << 2 | ((instr >> 7) & 0x3) << 6) as i32,
+        },
+        _ => DecodedInstr::Unknown,
+    }
+}
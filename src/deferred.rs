@@ -0,0 +1,105 @@
+//! Deferred work queue
+//!
+//! PicoRV32 masks all IRQs while one is being handled, so trap handlers should be short. With the
+//! `deferred` feature they can [`defer`](fn.defer.html) the rest of their work, which runs when
+//! the main loop calls [`process_deferred`](fn.process_deferred.html), with IRQs enabled.
+//!
+//! Work items are a function and a `u32` argument, e.g. a buffer index or an event code.
+//!
+//! ```
+//! use picorv32_rt::deferred;
+//!
+//! fn uart_rx(byte: u32) {
+//!     // parse, respond, ...
+//! }
+//!
+//! pub fn irq5(_regs: &picorv32_rt::PicoRV32StoredRegisters) {
+//!     let byte = 0x55; // read the UART
+//!     deferred::defer(uart_rx, byte);
+//! }
+//!
+//! fn main_loop() -> ! {
+//!     loop {
+//!         deferred::process_deferred();
+//!         picorv32_rt::wfi();
+//!     }
+//! }
+//! ```
+
+use crate::interrupt;
+
+/// Maximum number of queued work items
+pub const CAPACITY: usize = 32;
+
+/// A queued work item
+#[derive(Copy, Clone)]
+struct Work {
+    f: fn(u32),
+    arg: u32,
+}
+
+fn nop(_arg: u32) {}
+
+/// Ring buffer of work items, only accessed in critical sections
+struct Queue {
+    items: [Work; CAPACITY],
+    head: usize,
+    len: usize,
+    dropped: u32,
+}
+
+static mut QUEUE: Queue = Queue {
+    items: [Work { f: nop, arg: 0 }; CAPACITY],
+    head: 0,
+    len: 0,
+    dropped: 0,
+};
+
+/// Queues `f(arg)` to run in thread context, returns `false` if the queue is full
+pub fn defer(f: fn(u32), arg: u32) -> bool {
+    interrupt::free(|_| {
+        let queue = unsafe { &mut QUEUE };
+        if queue.len == CAPACITY {
+            queue.dropped = queue.dropped.wrapping_add(1);
+            return false;
+        }
+        queue.items[(queue.head + queue.len) % CAPACITY] = Work { f, arg };
+        queue.len += 1;
+        true
+    })
+}
+
+/// Runs queued work items until the queue is empty, returns how many ran
+///
+/// Items run with IRQs enabled, items deferred in the meantime run as part of the same call.
+pub fn process_deferred() -> usize {
+    let mut count = 0;
+    while let Some(work) = pop() {
+        (work.f)(work.arg);
+        count += 1;
+    }
+    count
+}
+
+/// Number of queued work items
+pub fn pending() -> usize {
+    interrupt::free(|_| unsafe { QUEUE.len })
+}
+
+/// Number of work items rejected because the queue was full, wrapping
+pub fn dropped() -> u32 {
+    interrupt::free(|_| unsafe { QUEUE.dropped })
+}
+
+fn pop() -> Option<Work> {
+    interrupt::free(|_| {
+        let queue = unsafe { &mut QUEUE };
+        if queue.len == 0 {
+            return None;
+        }
+        let work = queue.items[queue.head];
+        queue.head = (queue.head + 1) % CAPACITY;
+        queue.len -= 1;
+        Some(work)
+    })
+}
@@ -0,0 +1,105 @@
+//! Interrupt table of a device crate
+//!
+//! svd2rust generates PACs with an `Interrupt` enum implementing [`Nr`](trait.Nr.html) and a
+//! `__EXTERNAL_INTERRUPTS` table of handlers, one per interrupt number, whose entries default to
+//! `DefaultHandler` in the PAC's `device.x`. With the `device` feature the default `trap_handler`
+//! calls the entry of every pending IRQ line, indexed by its bit position, so a PAC of a PicoRV32
+//! based SoC needs no [`picorv32_interrupts!`](../macro.picorv32_interrupts.html) invocation:
+//!
+//! ``` text
+//! /* device.x */
+//! PROVIDE(UART0 = DefaultHandler);
+//! PROVIDE(GPIO = DefaultHandler);
+//! /* the table has entries for IRQ 0..=5 */
+//! _external_interrupts_len = 6;
+//! ```
+//!
+//! Lines beyond `_external_interrupts_len` and reserved entries go to `DefaultHandler`, which
+//! spins forever unless the application provides it. Handlers are declared with the names of
+//! the `Interrupt` variants:
+//!
+//! ```
+//! #[allow(non_snake_case)]
+//! #[no_mangle]
+//! pub extern "C" fn UART0() {
+//!     // read the UART
+//! }
+//! ```
+//!
+//! [`mask`](fn.mask.html) and [`unmask`](fn.unmask.html) take the variants of the `Interrupt`
+//! enum rather than bare line numbers.
+
+pub use bare_metal::Nr;
+
+use crate::interrupt;
+
+/// An entry of `__EXTERNAL_INTERRUPTS`, laid out like the one of svd2rust PACs
+///
+/// Reserved entries are `0` and go to `DefaultHandler`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union Vector {
+    /// The handler of the line
+    pub handler: unsafe extern "C" fn(),
+    /// `0` for a line without a handler
+    pub reserved: usize,
+}
+
+/// Masks the IRQ line of `interrupt`
+///
+/// Like [`unmask`](fn.unmask.html) this changes the mask a surrounding
+/// [`interrupt::free`](../interrupt/fn.free.html) restores when it ends.
+#[inline]
+pub fn mask<I: Nr>(interrupt: I) {
+    let mask = interrupt::disable();
+    unsafe { interrupt::restore(mask | 1 << interrupt.nr()) };
+}
+
+/// Unmasks the IRQ line of `interrupt`
+///
+/// # Safety
+///
+/// Unmasking an IRQ line inside a critical section breaks the guarantees of the
+/// `CriticalSection` token.
+#[inline]
+pub unsafe fn unmask<I: Nr>(interrupt: I) {
+    let mask = interrupt::disable();
+    interrupt::restore(mask & !(1 << interrupt.nr()));
+}
+
+/// Calls the handlers of the IRQs in `irqs` from `__EXTERNAL_INTERRUPTS`, the default
+/// `trap_handler`
+#[cfg(not(feature = "riscv-rt-compat"))]
+pub(crate) fn dispatch_all(irqs: u32) {
+    let mut pending = irqs;
+    while pending != 0 {
+        let irq = pending.trailing_zeros();
+        pending &= pending - 1;
+
+        crate::dispatch_irq(irq, || dispatch(irq));
+    }
+}
+
+/// Calls the handler of IRQ line `irq` from `__EXTERNAL_INTERRUPTS`, `DefaultHandler` if it has
+/// none
+pub(crate) fn dispatch(irq: u32) {
+    extern "C" {
+        fn DefaultHandler();
+
+        // Provided by the device crate, otherwise empty
+        static __EXTERNAL_INTERRUPTS: Vector;
+        // Set in device.x, its address is the number of entries
+        static _external_interrupts_len: u8;
+    }
+
+    unsafe {
+        let len = &_external_interrupts_len as *const u8 as u32;
+        let table = &__EXTERNAL_INTERRUPTS as *const Vector;
+        match irq {
+            n if n < len && (*table.add(n as usize)).reserved != 0 => {
+                ((*table.add(n as usize)).handler)()
+            }
+            _ => DefaultHandler(),
+        }
+    }
+}
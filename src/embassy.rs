@@ -0,0 +1,152 @@
+//! Embassy integration
+//!
+//! With the `embassy` feature this crate provides the `embassy-time` driver and an interrupt-mode
+//! `embassy-executor`, so embassy applications can run on PicoRV32.
+//!
+//! # Time driver
+//!
+//! Time is the [64-bit cycle counter](../monotonic/index.html), so the `tick-hz-*` feature of
+//! `embassy-time` has to match the clock of the core. Alarms use the hardware timer, which makes
+//! IRQ0 unavailable to the application, like with the `timers` feature. The two can't be enabled
+//! together.
+//!
+//! # Executor
+//!
+//! [`start`](fn.start.html) creates the executor and returns a spawner for it. Its tasks run from
+//! the trap dispatcher, after the IRQ handlers, so they preempt thread mode code but run with all
+//! IRQs blocked, like any other interrupt handler. Waking a task from thread mode pends the
+//! executor by arming the hardware timer for a single cycle.
+//!
+//! ```ignore
+//! #[embassy_executor::task]
+//! async fn blink() {
+//!     loop {
+//!         // toggle an LED
+//!         embassy_time::Timer::after_millis(500).await;
+//!     }
+//! }
+//!
+//! #[picorv32_rt::entry]
+//! fn main() -> ! {
+//!     let spawner = picorv32_rt::embassy::start();
+//!     spawner.spawn(blink()).unwrap();
+//!     loop {
+//!         picorv32_rt::wfi();
+//!     }
+//! }
+//! ```
+
+use core::ptr;
+use core::task::Waker;
+
+use embassy_executor::raw;
+use embassy_executor::SendSpawner;
+use embassy_time_driver::Driver;
+
+use crate::timer::Timer;
+use crate::{exception, interrupt, monotonic};
+
+/// Maximum number of distinct wakers waiting for an alarm
+pub const CAPACITY: usize = 16;
+
+struct Alarm {
+    at: u64,
+    waker: Waker,
+}
+
+const NO_ALARM: Option<Alarm> = None;
+
+/// Only accessed in critical sections and from the trap handler
+static mut ALARMS: [Option<Alarm>; CAPACITY] = [NO_ALARM; CAPACITY];
+static mut EXECUTOR: Option<raw::Executor> = None;
+static mut PENDED: bool = false;
+
+struct CycleDriver;
+
+impl Driver for CycleDriver {
+    fn now(&self) -> u64 {
+        monotonic::now()
+    }
+
+    fn schedule_wake(&self, at: u64, waker: &Waker) {
+        interrupt::free(|_| {
+            let alarms = unsafe { &mut ALARMS };
+            let slot = match alarms
+                .iter()
+                .position(|alarm| alarm.as_ref().map_or(false, |a| a.waker.will_wake(waker)))
+            {
+                Some(index) => index,
+                None => alarms
+                    .iter()
+                    .position(Option::is_none)
+                    .expect("too many embassy alarms"),
+            };
+            let at = match alarms[slot] {
+                Some(ref alarm) if alarm.at < at => alarm.at,
+                _ => at,
+            };
+            alarms[slot] = Some(Alarm {
+                at,
+                waker: waker.clone(),
+            });
+            rearm(monotonic::now());
+        })
+    }
+}
+
+embassy_time_driver::time_driver_impl!(static DRIVER: CycleDriver = CycleDriver);
+
+/// Creates the executor on the first call, returns a spawner for it
+pub fn start() -> SendSpawner {
+    interrupt::free(|_| unsafe {
+        if EXECUTOR.is_none() {
+            EXECUTOR = Some(raw::Executor::new(ptr::null_mut()));
+        }
+        EXECUTOR.as_ref().unwrap().spawner().make_send()
+    })
+}
+
+#[export_name = "__pender"]
+fn pender(_context: *mut ()) {
+    unsafe { ptr::write_volatile(&mut PENDED, true) };
+    if !exception::in_trap() {
+        // polled at the end of the trap
+        interrupt::free(|_| unsafe { Timer::steal() }.start_oneshot(1));
+    }
+}
+
+/// Programs the hardware timer for the nearest alarm, or stops it
+fn rearm(now: u64) {
+    let alarms = unsafe { &ALARMS };
+    let next = alarms.iter().filter_map(|a| a.as_ref()).map(|a| a.at).min();
+    let cycles = match next {
+        None => 0,
+        Some(at) if at <= now => 1,
+        Some(at) if at - now > u64::from(u32::max_value()) => u32::max_value(),
+        Some(at) => (at - now) as u32,
+    };
+    unsafe { Timer::steal() }.start_oneshot(cycles);
+}
+
+/// Wakes the expired alarms, called from the trap handler on IRQ0
+pub(crate) fn handle_timer() {
+    let now = monotonic::now();
+    for alarm in unsafe { ALARMS.iter_mut() } {
+        if alarm.as_ref().map_or(false, |a| a.at <= now) {
+            alarm.take().unwrap().waker.wake();
+        }
+    }
+    rearm(monotonic::now());
+}
+
+/// Polls the executor while it's pended, called at the end of the trap handler
+pub(crate) fn poll() {
+    unsafe {
+        while ptr::read_volatile(&PENDED) {
+            ptr::write_volatile(&mut PENDED, false);
+            if let Some(ref executor) = EXECUTOR {
+                executor.poll();
+            }
+        }
+    }
+}
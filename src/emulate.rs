@@ -0,0 +1,173 @@
+//! Software emulation of instructions the core can't execute
+//!
+//! - the M extension (`emulate-m`), for code built for `riscv32imc` running on a core configured
+//!   without `ENABLE_MUL`/`ENABLE_DIV`
+//! - misaligned loads and stores (`emulate-unaligned`), which PicoRV32 reports as bus errors
+//!
+//! The emulation itself must not contain any M instructions: PicoRV32 doesn't nest traps and
+//! halts on an IRQ1 raised inside one. All arithmetic here is spelled out as shift-and-add /
+//! shift-and-subtract loops, and the same holds for trap handlers, see the
+//! [`exception`](../exception/index.html) module.
+
+#[cfg(feature = "emulate-unaligned")]
+use core::ptr;
+
+#[cfg(feature = "emulate-m")]
+use crate::decode::MulDivOp;
+use crate::decode::{self, DecodedInstr};
+#[cfg(feature = "emulate-unaligned")]
+use crate::exception;
+use crate::PicoRV32StoredRegisters;
+
+/// Emulates a `MUL`/`MULH[[S]U]`/`DIV[U]`/`REM[U]` instruction, returns `false` for anything
+/// else or if the destination register isn't part of the trap frame
+#[cfg(feature = "emulate-m")]
+pub(crate) fn muldiv(regs: &mut PicoRV32StoredRegisters, instr: u32) -> bool {
+    let (op, rd, rs1, rs2) = match decode::decode(instr) {
+        DecodedInstr::MulDiv { op, rd, rs1, rs2 } => (op, rd, rs1, rs2),
+        _ => return false,
+    };
+    let (a, b) = match (regs.reg(rs1), regs.reg(rs2)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return false,
+    };
+
+    let result = match op {
+        MulDivOp::Mul => mul(a, b) as u32,
+        MulDivOp::Mulh => mulh(a, b, true, true),
+        MulDivOp::Mulhsu => mulh(a, b, true, false),
+        MulDivOp::Mulhu => mulh(a, b, false, false),
+        MulDivOp::Div => div(a, b).0,
+        MulDivOp::Divu => divu(a, b).0,
+        MulDivOp::Rem => div(a, b).1,
+        MulDivOp::Remu => divu(a, b).1,
+    };
+
+    regs.set_reg(rd, result)
+}
+
+/// Unsigned 32x32 -> 64 bit product
+#[cfg(feature = "emulate-m")]
+fn mul(a: u32, mut b: u32) -> u64 {
+    let mut a = u64::from(a);
+    let mut product = 0u64;
+    while b != 0 {
+        if b & 1 != 0 {
+            product = product.wrapping_add(a);
+        }
+        a <<= 1;
+        b >>= 1;
+    }
+    product
+}
+
+/// Upper half of the product, with either operand treated as signed
+#[cfg(feature = "emulate-m")]
+fn mulh(a: u32, b: u32, a_signed: bool, b_signed: bool) -> u32 {
+    let mut high = (mul(a, b) >> 32) as u32;
+    // a two's complement operand is its unsigned value minus 2^32
+    if a_signed && (a as i32) < 0 {
+        high = high.wrapping_sub(b);
+    }
+    if b_signed && (b as i32) < 0 {
+        high = high.wrapping_sub(a);
+    }
+    high
+}
+
+/// Unsigned quotient and remainder, as specified for division by zero
+#[cfg(feature = "emulate-m")]
+fn divu(n: u32, d: u32) -> (u32, u32) {
+    if d == 0 {
+        return (!0, n);
+    }
+
+    let mut q = 0u32;
+    let mut r = 0u64;
+    for i in (0..32).rev() {
+        r = (r << 1) | u64::from((n >> i) & 1);
+        if r >= u64::from(d) {
+            r -= u64::from(d);
+            q |= 1 << i;
+        }
+    }
+    (q, r as u32)
+}
+
+/// Signed quotient and remainder, as specified for division by zero and overflow
+#[cfg(feature = "emulate-m")]
+fn div(n: u32, d: u32) -> (u32, u32) {
+    if d == 0 {
+        return (!0, n);
+    }
+
+    let n_negative = (n as i32) < 0;
+    let d_negative = (d as i32) < 0;
+    let (q, r) = divu(
+        if n_negative { n.wrapping_neg() } else { n },
+        if d_negative { d.wrapping_neg() } else { d },
+    );
+    (
+        if n_negative != d_negative {
+            q.wrapping_neg()
+        } else {
+            q
+        },
+        if n_negative { r.wrapping_neg() } else { r },
+    )
+}
+
+/// Performs a misaligned load/store that caused a bus error byte by byte, returns `false` if the
+/// trap wasn't caused by one or the registers involved aren't part of the trap frame
+///
+/// An aligned access raises a bus error because nothing answers at its address, replaying it
+/// would only raise another one inside the trap and halt the core, so it's left to the handler.
+/// The return address already points past the instruction, so nothing needs to be skipped.
+#[cfg(feature = "emulate-unaligned")]
+pub(crate) fn unaligned(regs: &mut PicoRV32StoredRegisters) -> bool {
+    let instr = unsafe { exception::faulting_instruction(regs) };
+    let decoded = decode::decode(instr);
+    let (rs1, offset, width) = match decoded {
+        DecodedInstr::Load {
+            rs1, offset, width, ..
+        }
+        | DecodedInstr::Store {
+            rs1, offset, width, ..
+        } => (rs1, offset, width.bytes()),
+        _ => return false,
+    };
+    let addr = match regs.reg(rs1) {
+        Some(base) => base.wrapping_add(offset as u32),
+        None => return false,
+    };
+    if addr & (width - 1) == 0 {
+        return false;
+    }
+
+    match decoded {
+        DecodedInstr::Load { signed, rd, .. } => {
+            let mut value = 0u32;
+            for i in 0..width {
+                let byte = unsafe { ptr::read_volatile(addr.wrapping_add(i) as *const u8) };
+                value |= u32::from(byte) << (i << 3);
+            }
+            if signed {
+                let shift = 32 - (width << 3);
+                value = ((value << shift) as i32 >> shift) as u32;
+            }
+            regs.set_reg(rd, value)
+        }
+        DecodedInstr::Store { rs2, .. } => {
+            let value = match regs.reg(rs2) {
+                Some(value) => value,
+                None => return false,
+            };
+            for i in 0..width {
+                let byte = (value >> (i << 3)) as u8;
+                unsafe { ptr::write_volatile(addr.wrapping_add(i) as *mut u8, byte) };
+            }
+            true
+        }
+        _ => false,
+    }
+}
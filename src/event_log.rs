@@ -0,0 +1,320 @@
+//! Append-only event log in flash
+//!
+//! With the `event-log` feature a few flash sectors can hold a log of records that survives
+//! power loss. Sectors are used round robin: once the current one is full the oldest one is erased
+//! and reused, so all of them wear evenly.
+//!
+//! Once a log is [`install`](fn.install.html)ed, every [`crash::record`](../crash/fn.record.html)
+//! is appended to it as a [`kind::CRASH`](kind/constant.CRASH.html) record too, so `rt_assert!`
+//! and [`crash::record_panic`](../crash/fn.record_panic.html) leave durable evidence even if RAM
+//! contents are lost.
+//!
+//! ```
+//! use picorv32_rt::event_log::{self, EventLog, FlashWrite};
+//!
+//! struct SpiFlash;
+//!
+//! impl FlashWrite for SpiFlash {
+//!     fn erase_sector(&mut self, addr: u32) { /* ... */ }
+//!     fn write(&mut self, addr: u32, data: &[u8]) { /* ... */ }
+//! }
+//!
+//! static mut LOG: Option<EventLog<SpiFlash>> = None;
+//!
+//! fn init() {
+//!     unsafe {
+//!         // 4 sectors of 4K at the start of the second MiB of flash
+//!         LOG = Some(EventLog::new(SpiFlash, 0x0020_0000, 4096, 4));
+//!         event_log::install(LOG.as_mut().unwrap());
+//!     }
+//! }
+//! ```
+//!
+//! # Format
+//!
+//! All integers are little endian, all records are padded to 4 bytes.
+//!
+//! ``` text
+//! sector: magic: u32 = "PELG", sequence: u32, records...
+//! record: kind: u16, len: u16, crc32 of data: u32, data: [u8; len]
+//! ```
+//!
+//! The records of a sector end with an erased (`0xffffffff`) header. Records with a wrong CRC,
+//! e.g. from a write that was cut short by a reset, are skipped.
+
+use core::ptr;
+
+use crate::crash::{self, Crash};
+use crate::{crc, interrupt};
+
+/// `"PELG"`
+pub const MAGIC: u32 = 0x474c_4550;
+
+/// Maximum length of the data of a record
+pub const MAX_DATA_LEN: usize = 256;
+
+/// Sector header size
+const SECTOR_HEADER: u32 = 8;
+/// Record header size
+const RECORD_HEADER: u32 = 8;
+/// Erased flash word
+const ERASED: u32 = 0xffff_ffff;
+
+/// Well-known record kinds
+pub mod kind {
+    /// A [`Crash`](../../crash/enum.Crash.html), see [`crash`](../fn.crash.html)
+    pub const CRASH: u16 = 0x0001;
+    /// First kind available for applications
+    pub const USER: u16 = 0x8000;
+}
+
+/// Flash driver used by the log
+pub trait FlashWrite {
+    /// Erases the sector starting at `addr` to all ones
+    fn erase_sector(&mut self, addr: u32);
+
+    /// Programs erased flash at `addr`, both `addr` and the length of `data` are multiples of 4
+    fn write(&mut self, addr: u32, data: &[u8]);
+
+    /// Reads flash at `addr`, memory mapped by default
+    fn read(&self, addr: u32, buf: &mut [u8]) {
+        for (i, byte) in buf.iter_mut().enumerate() {
+            *byte = unsafe { ptr::read_volatile((addr as usize + i) as *const u8) };
+        }
+    }
+}
+
+/// A log in `sectors` consecutive flash sectors of `sector_size` bytes starting at `start`
+pub struct EventLog<F: FlashWrite> {
+    flash: F,
+    start: u32,
+    sector_size: u32,
+    sectors: u32,
+    /// Sector currently appended to
+    sector: u32,
+    /// Its sequence number
+    sequence: u32,
+    /// Offset of the next record in it
+    offset: u32,
+}
+
+impl<F: FlashWrite> EventLog<F> {
+    /// Opens the log, scanning flash for the sector to append to
+    ///
+    /// # Panics
+    ///
+    /// If `sector_size` is not a multiple of 4 or too small to hold a maximum size record, or if
+    /// `sectors` is less than 2
+    pub fn new(flash: F, start: u32, sector_size: u32, sectors: u32) -> Self {
+        assert!(sector_size % 4 == 0);
+        assert!(sector_size >= SECTOR_HEADER + RECORD_HEADER + MAX_DATA_LEN as u32);
+        assert!(sectors >= 2);
+
+        let mut log = EventLog {
+            flash,
+            start,
+            sector_size,
+            sectors,
+            sector: 0,
+            sequence: 0,
+            offset: SECTOR_HEADER,
+        };
+
+        let mut newest = None;
+        for sector in 0..sectors {
+            if let Some(sequence) = log.sequence(sector) {
+                match newest {
+                    Some((_, newest_sequence)) if newest_sequence >= sequence => {}
+                    _ => newest = Some((sector, sequence)),
+                }
+            }
+        }
+
+        match newest {
+            Some((sector, sequence)) => {
+                log.sector = sector;
+                log.sequence = sequence;
+                log.offset = SECTOR_HEADER;
+                while let Some(len) = log.record_len(sector, log.offset) {
+                    log.offset += len;
+                }
+                // a torn header, don't program over it
+                if log.offset + RECORD_HEADER <= sector_size
+                    && log.read_u32(log.sector_addr(sector) + log.offset) != ERASED
+                {
+                    log.offset = sector_size;
+                }
+            }
+            None => log.start_sector(0, 0),
+        }
+        log
+    }
+
+    /// Appends a record, returns `false` if `data` is longer than
+    /// [`MAX_DATA_LEN`](constant.MAX_DATA_LEN.html)
+    pub fn append(&mut self, kind: u16, data: &[u8]) -> bool {
+        if data.len() > MAX_DATA_LEN {
+            return false;
+        }
+
+        let len = RECORD_HEADER + ((data.len() as u32 + 3) & !3);
+        if self.offset + len > self.sector_size {
+            let next = (self.sector + 1) % self.sectors;
+            let sequence = self.sequence.wrapping_add(1);
+            self.start_sector(next, sequence);
+        }
+
+        let mut record = [0xffu8; RECORD_HEADER as usize + MAX_DATA_LEN];
+        write_u32(&mut record, u32::from(kind) | (data.len() as u32) << 16);
+        write_u32(&mut record[4..], crc::crc32(data));
+        record[RECORD_HEADER as usize..][..data.len()].copy_from_slice(data);
+
+        let addr = self.sector_addr(self.sector) + self.offset;
+        self.flash.write(addr, &record[..len as usize]);
+        self.offset += len;
+        true
+    }
+
+    /// Calls `f` with the kind and data of every intact record, oldest first
+    pub fn for_each<G: FnMut(u16, &[u8])>(&self, mut f: G) {
+        let mut buf = [0u8; MAX_DATA_LEN];
+        for i in 1..=self.sectors {
+            let sector = (self.sector + i) % self.sectors;
+            if self.sequence(sector).is_none() {
+                continue;
+            }
+
+            let mut offset = SECTOR_HEADER;
+            while let Some(len) = self.record_len(sector, offset) {
+                let addr = self.sector_addr(sector) + offset;
+                let header = self.read_u32(addr);
+                let data = &mut buf[..(header >> 16) as usize];
+                self.flash.read(addr + RECORD_HEADER, data);
+                if crc::crc32(data) == self.read_u32(addr + 4) {
+                    f(header as u16, data);
+                }
+                offset += len;
+            }
+        }
+    }
+
+    /// Erases all sectors
+    pub fn clear(&mut self) {
+        for sector in 1..self.sectors {
+            self.flash.erase_sector(self.sector_addr(sector));
+        }
+        self.start_sector(0, 0);
+    }
+
+    fn sector_addr(&self, sector: u32) -> u32 {
+        self.start + sector * self.sector_size
+    }
+
+    fn read_u32(&self, addr: u32) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.flash.read(addr, &mut bytes);
+        read_u32(&bytes)
+    }
+
+    /// Sequence number of `sector`, `None` if it isn't in use
+    fn sequence(&self, sector: u32) -> Option<u32> {
+        let addr = self.sector_addr(sector);
+        if self.read_u32(addr) == MAGIC {
+            Some(self.read_u32(addr + 4))
+        } else {
+            None
+        }
+    }
+
+    /// Padded length of the record at `offset` in `sector`, `None` at the end of the records
+    fn record_len(&self, sector: u32, offset: u32) -> Option<u32> {
+        if offset + RECORD_HEADER > self.sector_size {
+            return None;
+        }
+        let header = self.read_u32(self.sector_addr(sector) + offset);
+        let len = RECORD_HEADER + (((header >> 16) + 3) & !3);
+        if header == ERASED || header >> 16 > MAX_DATA_LEN as u32 || offset + len > self.sector_size
+        {
+            None
+        } else {
+            Some(len)
+        }
+    }
+
+    fn start_sector(&mut self, sector: u32, sequence: u32) {
+        let addr = self.sector_addr(sector);
+        let mut header = [0u8; SECTOR_HEADER as usize];
+        write_u32(&mut header, MAGIC);
+        write_u32(&mut header[4..], sequence);
+
+        self.flash.erase_sector(addr);
+        self.flash.write(addr, &header);
+        self.sector = sector;
+        self.sequence = sequence;
+        self.offset = SECTOR_HEADER;
+    }
+}
+
+/// Type-erased installed log
+trait Sink {
+    fn append(&mut self, kind: u16, data: &[u8]) -> bool;
+}
+
+impl<F: FlashWrite> Sink for EventLog<F> {
+    fn append(&mut self, kind: u16, data: &[u8]) -> bool {
+        EventLog::append(self, kind, data)
+    }
+}
+
+static mut INSTALLED: Option<&'static mut dyn Sink> = None;
+
+/// Makes `log` the one crashes are recorded in
+pub fn install<F: FlashWrite>(log: &'static mut EventLog<F>) {
+    interrupt::free(move |_| unsafe { INSTALLED = Some(log) });
+}
+
+/// Appends a record to the installed log, returns `false` if there's none or the data is too
+/// long
+pub fn append(kind: u16, data: &[u8]) -> bool {
+    interrupt::free(|_| unsafe {
+        match INSTALLED {
+            Some(ref mut log) => log.append(kind, data),
+            None => false,
+        }
+    })
+}
+
+/// Decodes the data of a [`kind::CRASH`](kind/constant.CRASH.html) record
+pub fn crash(data: &[u8]) -> Option<Crash> {
+    if data.len() != 16 {
+        return None;
+    }
+    let mut words = [0u32; 4];
+    for (i, word) in words.iter_mut().enumerate() {
+        *word = read_u32(&data[i * 4..]);
+    }
+    crash::decode(words)
+}
+
+/// Appends `crash` to the installed log, called by `crash::record`
+pub(crate) fn record_crash(crash: Crash) {
+    let mut data = [0u8; 16];
+    for (i, word) in crash::encode(crash).iter().enumerate() {
+        write_u32(&mut data[i * 4..], *word);
+    }
+    append(kind::CRASH, &data);
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from(bytes[0])
+        | u32::from(bytes[1]) << 8
+        | u32::from(bytes[2]) << 16
+        | u32::from(bytes[3]) << 24
+}
+
+fn write_u32(bytes: &mut [u8], value: u32) {
+    bytes[0] = value as u8;
+    bytes[1] = (value >> 8) as u8;
+    bytes[2] = (value >> 16) as u8;
+    bytes[3] = (value >> 24) as u8;
+}
@@ -0,0 +1,117 @@
+//! Time-stamped event queue
+//!
+//! With the `event-queue` feature IRQ handlers can [`push`](fn.push.html) events, e.g. encoder
+//! edges or ADC samples, together with the cycle they were observed at. The main loop
+//! [`pop`](fn.pop.html)s them in the same order. Stamps come from the cycle counter, so the core
+//! has to be built with `ENABLE_COUNTERS`.
+//!
+//! When the queue is full new events are dropped and counted, see
+//! [`overflows`](fn.overflows.html).
+//!
+//! ```
+//! use picorv32_rt::event_queue;
+//!
+//! pub fn irq5(_regs: &picorv32_rt::PicoRV32StoredRegisters) {
+//!     let sample = 0x123; // read the ADC
+//!     event_queue::push(sample);
+//! }
+//!
+//! fn main_loop() -> ! {
+//!     loop {
+//!         while let Some(event) = event_queue::pop() {
+//!             // filter `event.event`, using `event.cycle` for the time base
+//!         }
+//!         picorv32_rt::wfi();
+//!     }
+//! }
+//! ```
+
+use crate::{interrupt, runtime};
+
+/// Maximum number of queued events
+pub const CAPACITY: usize = 64;
+
+/// An event and the cycle it was observed at
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stamped {
+    /// Lower 32 bits of the cycle counter
+    pub cycle: u32,
+    /// The event, meaning is up to the application
+    pub event: u32,
+}
+
+/// Ring buffer of events, only accessed in critical sections
+struct Queue {
+    items: [Stamped; CAPACITY],
+    head: usize,
+    len: usize,
+    overflows: u32,
+}
+
+static mut QUEUE: Queue = Queue {
+    items: [Stamped { cycle: 0, event: 0 }; CAPACITY],
+    head: 0,
+    len: 0,
+    overflows: 0,
+};
+
+/// Queues `event`, stamped with the current cycle, returns `false` if the queue is full
+pub fn push(event: u32) -> bool {
+    push_stamped(Stamped {
+        cycle: runtime::cycles(),
+        event,
+    })
+}
+
+/// Queues an event stamped by the caller, e.g. with a capture register of a peripheral, returns
+/// `false` if the queue is full
+pub fn push_stamped(stamped: Stamped) -> bool {
+    interrupt::free(|_| {
+        let queue = unsafe { &mut QUEUE };
+        if queue.len == CAPACITY {
+            queue.overflows = queue.overflows.wrapping_add(1);
+            return false;
+        }
+        queue.items[(queue.head + queue.len) % CAPACITY] = stamped;
+        queue.len += 1;
+        true
+    })
+}
+
+/// Takes the oldest event
+pub fn pop() -> Option<Stamped> {
+    interrupt::free(|_| {
+        let queue = unsafe { &mut QUEUE };
+        if queue.len == 0 {
+            return None;
+        }
+        let stamped = queue.items[queue.head];
+        queue.head = (queue.head + 1) % CAPACITY;
+        queue.len -= 1;
+        Some(stamped)
+    })
+}
+
+/// Number of queued events
+pub fn len() -> usize {
+    interrupt::free(|_| unsafe { QUEUE.len })
+}
+
+/// Whether no events are queued
+pub fn is_empty() -> bool {
+    len() == 0
+}
+
+/// Number of events dropped because the queue was full, wrapping
+pub fn overflows() -> u32 {
+    interrupt::free(|_| unsafe { QUEUE.overflows })
+}
+
+/// Returns the number of dropped events and resets it
+pub fn take_overflows() -> u32 {
+    interrupt::free(|_| unsafe {
+        let overflows = QUEUE.overflows;
+        QUEUE.overflows = 0;
+        overflows
+    })
+}
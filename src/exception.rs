@@ -0,0 +1,219 @@
+//! Synchronous exceptions
+//!
+//! PicoRV32 signals `ebreak`, `ecall` and illegal instructions all via IRQ1. Before the user
+//! `trap_handler` is called the runtime decodes the offending instruction and hands the trap to
+//! a more specific handler:
+//!
+//! - `DebugTrapHandler` for `ebreak`/`c.ebreak` (e.g. from [`breakpoint!`](../macro.breakpoint.html)),
+//!   unless the [GDB stub](../gdbstub/index.html) of the `gdbstub` feature handles it
+//! - `syscall_handler` for `ecall` with the `syscalls` feature, see the
+//!   [`syscall`](../syscall/index.html) module
+//! - `CustomInstructionHandler` for instructions in the custom-0/custom-1 opcode space, e.g. those
+//!   of a PCPI coprocessor the core was configured without
+//! - `IllegalInstructionHandler` for everything else, including custom instructions
+//!   `CustomInstructionHandler` didn't handle
+//!
+//! Misaligned loads and stores are reported as bus errors via IRQ2. With the `emulate-unaligned`
+//! feature they're performed byte by byte instead, before `trap_handler` gets to see them. Bus
+//! errors of aligned accesses still reach it. Like `emulate-m` this needs the `full-trap-frame`
+//! feature, which is enabled automatically.
+//!
+//! With the `emulate-m` feature `MUL`/`DIV`/`REM` instructions are computed in software before
+//! they reach `IllegalInstructionHandler`, so code built for `riscv32imc` runs on cores without the
+//! hardware multiplier/divider. This needs the `full-trap-frame` feature (enabled automatically) to
+//! be able to write any destination register.
+//!
+//! Emulation only works where IRQ1 and IRQ2 can be taken, i.e. outside of traps. Trap handlers,
+//! and everything they call, must be free of M instructions, and of misaligned accesses with
+//! `emulate-unaligned`: the core halts on them, see below. That includes the trap path of the
+//! runtime itself, so with `emulate-m` the firmware should be built for a target without the M
+//! extension, `riscv32ic` or `riscv32i`, linking code built for `riscv32imc` that only runs in
+//! thread mode. [`interrupt::disable`](../interrupt/fn.disable.html) keeps IRQ1 and IRQ2
+//! unmasked for these features, so critical sections are fine.
+//!
+//! These handlers return `true` if they've dealt with the trap, in which case the IRQ1 bit is
+//! cleared before `trap_handler` is invoked and execution resumes right after the offending
+//! instruction. The default handlers return `false`, leaving IRQ1 to `trap_handler`.
+//!
+//! ```
+//! use picorv32_rt::PicoRV32StoredRegisters;
+//!
+//! #[allow(non_snake_case)]
+//! #[no_mangle]
+//! pub fn DebugTrapHandler(_regs: &mut PicoRV32StoredRegisters) -> bool {
+//!     // inspect registers, then carry on
+//!     true
+//! }
+//! ```
+//!
+//! PicoRV32 doesn't nest traps. An IRQ1 or IRQ2 raised while a trap is being handled, e.g. an
+//! illegal instruction or a misaligned access inside a handler, can't be taken: the core stops
+//! executing and raises its `trap` output instead. No software runs after that, so it has to be
+//! detected from outside, by wiring `trap` to the reset controller or by a watchdog that stops
+//! being fed. Such a reset keeps RAM, so the next boot sees
+//! [`BootCause::Reset`](../runtime/enum.BootCause.html).
+//!
+//! `CustomInstructionHandler` receives the [decoded](../decode/index.html) instruction, so firmware
+//! can emulate a missing accelerator and stay binary compatible across core configurations.
+//! Writing arbitrary destination registers needs the `full-trap-frame` feature.
+//!
+//! ```
+//! use picorv32_rt::decode::{DecodedInstr, OPCODE_CUSTOM1};
+//! use picorv32_rt::PicoRV32StoredRegisters;
+//!
+//! #[allow(non_snake_case)]
+//! #[no_mangle]
+//! pub fn CustomInstructionHandler(regs: &mut PicoRV32StoredRegisters, instr: DecodedInstr) -> bool {
+//!     match instr {
+//!         // a population count instruction
+//!         DecodedInstr::Custom { opcode: OPCODE_CUSTOM1, funct3: 0, funct7: 0, rd, rs1, .. } => {
+//!             match regs.reg(rs1) {
+//!                 Some(value) => regs.set_reg(rd, value.count_ones()),
+//!                 None => false,
+//!             }
+//!         }
+//!         _ => false,
+//!     }
+//! }
+//! ```
+
+use core::ptr;
+
+use crate::decode::{self, DecodedInstr};
+use crate::PicoRV32StoredRegisters;
+
+/// IRQ line used for `ebreak`, `ecall` and illegal instructions
+pub const IRQ: u32 = 1;
+
+/// IRQ line used for bus errors, i.e. misaligned memory accesses
+pub const BUS_ERROR_IRQ: u32 = 2;
+
+/// `ebreak`
+const EBREAK: u32 = 0x0010_0073;
+/// `c.ebreak`
+const C_EBREAK: u32 = 0x9002;
+/// `ecall`
+const ECALL: u32 = 0x0000_0073;
+
+/// Set while a trap is being handled
+#[cfg(any(feature = "embassy", feature = "priority"))]
+static mut IN_TRAP: bool = false;
+
+/// Kind of a synchronous exception signalled via IRQ1
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Exception {
+    /// `ebreak` or `c.ebreak`
+    Breakpoint,
+    /// `ecall`
+    EnvironmentCall,
+    /// Any instruction the core doesn't implement
+    IllegalInstruction,
+}
+
+impl Exception {
+    /// Classifies the instruction that caused an IRQ1
+    pub fn from_instruction(instr: u32) -> Exception {
+        match instr {
+            EBREAK | C_EBREAK => Exception::Breakpoint,
+            ECALL => Exception::EnvironmentCall,
+            _ => Exception::IllegalInstruction,
+        }
+    }
+}
+
+/// Address of the instruction that caused the trap
+///
+/// Only meaningful for IRQ1 and IRQ2, for which the return address in `q0` points right past
+/// the offending instruction.
+pub fn instruction_address(regs: &PicoRV32StoredRegisters) -> u32 {
+    let q0 = regs.q0();
+    if q0 & 1 == 1 {
+        q0 - 3
+    } else {
+        q0 - 4
+    }
+}
+
+/// Fetches the instruction that caused the trap
+///
+/// Compressed instructions are returned in the lower 16 bits.
+///
+/// # Safety
+///
+/// Only meaningful for IRQ1 and IRQ2, otherwise the computed address may be garbage.
+pub unsafe fn faulting_instruction(regs: &PicoRV32StoredRegisters) -> u32 {
+    let pc = instruction_address(regs);
+    let mut instr = ptr::read_volatile(pc as *const u16) as u32;
+    if instr & 3 == 3 {
+        instr |= (ptr::read_volatile((pc + 2) as *const u16) as u32) << 16;
+    }
+    instr
+}
+
+/// Routes an IRQ1 to a specific handler, returns `true` if it has been handled
+pub(crate) fn handle(regs: &mut PicoRV32StoredRegisters) -> bool {
+    extern "Rust" {
+        // These symbols can be provided by the user, otherwise they default to a no-op
+        fn DebugTrapHandler(regs: &mut PicoRV32StoredRegisters) -> bool;
+        fn CustomInstructionHandler(
+            regs: &mut PicoRV32StoredRegisters,
+            instr: DecodedInstr,
+        ) -> bool;
+        fn IllegalInstructionHandler(regs: &mut PicoRV32StoredRegisters, instr: u32) -> bool;
+    }
+
+    unsafe {
+        let instr = faulting_instruction(regs);
+        match Exception::from_instruction(instr) {
+            Exception::Breakpoint => {
+                #[cfg(feature = "gdbstub")]
+                {
+                    if crate::gdbstub::handle(regs) {
+                        return true;
+                    }
+                }
+
+                DebugTrapHandler(regs)
+            }
+            #[cfg(feature = "syscalls")]
+            Exception::EnvironmentCall => crate::syscall::handle(regs),
+            #[cfg(not(feature = "syscalls"))]
+            Exception::EnvironmentCall => IllegalInstructionHandler(regs, instr),
+            Exception::IllegalInstruction => {
+                #[cfg(feature = "emulate-m")]
+                {
+                    if crate::emulate::muldiv(regs, instr) {
+                        return true;
+                    }
+                }
+
+                let decoded = decode::decode(instr);
+                if let DecodedInstr::Custom { .. } = decoded {
+                    if CustomInstructionHandler(regs, decoded) {
+                        return true;
+                    }
+                }
+
+                IllegalInstructionHandler(regs, instr)
+            }
+        }
+    }
+}
+
+/// Marks a trap as being handled
+#[cfg(any(feature = "embassy", feature = "priority"))]
+pub(crate) fn enter() {
+    unsafe { ptr::write_volatile(ptr::addr_of_mut!(IN_TRAP), true) };
+}
+
+/// Marks the end of trap handling
+#[cfg(any(feature = "embassy", feature = "priority"))]
+pub(crate) fn exit() {
+    unsafe { ptr::write_volatile(ptr::addr_of_mut!(IN_TRAP), false) };
+}
+
+/// Whether a trap is being handled
+#[cfg(any(feature = "embassy", feature = "priority"))]
+pub(crate) fn in_trap() -> bool {
+    unsafe { ptr::read_volatile(ptr::addr_of!(IN_TRAP)) }
+}
@@ -0,0 +1,177 @@
+//! Minimal async executor
+//!
+//! With the `executor` feature up to 32 futures can be run by [`run`](fn.run.html), without an
+//! allocator. Tasks are polled when their waker is woken, and the CPU sleeps in `waitirq` while
+//! none is ready.
+//!
+//! Drivers wait for their IRQ line with [`wait_irq`](fn.wait_irq.html). The line is unmasked
+//! while a waker is registered for it. When it fires, the trap dispatcher wakes the waker and
+//! masks the line again instead of calling `trap_handler`, so the driver services the peripheral
+//! from the task, in thread context.
+//!
+//! The feature needs Rust 1.50 or newer.
+//!
+//! ```
+//! use core::future::Future;
+//! use core::pin::Pin;
+//! use picorv32_rt::executor;
+//!
+//! async fn uart_echo() {
+//!     loop {
+//!         executor::wait_irq(5).await;
+//!         // read the UART, write it back
+//!     }
+//! }
+//!
+//! async fn blink() {
+//!     loop {
+//!         executor::wait_irq(6).await;
+//!         // toggle an LED
+//!     }
+//! }
+//!
+//! fn main_loop() -> ! {
+//!     let mut uart = uart_echo();
+//!     let mut led = blink();
+//!     // the futures stay where they are until `run` returns, which it never does
+//!     let mut tasks: [Pin<&mut dyn Future<Output = ()>>; 2] =
+//!         unsafe { [Pin::new_unchecked(&mut uart), Pin::new_unchecked(&mut led)] };
+//!     executor::run(&mut tasks)
+//! }
+//! ```
+
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+use picorv32::asm;
+
+use crate::interrupt;
+
+/// Maximum number of tasks
+pub const CAPACITY: usize = 32;
+
+const NO_WAKER: Option<Waker> = None;
+
+/// Only accessed in critical sections and from the trap handler
+static mut READY: u32 = 0;
+static mut FIRED: u32 = 0;
+static mut WAKERS: [Option<Waker>; 32] = [NO_WAKER; 32];
+
+/// Runs `tasks` until all of them have completed, then sleeps forever
+///
+/// # Panics
+///
+/// If there are more than [`CAPACITY`](constant.CAPACITY.html) tasks
+pub fn run(tasks: &mut [Pin<&mut dyn Future<Output = ()>>]) -> ! {
+    assert!(tasks.len() <= CAPACITY);
+
+    let all = if tasks.len() == CAPACITY {
+        !0
+    } else {
+        (1 << tasks.len()) - 1
+    };
+    let mut pending = all;
+    interrupt::free(|_| unsafe { READY = all });
+
+    loop {
+        let mask = interrupt::disable();
+        let ready = unsafe { READY } & pending;
+        if ready == 0 {
+            // IRQs are masked, so one that arrives in between still ends the wait
+            unsafe { asm::waitirq() };
+        }
+        unsafe {
+            READY &= !ready;
+            interrupt::restore(mask);
+        }
+
+        for (index, task) in tasks.iter_mut().enumerate() {
+            if ready & (1 << index) == 0 {
+                continue;
+            }
+            let waker = unsafe { Waker::from_raw(task_waker(index)) };
+            let mut cx = Context::from_waker(&waker);
+            if let Poll::Ready(()) = task.as_mut().poll(&mut cx) {
+                pending &= !(1 << index);
+            }
+        }
+    }
+}
+
+/// Waits for IRQ line `irq` to fire
+///
+/// # Panics
+///
+/// If `irq` is not in the range `0..32`
+pub fn wait_irq(irq: u32) -> WaitIrq {
+    assert!(irq < 32);
+    WaitIrq { irq }
+}
+
+/// Future returned by [`wait_irq`](fn.wait_irq.html)
+pub struct WaitIrq {
+    irq: u32,
+}
+
+impl Future for WaitIrq {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let bit = 1 << self.irq;
+        let mask = interrupt::disable();
+        unsafe {
+            if FIRED & bit != 0 {
+                FIRED &= !bit;
+                interrupt::restore(mask);
+                Poll::Ready(())
+            } else {
+                WAKERS[self.irq as usize] = Some(cx.waker().clone());
+                interrupt::restore(mask & !bit);
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Wakes the wakers registered for `irqs`, called from the trap handler only
+///
+/// Returns the IRQs without a waker, which still have to be dispatched.
+pub(crate) fn wake(irqs: u32) -> u32 {
+    let mut woken = 0;
+    for irq in 0..32 {
+        if irqs & (1 << irq) == 0 {
+            continue;
+        }
+        if let Some(waker) = unsafe { WAKERS[irq].take() } {
+            woken |= 1 << irq;
+            waker.wake();
+        }
+    }
+
+    if woken != 0 {
+        unsafe {
+            FIRED |= woken;
+            let mask = interrupt::disable();
+            interrupt::restore(mask | woken);
+        }
+    }
+    irqs & !woken
+}
+
+static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake_task, wake_task, drop);
+
+/// Waker of the task with the given index, which is its data pointer
+fn task_waker(index: usize) -> RawWaker {
+    RawWaker::new(index as *const (), &VTABLE)
+}
+
+unsafe fn clone(data: *const ()) -> RawWaker {
+    task_waker(data as usize)
+}
+
+unsafe fn wake_task(data: *const ()) {
+    interrupt::free(|_| READY |= 1 << data as usize);
+}
+
+unsafe fn drop(_data: *const ()) {}
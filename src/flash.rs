@@ -0,0 +1,45 @@
+//! Flash layout
+//!
+//! The linker script rounds the firmware image and the
+//! [configuration block](../flash_config/index.html) out to whole sectors of
+//! `_flash_sector_size` bytes, so updating one of them never erases the other. These are the
+//! ranges an in-application programmer has to erase.
+//!
+//! ```
+//! use picorv32_rt::flash;
+//!
+//! fn erase_image(erase_sector: fn(usize)) {
+//!     let sectors = flash::image_sectors();
+//!     let mut addr = sectors.start;
+//!     while addr < sectors.end {
+//!         erase_sector(addr);
+//!         addr += flash::sector_size();
+//!     }
+//! }
+//! ```
+
+use core::ops::Range;
+
+extern "C" {
+    static _flash_sector_size: u8;
+    static _simage: u8;
+    static _eimage: u8;
+    static _sconfig_sectors: u8;
+    static _econfig_sectors: u8;
+}
+
+/// Erase granularity of the flash in bytes
+pub fn sector_size() -> usize {
+    unsafe { &_flash_sector_size as *const u8 as usize }
+}
+
+/// Addresses of the sectors holding the firmware image, `.text`, `.rodata` and the initial
+/// values of `.data`
+pub fn image_sectors() -> Range<usize> {
+    unsafe { &_simage as *const u8 as usize..&_eimage as *const u8 as usize }
+}
+
+/// Addresses of the sectors holding the configuration block, empty if there is none
+pub fn config_sectors() -> Range<usize> {
+    unsafe { &_sconfig_sectors as *const u8 as usize..&_econfig_sectors as *const u8 as usize }
+}
@@ -0,0 +1,182 @@
+//! Board configuration block in flash
+//!
+//! With the `flash-config` feature the linker script reserves `_config_size` bytes of FLASH for a
+//! configuration block that isn't part of the compiled image, so board-specific calibration can
+//! be provisioned separately from the firmware. By default the block sits at the very end of
+//! FLASH, `memory.x` can move it by defining `_sconfig`:
+//!
+//! ``` text
+//! _config_size = 4K;
+//! /* optional, defaults to ORIGIN(FLASH) + LENGTH(FLASH) - _config_size */
+//! _sconfig = ORIGIN(FLASH) + 0x3ff000;
+//! ```
+//!
+//! # Format
+//!
+//! All integers are little endian.
+//!
+//! ``` text
+//! magic: u32 = "PRCF"
+//! entries: [{ tag: u16, len: u16, value: [u8; len], padding to 4 bytes }]
+//! ```
+//!
+//! The list ends with tag `0xffff` (erased flash), tag `0x0000` or at the end of the block.
+//! Tags below `0x8000` are reserved for this crate, see [`tag`](tag/index.html).
+//!
+//! ```
+//! use picorv32_rt::flash_config::{tag, Config};
+//!
+//! fn uart_baud() -> u32 {
+//!     Config::get()
+//!         .and_then(|config| config.u32(tag::UART_BAUD))
+//!         .unwrap_or(115_200)
+//! }
+//! ```
+
+use core::slice;
+use core::str;
+
+/// `"PRCF"`
+pub const MAGIC: u32 = 0x4643_5250;
+
+/// Well-known tags
+pub mod tag {
+    /// End of the entry list
+    pub const END: u16 = 0x0000;
+    /// Core clock frequency in Hz, `u32`
+    pub const CLOCK_HZ: u16 = 0x0001;
+    /// Console UART baud rate, `u32`
+    pub const UART_BAUD: u16 = 0x0002;
+    /// Board-specific feature flags, `u32`
+    pub const FEATURE_FLAGS: u16 = 0x0003;
+    /// Board name, UTF-8 string
+    pub const BOARD_NAME: u16 = 0x0004;
+    /// First tag available for applications
+    pub const USER: u16 = 0x8000;
+    /// Erased flash, end of the entry list
+    pub const ERASED: u16 = 0xffff;
+}
+
+/// A parsed configuration block
+#[derive(Copy, Clone, Debug)]
+pub struct Config {
+    entries: &'static [u8],
+}
+
+impl Config {
+    /// The configuration block reserved by the linker script, `None` if it's empty or not
+    /// provisioned
+    pub fn get() -> Option<Config> {
+        extern "C" {
+            static _sconfig: u8;
+            static _config_size: u8;
+        }
+
+        unsafe {
+            let start = &_sconfig as *const u8;
+            let size = &_config_size as *const u8 as usize;
+            Config::from_bytes(slice::from_raw_parts(start, size))
+        }
+    }
+
+    /// Parses a configuration block from `data`, `None` if the magic number doesn't match
+    pub fn from_bytes(data: &'static [u8]) -> Option<Config> {
+        if data.len() < 4 || read_u32(data) != MAGIC {
+            return None;
+        }
+        Some(Config {
+            entries: &data[4..],
+        })
+    }
+
+    /// All entries as `(tag, value)` pairs
+    pub fn entries(&self) -> Entries {
+        Entries { rest: self.entries }
+    }
+
+    /// Raw value of the first entry with the given tag
+    pub fn raw(&self, tag: u16) -> Option<&'static [u8]> {
+        self.entries()
+            .find(|&(t, _)| t == tag)
+            .map(|(_, value)| value)
+    }
+
+    /// Value of the given tag as `u8`
+    pub fn u8(&self, tag: u16) -> Option<u8> {
+        match self.raw(tag) {
+            Some(value) if value.len() == 1 => Some(value[0]),
+            _ => None,
+        }
+    }
+
+    /// Value of the given tag as `u16`
+    pub fn u16(&self, tag: u16) -> Option<u16> {
+        match self.raw(tag) {
+            Some(value) if value.len() == 2 => Some(read_u16(value)),
+            _ => None,
+        }
+    }
+
+    /// Value of the given tag as `u32`
+    pub fn u32(&self, tag: u16) -> Option<u32> {
+        match self.raw(tag) {
+            Some(value) if value.len() == 4 => Some(read_u32(value)),
+            _ => None,
+        }
+    }
+
+    /// Value of the given tag as `bool`, any non-zero byte is `true`
+    pub fn bool(&self, tag: u16) -> Option<bool> {
+        self.u8(tag).map(|value| value != 0)
+    }
+
+    /// Value of the given tag as a UTF-8 string
+    pub fn str(&self, tag: u16) -> Option<&'static str> {
+        self.raw(tag).and_then(|value| str::from_utf8(value).ok())
+    }
+}
+
+/// Iterator over the entries of a [`Config`](struct.Config.html)
+#[derive(Clone, Debug)]
+pub struct Entries {
+    rest: &'static [u8],
+}
+
+impl Iterator for Entries {
+    type Item = (u16, &'static [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        next_entry(&mut self.rest)
+    }
+}
+
+/// Takes the first entry off `rest`, shared with the host tools
+pub(crate) fn next_entry<'a>(rest: &mut &'a [u8]) -> Option<(u16, &'a [u8])> {
+    if rest.len() < 4 {
+        return None;
+    }
+
+    let tag = read_u16(rest);
+    let len = read_u16(&rest[2..]) as usize;
+    if tag == tag::END || tag == tag::ERASED || rest.len() < 4 + len {
+        *rest = &[];
+        return None;
+    }
+
+    let value = &rest[4..4 + len];
+    let next = (4 + len + 3) & !3;
+    *rest = if next < rest.len() {
+        &rest[next..]
+    } else {
+        &[]
+    };
+    Some((tag, value))
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from(bytes[0]) | u16::from(bytes[1]) << 8
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from(read_u16(bytes)) | u32::from(read_u16(&bytes[2..])) << 16
+}
@@ -0,0 +1,593 @@
+//! GDB remote serial protocol stub
+//!
+//! PicoRV32 has no debug module, so with the `gdbstub` feature the firmware debugs itself: every
+//! `ebreak` stops in the IRQ1 handler, which then talks to GDB over a byte
+//! [`Transport`](trait.Transport.html) (a UART, a mailbox register of the testbench, ...) until
+//! it's told to go on. Once a transport is [`install`](fn.install.html)ed, a
+//! [`breakpoint!`](../macro.breakpoint.html) hands control to GDB:
+//!
+//! ```
+//! use picorv32_rt::gdbstub::{self, Transport};
+//!
+//! struct Uart;
+//!
+//! impl Transport for Uart {
+//!     fn read(&mut self) -> u8 {
+//!         // poll the UART until a byte arrives
+//!         0
+//!     }
+//!
+//!     fn write(&mut self, _byte: u8) {
+//!         // wait for the UART and send the byte
+//!     }
+//! }
+//!
+//! static mut UART: Uart = Uart;
+//!
+//! fn init() {
+//!     gdbstub::install(unsafe { &mut UART });
+//!     // wait for GDB
+//!     picorv32_rt::breakpoint!();
+//! }
+//! ```
+//!
+//! ``` text
+//! $ riscv32-unknown-elf-gdb target/riscv32imc-unknown-none-elf/debug/app
+//! (gdb) target remote /dev/ttyUSB1
+//! ```
+//!
+//! The stub supports reading and writing registers and memory, software breakpoints and single
+//! stepping. Breakpoints replace the instruction with an `ebreak` while the firmware runs, so they
+//! only work in RAM, e.g. with the `copy-to-ram` or `ram-only` features. Single steps are
+//! emulated with a temporary breakpoint on the next instruction, found by
+//! [decoding](../decode/index.html) the current one.
+//!
+//! The stub runs in the trap handler with all IRQs masked, so the transport has to poll, and
+//! neither the stub nor code running in trap handlers can be debugged. `gp` (without
+//! `interrupts-qregs`) and `tp` aren't part of the trap frame, they read as unavailable and writes
+//! to them are ignored. An `ebreak` without a transport installed goes to `DebugTrapHandler` as
+//! usual.
+
+use core::fmt::{self, Write};
+use core::ptr;
+
+use crate::decode::{self, BranchOp, DecodedInstr};
+use crate::{exception, interrupt, runtime, PicoRV32StoredRegisters};
+
+/// Maximum number of breakpoints set by GDB
+pub const BREAKPOINTS: usize = 8;
+
+/// Maximum length of a packet, advertised to GDB
+const PACKET_LEN: usize = 512;
+
+/// `ebreak`
+const EBREAK: u32 = 0x0010_0073;
+/// `c.ebreak`
+const C_EBREAK: u32 = 0x9002;
+
+/// Byte transport between the firmware and GDB
+///
+/// Called with IRQs masked, so it has to poll.
+pub trait Transport {
+    /// Blocks until a byte is received
+    fn read(&mut self) -> u8;
+
+    /// Sends a byte
+    fn write(&mut self, byte: u8);
+}
+
+/// A software breakpoint, `saved` is the instruction it replaced while armed
+#[derive(Copy, Clone)]
+struct Breakpoint {
+    addr: u32,
+    len: u32,
+    saved: u32,
+}
+
+/// What the firmware was resumed for
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Resume {
+    /// Stopped, GDB hasn't resumed it since it connected
+    Stopped,
+    /// Continuing
+    Continue,
+    /// Stepping over a breakpoint at the resume address, then continuing
+    StepOver,
+    /// Single-stepping
+    Step,
+}
+
+static mut INSTALLED: Option<&'static mut dyn Transport> = None;
+
+// Only accessed from the IRQ1 handler
+static mut BREAKPOINT_TABLE: [Option<Breakpoint>; BREAKPOINTS] = [None; BREAKPOINTS];
+static mut ARMED: bool = false;
+static mut STEP: Option<Breakpoint> = None;
+static mut RESUME: Resume = Resume::Stopped;
+static mut PACKET: [u8; PACKET_LEN] = [0; PACKET_LEN];
+
+/// Makes `transport` the connection to GDB
+pub fn install(transport: &'static mut dyn Transport) {
+    interrupt::free(move |_| unsafe { INSTALLED = Some(transport) });
+}
+
+/// Handles an `ebreak`, returns `false` if no transport is installed
+pub(crate) fn handle(regs: &mut PicoRV32StoredRegisters) -> bool {
+    let transport = match unsafe { INSTALLED.as_mut() } {
+        Some(transport) => &mut **transport,
+        None => return false,
+    };
+
+    let addr = exception::instruction_address(regs);
+    let step = unsafe { STEP.take() };
+    disarm(step);
+
+    // a breakpoint of ours re-executes the original instruction, one compiled into the firmware
+    // is stepped over
+    let hit_step = step.map(|bp| bp.addr == addr).unwrap_or(false);
+    let pc = if hit_step || breakpoint_at(addr) {
+        addr
+    } else {
+        regs.q0() & !1
+    };
+
+    let resume = unsafe { RESUME };
+    if hit_step && resume == Resume::StepOver {
+        // stepped over the breakpoint GDB continued from, carry on
+        unsafe {
+            RESUME = Resume::Continue;
+            regs.set_q0(pc);
+        }
+        arm(None);
+        return true;
+    }
+    if resume != Resume::Stopped {
+        respond(transport, |w| w.write_str("S05"));
+    }
+
+    let mut stub = Stub {
+        transport,
+        regs,
+        pc,
+    };
+    let resume = stub.serve();
+    let pc = stub.pc;
+    unsafe {
+        RESUME = resume;
+        stub.regs.set_q0(pc);
+    }
+
+    match resume {
+        Resume::Stopped | Resume::Continue => arm(None),
+        // the breakpoints are armed once the instruction at `pc` has been stepped over
+        Resume::StepOver => unsafe { STEP = Some(arm_step(breakpoint(next_pc(stub.regs, pc)))) },
+        Resume::Step => arm(Some(breakpoint(next_pc(stub.regs, pc)))),
+    }
+    true
+}
+
+struct Stub<'a> {
+    transport: &'a mut dyn Transport,
+    regs: &'a mut PicoRV32StoredRegisters,
+    pc: u32,
+}
+
+impl<'a> Stub<'a> {
+    /// Executes packets until GDB resumes the firmware
+    fn serve(&mut self) -> Resume {
+        loop {
+            let len = receive(self.transport);
+            let packet = unsafe { &PACKET[..len] };
+            let (command, args) = match packet.split_first() {
+                Some((&command, args)) => (command, args),
+                None => (0, packet),
+            };
+
+            match command {
+                b'?' => respond(self.transport, |w| w.write_str("S05")),
+                b'g' => {
+                    let regs = &*self.regs;
+                    let pc = self.pc;
+                    respond(self.transport, |w| {
+                        for n in 0..33 {
+                            write_reg(w, read_reg(regs, pc, n))?;
+                        }
+                        Ok(())
+                    })
+                }
+                b'G' => {
+                    for n in 0..33 {
+                        let value = args.get(n * 8..n * 8 + 8).and_then(parse_hex_le);
+                        if let Some(value) = value {
+                            self.write_reg(n as u32, value);
+                        }
+                    }
+                    respond(self.transport, |w| w.write_str("OK"))
+                }
+                b'p' => {
+                    let regs = &*self.regs;
+                    let pc = self.pc;
+                    match parse_hex(args) {
+                        Some(n) if n <= 32 => {
+                            respond(self.transport, |w| write_reg(w, read_reg(regs, pc, n)))
+                        }
+                        _ => respond(self.transport, |w| w.write_str("E01")),
+                    }
+                }
+                b'P' => match split(args, b'=') {
+                    Some((n, value)) => match (parse_hex(n), parse_hex_le(value)) {
+                        (Some(n), Some(value)) if n <= 32 => {
+                            self.write_reg(n, value);
+                            respond(self.transport, |w| w.write_str("OK"))
+                        }
+                        _ => respond(self.transport, |w| w.write_str("E01")),
+                    },
+                    None => respond(self.transport, |w| w.write_str("E01")),
+                },
+                b'm' => match parse_range(args) {
+                    Some((addr, len)) if len as usize <= (PACKET_LEN - 4) / 2 => {
+                        respond(self.transport, |w| {
+                            for i in 0..len {
+                                let addr = addr.wrapping_add(i);
+                                let byte = unsafe { ptr::read_volatile(addr as *const u8) };
+                                write!(w, "{:02x}", byte)?;
+                            }
+                            Ok(())
+                        })
+                    }
+                    _ => respond(self.transport, |w| w.write_str("E01")),
+                },
+                b'M' => match split(args, b':') {
+                    Some((range, data)) => match parse_range(range) {
+                        Some((addr, len)) if (len as usize).checked_mul(2) == Some(data.len()) => {
+                            for (i, digits) in data.chunks(2).enumerate() {
+                                let byte = parse_hex(digits).unwrap_or(0) as u8;
+                                let addr = addr.wrapping_add(i as u32);
+                                unsafe { ptr::write_volatile(addr as *mut u8, byte) };
+                            }
+                            respond(self.transport, |w| w.write_str("OK"))
+                        }
+                        _ => respond(self.transport, |w| w.write_str("E01")),
+                    },
+                    None => respond(self.transport, |w| w.write_str("E01")),
+                },
+                b'c' => {
+                    if let Some(addr) = parse_hex(args) {
+                        self.pc = addr;
+                    }
+                    return if breakpoint_at(self.pc) {
+                        Resume::StepOver
+                    } else {
+                        Resume::Continue
+                    };
+                }
+                b's' => {
+                    if let Some(addr) = parse_hex(args) {
+                        self.pc = addr;
+                    }
+                    return Resume::Step;
+                }
+                b'Z' | b'z' => {
+                    let ok = match parse_breakpoint(args) {
+                        Some((addr, len)) if command == b'Z' => insert_breakpoint(addr, len),
+                        Some((addr, _)) => remove_breakpoint(addr),
+                        None => false,
+                    };
+                    if ok {
+                        respond(self.transport, |w| w.write_str("OK"))
+                    } else {
+                        // GDB falls back to other breakpoint kinds or gives up
+                        respond(self.transport, |_| Ok(()))
+                    }
+                }
+                b'D' => {
+                    respond(self.transport, |w| w.write_str("OK"));
+                    unsafe { BREAKPOINT_TABLE = [None; BREAKPOINTS] };
+                    return Resume::Stopped;
+                }
+                b'k' => runtime::restart(),
+                b'H' => respond(self.transport, |w| w.write_str("OK")),
+                b'q' if args.starts_with(b"Supported") => {
+                    respond(self.transport, |w| write!(w, "PacketSize={:x}", PACKET_LEN))
+                }
+                _ => respond(self.transport, |_| Ok(())),
+            }
+        }
+    }
+
+    /// Sets register `n` in GDB's numbering, `x0`..`x31` and the PC
+    fn write_reg(&mut self, n: u32, value: u32) {
+        if n == 32 {
+            self.pc = value;
+        } else {
+            self.regs.set_reg(n, value);
+        }
+    }
+}
+
+/// Register `n` in GDB's numbering, `None` if it isn't part of the trap frame
+fn read_reg(regs: &PicoRV32StoredRegisters, pc: u32, n: u32) -> Option<u32> {
+    if n == 32 {
+        Some(pc)
+    } else {
+        regs.reg(n)
+    }
+}
+
+/// Writes a register in target byte order, or as unavailable
+fn write_reg<W: Write>(w: &mut W, value: Option<u32>) -> fmt::Result {
+    match value {
+        Some(value) => write!(w, "{:08x}", value.swap_bytes()),
+        None => w.write_str("xxxxxxxx"),
+    }
+}
+
+/// Writes the body of a packet and computes its checksum
+struct PacketWriter<'a> {
+    transport: &'a mut dyn Transport,
+    checksum: u8,
+}
+
+impl<'a> Write for PacketWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            self.transport.write(byte);
+            self.checksum = self.checksum.wrapping_add(byte);
+        }
+        Ok(())
+    }
+}
+
+/// Sends a packet with the body `f` writes, again until GDB acknowledges it
+fn respond<F>(transport: &mut dyn Transport, f: F)
+where
+    F: Fn(&mut PacketWriter) -> fmt::Result,
+{
+    loop {
+        transport.write(b'$');
+        let mut w = PacketWriter {
+            transport: &mut *transport,
+            checksum: 0,
+        };
+        let _ = f(&mut w);
+        let checksum = w.checksum;
+        let _ = write!(Raw(&mut *transport), "#{:02x}", checksum);
+
+        if transport.read() != b'-' {
+            return;
+        }
+    }
+}
+
+/// Writes to the transport as is, for the packet trailer
+struct Raw<'a>(&'a mut dyn Transport);
+
+impl<'a> Write for Raw<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            self.0.write(byte);
+        }
+        Ok(())
+    }
+}
+
+/// Receives a packet into `PACKET`, acknowledging it, returns the length of its body
+fn receive(transport: &mut dyn Transport) -> usize {
+    loop {
+        // acks, interrupt requests and noise before the start of the packet
+        while transport.read() != b'$' {}
+
+        let mut len = 0;
+        let mut checksum = 0u8;
+        let mut overflow = false;
+        loop {
+            let byte = transport.read();
+            if byte == b'#' {
+                break;
+            }
+            checksum = checksum.wrapping_add(byte);
+            if len < PACKET_LEN {
+                unsafe { PACKET[len] = byte };
+                len += 1;
+            } else {
+                overflow = true;
+            }
+        }
+        let expected = [transport.read(), transport.read()];
+
+        if !overflow && parse_hex(&expected) == Some(u32::from(checksum)) {
+            transport.write(b'+');
+            return len;
+        }
+        transport.write(b'-');
+    }
+}
+
+/// Address of the instruction executed after the one at `pc`
+fn next_pc(regs: &PicoRV32StoredRegisters, pc: u32) -> u32 {
+    let instr = read_instruction(pc);
+    let next = pc.wrapping_add(decode::length(instr));
+
+    match decode::decode(instr) {
+        DecodedInstr::Jal { offset, .. } => pc.wrapping_add(offset as u32),
+        DecodedInstr::Jalr { rs1, offset, .. } => match regs.reg(rs1) {
+            Some(base) => base.wrapping_add(offset as u32) & !1,
+            None => next,
+        },
+        DecodedInstr::Branch {
+            op,
+            rs1,
+            rs2,
+            offset,
+        } => {
+            let a = regs.reg(rs1).unwrap_or(0);
+            let b = regs.reg(rs2).unwrap_or(0);
+            let taken = match op {
+                BranchOp::Eq => a == b,
+                BranchOp::Ne => a != b,
+                BranchOp::Lt => (a as i32) < (b as i32),
+                BranchOp::Ge => (a as i32) >= (b as i32),
+                BranchOp::Ltu => a < b,
+                BranchOp::Geu => a >= b,
+            };
+            if taken {
+                pc.wrapping_add(offset as u32)
+            } else {
+                next
+            }
+        }
+        _ => next,
+    }
+}
+
+/// Reads the instruction at `addr`, compressed ones in the lower 16 bits
+fn read_instruction(addr: u32) -> u32 {
+    unsafe {
+        let mut instr = u32::from(ptr::read_volatile(addr as *const u16));
+        if decode::length(instr) == 4 {
+            instr |= u32::from(ptr::read_volatile((addr + 2) as *const u16)) << 16;
+        }
+        instr
+    }
+}
+
+/// A breakpoint at `addr` as long as the instruction there
+fn breakpoint(addr: u32) -> Breakpoint {
+    Breakpoint {
+        addr,
+        len: decode::length(read_instruction(addr)),
+        saved: 0,
+    }
+}
+
+fn breakpoint_at(addr: u32) -> bool {
+    unsafe { BREAKPOINT_TABLE.iter() }.any(|bp| bp.map(|bp| bp.addr == addr).unwrap_or(false))
+}
+
+/// Adds a breakpoint of `len` bytes at `addr`, returns `false` if the table is full
+fn insert_breakpoint(addr: u32, len: u32) -> bool {
+    if breakpoint_at(addr) {
+        return true;
+    }
+    match unsafe { BREAKPOINT_TABLE.iter_mut() }.find(|bp| bp.is_none()) {
+        Some(slot) => {
+            *slot = Some(Breakpoint {
+                addr,
+                len,
+                saved: 0,
+            });
+            true
+        }
+        None => false,
+    }
+}
+
+fn remove_breakpoint(addr: u32) -> bool {
+    for bp in unsafe { BREAKPOINT_TABLE.iter_mut() } {
+        if bp.map(|bp| bp.addr == addr).unwrap_or(false) {
+            *bp = None;
+        }
+    }
+    true
+}
+
+/// Places the breakpoints in memory, followed by `step`
+fn arm(step: Option<Breakpoint>) {
+    for bp in unsafe { BREAKPOINT_TABLE.iter_mut() } {
+        if let Some(bp) = bp {
+            arm_one(bp);
+        }
+    }
+    unsafe { ARMED = true };
+    if let Some(step) = step {
+        unsafe { STEP = Some(arm_step(step)) };
+    }
+}
+
+fn arm_step(mut step: Breakpoint) -> Breakpoint {
+    arm_one(&mut step);
+    step
+}
+
+/// Restores the instructions under `step` and the breakpoints, in the opposite order of `arm`
+fn disarm(step: Option<Breakpoint>) {
+    if let Some(step) = step {
+        disarm_one(&step);
+    }
+    if !unsafe { ptr::replace(&mut ARMED, false) } {
+        return;
+    }
+    for bp in unsafe { BREAKPOINT_TABLE.iter().rev() } {
+        if let Some(bp) = bp {
+            disarm_one(bp);
+        }
+    }
+}
+
+fn arm_one(bp: &mut Breakpoint) {
+    unsafe {
+        let addr = bp.addr as *mut u16;
+        bp.saved = u32::from(ptr::read_volatile(addr));
+        if bp.len == 4 {
+            bp.saved |= u32::from(ptr::read_volatile(addr.add(1))) << 16;
+            ptr::write_volatile(addr, EBREAK as u16);
+            ptr::write_volatile(addr.add(1), (EBREAK >> 16) as u16);
+        } else {
+            ptr::write_volatile(addr, C_EBREAK as u16);
+        }
+    }
+}
+
+fn disarm_one(bp: &Breakpoint) {
+    unsafe {
+        let addr = bp.addr as *mut u16;
+        ptr::write_volatile(addr, bp.saved as u16);
+        if bp.len == 4 {
+            ptr::write_volatile(addr.add(1), (bp.saved >> 16) as u16);
+        }
+    }
+}
+
+/// Splits `bytes` at the first `separator`
+fn split(bytes: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let i = bytes.iter().position(|&b| b == separator)?;
+    Some((&bytes[..i], &bytes[i + 1..]))
+}
+
+/// Parses `addr,len`
+fn parse_range(args: &[u8]) -> Option<(u32, u32)> {
+    let (addr, len) = split(args, b',')?;
+    Some((parse_hex(addr)?, parse_hex(len)?))
+}
+
+/// Parses `0,addr,kind` of a software breakpoint, `kind` being its length
+fn parse_breakpoint(args: &[u8]) -> Option<(u32, u32)> {
+    let (kind, rest) = split(args, b',')?;
+    if kind != b"0" {
+        return None;
+    }
+    match parse_range(rest)? {
+        (addr, len) if len == 2 || len == 4 => Some((addr, len)),
+        _ => None,
+    }
+}
+
+/// Parses a big-endian hex number
+fn parse_hex(digits: &[u8]) -> Option<u32> {
+    if digits.is_empty() || digits.len() > 8 {
+        return None;
+    }
+    let mut value = 0;
+    for &digit in digits {
+        value = value << 4 | (digit as char).to_digit(16)?;
+    }
+    Some(value)
+}
+
+/// Parses a register value, 8 hex digits in target byte order
+fn parse_hex_le(digits: &[u8]) -> Option<u32> {
+    if digits.len() != 8 {
+        return None;
+    }
+    parse_hex(digits).map(u32::swap_bytes)
+}
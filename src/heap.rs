@@ -0,0 +1,349 @@
+//! Heap region reserved by the linker script
+//!
+//! `link.x` reserves `_heap_size` bytes of RAM right after the statics, 0 by default, see the
+//! crate docs. These functions return where it is, so an allocator can be set up without
+//! declaring the linker symbols:
+//!
+//! ``` ignore
+//! use picorv32_rt::heap;
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: some_allocator::Heap = some_allocator::Heap::empty();
+//!
+//! fn init_heap() {
+//!     unsafe { ALLOCATOR.init(heap::heap_start() as usize, heap::heap_size()) }
+//! }
+//! ```
+//!
+//! # Allocator
+//!
+//! [`Heap`](struct.Heap.html) is a small first-fit allocator that keeps the free blocks in a list
+//! sorted by address and merges neighbours when a block is freed. With the `alloc` feature it's
+//! registered as the `#[global_allocator]` and set up over the heap region by the startup code,
+//! before `main`, so the `alloc` crate works with only a `_heap_size` line in memory.x:
+//!
+//! ``` text
+//! _heap_size = 16K;
+//! ```
+//!
+//! Allocations are done in a critical section, they're fine from interrupt handlers too.
+//!
+//! # Out of memory
+//!
+//! When the `#[global_allocator]` can't fit a request it calls the `__alloc_error` hook with its
+//! layout, and tries once more if the hook returns. The default records a
+//! [`Crash::OutOfMemory`](../crash/enum.Crash.html) and stops the firmware with
+//! [`runtime::fatal`](../runtime/fn.fatal.html). An application that can do without the memory
+//! provides its own hook, which e.g. drops caches to make room, or just returns to let the
+//! allocation fail, so the fallible APIs like `Vec::try_reserve` see the error. It must not
+//! allocate itself. Other allocators can call the same hook through
+//! [`alloc_error`](fn.alloc_error.html).
+//!
+//! ``` ignore
+//! use core::alloc::Layout;
+//!
+//! #[no_mangle]
+//! pub fn __alloc_error(_layout: Layout) {
+//!     unsafe { CACHE.clear() }
+//! }
+//! ```
+//!
+//! # Second heap
+//!
+//! `_heap2_size` bytes are reserved after the statics of the second RAM region, `RAM2` with the
+//! `ram2` feature, e.g. a large external SRAM next to a small internal one. 0 by default.
+//! [`heap2_range`](fn.heap2_range.html) and friends return where it is.
+//! [`DualHeap`](struct.DualHeap.html) allocates from a fast heap and falls back to a slow one
+//! once it's full. With the `alloc` feature the `#[global_allocator]` is one, over the first heap
+//! and the second:
+//!
+//! ``` text
+//! _heap_size = 4K;
+//! _heap2_size = 256K;
+//! ```
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::ops::Range;
+use core::{mem, ptr};
+
+use crate::interrupt;
+
+extern "C" {
+    static mut _sheap: u8;
+    static _eheap: u8;
+    static mut _sheap2: u8;
+    static _eheap2: u8;
+}
+
+/// Start of the heap, `_sheap`, a multiple of 4
+pub fn heap_start() -> *mut u8 {
+    ptr::addr_of_mut!(_sheap)
+}
+
+/// Size of the heap in bytes, `_heap_size` rounded up to a multiple of 4
+pub fn heap_size() -> usize {
+    heap_range().len()
+}
+
+/// Addresses of the heap, from `_sheap` up to `_eheap`
+pub fn heap_range() -> Range<usize> {
+    heap_start() as usize..ptr::addr_of!(_eheap) as usize
+}
+
+/// Start of the second heap, `_sheap2`, a multiple of 4
+pub fn heap2_start() -> *mut u8 {
+    ptr::addr_of_mut!(_sheap2)
+}
+
+/// Size of the second heap in bytes, `_heap2_size` rounded up to a multiple of 4
+pub fn heap2_size() -> usize {
+    heap2_range().len()
+}
+
+/// Addresses of the second heap, from `_sheap2` up to `_eheap2`
+pub fn heap2_range() -> Range<usize> {
+    heap2_start() as usize..ptr::addr_of!(_eheap2) as usize
+}
+
+/// Calls the `__alloc_error` hook, meant for allocators that failed to fit `layout`
+///
+/// The default hook records a [`Crash::OutOfMemory`](../crash/enum.Crash.html) and doesn't
+/// return. One provided by the application returns once the allocation may be retried, or should
+/// fail.
+pub fn alloc_error(layout: Layout) {
+    extern "Rust" {
+        // This symbol can be provided by the user, otherwise it defaults to recording the layout
+        // and `runtime::fatal`
+        fn __alloc_error(layout: Layout);
+    }
+
+    unsafe { __alloc_error(layout) }
+}
+
+/// The `#[global_allocator]` with the `alloc` feature
+#[cfg(feature = "alloc")]
+#[global_allocator]
+static HEAP: Global = Global(DualHeap::empty());
+
+/// [`DualHeap`](struct.DualHeap.html) calling the `__alloc_error` hook when it's full
+#[cfg(feature = "alloc")]
+struct Global(DualHeap);
+
+#[cfg(feature = "alloc")]
+unsafe impl GlobalAlloc for Global {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let block = self.0.alloc(layout);
+        if block.is_null() {
+            alloc_error(layout);
+            self.0.alloc(layout)
+        } else {
+            block
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.dealloc(ptr, layout)
+    }
+}
+
+/// Hands the heap regions to the `#[global_allocator]`, called by the startup code
+#[cfg(feature = "alloc")]
+pub(crate) unsafe fn init() {
+    HEAP.0.fast().init(heap_start() as usize, heap_size());
+    HEAP.0.slow().init(heap2_start() as usize, heap2_size());
+}
+
+/// Free block, at its own start
+struct Hole {
+    size: usize,
+    next: *mut Hole,
+}
+
+/// Granularity of the blocks, they have to be able to hold a `Hole` once freed
+const UNIT: usize = mem::size_of::<Hole>();
+
+fn round_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+/// First-fit allocator over a region of memory, see the [module docs](index.html)
+pub struct Heap {
+    holes: UnsafeCell<*mut Hole>,
+    bounds: UnsafeCell<(usize, usize)>,
+}
+
+unsafe impl Sync for Heap {}
+
+impl Heap {
+    /// A heap without any memory, every allocation fails until [`init`](#method.init)
+    pub const fn empty() -> Heap {
+        Heap {
+            holes: UnsafeCell::new(ptr::null_mut()),
+            bounds: UnsafeCell::new((0, 0)),
+        }
+    }
+
+    /// Gives the heap `size` bytes of memory starting at `start`
+    ///
+    /// # Safety
+    ///
+    /// The memory must be unused, and stay so for as long as the heap is. Must be called once,
+    /// before the first allocation.
+    pub unsafe fn init(&self, start: usize, size: usize) {
+        let end = (start + size) & !(UNIT - 1);
+        let start = round_up(start, UNIT);
+        if end > start {
+            let hole = start as *mut Hole;
+            ptr::write(
+                hole,
+                Hole {
+                    size: end - start,
+                    next: ptr::null_mut(),
+                },
+            );
+            *self.holes.get() = hole;
+            *self.bounds.get() = (start, end);
+        }
+    }
+
+    /// Whether `ptr` points into the memory given to the heap
+    pub fn contains(&self, ptr: *const u8) -> bool {
+        let (start, end) = unsafe { *self.bounds.get() };
+        start <= ptr as usize && (ptr as usize) < end
+    }
+
+    /// Bytes in the free blocks, not all of which may fit a single allocation
+    pub fn available(&self) -> usize {
+        interrupt::free(|_| unsafe {
+            let mut total = 0;
+            let mut hole = *self.holes.get();
+            while !hole.is_null() {
+                total += (*hole).size;
+                hole = (*hole).next;
+            }
+            total
+        })
+    }
+
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8 {
+        // every block and every address is a multiple of `UNIT`, so are the bits split off
+        let size = round_up(layout.size().max(1), UNIT);
+        let align = layout.align().max(UNIT);
+
+        let mut link = self.holes.get();
+        while !(*link).is_null() {
+            let hole = *link;
+            let start = hole as usize;
+            let end = start + (*hole).size;
+            let addr = round_up(start, align);
+            if addr + size <= end {
+                let mut rest = (*hole).next;
+                if addr + size < end {
+                    let tail = (addr + size) as *mut Hole;
+                    ptr::write(
+                        tail,
+                        Hole {
+                            size: end - (addr + size),
+                            next: rest,
+                        },
+                    );
+                    rest = tail;
+                }
+                if addr == start {
+                    *link = rest;
+                } else {
+                    (*hole).size = addr - start;
+                    (*hole).next = rest;
+                }
+                return addr as *mut u8;
+            }
+            link = &mut (*hole).next;
+        }
+        ptr::null_mut()
+    }
+
+    unsafe fn release(&self, block: *mut u8, layout: Layout) {
+        let addr = block as usize;
+        let size = round_up(layout.size().max(1), UNIT);
+
+        // the holes around the block
+        let mut prev: *mut Hole = ptr::null_mut();
+        let mut next = *self.holes.get();
+        while !next.is_null() && (next as usize) < addr {
+            prev = next;
+            next = (*next).next;
+        }
+
+        let hole = block as *mut Hole;
+        ptr::write(hole, Hole { size, next });
+        if !next.is_null() && addr + size == next as usize {
+            (*hole).size += (*next).size;
+            (*hole).next = (*next).next;
+        }
+        if prev.is_null() {
+            *self.holes.get() = hole;
+        } else if prev as usize + (*prev).size == addr {
+            (*prev).size += (*hole).size;
+            (*prev).next = (*hole).next;
+        } else {
+            (*prev).next = hole;
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for Heap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        interrupt::free(|_| self.allocate(layout))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        interrupt::free(|_| self.release(ptr, layout))
+    }
+}
+
+/// Two heaps, allocating from the fast one first, see the [module docs](index.html)
+pub struct DualHeap {
+    fast: Heap,
+    slow: Heap,
+}
+
+impl DualHeap {
+    /// Two heaps without any memory, each is given some with
+    /// [`Heap::init`](struct.Heap.html#method.init)
+    pub const fn empty() -> DualHeap {
+        DualHeap {
+            fast: Heap::empty(),
+            slow: Heap::empty(),
+        }
+    }
+
+    /// The heap tried first
+    pub fn fast(&self) -> &Heap {
+        &self.fast
+    }
+
+    /// The heap used once the fast one can't fit an allocation
+    pub fn slow(&self) -> &Heap {
+        &self.slow
+    }
+}
+
+unsafe impl GlobalAlloc for DualHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let block = self.fast.alloc(layout);
+        if block.is_null() {
+            self.slow.alloc(layout)
+        } else {
+            block
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if self.fast.contains(ptr) {
+            self.fast.dealloc(ptr, layout)
+        } else {
+            self.slow.dealloc(ptr, layout)
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Hardware-in-the-loop test hooks
+//!
+//! With the `hil` feature the firmware can hand a byte transport (UART, mailbox, ...) to
+//! [`serve`](fn.serve.html), which then executes line based commands from a test host:
+//!
+//! ``` text
+//! peek <addr>          -> ok <value>       read a word
+//! poke <addr> <value>  -> ok               write a word
+//! call <addr>          -> ok <a0>          call `extern "C" fn() -> u32` at <addr>
+//! crc <addr> <len>     -> ok <crc32>       CRC-32 of a memory range
+//! reset                                    restart the firmware from `_start`
+//! ```
+//!
+//! All numbers are hexadecimal, with or without a `0x` prefix. Failures are answered with
+//! `err <reason>`. Symbol names are resolved to addresses on the host, from the ELF file.
+//!
+//! The host gets to read, write and run whatever it likes, so the transport has to be one only
+//! the test setup can reach, and the firmware must not be in the middle of something these
+//! accesses could break.
+
+use core::fmt::{self, Write};
+use core::{mem, ptr, slice, str};
+
+use crate::crc;
+
+/// Maximum length of a command line
+const LINE_LEN: usize = 64;
+
+/// Byte transport between the firmware and the test host
+pub trait Transport {
+    /// Blocks until a byte is received
+    fn read(&mut self) -> u8;
+
+    /// Sends a byte
+    fn write(&mut self, byte: u8);
+}
+
+struct Writer<'a, T: Transport>(&'a mut T);
+
+impl<'a, T: Transport> Write for Writer<'a, T> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            self.0.write(byte);
+        }
+        Ok(())
+    }
+}
+
+/// Executes commands received over `transport`, forever
+///
+/// # Safety
+///
+/// See [`execute`](fn.execute.html), for every command the host sends.
+pub unsafe fn serve<T: Transport>(transport: &mut T) -> ! {
+    let mut line = [0u8; LINE_LEN];
+    let mut len = 0;
+
+    loop {
+        match transport.read() {
+            b'\r' | b'\n' => {
+                if len != 0 {
+                    let _ = execute(&line[..len], &mut Writer(transport));
+                    len = 0;
+                }
+            }
+            // swallow the rest of an overlong line, it gets rejected as a whole
+            _ if len == LINE_LEN => {}
+            byte => {
+                line[len] = byte;
+                len += 1;
+            }
+        }
+    }
+}
+
+/// Executes a single command line, writing the response to `out`
+///
+/// # Safety
+///
+/// `peek`, `poke` and `crc` access memory at any address given, and `call` runs any code at
+/// one. The caller must make sure the addresses are valid for that, `call` is only rejected for
+/// address 0.
+pub unsafe fn execute<W: Write>(line: &[u8], out: &mut W) -> fmt::Result {
+    let line = match str::from_utf8(line) {
+        Ok(line) if line.len() < LINE_LEN => line,
+        _ => return writeln!(out, "err line"),
+    };
+
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or("");
+    let mut args = [0u32; 2];
+    let mut nargs = 0;
+    for word in words {
+        if nargs == args.len() {
+            return writeln!(out, "err args");
+        }
+        args[nargs] = match parse_hex(word) {
+            Some(arg) => arg,
+            None => return writeln!(out, "err number"),
+        };
+        nargs += 1;
+    }
+
+    match (command, nargs) {
+        ("peek", 1) => {
+            let value = ptr::read_volatile(args[0] as *const u32);
+            writeln!(out, "ok {:08x}", value)
+        }
+        ("poke", 2) => {
+            ptr::write_volatile(args[0] as *mut u32, args[1]);
+            writeln!(out, "ok")
+        }
+        ("call", 1) if args[0] == 0 => writeln!(out, "err address"),
+        ("call", 1) => {
+            let f: extern "C" fn() -> u32 = mem::transmute(args[0] as usize);
+            writeln!(out, "ok {:08x}", f())
+        }
+        ("crc", 2) => {
+            let data = slice::from_raw_parts(args[0] as *const u8, args[1] as usize);
+            writeln!(out, "ok {:08x}", crc::crc32(data))
+        }
+        ("reset", 0) => {
+            writeln!(out, "ok")?;
+            crate::runtime::soft_reset()
+        }
+        ("peek", _) | ("poke", _) | ("call", _) | ("crc", _) | ("reset", _) => {
+            writeln!(out, "err args")
+        }
+        _ => writeln!(out, "err command"),
+    }
+}
+
+fn parse_hex(word: &str) -> Option<u32> {
+    let digits = if word.starts_with("0x") {
+        &word[2..]
+    } else {
+        word
+    };
+    u32::from_str_radix(digits, 16).ok()
+}
@@ -0,0 +1,405 @@
+//! Host-side parsing of the formats defined by this crate
+//!
+//! With the `host-tools` feature this crate can be used from `std` programs running on the
+//! development machine, e.g. flashing or fleet tools, to read what the firmware produces without
+//! re-implementing the binary layouts:
+//!
+//! - the generated [`picorv32_rt.h`](../index.html#picorv32_rth), [`Header`](struct.Header.html),
+//!   including the layout of trap frames dumped from the target
+//! - [crash records](../crash/index.html), [`crash`](fn.crash.html)
+//! - [configuration blocks](../flash_config/index.html), [`config`](fn.config.html)
+//! - [event logs](../event_log/index.html), [`event_log`](fn.event_log.html)
+//! - binary images with a [compressed `.data`](../rle/index.html),
+//!   [`compress_data`](fn.compress_data.html)
+//! - memory images for FPGA flows, a word per line for `$readmemh` or Intel HEX, from the built
+//!   ELF, [`readmemh`](fn.readmemh.html) and [`intel_hex`](fn.intel_hex.html)
+//!
+//! The parsers share their code with the firmware side, so both always agree on the formats. The
+//! feature enables `event-log` and `flash-config`, and is meant for host builds only.
+//!
+//! ```ignore
+//! use picorv32_rt::host::{self, Header};
+//!
+//! let header = Header::parse(&std::fs::read_to_string("picorv32_rt.h")?).unwrap();
+//! let frame = std::fs::read("frame.bin")?;
+//! for (reg, value) in header.trap_frame(&frame).unwrap() {
+//!     println!("x{:<2} = {:#010x}", reg, value);
+//! }
+//!
+//! let flash = std::fs::read("flash.bin")?;
+//! for record in host::event_log(&flash[0x20_0000..0x20_4000], 4096) {
+//!     if let Some(crash) = record.crash() {
+//!         println!("{:?}", crash);
+//!     }
+//! }
+//! ```
+
+use std::fmt::Write;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+use crate::crash::{self, Crash};
+use crate::event_log::{self, EventLog, FlashWrite};
+use crate::flash_config;
+use crate::image::ImageHeader;
+use crate::rle;
+
+/// `"DATA"`, the first word of the trailer `compressed-data.x` puts after the `.data` load image
+const DATA_TRAILER_MAGIC: u32 = 0x4154_4144;
+
+/// Definitions of a `picorv32_rt.h` generated by the build script
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    defines: Vec<(String, String)>,
+}
+
+impl Header {
+    /// Parses the `#define`s of `text`, `None` if it isn't a `picorv32_rt.h`
+    pub fn parse(text: &str) -> Option<Header> {
+        let defines: Vec<(String, String)> = text
+            .lines()
+            .filter_map(|line| {
+                let mut words = line.split_whitespace();
+                if words.next() != Some("#define") {
+                    return None;
+                }
+                let name = words.next()?;
+                let value = words.next().unwrap_or("");
+                Some((name.to_string(), value.to_string()))
+            })
+            .collect();
+
+        if defines
+            .iter()
+            .any(|(name, _)| name == "PICORV32_RT_LINK_X_VERSION")
+        {
+            Some(Header { defines })
+        } else {
+            None
+        }
+    }
+
+    /// Value of the `#define` called `name`
+    pub fn define(&self, name: &str) -> Option<&str> {
+        self.defines
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Version of the linker script the runtime was built for
+    pub fn link_x_version(&self) -> Option<u32> {
+        self.number("PICORV32_RT_LINK_X_VERSION")
+    }
+
+    /// Whether the runtime was built with a feature, e.g. `"INTERRUPTS_QREGS"` for
+    /// `interrupts-qregs`
+    ///
+    /// Only the features that change the interface to C code are listed in the header.
+    pub fn feature(&self, name: &str) -> bool {
+        self.number(&["PICORV32_RT_", name].concat()) == Some(1)
+    }
+
+    /// Size of a trap frame in bytes
+    pub fn frame_size(&self) -> Option<usize> {
+        self.number("PICORV32_RT_FRAME_SIZE")
+            .map(|size| size as usize)
+    }
+
+    /// Byte offset of register `x<reg>` in a trap frame, `None` if it isn't saved there
+    pub fn frame_offset(&self, reg: u32) -> Option<usize> {
+        self.number(&["PICORV32_RT_FRAME_X", &reg.to_string()].concat())
+            .map(|offset| offset as usize)
+    }
+
+    /// Registers saved in a trap frame dumped from the target, as `(register, value)` pairs
+    /// ordered by register number, `None` if `frame` is too short
+    pub fn trap_frame(&self, frame: &[u8]) -> Option<Vec<(u32, u32)>> {
+        if frame.len() < self.frame_size()? {
+            return None;
+        }
+        Some(
+            (1..32)
+                .filter_map(|reg| {
+                    self.frame_offset(reg)
+                        .map(|offset| (reg, read_u32(&frame[offset..])))
+                })
+                .collect(),
+        )
+    }
+
+    fn number(&self, name: &str) -> Option<u32> {
+        self.define(name)?.parse().ok()
+    }
+}
+
+/// Decodes a crash record read from the target, e.g. from `.uninit` with a debugger
+pub fn crash(record: &[u8]) -> Option<Crash> {
+    if record.len() < 16 {
+        return None;
+    }
+    let mut words = [0u32; 4];
+    for (i, word) in words.iter_mut().enumerate() {
+        *word = read_u32(&record[i * 4..]);
+    }
+    crash::decode(words)
+}
+
+/// Entries of a configuration block as `(tag, value)` pairs, `None` if the magic number doesn't
+/// match
+pub fn config(block: &[u8]) -> Option<Vec<(u16, Vec<u8>)>> {
+    if block.len() < 4 || read_u32(block) != flash_config::MAGIC {
+        return None;
+    }
+
+    let mut rest = &block[4..];
+    let mut entries = Vec::new();
+    while let Some((tag, value)) = flash_config::next_entry(&mut rest) {
+        entries.push((tag, value.to_vec()));
+    }
+    Some(entries)
+}
+
+/// Encodes the `.data` load image of `binary`, a firmware built with the `compressed-data`
+/// feature, returns the binary to write to FLASH, `None` if it doesn't end with a `.data` load
+/// image
+///
+/// `binary` is the firmware as produced by `objcopy -O binary`. The size in an image header is
+/// updated, its CRC has to be [filled in](../image/fn.fill_crc.html) afterwards.
+pub fn compress_data(binary: &[u8]) -> Option<Vec<u8>> {
+    let trailer = binary.len().checked_sub(8)?;
+    if read_u32(&binary[trailer..]) != DATA_TRAILER_MAGIC {
+        return None;
+    }
+    let start = trailer.checked_sub(read_u32(&binary[trailer + 4..]) as usize)?;
+
+    let mut compressed = binary[..start].to_vec();
+    rle::encode(&binary[start..trailer], |byte| compressed.push(byte));
+
+    if ImageHeader::parse(&compressed).is_some() {
+        let size = compressed.len() as u32;
+        for (i, byte) in compressed[12..16].iter_mut().enumerate() {
+            *byte = (size >> (i * 8)) as u8;
+        }
+        debug_assert_eq!(ImageHeader::parse(&compressed).unwrap().size, size);
+    }
+    Some(compressed)
+}
+
+/// A loadable segment of an ELF file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    /// Load address, e.g. in FLASH for `.data`
+    pub address: u32,
+    /// Contents, without the zero-filled tail of `.bss` and the like
+    pub data: Vec<u8>,
+}
+
+/// The loadable segments of `elf`, a little-endian ELF32 file like the firmware, `None` if it
+/// isn't one
+pub fn elf_segments(elf: &[u8]) -> Option<Vec<Segment>> {
+    const PT_LOAD: u32 = 1;
+
+    if elf.len() < 0x34 || elf[..4] != b"\x7fELF"[..] || elf[4] != 1 || elf[5] != 1 {
+        return None;
+    }
+    let read_u16 = |offset: usize| u16::from(elf[offset]) | u16::from(elf[offset + 1]) << 8;
+    let phoff = read_u32(&elf[0x1c..]) as usize;
+    let phentsize = read_u16(0x2a) as usize;
+    let phnum = read_u16(0x2c) as usize;
+
+    let mut segments = Vec::new();
+    for i in 0..phnum {
+        let header = elf.get(phoff + i * phentsize..phoff + i * phentsize + 0x20)?;
+        let offset = read_u32(&header[4..]) as usize;
+        let size = read_u32(&header[16..]) as usize;
+        if read_u32(header) != PT_LOAD || size == 0 {
+            continue;
+        }
+        segments.push(Segment {
+            address: read_u32(&header[12..]),
+            data: elf.get(offset..offset + size)?.to_vec(),
+        });
+    }
+    segments.sort_by_key(|segment| segment.address);
+    Some(segments)
+}
+
+/// `ORIGIN` and `LENGTH` of the region `name` in the `MEMORY` command of `memory_x`
+///
+/// Understands the plain `NAME : ORIGIN = 0x..., LENGTH = 16K` form the examples use.
+pub fn memory_region(memory_x: &str, name: &str) -> Option<(u32, u32)> {
+    fn number(text: &str) -> Option<u32> {
+        let text = text.trim();
+        let (digits, scale) = if text.ends_with('K') {
+            (&text[..text.len() - 1], 1024)
+        } else if text.ends_with('M') {
+            (&text[..text.len() - 1], 1024 * 1024)
+        } else {
+            (text, 1)
+        };
+        let value = if digits.starts_with("0x") || digits.starts_with("0X") {
+            u32::from_str_radix(&digits[2..], 16).ok()?
+        } else {
+            digits.parse().ok()?
+        };
+        value.checked_mul(scale)
+    }
+    fn attribute<'a>(text: &'a str, key: &str) -> Option<&'a str> {
+        let value = &text[text.find(key)? + key.len()..];
+        let value = value.trim_start().trim_start_matches('=');
+        Some(value.split(',').next()?)
+    }
+
+    memory_x.lines().find_map(|line| {
+        let colon = line.find(':')?;
+        if line[..colon].split_whitespace().next()? != name {
+            return None;
+        }
+        let attributes = &line[colon + 1..];
+        Some((
+            number(attribute(attributes, "ORIGIN")?)?,
+            number(attribute(attributes, "LENGTH")?)?,
+        ))
+    })
+}
+
+/// The contents of `[origin, origin + length)` loaded by `segments`, up to the last byte loaded,
+/// zero where nothing is, `None` if a segment doesn't fit
+pub fn memory_image(segments: &[Segment], origin: u32, length: u32) -> Option<Vec<u8>> {
+    let mut image = Vec::new();
+    for segment in segments {
+        let start = segment.address.checked_sub(origin)? as usize;
+        let end = start + segment.data.len();
+        if end > length as usize {
+            return None;
+        }
+        if image.len() < end {
+            image.resize(end, 0);
+        }
+        image[start..end].copy_from_slice(&segment.data);
+    }
+    Some(image)
+}
+
+/// `image` as a word per line, little-endian, the format of Verilog's `$readmemh`
+///
+/// A trailing partial word is padded with zeroes.
+pub fn readmemh(image: &[u8]) -> String {
+    let mut hex = String::new();
+    for word in image.chunks(4) {
+        let mut bytes = [0; 4];
+        bytes[..word.len()].copy_from_slice(word);
+        writeln!(hex, "{:08x}", read_u32(&bytes)).unwrap();
+    }
+    hex
+}
+
+/// `segments` in the Intel HEX format, at their load addresses
+pub fn intel_hex(segments: &[Segment]) -> String {
+    fn record(hex: &mut String, kind: u8, address: u16, data: &[u8]) {
+        let mut checksum = (data.len() as u8)
+            .wrapping_add((address >> 8) as u8)
+            .wrapping_add(address as u8)
+            .wrapping_add(kind);
+        write!(hex, ":{:02X}{:04X}{:02X}", data.len(), address, kind).unwrap();
+        for &byte in data {
+            write!(hex, "{:02X}", byte).unwrap();
+            checksum = checksum.wrapping_add(byte);
+        }
+        writeln!(hex, "{:02X}", checksum.wrapping_neg()).unwrap();
+    }
+
+    let mut hex = String::new();
+    // the upper half of the address the data records are relative to
+    let mut upper = None;
+    for segment in segments {
+        let mut address = segment.address;
+        for chunk in segment.data.chunks(16) {
+            // a record can't cross into the next 64K
+            let room = 0x1_0000 - (address & 0xffff) as usize;
+            for part in &[
+                &chunk[..chunk.len().min(room)],
+                &chunk[chunk.len().min(room)..],
+            ] {
+                if part.is_empty() {
+                    continue;
+                }
+                if upper != Some(address >> 16) {
+                    upper = Some(address >> 16);
+                    record(
+                        &mut hex,
+                        4,
+                        0,
+                        &[(address >> 24) as u8, (address >> 16) as u8],
+                    );
+                }
+                record(&mut hex, 0, address as u16, part);
+                address = address.wrapping_add(part.len() as u32);
+            }
+        }
+    }
+    record(&mut hex, 1, 0, &[]);
+    hex
+}
+
+/// A record of an event log
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    /// Kind, see [`event_log::kind`](../event_log/kind/index.html)
+    pub kind: u16,
+    /// Data
+    pub data: Vec<u8>,
+}
+
+impl Record {
+    /// The crash of a [`kind::CRASH`](../event_log/kind/constant.CRASH.html) record
+    pub fn crash(&self) -> Option<Crash> {
+        if self.kind == event_log::kind::CRASH {
+            event_log::crash(&self.data)
+        } else {
+            None
+        }
+    }
+}
+
+/// Intact records of the event log in `image`, a copy of its flash sectors, oldest first
+///
+/// # Panics
+///
+/// Under the same conditions as [`EventLog::new`](../event_log/struct.EventLog.html#method.new),
+/// with the number of sectors being the length of `image` divided by `sector_size`
+pub fn event_log(image: &[u8], sector_size: u32) -> Vec<Record> {
+    let sectors = image.len() as u32 / sector_size;
+    let log = EventLog::new(Image(image), 0, sector_size, sectors);
+
+    let mut records = Vec::new();
+    log.for_each(|kind, data| {
+        records.push(Record {
+            kind,
+            data: data.to_vec(),
+        })
+    });
+    records
+}
+
+/// Copy of flash sectors, read only, so opening an empty log doesn't initialize it
+struct Image<'a>(&'a [u8]);
+
+impl<'a> FlashWrite for Image<'a> {
+    fn erase_sector(&mut self, _addr: u32) {}
+
+    fn write(&mut self, _addr: u32, _data: &[u8]) {}
+
+    fn read(&self, addr: u32, buf: &mut [u8]) {
+        let addr = addr as usize;
+        buf.copy_from_slice(&self.0[addr..addr + buf.len()]);
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from(bytes[0])
+        | u32::from(bytes[1]) << 8
+        | u32::from(bytes[2]) << 16
+        | u32::from(bytes[3]) << 24
+}
@@ -0,0 +1,175 @@
+//! Host calls for simulations
+//!
+//! A testbench has the whole host OS at hand, so with the `hostcall` feature simulated firmware
+//! gets stdio, its command line and the simulation time without a peripheral model. Calls go
+//! through a window of two registers at `_hostcall_addr`, set in `memory.x`:
+//!
+//! ``` text
+//! _hostcall_addr = 0x10000100;
+//! ```
+//!
+//! | Offset | Write                                                        |
+//! |--------|--------------------------------------------------------------|
+//! | `0x0`  | address of a parameter block of four words                   |
+//! | `0x4`  | operation, performs the call and writes the results into the block |
+//!
+//! | Operation    | Parameters         | Results                                          |
+//! |--------------|--------------------|--------------------------------------------------|
+//! | 1, `WRITE`   | `fd`, `ptr`, `len` | bytes written, `0xffff_ffff` on failure          |
+//! | 2, `ARGS`    | `ptr`, `len`       | length of the NUL separated arguments, which are copied to `ptr` up to `len` bytes |
+//! | 3, `TIME`    |                    | nanoseconds since the start, low and high word   |
+//!
+//! The testbench performs the call before the store to `0x4` completes, e.g. in the `$display`
+//! loop most PicoRV32 testbenches already have for a `putchar` register. Without `_hostcall_addr`
+//! every call fails, so the same firmware runs on hardware.
+//!
+//! ```
+//! use core::fmt::Write;
+//! use picorv32_rt::hostcall;
+//!
+//! fn report() {
+//!     let mut buf = [0; 64];
+//!     for arg in hostcall::args(&mut buf).into_iter().flatten() {
+//!         let _ = writeln!(hostcall::Stdout, "arg: {}", arg);
+//!     }
+//!     if let Some(ns) = hostcall::time_ns() {
+//!         let _ = writeln!(hostcall::Stdout, "{} ns", ns);
+//!     }
+//! }
+//! ```
+
+use core::fmt;
+use core::ptr;
+use core::str;
+use core::sync::atomic::{self, Ordering};
+
+/// Writes to a file descriptor of the testbench
+pub const WRITE: u32 = 1;
+/// Copies the command line arguments of the simulation
+pub const ARGS: u32 = 2;
+/// Reads the simulation time
+pub const TIME: u32 = 3;
+
+/// File descriptor of the testbench's standard output
+pub const STDOUT: u32 = 1;
+/// File descriptor of the testbench's standard error
+pub const STDERR: u32 = 2;
+
+/// Whether `memory.x` sets `_hostcall_addr`
+pub fn available() -> bool {
+    extern "C" {
+        // Defined by link.x, 1 if memory.x sets `_hostcall_addr`
+        static _has_hostcall: u8;
+    }
+
+    unsafe { &_has_hostcall as *const u8 as usize != 0 }
+}
+
+/// Performs host call `op` with the parameter block `block`, which the results are written to
+///
+/// Returns `false` without `_hostcall_addr`.
+///
+/// # Safety
+///
+/// The testbench accesses the memory the parameters point to.
+pub unsafe fn call(op: u32, block: &mut [u32; 4]) -> bool {
+    extern "C" {
+        static mut _hostcall_addr: [u32; 2];
+    }
+
+    if !available() {
+        return false;
+    }
+
+    atomic::compiler_fence(Ordering::SeqCst);
+    ptr::write_volatile(&mut _hostcall_addr[0], block.as_mut_ptr() as u32);
+    ptr::write_volatile(&mut _hostcall_addr[1], op);
+    atomic::compiler_fence(Ordering::SeqCst);
+    *block = ptr::read_volatile(block);
+    true
+}
+
+/// Writes `data` to file descriptor `fd` of the testbench, returns the number of bytes written
+pub fn write(fd: u32, data: &[u8]) -> Option<usize> {
+    let mut block = [fd, data.as_ptr() as u32, data.len() as u32, 0];
+    if !unsafe { call(WRITE, &mut block) } || block[0] == !0 {
+        return None;
+    }
+    Some(block[0] as usize)
+}
+
+/// Copies the command line arguments of the simulation to `buf`, `None` if they don't fit
+pub fn args(buf: &mut [u8]) -> Option<Args<'_>> {
+    let mut block = [buf.as_mut_ptr() as u32, buf.len() as u32, 0, 0];
+    if !unsafe { call(ARGS, &mut block) } || block[0] as usize > buf.len() {
+        return None;
+    }
+    Some(Args {
+        rest: Some(&buf[..block[0] as usize]),
+    })
+}
+
+/// Nanoseconds since the simulation started
+pub fn time_ns() -> Option<u64> {
+    let mut block = [0; 4];
+    if !unsafe { call(TIME, &mut block) } {
+        return None;
+    }
+    Some(u64::from(block[1]) << 32 | u64::from(block[0]))
+}
+
+/// Iterator over the command line arguments, skipping those that aren't UTF-8
+pub struct Args<'a> {
+    rest: Option<&'a [u8]>,
+}
+
+impl<'a> Iterator for Args<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        loop {
+            let rest = self.rest.take()?;
+            if rest.is_empty() {
+                return None;
+            }
+            let arg = match rest.iter().position(|&b| b == 0) {
+                Some(end) => {
+                    self.rest = Some(&rest[end + 1..]);
+                    &rest[..end]
+                }
+                None => rest,
+            };
+            if let Ok(arg) = str::from_utf8(arg) {
+                return Some(arg);
+            }
+        }
+    }
+}
+
+/// The testbench's standard output
+pub struct Stdout;
+
+impl fmt::Write for Stdout {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_all(STDOUT, s.as_bytes())
+    }
+}
+
+/// The testbench's standard error
+pub struct Stderr;
+
+impl fmt::Write for Stderr {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_all(STDERR, s.as_bytes())
+    }
+}
+
+fn write_all(fd: u32, mut data: &[u8]) -> fmt::Result {
+    while !data.is_empty() {
+        match write(fd, data) {
+            Some(n) if n != 0 => data = &data[n.min(data.len())..],
+            _ => return Err(fmt::Error),
+        }
+    }
+    Ok(())
+}
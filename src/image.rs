@@ -0,0 +1,277 @@
+//! Bootloader and application images
+//!
+//! With the `bootloader-layout` feature the linker script keeps the firmware, a bootloader, within
+//! the first `_bootloader_size` bytes of FLASH. With the `application-layout` feature the
+//! firmware, an application, is linked to start right after that, with an
+//! [`ImageHeader`](struct.ImageHeader.html) in front of its code. `_bootloader_size` is defined in
+//! `memory.x` of both, as a multiple of `_flash_sector_size`:
+//!
+//! ``` text
+//! /* memory.x */
+//! MEMORY
+//! {
+//!   FLASH : ORIGIN = 0x00100000, LENGTH = 1M
+//!   RAM : ORIGIN = 0x00000000, LENGTH = 64K
+//! }
+//!
+//! _bootloader_size = 64K;
+//! ```
+//!
+//! The header records the entry point, the trap entry, the size of the image and a CRC-32 of the
+//! rest of it. The linker leaves the CRC as [`NO_CRC`](constant.NO_CRC.html), it's filled in by
+//! [`fill_crc`](fn.fill_crc.html) on the binary image after linking, e.g. by the tool that
+//! flashes or packages it.
+//!
+//! # A/B slots
+//!
+//! With `_slot_size` defined as well there's room for two applications, slot A right after the
+//! bootloader and slot B after that, so an update can be written to one slot while the other
+//! still holds a working image. Code isn't position independent, so every application is linked
+//! for one of them, picked by `_application_slot` in its `memory.x`, and an update is built for
+//! the slot it goes to. The application finds out which one it runs from with
+//! [`slot`](fn.slot.html), and the bootloader decides which one to start with
+//! [`select_slot`](fn.select_slot.html).
+//!
+//! ``` text
+//! /* memory.x of an application in slot B */
+//! _bootloader_size = 64K;
+//! _slot_size = 448K;
+//! _application_slot = 1;
+//! ```
+//!
+//! # Traps
+//!
+//! PicoRV32 jumps to `PROGADDR_IRQ` on traps, which is fixed in the gateware. Either it points at
+//! the trap entry of the application, or the bootloader doesn't use IRQs and its code at that
+//! address jumps on to the application.
+//!
+//! ``` ignore
+//! use picorv32_rt::bootloader::{self, Launch};
+//! use picorv32_rt::image;
+//!
+//! // in the bootloader
+//! fn boot() -> ! {
+//!     match image::select_slot().and_then(image::slot_image) {
+//!         Some((header, _)) => unsafe {
+//!             bootloader::jump_to_application(header.entry, &Launch::default())
+//!         },
+//!         None => loop { /* wait for an update */ },
+//!     }
+//! }
+//! ```
+
+use crate::crc;
+
+/// `"IMAG"`, the first word of a header
+pub const MAGIC: u32 = 0x4741_4d49;
+
+/// Size of an [`ImageHeader`](struct.ImageHeader.html) in bytes
+pub const HEADER_SIZE: usize = 20;
+
+/// Value of [`ImageHeader::crc`](struct.ImageHeader.html#structfield.crc) before it's filled in
+pub const NO_CRC: u32 = 0xffff_ffff;
+
+/// Header at the start of an application image
+///
+/// Stored as little-endian words: [`MAGIC`](constant.MAGIC.html) followed by the fields in order.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ImageHeader {
+    /// Address of `_start`
+    pub entry: u32,
+    /// Address of `_start_trap`
+    pub trap: u32,
+    /// Size of the image in bytes, including the header
+    pub size: u32,
+    /// CRC-32 of the image after the header, see [`crc::crc32`](../crc/fn.crc32.html)
+    pub crc: u32,
+}
+
+impl ImageHeader {
+    /// Parses the header at the start of `image`, `None` if it doesn't start with one
+    pub fn parse(image: &[u8]) -> Option<ImageHeader> {
+        if image.len() < HEADER_SIZE || read_u32(image, 0) != MAGIC {
+            return None;
+        }
+        Some(ImageHeader {
+            entry: read_u32(image, 1),
+            trap: read_u32(image, 2),
+            size: read_u32(image, 3),
+            crc: read_u32(image, 4),
+        })
+    }
+
+    /// Whether `image`, starting with this header, is complete and its CRC matches
+    pub fn crc_matches(&self, image: &[u8]) -> bool {
+        let size = self.size as usize;
+        size >= HEADER_SIZE
+            && size <= image.len()
+            && crc::crc32(&image[HEADER_SIZE..size]) == self.crc
+    }
+}
+
+/// Fills in the CRC of the header at the start of `image`, returns the updated header
+///
+/// `None` if `image` doesn't start with a header or is shorter than the size it records.
+pub fn fill_crc(image: &mut [u8]) -> Option<ImageHeader> {
+    let mut header = ImageHeader::parse(image)?;
+    let size = header.size as usize;
+    if size < HEADER_SIZE || size > image.len() {
+        return None;
+    }
+
+    header.crc = crc::crc32(&image[HEADER_SIZE..size]);
+    for (i, byte) in image[16..20].iter_mut().enumerate() {
+        *byte = (header.crc >> (i * 8)) as u8;
+    }
+    Some(header)
+}
+
+/// One of the two application regions of an A/B layout
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Slot {
+    /// The region right after the bootloader, the only one without `_slot_size`
+    A,
+    /// The region right after slot A
+    B,
+}
+
+impl Slot {
+    /// The other slot
+    pub fn other(self) -> Slot {
+        match self {
+            Slot::A => Slot::B,
+            Slot::B => Slot::A,
+        }
+    }
+}
+
+#[cfg(slot_layout)]
+extern "C" {
+    static _sapplication: u8;
+    static _application_slot: u8;
+    static _slot_size: u8;
+    static _sslot_a: u8;
+    static _sslot_b: u8;
+}
+
+/// Address of the image of this application, or of slot A in a bootloader
+#[cfg(slot_layout)]
+pub fn application_start() -> usize {
+    unsafe { &_sapplication as *const u8 as usize }
+}
+
+/// Header of the image at [`application_start`](fn.application_start.html) and the whole image
+/// it describes, `None` if there's no header
+///
+/// The image isn't checked, see [`ImageHeader::crc_matches`](struct.ImageHeader.html#method.crc_matches).
+#[cfg(slot_layout)]
+pub fn application() -> Option<(ImageHeader, &'static [u8])> {
+    image_at(application_start())
+}
+
+/// Slot this application is linked for, `_application_slot` in `memory.x`
+#[cfg(feature = "application-layout")]
+pub fn slot() -> Slot {
+    if unsafe { &_application_slot as *const u8 as usize } == 0 {
+        Slot::A
+    } else {
+        Slot::B
+    }
+}
+
+/// Size of a slot in bytes, `_slot_size` in `memory.x`, 0 if there's only one application region
+#[cfg(slot_layout)]
+pub fn slot_size() -> usize {
+    unsafe { &_slot_size as *const u8 as usize }
+}
+
+/// Address of the image in `slot`
+#[cfg(slot_layout)]
+pub fn slot_start(slot: Slot) -> usize {
+    match slot {
+        Slot::A => unsafe { &_sslot_a as *const u8 as usize },
+        Slot::B => unsafe { &_sslot_b as *const u8 as usize },
+    }
+}
+
+/// Header of the image in `slot` and the whole image it describes, `None` if there's no header
+/// or no such slot
+///
+/// The image isn't checked, see [`ImageHeader::crc_matches`](struct.ImageHeader.html#method.crc_matches).
+#[cfg(slot_layout)]
+pub fn slot_image(slot: Slot) -> Option<(ImageHeader, &'static [u8])> {
+    if slot == Slot::B && slot_size() == 0 {
+        return None;
+    }
+    image_at(slot_start(slot))
+}
+
+/// Slot to boot, as chosen by the `__select_slot` hook, `None` if neither holds a usable image
+///
+/// By default the first slot with an intact image is chosen, the application can provide
+/// `__select_slot` for other policies, e.g. to try an updated image in slot B once and fall back
+/// to slot A if it didn't confirm itself. It doesn't rely on initialized RAM, so it can be called
+/// from `#[pre_init]` as well as from the `main` of a bootloader.
+///
+/// ``` ignore
+/// use picorv32_rt::image::{self, Slot};
+///
+/// #[export_name = "__select_slot"]
+/// fn select_slot() -> Option<Slot> {
+///     // prefers B, e.g. because slot A is the factory image
+///     [Slot::B, Slot::A]
+///         .iter()
+///         .cloned()
+///         .find(|&slot| image::slot_image(slot).map_or(false, |(h, image)| h.crc_matches(image)))
+/// }
+/// ```
+#[cfg(slot_layout)]
+pub fn select_slot() -> Option<Slot> {
+    extern "Rust" {
+        // This symbol can be provided by the user, otherwise it defaults to `first_intact_slot`
+        fn __select_slot() -> Option<Slot>;
+    }
+
+    unsafe { __select_slot() }
+}
+
+/// First slot with an image whose CRC matches, the default of
+/// [`select_slot`](fn.select_slot.html)
+#[cfg(slot_layout)]
+pub fn first_intact_slot() -> Option<Slot> {
+    [Slot::A, Slot::B]
+        .iter()
+        .cloned()
+        .find(|&slot| slot_image(slot).map_or(false, |(header, image)| header.crc_matches(image)))
+}
+
+/// Image starting at `start`, if there's a header there
+#[cfg(slot_layout)]
+fn image_at(start: usize) -> Option<(ImageHeader, &'static [u8])> {
+    let header = ImageHeader::parse(unsafe {
+        core::slice::from_raw_parts(start as *const u8, HEADER_SIZE)
+    })?;
+
+    // a partly written header may claim any size, the image can't reach into the next slot or
+    // the config block
+    let limit = match slot_size() {
+        0 => crate::flash::config_sectors().start - start,
+        size => size,
+    };
+    let size = header.size as usize;
+    if size < HEADER_SIZE || size > limit {
+        return None;
+    }
+    Some((header, unsafe {
+        core::slice::from_raw_parts(start as *const u8, size)
+    }))
+}
+
+/// Word `index` of `bytes`, little-endian
+fn read_u32(bytes: &[u8], index: usize) -> u32 {
+    let bytes = &bytes[index * 4..index * 4 + 4];
+    u32::from(bytes[0])
+        | u32::from(bytes[1]) << 8
+        | u32::from(bytes[2]) << 16
+        | u32::from(bytes[3]) << 24
+}
@@ -0,0 +1,96 @@
+//! Startup integrity check
+//!
+//! Firmware executed from SPI flash can be corrupted by a bad write or a flaky bus without
+//! anything noticing until a constant reads wrong. With the `integrity-check` feature the startup
+//! code computes the CRC-32 of `.text`, `.rodata` and the `.ramfunc` and `.data` load images,
+//! the range [`checked_range`](fn.checked_range.html), and compares it with the expected value
+//! the image carries in its last word. On a mismatch it calls `__integrity_failure`, before `.data` is
+//! initialized from the image and before `main` runs.
+//!
+//! The linker leaves the expected CRC as `0xffffffff`. It's filled in by
+//! [`fill_crc`](fn.fill_crc.html) on the binary image after linking, e.g. by the build helper
+//! that flashes or packages it. With the `application-layout` feature it has to be filled in
+//! before the CRC of the [image header](../image/index.html), which covers it.
+//!
+//! `__integrity_failure` can be provided by the application, e.g. to fall back to a recovery
+//! image, and spins forever by default. It runs before RAM is initialized, like `#[pre_init]`.
+//!
+//! ``` ignore
+//! use picorv32_rt::bootloader::{self, Launch};
+//!
+//! const RECOVERY_ENTRY: u32 = 0x0030_0000;
+//!
+//! #[export_name = "__integrity_failure"]
+//! fn integrity_failure() -> ! {
+//!     unsafe { bootloader::jump_to_application(RECOVERY_ENTRY, &Launch::default()) }
+//! }
+//! ```
+
+#[cfg(feature = "integrity-check")]
+use core::ops::Range;
+
+use crate::{crc, image};
+
+/// Placeholder for the expected CRC, replaced by `fill_crc`
+#[cfg(feature = "integrity-check")]
+#[link_section = ".integrity"]
+#[used]
+static EXPECTED_CRC: u32 = 0xffff_ffff;
+
+/// Fills in the expected CRC, the last word of `binary`, returns it
+///
+/// `binary` is the firmware as written to FLASH, e.g. by `objcopy -O binary`, starting either with
+/// `.text` or with an image header, which isn't covered. `None` if `binary` is too short.
+pub fn fill_crc(binary: &mut [u8]) -> Option<u32> {
+    let start = match image::ImageHeader::parse(binary) {
+        Some(_) => image::HEADER_SIZE,
+        None => 0,
+    };
+    let end = binary.len().checked_sub(4)?;
+    if end < start {
+        return None;
+    }
+
+    let crc = crc::crc32(&binary[start..end]);
+    for (i, byte) in binary[end..].iter_mut().enumerate() {
+        *byte = (crc >> (i * 8)) as u8;
+    }
+    Some(crc)
+}
+
+/// Addresses covered by the check, from the reset vector to the end of the `.data` load image
+#[cfg(feature = "integrity-check")]
+pub fn checked_range() -> Range<usize> {
+    extern "C" {
+        static _reset_vector_address: u8;
+        static _sintegrity: u8;
+    }
+
+    // the image starts with the reset vector
+    let start = unsafe { &_reset_vector_address as *const u8 as usize };
+    start..unsafe { &_sintegrity as *const u8 as usize }
+}
+
+/// Whether the CRC of [`checked_range`](fn.checked_range.html) matches the expected one
+///
+/// Takes a while, every byte of the image is read. The startup code calls it once.
+#[cfg(feature = "integrity-check")]
+pub fn intact() -> bool {
+    let range = checked_range();
+    let image =
+        unsafe { core::slice::from_raw_parts(range.start as *const u8, range.end - range.start) };
+    crc::crc32(image) == unsafe { core::ptr::read_volatile(&EXPECTED_CRC) }
+}
+
+/// Runs the check, called by the startup code before RAM is initialized
+#[cfg(feature = "integrity-check")]
+pub(crate) fn check() {
+    extern "Rust" {
+        // This symbol can be provided by the user, otherwise it defaults to spinning forever
+        fn __integrity_failure() -> !;
+    }
+
+    if !intact() {
+        unsafe { __integrity_failure() }
+    }
+}
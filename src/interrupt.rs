@@ -0,0 +1,116 @@
+//! Interrupt masking
+//!
+//! PicoRV32 has no global interrupt enable bit that could be toggled cheaply, instead all IRQ
+//! lines are masked via the `maskirq` instruction. Inside a trap handler all IRQs are blocked by
+//! the core anyway, so these functions are safe to call from both thread and interrupt context.
+//!
+//! # Atomics
+//!
+//! The `riscv32i` and `riscv32imc` targets have no A extension, so `core::sync::atomic` only
+//! offers loads and stores, no `fetch_add` or `compare_exchange`. With the `atomic-emulation`
+//! feature this crate implements [`critical-section`](https://docs.rs/critical-section) by
+//! masking all IRQ lines, like [`free`](fn.free.html). `portable-atomic` built with its
+//! `critical-section` feature then provides the full atomic API on top of it, and so does every
+//! crate that uses it or `critical-section` directly, e.g. `heapless` and `embassy-sync`:
+//!
+//! ``` text
+//! [dependencies]
+//! picorv32-rt = { version = "0.5", features = ["atomic-emulation"] }
+//! portable-atomic = { version = "1", features = ["critical-section"] }
+//! ```
+//!
+//! Each emulated operation is a short critical section, which is correct on the single-core
+//! PicoRV32 but delays IRQs by a few cycles.
+//!
+//! The `defmt` feature enables it too, for the transport that claims the logger.
+//!
+//! # Emulated instructions
+//!
+//! With the `emulate-m` and `emulate-unaligned` features [`disable`](fn.disable.html) leaves
+//! IRQ1 and IRQ2 unmasked respectively, as the core halts instead of taking a masked IRQ1 or
+//! IRQ2, and an emulated instruction in a critical section would stop the firmware. They're
+//! raised by the instruction being executed and never preempt it, so the critical section still
+//! holds. Emulation doesn't work inside trap handlers, see the
+//! [`exception`](../exception/index.html) module.
+
+pub use bare_metal::{CriticalSection, Mutex};
+#[cfg(feature = "interrupts")]
+use picorv32::asm;
+
+/// Mask set by [`disable`](fn.disable.html), all IRQ lines but those instructions are emulated on
+#[cfg(feature = "interrupts")]
+pub(crate) const MASK_ALL: u32 = !(((cfg!(feature = "emulate-m") as u32) << 1)
+    | ((cfg!(feature = "emulate-unaligned") as u32) << 2));
+
+/// Masks all IRQ lines and returns the previous mask
+///
+/// A set bit in the mask means the corresponding IRQ line is disabled. IRQ1 and IRQ2 stay
+/// unmasked if instructions are emulated on them, see the [module documentation](index.html).
+#[inline]
+pub fn disable() -> u32 {
+    #[cfg(feature = "interrupts")]
+    unsafe {
+        asm::maskirq(MASK_ALL)
+    }
+    #[cfg(not(feature = "interrupts"))]
+    !0
+}
+
+/// Restores an IRQ mask previously returned by [`disable`](fn.disable.html)
+///
+/// # Safety
+///
+/// Unmasking IRQ lines inside a critical section breaks the guarantees of the
+/// `CriticalSection` token.
+#[inline]
+pub unsafe fn restore(_mask: u32) {
+    #[cfg(feature = "interrupts")]
+    asm::maskirq(_mask);
+}
+
+/// Unmasks all IRQ lines, ending the critical section an entry point declared as
+/// `fn main(cs: CriticalSection)` starts in
+///
+/// See the [`entry`](../attr.entry.html) attribute.
+#[inline]
+pub fn enable(_cs: CriticalSection) {
+    #[cfg(feature = "interrupts")]
+    unsafe {
+        asm::maskirq(0);
+    }
+}
+
+/// Execute closure `f` with all IRQ lines masked
+///
+/// The previous IRQ mask is restored afterwards, so critical sections can be nested.
+#[inline]
+pub fn free<F, R>(f: F) -> R
+where
+    F: FnOnce(&CriticalSection) -> R,
+{
+    let mask = disable();
+
+    let r = f(unsafe { &CriticalSection::new() });
+
+    unsafe { restore(mask) };
+
+    r
+}
+
+/// `critical-section` implementation, with the `atomic-emulation` feature
+#[cfg(feature = "atomic-emulation")]
+struct MaskIrq;
+
+#[cfg(feature = "atomic-emulation")]
+critical_section::set_impl!(MaskIrq);
+
+#[cfg(feature = "atomic-emulation")]
+unsafe impl critical_section::Impl for MaskIrq {
+    unsafe fn acquire() -> critical_section::RawRestoreState {
+        disable()
+    }
+
+    unsafe fn release(mask: critical_section::RawRestoreState) {
+        restore(mask)
+    }
+}
@@ -0,0 +1,60 @@
+//! Per-IRQ statistics
+//!
+//! With the `irq-stats` feature the handlers registered with
+//! [`picorv32_interrupts!`](../macro.picorv32_interrupts.html) are timed with the cycle counter,
+//! so the core has to be built with `ENABLE_COUNTERS`. Traps handled by the runtime itself, like
+//! emulated instructions, aren't counted.
+//!
+//! ```
+//! use picorv32_rt::irq_stats;
+//!
+//! fn timer_budget_exceeded() -> bool {
+//!     irq_stats::stats(0).max_cycles > 2_000
+//! }
+//! ```
+
+use crate::interrupt;
+
+/// Statistics of a single IRQ line
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct IrqStats {
+    /// Number of handler invocations, wrapping
+    pub count: u32,
+    /// Duration of the last handler invocation in cycles
+    pub last_cycles: u32,
+    /// Duration of the longest handler invocation in cycles
+    pub max_cycles: u32,
+}
+
+const NO_STATS: IrqStats = IrqStats {
+    count: 0,
+    last_cycles: 0,
+    max_cycles: 0,
+};
+
+/// Only written from the trap handler
+static mut STATS: [IrqStats; 32] = [NO_STATS; 32];
+
+/// Statistics of IRQ line `irq`
+///
+/// # Panics
+///
+/// If `irq` is not in the range `0..32`
+pub fn stats(irq: u32) -> IrqStats {
+    interrupt::free(|_| unsafe { STATS[irq as usize] })
+}
+
+/// Resets the statistics of all IRQ lines
+pub fn reset() {
+    interrupt::free(|_| unsafe { STATS = [NO_STATS; 32] })
+}
+
+/// Accounts a handler invocation, called from the trap handler only
+pub(crate) fn record(irq: u32, cycles: u32) {
+    let stats = unsafe { &mut STATS[irq as usize] };
+    stats.count = stats.count.wrapping_add(1);
+    stats.last_cycles = cycles;
+    if cycles > stats.max_cycles {
+        stats.max_cycles = cycles;
+    }
+}
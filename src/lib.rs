@@ -2,7 +2,7 @@
 //!
 //! # Minimum Supported Rust Version (MSRV)
 //!
-//! This crate is guaranteed to compile on stable Rust 1.32 and up. It *might*
+//! This crate is guaranteed to compile on stable Rust 1.51 and up. It *might*
 //! compile with older versions but that may change in any new patch release.
 //!
 //! # Features
@@ -101,11 +101,24 @@
 //! Disassembly of section .text:
 //!
 //! 20000000 <_start>:
-//! 20000000:	800011b7          	lui	gp,0x80001
-//! 20000004:	80018193          	addi	gp,gp,-2048 # 80000800 <_stack_start+0xffffc800>
-//! 20000008:	80004137          	lui	sp,0x80004
+//! 20000000:   800011b7                lui     gp,0x80001
+//! 20000004:   80018193                addi    gp,gp,-2048 # 80000800 <_stack_start+0xffffc800>
+//! 20000008:   80004137                lui     sp,0x80004
 //! ```
 //!
+//! `link.x` is provided by this crate and shouldn't be copied into the application. A copy that
+//! went stale after updating this crate makes linking fail with an undefined
+//! `__picorv32_rt_link_x_v<N>` symbol, `<N>` being the version of the script the runtime expects.
+//!
+//! The startup code is built for the extensions the target names: `riscv32i` and `riscv32im`
+//! get it without compressed instructions, `riscv32imc` with them. Targets PicoRV32 can't run,
+//! with the A, F or D extension or RV32E, fail the build. The `compressed-isa` feature, on by
+//! default, has to be disabled for targets without the C extension, and picks the startup code
+//! for custom target specifications whose name doesn't spell out the extensions.
+//!
+//! Feature combinations the runtime can't be built with fail the build as well, with a message
+//! naming the feature to add or remove.
+//!
 //! # Symbol interfaces
 //!
 //! This crate makes heavy use of symbols, linker sections and linker scripts to
@@ -129,6 +142,10 @@
 //! region named FLASH, and the program `static` variables (the sections `.bss`
 //! and `.data`) will be allocated in the memory region named RAM.
 //!
+//! A missing `memory.x`, or one without either region, fails the link with an error saying so.
+//! The build script of this crate also warns about a `memory.x` without them when it can find
+//! one, in the directory `cargo` runs in or in a `-L` directory of `RUSTFLAGS`.
+//!
 //! ### `_stack_start`
 //!
 //! This symbol provides the address at which the call stack will be allocated.
@@ -136,13 +153,14 @@
 //! valid RAM address plus one (this *is* an invalid address but the processor
 //! will decrement the stack pointer *before* using its value as an address).
 //!
-//! If omitted this symbol value will default to `ORIGIN(RAM) + LENGTH(RAM)`.
+//! If omitted this symbol value will default to `ORIGIN(RAM) + LENGTH(RAM)`, the end of
+//! `REGION_STACK`.
 //!
 //! #### Example
 //!
 //! Allocating the call stack on a different RAM region.
 //!
-//! ```
+//! ``` text
 //! MEMORY
 //! {
 //!   /* call stack will go here */
@@ -155,10 +173,35 @@
 //! _stack_start = ORIGIN(CCRAM) + LENGTH(CCRAM);
 //! ```
 //!
+//! ### `_stack_size`
+//!
+//! The size of the stack, reserved right below `_stack_start`. Linking fails if `.data`, `.bss`
+//! and the heap don't leave that much room in `RAM`, rather than the stack running into them at
+//! run time. The default value is 0, the stack gets whatever is left.
+//!
+//! With the `stack-first` feature the stack is placed at the bottom of `RAM` instead, below
+//! `.data`, `.bss` and the heap, and `_stack_size` is required. The stack grows
+//! downwards, so an overflow runs off the start of `RAM`, e.g. wraps around to `0xfffffffc` with
+//! `RAM` at address 0, instead of silently overwriting the statics. On SoCs that raise a bus error
+//! for unmapped addresses it ends in a trap on IRQ 2, the same protection `flip-link` offers on
+//! other targets, at no run-time cost. `_stack_start` then defaults to the top of the
+//! reservation.
+//!
+//! ``` text
+//! _stack_size = 4K;
+//! ```
+//!
 //! ### `_heap_size`
 //!
 //! This symbol provides the size of a heap region. The default value is 0. You can set `_heap_size`
-//! to a non-zero value if you are planning to use heap allocations.
+//! to a non-zero value if you are planning to use heap allocations. With the `alloc` feature this
+//! crate provides the `#[global_allocator]` over it, see the [`heap`](heap/index.html) module.
+//!
+//! ### `_heap2_size`
+//!
+//! The size of a second heap, after the statics of the `RAM2` region with the `ram2` feature and
+//! after the first heap otherwise. The default value is 0. See the [`heap`](heap/index.html)
+//! module for an allocator that prefers the first one.
 //!
 //! ### `_sheap`
 //!
@@ -166,25 +209,313 @@
 //! You can use the address of this symbol as the start address of a heap
 //! region. This symbol is 4 byte aligned so that address will be a multiple of 4.
 //!
+//! The [`heap`](heap/index.html) module returns the heap region without declaring the symbols.
+//!
 //! #### Example
 //!
-//! ```
+//! ``` ignore
 //! extern crate some_allocator;
 //!
-//! extern "C" {
-//!     static _sheap: u8;
-//!     static _heap_size: u8;
-//! }
+//! use picorv32_rt::heap;
 //!
 //! fn main() {
-//!     unsafe {
-//!         let heap_bottom = &_sheap as *const u8 as usize;
-//!         let heap_size = &_heap_size as *const u8 as usize;
-//!         some_allocator::initialize(heap_bottom, heap_size);
+//!     some_allocator::initialize(heap::heap_start() as usize, heap::heap_size());
+//! }
+//! ```
+//!
+//! ### `_flash_sector_size`
+//!
+//! The erase granularity of the flash, a power of two. The linker script rounds the firmware image
+//! and the configuration block out to whole sectors and checks that they don't share one, see
+//! the [`flash`](flash/index.html) module. The default value is 4, i.e. no rounding.
+//!
+//! #### Example
+//!
+//! ``` text
+//! _flash_sector_size = 4K;
+//! ```
+//!
+//! ### `_reset_vector_address` / `_irq_vector_address`
+//!
+//! `PROGADDR_RESET` and `PROGADDR_IRQ`, the parameters the core was synthesized with. The jump to
+//! `_start` is placed at the first and the trap entry at the second, the rest of the startup code
+//! follows it. By default the reset vector is at the start of `FLASH` and the IRQ vector at the
+//! next multiple of 16 bytes after it. The IRQ vector has to come after the reset vector, and
+//! linking fails if either one can't be placed where it's defined.
+//!
+//! #### Example
+//!
+//! ``` text
+//! _reset_vector_address = 0x00100000;
+//! _irq_vector_address = 0x00100100;
+//! ```
+//!
+//! ### `_bootloader_size`
+//!
+//! With the `bootloader-layout` or `application-layout` feature, the size of the bootloader at
+//! the start of FLASH, a multiple of `_flash_sector_size`. The bootloader has to fit in it and the
+//! application starts right after it, see the [`image`](image/index.html) module.
+//!
+//! ### `_slot_size` / `_application_slot`
+//!
+//! Size of each of the two application slots of an A/B layout, a multiple of
+//! `_flash_sector_size`, and the slot an application is linked for, 0 for A and 1 for B. Both
+//! default to 0, a single application region.
+//!
+//! ### `RAM2`
+//!
+//! With the `ram2` feature a second RAM region, e.g. a large external SRAM next to a small TCM.
+//! Statics placed in its `.data_RAM2` and `.bss_RAM2` sections are initialized by the startup
+//! code like those in `.data` and `.bss`, after `__memory_setup`, which can bring up the memory
+//! controller. Without the feature these sections end up in `RAM`.
+//!
+//! ``` text
+//! MEMORY
+//! {
+//!   FLASH : ORIGIN = 0x00100000, LENGTH = 1M
+//!   RAM : ORIGIN = 0x00000000, LENGTH = 16K
+//!   RAM2 : ORIGIN = 0x02000000, LENGTH = 1M
+//! }
+//! ```
+//!
+//! The [`in_region`](attr.in_region.html) attribute picks the right section for a static:
+//!
+//! ``` ignore
+//! use picorv32_rt::in_region;
+//!
+//! #[in_region("RAM2")]
+//! static mut FRAME_BUFFER: [u32; 64 * 1024] = [0; 64 * 1024];
+//! ```
+//!
+//! ### Region aliases
+//!
+//! `link.x` doesn't place anything in `FLASH` or `RAM` directly but in region aliases, which the
+//! build script points at them:
+//!
+//! - `REGION_BOOT`: the reset vector, the trap entry, `_start` and the load image of everything
+//!   else, `FLASH`, or `RAM` with the `ram-only` feature
+//! - `REGION_TEXT`: `.text` and `.rodata`, `FLASH`, or `RAM` with the `copy-to-ram` and `ram-only`
+//!   features
+//! - `REGION_DATA`: `.ramfunc`, the statics and the heap, `RAM`
+//! - `REGION_STACK`: the stack, `RAM`
+//! - `REGION_RAM2`: `.data_RAM2` and `.bss_RAM2`, `RAM2` with the `ram2` feature, `RAM` otherwise
+//!
+//! With the `custom-regions` feature `memory.x` defines them instead, for SoCs whose memories
+//! aren't a flash and a RAM, e.g. one that boots from a single block RAM. `copy-to-ram` is then
+//! replaced by aliasing `REGION_TEXT` to a RAM region. Leaving one of them out fails the link
+//! with an error naming it.
+//!
+//! ``` text
+//! MEMORY
+//! {
+//!   BRAM : ORIGIN = 0x00000000, LENGTH = 64K
+//! }
+//!
+//! REGION_ALIAS("REGION_BOOT", BRAM);
+//! REGION_ALIAS("REGION_TEXT", BRAM);
+//! REGION_ALIAS("REGION_DATA", BRAM);
+//! REGION_ALIAS("REGION_STACK", BRAM);
+//! REGION_ALIAS("REGION_RAM2", BRAM);
+//! ```
+//!
+//! The `.data` load image and its run address coincide there, so the copy at startup is a no-op.
+//! The `ram-test` and `zero-ram` features, which overwrite all of `REGION_DATA`, can't be used
+//! with a region that also holds the code.
+//!
+//! ## `device.x`
+//!
+//! With the `device` feature the linker script also includes a `device.x` file, which describes
+//! the SoC rather than the board and is usually provided by a device crate.
+//!
+//! ### `__EXTERNAL_INTERRUPTS`
+//!
+//! The interrupt table of an svd2rust PAC, with `_external_interrupts_len` set to the number of
+//! its entries. The default `trap_handler` calls the entries of the pending IRQ lines, see the
+//! [`device`](device/index.html) module.
+//!
+//! ### `_irq_unconnected`
+//!
+//! A mask of the IRQ lines that aren't connected to anything on this SoC. Linking fails if
+//! `picorv32_interrupts!` registers a handler for one of them, which would otherwise just never
+//! run. The lines that have a handler end up in `_irq_handled` in the linker map.
+//!
+//! If omitted this symbol defaults to 0, i.e. all lines are considered connected.
+//!
+//! #### Example
+//!
+//! ``` text
+//! /* IRQ 3..=4 and 8..=31 aren't wired up */
+//! _irq_unconnected = 0xffffff18;
+//! ```
+//!
+//! ## `picorv32_rt.h`
+//!
+//! The build script generates a C header with the trap frame layout, the linker script symbols and
+//! the runtime entry points, matching the enabled features. Build scripts of crates that depend on
+//! this one find its directory in the `DEP_PICORV32_RT_INCLUDE` environment variable, e.g. to pass
+//! it to the `cc` crate.
+//!
+//! ## Fast IRQ
+//!
+//! With `interrupts-qregs` the header also defines `PICORV32_RT_FAST_IRQ(line, handler)`, an
+//! assembler macro for one latency-critical IRQ line. It puts a prologue in front of the trap
+//! entry that jumps straight to `handler` when `line` is the only pending IRQ, 8 instructions
+//! after the IRQ fired, without saving any registers:
+//!
+//! ``` text
+//! #include "picorv32_rt.h"
+//!
+//! PICORV32_RT_FAST_IRQ(5, adc_ready)
+//!
+//! .text
+//! adc_ready:
+//!     /* all registers hold the values of the interrupted code, q2 and q3 are free */
+//!     ...
+//!     retirq
+//! ```
+//!
+//! The handler has to be written in assembly: it must save and restore every register it uses,
+//! e.g. in `q2`/`q3`, and return with `retirq`. Handlers written in Rust can't keep to that, so
+//! there's no fast variant of the [`interrupt`](attr.interrupt.html) attribute and
+//! `#[interrupt(fast)]` is rejected. If other IRQs are pending at the same time the trap is
+//! dispatched as usual, so the line also needs a regular handler.
+//!
+//! Without `interrupts-qregs` there are no q registers to free `x1` and `x2` with, and the macro
+//! fails the build with an assembler error instead.
+//!
+//! ## `__trap_enter` / `__trap_exit`
+//!
+//! Functions called with the pending IRQs right before and after every trap is dispatched, no-ops
+//! by default. Profilers, tracers or watchdog kickers can provide them to observe every interrupt:
+//!
+//! ```
+//! #[no_mangle]
+//! pub fn __trap_enter(irqs: u32) {
+//!     // e.g. raise a GPIO for a logic analyzer
+//! }
+//!
+//! #[no_mangle]
+//! pub fn __trap_exit(irqs: u32) {
+//!     // ...
+//! }
+//! ```
+//!
+//! ## `__boot_beacon`
+//!
+//! Function called with a [`BootStage`](runtime/enum.BootStage.html) at every boot milestone, a
+//! no-op by default. Board crates can provide it to show progress codes, e.g. on LEDs, while
+//! bringing up a board where no console works yet:
+//!
+//! ```
+//! use picorv32_rt::runtime::BootStage;
+//!
+//! #[no_mangle]
+//! pub fn __boot_beacon(stage: BootStage) {
+//!     let leds = 0x0300_0000 as *mut u32;
+//!     unsafe { leds.write_volatile(stage as u32) };
+//! }
+//! ```
+//!
+//! The first two stages are reported before RAM is initialized, so the function must not use
+//! any statics.
+//!
+//! ## `__pre_start`
+//!
+//! Routine jumped to at the very top of `_start`, before the global and stack pointers are set
+//! up, a no-op by default. It's meant for the few MMIO writes needed before there is a usable
+//! stack, e.g. enabling the external RAM the stack lives in. It's called with `jal t0`, so it
+//! returns with `jr t0`, and it must not use the stack or `gp`. Ordinary Rust functions return
+//! through `ra` and may spill to the stack, so it's written in assembly:
+//!
+//! ``` text
+//! .section .text.__pre_start, "ax"
+//! .global __pre_start
+//!
+//! __pre_start:
+//!     li t1, 0x02000000 /* external RAM controller */
+//!     li t2, 1
+//!     sw t2, 0(t1)
+//!     jr t0
+//! ```
+//!
+//! With the `copy-to-ram` feature it has to be placed in the `.init` section instead, it runs
+//! before `.text` is copied.
+//!
+//! ## `copy-to-ram`
+//!
+//! With this feature `.text` and `.rodata` are linked to run from `RAM`, ahead of `.bss` and
+//! `.data`, and their load image stays in `FLASH`. `_start` copies it to RAM before it jumps to
+//! `_start_rust`, so only the code in the `.boot` output section runs from `FLASH`: the reset
+//! vector, the trap entry and `_start` itself. Code runs much faster from RAM on boards with a
+//! slow SPI flash, as long as `RAM` has room for all of it.
+//!
+//! The copied range is `_sramtext`..`_eramtext`, loaded from `_sitext`. Without the feature the
+//! load image and the run address are the same and nothing is copied.
+//!
+//! ## `ram-only`
+//!
+//! With this feature there's no `FLASH`, `memory.x` only defines `RAM` and everything is linked
+//! there, the code, the statics with their initial values in place and the stack. It's meant for
+//! images a debugger or a simulator loads straight into RAM. The startup code doesn't copy
+//! anything, so the initial values of `.data` are only there on the first boot, a reset that
+//! doesn't reload the image keeps what the program left in them. `.bss` is still zeroed.
+//!
+//! It excludes the features that need a separate load image or more RAM regions:
+//! `compressed-data`, `copy-to-ram`, `custom-regions`, `ram-test`, `ram2` and `zero-ram`.
+//!
+//! ## `zero-ram`
+//!
+//! With this feature `_start` writes zeros over all of `RAM` right after setting up the stack
+//! pointer, before anything is stored there, so no stale data from before a reset survives in the
+//! stack, the heap or the padding between statics. This is what ECC-protected memories need
+//! before their first read, and it makes the boot state reproducible.
+//!
+//! `.uninit` is skipped, so `#[no_init]` statics still keep their values. Only `RAM` is cleared,
+//! `RAM2` isn't. The cleared range is `_szeroed`..`_ezeroed`, empty without the feature. It runs
+//! after `__pre_start`, which may be what makes `RAM` usable in the first place.
+//!
+//! ## `ram-test`
+//!
+//! With this feature `_start` runs a March C- test over all of `RAM` right after setting up the
+//! stack pointer, before the `zero-ram` clearing and before anything is stored there. It's
+//! destructive and leaves every word zeroed. It runs from `FLASH` and keeps its state in
+//! registers, so it catches stuck bits, coupling between words and address decoder faults, e.g.
+//! an external SRAM controller that doesn't meet timing, before they corrupt the program in
+//! subtle ways. The tested range is `_sramtest`..`_eramtest`, empty without the feature.
+//!
+//! `.uninit` is skipped like with `zero-ram`, the parts below and above it are tested one after
+//! the other. So `#[no_init]` statics, the [crash record](crash/index.html) and the message kept
+//! by `panic-persist` survive a reset, and the words of `.uninit` aren't tested.
+//!
+//! The first bad word is reported to the `RamTestFailed` symbol, jumped to with its address in
+//! `a0` and the value read from it in `a1`. The default hangs. It can be overridden by a function
+//! that never returns and doesn't rely on `RAM`, e.g. one that shows the address on the LEDs:
+//!
+//! ``` ignore,no_run
+//! #[no_mangle]
+//! pub extern "C" fn RamTestFailed(address: usize, value: usize) -> ! {
+//!     let leds = 0x0300_0000 as *mut usize;
+//!     loop {
+//!         unsafe { leds.write_volatile(address) };
 //!     }
 //! }
 //! ```
 //!
+//! With the `copy-to-ram` feature it has to be placed in the `.init` section, it runs before
+//! `.text` is copied.
+//!
+//! ## `ram-trap`
+//!
+//! With this feature the trap entry, `_start_trap` with the `PICORV32_RT_FAST_IRQ` prologues in
+//! front of it, runs from `.ramfunc` instead of from `FLASH`. `PROGADDR_IRQ` is fixed in the
+//! gateware, so the code there is replaced with three instructions that save `t0` right below the
+//! stack pointer and jump to `_start_trap_ram`, which restores it. On designs that execute from
+//! SPI flash this cuts most of the flash fetches out of the interrupt latency.
+//!
+//! The trap entry is copied at startup along with the rest of `.ramfunc`, before IRQs are
+//! enabled. Fast IRQ handlers jumped to from the prologues have to be reachable with `j`, e.g. by
+//! placing them in a `.ramfunc` section as well.
+//!
 //! ## `pre_init!`
 //!
 //! A user-defined function can be run at the start of the reset handler, before RAM is
@@ -192,20 +523,256 @@
 //! intended to perform actions that cannot wait the time it takes for RAM to be initialized, such
 //! as disabling a watchdog. As the function is called before RAM is initialized, any access of
 //! static variables will result in undefined behavior.
+//!
+//! A function declared as `fn(PreInit)` gets a [`PreInit`](runtime/struct.PreInit.html) token
+//! instead, which offers volatile MMIO accesses, so the usual work needs no `unsafe`:
+//!
+//! ``` ignore
+//! use picorv32_rt::{pre_init, runtime::PreInit};
+//!
+//! #[pre_init]
+//! fn disable_watchdog(p: PreInit) {
+//!     p.write(0x0300_0010, 0);
+//! }
+//! ```
+//!
+//! ## `.uninit`
+//!
+//! RAM section that the startup code neither zeroes nor initializes, between the `_suninit` and
+//! `_euninit` symbols. Statics are placed there with the [`no_init`](attr.no_init.html)
+//! attribute, e.g. large buffers that don't need to be cleared at boot or data that has to
+//! survive a restart of the firmware:
+//!
+//! ``` ignore
+//! use core::mem::MaybeUninit;
+//! use picorv32_rt::no_init;
+//!
+//! #[no_init]
+//! static mut DMA_BUFFER: MaybeUninit<[u8; 16384]> = MaybeUninit::uninit();
+//! ```
+//!
+//! ## `.ramfunc`
+//!
+//! RAM section for code, between the `_sramfunc` and `_eramfunc` symbols, copied from its load
+//! image in FLASH at `_siramfunc` along with `.data`. Functions are placed there with the
+//! [`ram_func`](attr.ram_func.html) attribute, e.g. hot loops that need a deterministic execution
+//! speed on a slow SPI flash, or routines that program the flash the code would otherwise be
+//! fetched from:
+//!
+//! ``` ignore
+//! use picorv32_rt::ram_func;
+//!
+//! #[ram_func]
+//! fn erase_sector(addr: u32) {
+//!     // ...
+//! }
+//! ```
+//!
+//! ## Small data
+//!
+//! Small statics in the `.sdata` and `.sbss` sections, as emitted by C compilers for
+//! `-msmall-data-limit` and by `rustc` for `-Z small-data-threshold`, are kept next to each other,
+//! at the end of `.data` and at the start of `.bss`. `__global_pointer$` points 2K past the start
+//! of `.sdata`, so with linker relaxation the accesses to them become single `gp`-relative loads
+//! and stores instead of `lui`/`addi` pairs. GNU ld relaxes them by default, `rust-lld` needs
+//! `--relax-gp`:
+//!
+//! ``` text
+//! [target.riscv32imc-unknown-none-elf]
+//! rustflags = [
+//!   "-C", "link-arg=-Tlink.x",
+//!   "-C", "link-arg=--relax-gp",
+//! ]
+//! ```
+//!
+//! This is only valid without the `interrupts` feature or with `interrupts-qregs`. With plain
+//! `interrupts` the core puts the return address of a trap in `x3`, which is `gp`, and the trap
+//! returns with it there, so `gp`-relative accesses in trap handlers and in the interrupted code
+//! hit random addresses afterwards. The build fails if `--relax-gp` is passed then, but GNU ld,
+//! which relaxes by default, has to be given `--no-relax-gp` by hand.
+//!
+//! ## Static constructors
+//!
+//! The functions listed in the `.preinit_array` and `.init_array` sections, in that order, are
+//! called right before `main`, once `.data` and `.bss` are initialized and with all IRQs still
+//! masked. `.init_array.<priority>` entries, as emitted for C++ and the `constructor` attribute
+//! of C, come first in order of their priority. Crates like `ctor` register Rust functions this
+//! way:
+//!
+//! ```
+//! extern "C" fn init_log() {
+//!     // ...
+//! }
+//!
+//! #[link_section = ".init_array"]
+//! #[used]
+//! static INIT_LOG: extern "C" fn() = init_log;
+//! ```
+//!
+//! ## `__memory_setup`
+//!
+//! Function called right after the `pre_init!` function and before `.bss` is zeroed and `.data`
+//! is copied, a no-op by default. Boards that keep `.data`, `.bss` or the heap in external memory,
+//! e.g. SDRAM behind a controller, initialize the controller here:
+//!
+//! ```
+//! #[no_mangle]
+//! pub unsafe fn __memory_setup() {
+//!     let sdram_ctrl = 0x0200_0000 as *mut u32;
+//!     sdram_ctrl.write_volatile(1); // bring up the SDRAM
+//! }
+//! ```
+//!
+//! Like the `pre_init!` function it runs with the stack in `RAM` and with `.data` and `.bss` not
+//! initialized yet, so it must not access any statics. If the stack itself lives in the external
+//! memory, the controller has to be set up by the `pre_init!` function instead, or by a boot loader.
+//!
+//! ## `__on_main_exit`
+//!
+//! The entry point can also be declared as `fn main()`, e.g. for test firmware. Once it returns
+//! this function is called, a no-op by default, and then the CPU sleeps in `waitirq` forever with
+//! interrupts still being handled. A simulation can be ended here:
+//!
+//! ```
+//! #[no_mangle]
+//! pub fn __on_main_exit() {
+//!     let exit = 0x2000_0000 as *mut u32;
+//!     unsafe { exit.write_volatile(123_456_789) };
+//! }
+//! ```
+//!
+//! ## `defmt`
+//!
+//! With the `defmt` feature the trap frames implement `defmt::Format`, and log frames are
+//! timestamped with the cycle counter, so the core has to be built with `ENABLE_COUNTERS` and
+//! `ENABLE_COUNTERS64`. The feature includes `atomic-emulation`, whose `critical-section`
+//! implementation transports like `defmt-rtt` use to claim the logger, see the
+//! [`interrupt`](interrupt/index.html) module. The application only adds the transport:
+//!
+//! ``` text
+//! [dependencies]
+//! picorv32-rt = { version = "0.5", features = ["defmt"] }
+//! defmt = "0.3"
+//! defmt-rtt = "0.4"
+//! ```
 
 // NOTE: Adapted from cortex-m/src/lib.rs
 #![no_std]
 #![deny(missing_docs)]
 
+#[cfg(all(feature = "embassy", feature = "timers"))]
+compile_error!("the `embassy` and `timers` features both use the hardware timer");
+
+#[cfg(feature = "malloc-shims")]
+extern crate alloc;
+extern crate bare_metal;
+#[cfg(feature = "defmt")]
+extern crate defmt_03 as defmt;
 extern crate picorv32_rt_macros as macros;
 extern crate r0;
-extern crate riscv;
+#[doc(hidden)]
+pub extern crate riscv;
+#[cfg(any(feature = "build-helper", feature = "host-tools"))]
+extern crate std;
 
 use core::fmt;
-use core::ptr::NonNull;
-pub use macros::{entry, pre_init};
+use core::ptr::{self, NonNull};
+#[cfg(feature = "interrupts")]
+pub use macros::interrupt;
+#[cfg(feature = "interrupts")]
+#[doc(hidden)]
+pub use macros::irq_marker;
+#[cfg(feature = "test-harness")]
+pub use macros::picorv32_test;
+pub use macros::{entry, in_region, no_init, pre_init, ram_func};
 use picorv32::asm;
 
+#[cfg(feature = "background")]
+pub mod background;
+#[cfg(feature = "backtrace")]
+pub mod backtrace;
+#[cfg(feature = "fault-blink")]
+pub mod blink;
+#[cfg(feature = "bootloader")]
+pub mod bootloader;
+#[cfg(feature = "build-helper")]
+pub mod build;
+#[cfg(feature = "console")]
+pub mod console;
+#[cfg(feature = "context-switch")]
+pub mod context;
+pub mod crash;
+pub mod crc;
+pub mod decode;
+#[cfg(feature = "deferred")]
+pub mod deferred;
+#[cfg(interrupt_table)]
+pub mod device;
+#[cfg(feature = "embassy")]
+pub mod embassy;
+#[cfg(any(feature = "emulate-m", feature = "emulate-unaligned"))]
+mod emulate;
+#[cfg(feature = "event-log")]
+pub mod event_log;
+#[cfg(feature = "event-queue")]
+pub mod event_queue;
+#[cfg(feature = "interrupts")]
+pub mod exception;
+#[cfg(feature = "executor")]
+pub mod executor;
+pub mod flash;
+#[cfg(feature = "flash-config")]
+pub mod flash_config;
+#[cfg(feature = "gdbstub")]
+pub mod gdbstub;
+pub mod heap;
+#[cfg(feature = "hil")]
+pub mod hil;
+#[cfg(feature = "host-tools")]
+pub mod host;
+#[cfg(feature = "hostcall")]
+pub mod hostcall;
+pub mod image;
+pub mod integrity;
+pub mod interrupt;
+#[cfg(feature = "irq-stats")]
+pub mod irq_stats;
+#[cfg(feature = "malloc-shims")]
+pub mod malloc;
+pub mod memory;
+#[cfg(feature = "monotonic")]
+pub mod monotonic;
+#[cfg(feature = "panic-handler")]
+pub mod panic_handler;
+#[cfg(feature = "panic-persist")]
+pub mod panic_persist;
+#[cfg(feature = "priority")]
+pub mod priority;
+#[cfg(feature = "protect")]
+pub mod protect;
+#[cfg(feature = "recover")]
+pub mod recover;
+#[cfg(feature = "irq-replay")]
+pub mod replay;
+#[cfg(feature = "riscv-rt-compat")]
+pub mod riscv_rt;
+pub mod rle;
+pub mod runtime;
+#[cfg(feature = "scheduler")]
+pub mod scheduler;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(any(feature = "stack-canary", feature = "stack-paint"))]
+pub mod stack;
+#[cfg(feature = "syscalls")]
+pub mod syscall;
+#[cfg(feature = "test-harness")]
+pub mod test;
+#[cfg(feature = "interrupts")]
+pub mod timer;
+#[cfg(feature = "timers")]
+pub mod timers;
+
 extern "C" {
     // Boundaries of the .bss section
     static mut _ebss: u32;
@@ -218,38 +785,182 @@ extern "C" {
     // Initial values of the .data section (stored in Flash)
     static _sidata: u32;
 
+    // Boundaries of the sections in the second RAM region and the initial values of .data_RAM2
+    static mut _sbss_ram2: u32;
+    static mut _ebss_ram2: u32;
+    static mut _sdata_ram2: u32;
+    static mut _edata_ram2: u32;
+    static _sidata_ram2: u32;
+
+    // Boundaries of the .ramfunc section and its load image in Flash
+    static mut _sramfunc: u32;
+    static mut _eramfunc: u32;
+    static _siramfunc: u32;
+
+    // Static constructors, see `run_constructors`
+    static __preinit_array_start: unsafe extern "C" fn();
+    static __preinit_array_end: unsafe extern "C" fn();
+    static __init_array_start: unsafe extern "C" fn();
+    static __init_array_end: unsafe extern "C" fn();
+
     // Address of _start_trap
     #[cfg(feature = "interrupts")]
     static _start_trap: u32;
 }
 
+/// Memory regions `#[in_region]` can place statics in, a marker type for each enabled one
+#[doc(hidden)]
+pub mod region {
+    /// `RAM2`, with the `ram2` feature
+    #[cfg(feature = "ram2")]
+    pub struct RAM2;
+}
+
 /// Rust entry point (_start_rust)
 ///
 /// Zeros bss section, initializes data section and calls main. This function
 /// never returns.
+///
+/// # Safety
+///
+/// Only `_start` calls it, once, with the stack set up; calling it again reinitializes RAM
+/// from under live references.
 #[link_section = ".init.rust"]
 #[export_name = "_start_rust"]
 pub unsafe extern "C" fn start_rust() -> ! {
     extern "Rust" {
-        // This symbol will be provided by the user via `#[entry]`
-        fn main() -> !;
+        // This symbol will be provided by the user via `#[entry]`, it only returns if it's
+        // declared as `fn main()`
+        fn main();
 
         // This symbol will be provided by the user via `#[pre_init]`
         fn __pre_init();
+
+        // This symbol can be provided by the user, otherwise it defaults to a no-op
+        fn __memory_setup();
+
+        // This symbol can be provided by the user, otherwise it defaults to a no-op
+        fn __on_main_exit();
+    }
+    extern "C" {
+        // Defined by link.x, a stale copy of it defines a different version, see `link_x_version`
+        // in build.rs
+        static __picorv32_rt_link_x_v1: u8;
     }
 
+    // referenced so that linking with a mismatching link.x fails
+    ptr::read_volatile(&(&__picorv32_rt_link_x_v1 as *const u8));
+
+    runtime::beacon(runtime::BootStage::PreInit);
     __pre_init();
+    __memory_setup();
+
+    #[cfg(feature = "stack-paint")]
+    stack::paint();
+    #[cfg(feature = "stack-canary")]
+    stack::place_canary();
+
+    #[cfg(feature = "integrity-check")]
+    integrity::check();
+
+    runtime::beacon(runtime::BootStage::RamInit);
+    // with `riscv-rt-compat` `_mp_hook` can opt out, like a secondary hart with riscv-rt
+    #[cfg(feature = "riscv-rt-compat")]
+    let init_ram = riscv_rt::mp_hook();
+    #[cfg(not(feature = "riscv-rt-compat"))]
+    let init_ram = true;
+    if init_ram {
+        r0::zero_bss(ptr::addr_of_mut!(_sbss), ptr::addr_of_mut!(_ebss));
+        r0::zero_bss(ptr::addr_of_mut!(_sbss_ram2), ptr::addr_of_mut!(_ebss_ram2));
+        // with `ram-only` the initial values were loaded in place along with the code
+        #[cfg(not(feature = "ram-only"))]
+        r0::init_data(
+            ptr::addr_of_mut!(_sramfunc),
+            ptr::addr_of_mut!(_eramfunc),
+            ptr::addr_of!(_siramfunc),
+        );
+        #[cfg(not(feature = "ram-only"))]
+        r0::init_data(
+            ptr::addr_of_mut!(_sdata_ram2),
+            ptr::addr_of_mut!(_edata_ram2),
+            ptr::addr_of!(_sidata_ram2),
+        );
+        #[cfg(not(any(feature = "compressed-data", feature = "ram-only")))]
+        r0::init_data(
+            ptr::addr_of_mut!(_sdata),
+            ptr::addr_of_mut!(_edata),
+            ptr::addr_of!(_sidata),
+        );
+        #[cfg(feature = "compressed-data")]
+        {
+            // decoded until `.data` is full, the encoded image has no length of its own
+            let src = &_sidata as *const u32 as *const u8;
+            let dst = ptr::addr_of_mut!(_sdata) as *mut u8;
+            let len = &_edata as *const u32 as usize - dst as usize;
+            rle::decode(
+                (0..).map(|i| ptr::read(src.add(i))),
+                core::slice::from_raw_parts_mut(dst, len),
+            );
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    heap::init();
+
+    runtime::detect_boot_cause();
+    runtime::configure();
 
-    r0::zero_bss(&mut _sbss, &mut _ebss);
-    r0::init_data(&mut _sdata, &mut _edata, &_sidata);
+    run_constructors(&__preinit_array_start, &__preinit_array_end);
+    run_constructors(&__init_array_start, &__init_array_end);
+
+    #[cfg(feature = "riscv-rt-compat")]
+    riscv_rt::setup_interrupts();
 
     #[cfg(feature = "interrupts")]
-    picorv32::interrupt::enable();
+    {
+        extern "C" {
+            // 1 if `main` takes a `CriticalSection`, defined by `#[entry]`
+            static __picorv32_rt_main_masks_irqs: u8;
+        }
+
+        if ptr::read_volatile(&__picorv32_rt_main_masks_irqs) == 0 {
+            picorv32::interrupt::enable();
+        }
+    }
 
+    runtime::beacon(runtime::BootStage::Main);
     main();
+
+    __on_main_exit();
+    loop {
+        #[cfg(feature = "interrupts")]
+        wfi();
+        #[cfg(not(feature = "interrupts"))]
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+/// Calls the functions in `[start, end)`, an array of static constructors
+unsafe fn run_constructors(
+    start: *const unsafe extern "C" fn(),
+    end: *const unsafe extern "C" fn(),
+) {
+    let mut constructor = start;
+    while constructor < end {
+        (*constructor)();
+        constructor = constructor.add(1);
+    }
 }
 
 /// A block of registers saved for the duration of handling an interrupt
+///
+/// With the `full-trap-frame` feature the callee-saved registers `s0`..`s11` are saved as well,
+/// at the cost of a few extra cycles on every trap entry and exit.
+///
+/// With `interrupts-qregs` `x1`/`x2` live in `q2`/`q3` while the trap is handled, and with
+/// `interrupts-qregs-spill` `x10` lives in `q1` as well, once the pending IRQ mask has been read
+/// from it. `q0` holds the address `retirq` returns to, so it can't be used. The accessors hide the
+/// difference, but the frame is only valid during the trap it was passed to.
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct PicoRV32StoredRegisters {
@@ -261,6 +972,7 @@ pub struct PicoRV32StoredRegisters {
     x5: u32,
     x6: u32,
     x7: u32,
+    #[cfg(not(feature = "interrupts-qregs-spill"))]
     x10: u32,
     x11: u32,
     x12: u32,
@@ -273,9 +985,199 @@ pub struct PicoRV32StoredRegisters {
     x29: u32,
     x30: u32,
     x31: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x8: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x9: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x18: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x19: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x20: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x21: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x22: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x23: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x24: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x25: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x26: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x27: u32,
 }
 
 impl PicoRV32StoredRegisters {
+    /// `q0` (return address of the trap, LSB is set if the interrupted instruction is compressed)
+    #[inline]
+    #[cfg(feature = "interrupts-qregs")]
+    pub fn q0(&self) -> u32 {
+        unsafe { picorv32::asm::getq0() }
+    }
+
+    /// `q0` (return address of the trap, LSB is set if the interrupted instruction is compressed)
+    ///
+    /// Without q registers the core stores it in `x3`.
+    #[inline]
+    #[cfg(not(feature = "interrupts-qregs"))]
+    pub fn q0(&self) -> u32 {
+        self.x3
+    }
+
+    /// Sets the address `retirq` returns to
+    ///
+    /// # Safety
+    ///
+    /// The interrupted code continues at `addr` with the register state from this frame.
+    #[inline]
+    #[cfg(feature = "interrupts-qregs")]
+    pub unsafe fn set_q0(&mut self, addr: u32) {
+        picorv32::asm::setq0(addr)
+    }
+
+    /// Sets the address `retirq` returns to
+    ///
+    /// # Safety
+    ///
+    /// The interrupted code continues at `addr` with the register state from this frame.
+    #[inline]
+    #[cfg(not(feature = "interrupts-qregs"))]
+    pub unsafe fn set_q0(&mut self, addr: u32) {
+        self.x3 = addr;
+    }
+
+    /// Register `x<n>` of the interrupted code, `None` if it isn't part of the frame
+    ///
+    /// `x4`/`tp` is never saved, `x3`/`gp` only with `interrupts-qregs` (otherwise the core uses it
+    /// for `q0`) and `s0`..`s11` only with `full-trap-frame`.
+    pub fn reg(&self, n: u32) -> Option<u32> {
+        Some(match n {
+            0 => 0,
+            1 => self.x1(),
+            2 => self.x2(),
+            #[cfg(feature = "interrupts-qregs")]
+            3 => self.x3,
+            5 => self.x5,
+            6 => self.x6,
+            7 => self.x7,
+            #[cfg(feature = "full-trap-frame")]
+            8 => self.x8,
+            #[cfg(feature = "full-trap-frame")]
+            9 => self.x9,
+            10 => self.x10(),
+            11 => self.x11,
+            12 => self.x12,
+            13 => self.x13,
+            14 => self.x14,
+            15 => self.x15,
+            16 => self.x16,
+            17 => self.x17,
+            #[cfg(feature = "full-trap-frame")]
+            18 => self.x18,
+            #[cfg(feature = "full-trap-frame")]
+            19 => self.x19,
+            #[cfg(feature = "full-trap-frame")]
+            20 => self.x20,
+            #[cfg(feature = "full-trap-frame")]
+            21 => self.x21,
+            #[cfg(feature = "full-trap-frame")]
+            22 => self.x22,
+            #[cfg(feature = "full-trap-frame")]
+            23 => self.x23,
+            #[cfg(feature = "full-trap-frame")]
+            24 => self.x24,
+            #[cfg(feature = "full-trap-frame")]
+            25 => self.x25,
+            #[cfg(feature = "full-trap-frame")]
+            26 => self.x26,
+            #[cfg(feature = "full-trap-frame")]
+            27 => self.x27,
+            28 => self.x28,
+            29 => self.x29,
+            30 => self.x30,
+            31 => self.x31,
+            _ => return None,
+        })
+    }
+
+    /// Sets register `x<n>`, which is restored when returning from the trap
+    ///
+    /// Returns `false` if the register isn't part of the frame, see [`reg`](#method.reg). Writes
+    /// to `x0` are ignored.
+    pub fn set_reg(&mut self, n: u32, value: u32) -> bool {
+        let slot = match n {
+            0 => return true,
+            #[cfg(feature = "interrupts-qregs")]
+            1 => {
+                unsafe { picorv32::asm::setq2(value) };
+                return true;
+            }
+            #[cfg(feature = "interrupts-qregs")]
+            2 => {
+                unsafe { picorv32::asm::setq3(value) };
+                return true;
+            }
+            #[cfg(not(feature = "interrupts-qregs"))]
+            1 => &mut self.x1,
+            #[cfg(not(feature = "interrupts-qregs"))]
+            2 => &mut self.x2,
+            #[cfg(feature = "interrupts-qregs")]
+            3 => &mut self.x3,
+            5 => &mut self.x5,
+            6 => &mut self.x6,
+            7 => &mut self.x7,
+            #[cfg(feature = "full-trap-frame")]
+            8 => &mut self.x8,
+            #[cfg(feature = "full-trap-frame")]
+            9 => &mut self.x9,
+            #[cfg(feature = "interrupts-qregs-spill")]
+            10 => {
+                unsafe { picorv32::asm::setq1(value) };
+                return true;
+            }
+            #[cfg(not(feature = "interrupts-qregs-spill"))]
+            10 => &mut self.x10,
+            11 => &mut self.x11,
+            12 => &mut self.x12,
+            13 => &mut self.x13,
+            14 => &mut self.x14,
+            15 => &mut self.x15,
+            16 => &mut self.x16,
+            17 => &mut self.x17,
+            #[cfg(feature = "full-trap-frame")]
+            18 => &mut self.x18,
+            #[cfg(feature = "full-trap-frame")]
+            19 => &mut self.x19,
+            #[cfg(feature = "full-trap-frame")]
+            20 => &mut self.x20,
+            #[cfg(feature = "full-trap-frame")]
+            21 => &mut self.x21,
+            #[cfg(feature = "full-trap-frame")]
+            22 => &mut self.x22,
+            #[cfg(feature = "full-trap-frame")]
+            23 => &mut self.x23,
+            #[cfg(feature = "full-trap-frame")]
+            24 => &mut self.x24,
+            #[cfg(feature = "full-trap-frame")]
+            25 => &mut self.x25,
+            #[cfg(feature = "full-trap-frame")]
+            26 => &mut self.x26,
+            #[cfg(feature = "full-trap-frame")]
+            27 => &mut self.x27,
+            28 => &mut self.x28,
+            29 => &mut self.x29,
+            30 => &mut self.x30,
+            31 => &mut self.x31,
+            _ => return false,
+        };
+        *slot = value;
+        true
+    }
+
     /// `x1`/`ra` (return address, saved by caller)
     #[inline]
     #[cfg(feature = "interrupts-qregs")]
@@ -330,21 +1232,46 @@ impl PicoRV32StoredRegisters {
 
     /// `x10`/`a0` (a0, saved by caller)
     #[inline]
-    #[cfg(not(feature = "interrupts-qregs"))]
+    #[cfg(feature = "interrupts-qregs-spill")]
+    pub fn x10(&self) -> u32 {
+        unsafe { picorv32::asm::getq1() }
+    }
+
+    /// `x10`/`a0` (a0, saved by caller)
+    #[inline]
+    #[cfg(not(feature = "interrupts-qregs-spill"))]
     pub fn x10(&self) -> u32 {
         self.x10
     }
 
-    /// `x11`/`a1` (a1, saved by caller)
+    /// Sets `x10`/`a0`, which is restored when returning from the trap
     #[inline]
-    #[cfg(not(feature = "interrupts-qregs"))]
-    pub fn x11(&self) -> u32 {
-        self.x11
+    #[cfg(feature = "interrupts-qregs-spill")]
+    pub fn set_x10(&mut self, value: u32) {
+        unsafe { picorv32::asm::setq1(value) }
     }
 
-    /// `x12`/`a2` (a2, saved by caller)
+    /// Sets `x10`/`a0`, which is restored when returning from the trap
+    #[inline]
+    #[cfg(not(feature = "interrupts-qregs-spill"))]
+    pub fn set_x10(&mut self, value: u32) {
+        self.x10 = value;
+    }
+
+    /// `x11`/`a1` (a1, saved by caller)
+    #[inline]
+    pub fn x11(&self) -> u32 {
+        self.x11
+    }
+
+    /// Sets `x11`/`a1`, which is restored when returning from the trap
+    #[inline]
+    pub fn set_x11(&mut self, value: u32) {
+        self.x11 = value;
+    }
+
+    /// `x12`/`a2` (a2, saved by caller)
     #[inline]
-    #[cfg(not(feature = "interrupts-qregs"))]
     pub fn x12(&self) -> u32 {
         self.x12
     }
@@ -402,6 +1329,90 @@ impl PicoRV32StoredRegisters {
     pub fn x31(&self) -> u32 {
         self.x31
     }
+
+    /// `x8`/`s0` (s0/fp, saved by callee)
+    #[inline]
+    #[cfg(feature = "full-trap-frame")]
+    pub fn x8(&self) -> u32 {
+        self.x8
+    }
+
+    /// `x9`/`s1` (s1, saved by callee)
+    #[inline]
+    #[cfg(feature = "full-trap-frame")]
+    pub fn x9(&self) -> u32 {
+        self.x9
+    }
+
+    /// `x18`/`s2` (s2, saved by callee)
+    #[inline]
+    #[cfg(feature = "full-trap-frame")]
+    pub fn x18(&self) -> u32 {
+        self.x18
+    }
+
+    /// `x19`/`s3` (s3, saved by callee)
+    #[inline]
+    #[cfg(feature = "full-trap-frame")]
+    pub fn x19(&self) -> u32 {
+        self.x19
+    }
+
+    /// `x20`/`s4` (s4, saved by callee)
+    #[inline]
+    #[cfg(feature = "full-trap-frame")]
+    pub fn x20(&self) -> u32 {
+        self.x20
+    }
+
+    /// `x21`/`s5` (s5, saved by callee)
+    #[inline]
+    #[cfg(feature = "full-trap-frame")]
+    pub fn x21(&self) -> u32 {
+        self.x21
+    }
+
+    /// `x22`/`s6` (s6, saved by callee)
+    #[inline]
+    #[cfg(feature = "full-trap-frame")]
+    pub fn x22(&self) -> u32 {
+        self.x22
+    }
+
+    /// `x23`/`s7` (s7, saved by callee)
+    #[inline]
+    #[cfg(feature = "full-trap-frame")]
+    pub fn x23(&self) -> u32 {
+        self.x23
+    }
+
+    /// `x24`/`s8` (s8, saved by callee)
+    #[inline]
+    #[cfg(feature = "full-trap-frame")]
+    pub fn x24(&self) -> u32 {
+        self.x24
+    }
+
+    /// `x25`/`s9` (s9, saved by callee)
+    #[inline]
+    #[cfg(feature = "full-trap-frame")]
+    pub fn x25(&self) -> u32 {
+        self.x25
+    }
+
+    /// `x26`/`s10` (s10, saved by callee)
+    #[inline]
+    #[cfg(feature = "full-trap-frame")]
+    pub fn x26(&self) -> u32 {
+        self.x26
+    }
+
+    /// `x27`/`s11` (s11, saved by callee)
+    #[inline]
+    #[cfg(feature = "full-trap-frame")]
+    pub fn x27(&self) -> u32 {
+        self.x27
+    }
 }
 
 impl fmt::Debug for PicoRV32StoredRegisters {
@@ -419,7 +1430,7 @@ impl fmt::Debug for PicoRV32StoredRegisters {
             if long_instr {
                 let instr2 =
                     *(unsafe { NonNull::new_unchecked((pc + 2) as *mut u16).as_ref() }) as u32;
-                instr = instr | instr2 << 16;
+                instr |= instr2 << 16;
             }
             (instr, long_instr)
         };
@@ -431,39 +1442,198 @@ impl fmt::Debug for PicoRV32StoredRegisters {
             writeln!(f, "{:04x}", instr)?;
         }
 
-        writeln!(f, "SP: {:08x}\tGP: {:08x}", self.x2(), self.x3())?;
+        self.write_registers(f)
+    }
+}
+
+/// Capacity of a [`FrameString`](struct.FrameString.html) in bytes
+const FRAME_STRING_CAPACITY: usize = 512;
+
+impl PicoRV32StoredRegisters {
+    /// Writes the return address and the saved registers to `w`
+    ///
+    /// Unlike the `Debug` output this doesn't read the interrupted instruction from memory, so
+    /// it's safe to use on a frame with a bogus return address, e.g. in a fault handler.
+    pub fn write_to<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        writeln!(w, "PC: {:08x}\tRA: {:08x}", self.q0() & !1, self.x1())?;
+        self.write_registers(w)
+    }
+
+    /// Formats the frame like [`write_to`](#method.write_to) into a buffer on the stack, for
+    /// fault paths without a console
+    pub fn to_array_string(&self) -> FrameString {
+        let mut s = FrameString {
+            buf: [0; FRAME_STRING_CAPACITY],
+            len: 0,
+        };
+        // cut short if it doesn't fit, which doesn't happen with the current format
+        let _ = self.write_to(&mut s);
+        s
+    }
+
+    fn write_registers<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        write_registers(w, &self.registers(), cfg!(feature = "full-trap-frame"))
+    }
+
+    /// The saved registers indexed by register number, 0 for the ones the frame doesn't have
+    fn registers(&self) -> [u32; 32] {
+        let mut x = [0; 32];
+        x[1] = self.x1();
+        x[2] = self.x2();
+        x[3] = self.x3();
+        x[5] = self.x5();
+        x[6] = self.x6();
+        x[7] = self.x7();
+        x[10] = self.x10();
+        x[11] = self.x11();
+        x[12] = self.x12();
+        x[13] = self.x13();
+        x[14] = self.x14();
+        x[15] = self.x15();
+        x[16] = self.x16();
+        x[17] = self.x17();
+        x[28] = self.x28();
+        x[29] = self.x29();
+        x[30] = self.x30();
+        x[31] = self.x31();
+        #[cfg(feature = "full-trap-frame")]
+        {
+            x[8] = self.x8();
+            x[9] = self.x9();
+            x[18] = self.x18();
+            x[19] = self.x19();
+            x[20] = self.x20();
+            x[21] = self.x21();
+            x[22] = self.x22();
+            x[23] = self.x23();
+            x[24] = self.x24();
+            x[25] = self.x25();
+            x[26] = self.x26();
+            x[27] = self.x27();
+        }
+        x
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for PicoRV32StoredRegisters {
+    fn format(&self, f: defmt::Formatter) {
+        format_registers(f, &self.registers(), cfg!(feature = "full-trap-frame"))
+    }
+}
+
+/// Writes the registers of `x`, indexed by register number, in the format of the trap frame dumps,
+/// the callee-saved ones only if `callee_saved`
+pub(crate) fn write_registers<W: fmt::Write>(
+    w: &mut W,
+    x: &[u32; 32],
+    callee_saved: bool,
+) -> fmt::Result {
+    writeln!(w, "SP: {:08x}\tGP: {:08x}", x[2], x[3])?;
+    writeln!(w, "T0: {:08x}\tT1: {:08x}\tT2: {:08x}", x[5], x[6], x[7])?;
+    writeln!(
+        w,
+        "A0: {:08x}\tA1: {:08x}\tA2: {:08x}\tA3: {:08x}",
+        x[10], x[11], x[12], x[13]
+    )?;
+    writeln!(
+        w,
+        "A4: {:08x}\tA5: {:08x}\tA6: {:08x}\tA7: {:08x}",
+        x[14], x[15], x[16], x[17]
+    )?;
+    writeln!(
+        w,
+        "T3: {:08x}\tT4: {:08x}\tT5: {:08x}\tT6: {:08x}",
+        x[28], x[29], x[30], x[31]
+    )?;
+    if callee_saved {
         writeln!(
-            f,
-            "T0: {:08x}\tT1: {:08x}\tT2: {:08x}",
-            self.x5(),
-            self.x6(),
-            self.x7()
+            w,
+            "S0: {:08x}\tS1: {:08x}\tS2: {:08x}\tS3: {:08x}",
+            x[8], x[9], x[18], x[19]
         )?;
         writeln!(
-            f,
-            "A0: {:08x}\tA1: {:08x}\tA2: {:08x}\tA3: {:08x}",
-            self.x10(),
-            self.x11(),
-            self.x12(),
-            self.x13()
+            w,
+            "S4: {:08x}\tS5: {:08x}\tS6: {:08x}\tS7: {:08x}",
+            x[20], x[21], x[22], x[23]
         )?;
         writeln!(
-            f,
-            "A4: {:08x}\tA5: {:08x}\tA6: {:08x}\tA7: {:08x}",
-            self.x14(),
-            self.x15(),
-            self.x16(),
-            self.x17()
+            w,
+            "S8: {:08x}\tS9: {:08x}\tS10: {:08x}\tS11: {:08x}",
+            x[24], x[25], x[26], x[27]
         )?;
-        writeln!(
+    }
+    Ok(())
+}
+
+/// Formats the registers of `x` like [`write_registers`](fn.write_registers.html) for defmt
+#[cfg(feature = "defmt")]
+fn format_registers(f: defmt::Formatter, x: &[u32; 32], callee_saved: bool) {
+    if callee_saved {
+        defmt::write!(
             f,
-            "T3: {:08x}\tT4: {:08x}\tT5: {:08x}\tT6: {:08x}",
-            self.x28(),
-            self.x29(),
-            self.x30(),
-            self.x31()
-        )?;
-        Ok(())
+            "RA: {=u32:08x} SP: {=u32:08x} GP: {=u32:08x} T0: {=u32:08x} T1: {=u32:08x} T2: {=u32:08x} A0: {=u32:08x} A1: {=u32:08x} A2: {=u32:08x} A3: {=u32:08x} A4: {=u32:08x} A5: {=u32:08x} A6: {=u32:08x} A7: {=u32:08x} T3: {=u32:08x} T4: {=u32:08x} T5: {=u32:08x} T6: {=u32:08x} S0: {=u32:08x} S1: {=u32:08x} S2: {=u32:08x} S3: {=u32:08x} S4: {=u32:08x} S5: {=u32:08x} S6: {=u32:08x} S7: {=u32:08x} S8: {=u32:08x} S9: {=u32:08x} S10: {=u32:08x} S11: {=u32:08x}",
+            x[1], x[2], x[3], x[5], x[6], x[7], x[10], x[11], x[12], x[13], x[14], x[15], x[16],
+            x[17], x[28], x[29], x[30], x[31], x[8], x[9], x[18], x[19], x[20], x[21], x[22],
+            x[23], x[24], x[25], x[26], x[27]
+        )
+    } else {
+        defmt::write!(
+            f,
+            "RA: {=u32:08x} SP: {=u32:08x} GP: {=u32:08x} T0: {=u32:08x} T1: {=u32:08x} T2: {=u32:08x} A0: {=u32:08x} A1: {=u32:08x} A2: {=u32:08x} A3: {=u32:08x} A4: {=u32:08x} A5: {=u32:08x} A6: {=u32:08x} A7: {=u32:08x} T3: {=u32:08x} T4: {=u32:08x} T5: {=u32:08x} T6: {=u32:08x}",
+            x[1], x[2], x[3], x[5], x[6], x[7], x[10], x[11], x[12], x[13], x[14], x[15], x[16],
+            x[17], x[28], x[29], x[30], x[31]
+        )
+    }
+}
+
+/// Text of a trap frame, returned by
+/// [`PicoRV32StoredRegisters::to_array_string`](struct.PicoRV32StoredRegisters.html#method.to_array_string)
+#[derive(Copy, Clone)]
+pub struct FrameString {
+    buf: [u8; FRAME_STRING_CAPACITY],
+    len: usize,
+}
+
+impl FrameString {
+    /// The text
+    pub fn as_str(&self) -> &str {
+        // only whole characters are copied in
+        unsafe { core::str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+
+    /// The text as bytes, e.g. for copying it into a persistent buffer
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl fmt::Write for FrameString {
+    /// Appends as much of `s` as fits, fails if it had to be cut short
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut n = s.len().min(FRAME_STRING_CAPACITY - self.len);
+        while !s.is_char_boundary(n) {
+            n -= 1;
+        }
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        if n == s.len() {
+            Ok(())
+        } else {
+            Err(fmt::Error)
+        }
+    }
+}
+
+impl fmt::Display for FrameString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for FrameString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
     }
 }
 
@@ -489,6 +1659,79 @@ pub struct PicoRV32AllStoredRegisters {
     x29: u32,
     x30: u32,
     x31: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x8: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x9: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x18: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x19: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x20: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x21: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x22: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x23: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x24: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x25: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x26: u32,
+    #[cfg(feature = "full-trap-frame")]
+    x27: u32,
+}
+
+impl PicoRV32AllStoredRegisters {
+    /// The registers indexed by register number, 0 for the ones the frame doesn't have
+    #[cfg(feature = "defmt")]
+    fn registers(&self) -> [u32; 32] {
+        let mut x = [0; 32];
+        x[1] = self.x1;
+        x[2] = self.x2;
+        x[3] = self.x3;
+        x[5] = self.x5;
+        x[6] = self.x6;
+        x[7] = self.x7;
+        x[10] = self.x10;
+        x[11] = self.x11;
+        x[12] = self.x12;
+        x[13] = self.x13;
+        x[14] = self.x14;
+        x[15] = self.x15;
+        x[16] = self.x16;
+        x[17] = self.x17;
+        x[28] = self.x28;
+        x[29] = self.x29;
+        x[30] = self.x30;
+        x[31] = self.x31;
+        #[cfg(feature = "full-trap-frame")]
+        {
+            x[8] = self.x8;
+            x[9] = self.x9;
+            x[18] = self.x18;
+            x[19] = self.x19;
+            x[20] = self.x20;
+            x[21] = self.x21;
+            x[22] = self.x22;
+            x[23] = self.x23;
+            x[24] = self.x24;
+            x[25] = self.x25;
+            x[26] = self.x26;
+            x[27] = self.x27;
+        }
+        x
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for PicoRV32AllStoredRegisters {
+    fn format(&self, f: defmt::Formatter) {
+        format_registers(f, &self.registers(), cfg!(feature = "full-trap-frame"))
+    }
 }
 
 impl From<PicoRV32StoredRegisters> for PicoRV32AllStoredRegisters {
@@ -513,6 +1756,30 @@ impl From<PicoRV32StoredRegisters> for PicoRV32AllStoredRegisters {
                 x29: r.x29(),
                 x30: r.x30(),
                 x31: r.x31(),
+                #[cfg(feature = "full-trap-frame")]
+                x8: r.x8(),
+                #[cfg(feature = "full-trap-frame")]
+                x9: r.x9(),
+                #[cfg(feature = "full-trap-frame")]
+                x18: r.x18(),
+                #[cfg(feature = "full-trap-frame")]
+                x19: r.x19(),
+                #[cfg(feature = "full-trap-frame")]
+                x20: r.x20(),
+                #[cfg(feature = "full-trap-frame")]
+                x21: r.x21(),
+                #[cfg(feature = "full-trap-frame")]
+                x22: r.x22(),
+                #[cfg(feature = "full-trap-frame")]
+                x23: r.x23(),
+                #[cfg(feature = "full-trap-frame")]
+                x24: r.x24(),
+                #[cfg(feature = "full-trap-frame")]
+                x25: r.x25(),
+                #[cfg(feature = "full-trap-frame")]
+                x26: r.x26(),
+                #[cfg(feature = "full-trap-frame")]
+                x27: r.x27(),
             }
         } else {
             unsafe { core::mem::transmute_copy(&r) }
@@ -520,38 +1787,306 @@ impl From<PicoRV32StoredRegisters> for PicoRV32AllStoredRegisters {
     }
 }
 
+// Cycles since reset, printed with every defmt log frame
+#[cfg(feature = "defmt")]
+defmt::timestamp!("{=u64}", {
+    extern "C" {
+        fn __picorv32_rt_rdcycle64() -> u64;
+    }
+
+    unsafe { __picorv32_rt_rdcycle64() }
+});
+
 /// Trap entry point rust (_start_trap_rust)
 ///
-/// `irqs` is a bitmask off IRQs to handle
+/// `irqs` is a bitmask off IRQs to handle, returns the frame the trap returns through
 #[link_section = ".trap.rust"]
 #[export_name = "_start_trap_rust"]
-pub extern "C" fn start_trap_rust(regs: *const u32, irqs: u32) {
+pub extern "C" fn start_trap_rust(regs: *mut u32, irqs: u32) -> *mut u32 {
     extern "C" {
-        fn trap_handler(regs: &PicoRV32StoredRegisters, irqs: u32);
+        fn trap_handler(regs: &mut PicoRV32StoredRegisters, irqs: u32);
+    }
+    extern "Rust" {
+        // These symbols can be provided by the user, otherwise they default to a no-op
+        fn __trap_enter(irqs: u32);
+        fn __trap_exit(irqs: u32);
     }
 
-    unsafe {
-        // dispatch trap to handler
-        trap_handler(
-            NonNull::new_unchecked(regs as *mut PicoRV32StoredRegisters).as_ref(),
-            irqs,
-        );
+    let regs = unsafe { NonNull::new_unchecked(regs as *mut PicoRV32StoredRegisters).as_mut() };
+
+    #[cfg(any(feature = "embassy", feature = "priority"))]
+    exception::enter();
+
+    #[cfg(feature = "irq-replay")]
+    replay::record(irqs);
+
+    let pending_irqs = irqs;
+    unsafe { __trap_enter(pending_irqs) };
+
+    #[cfg(feature = "timers")]
+    let irqs = if irqs & (1 << timers::IRQ) != 0 {
+        timers::handle();
+        irqs & !(1 << timers::IRQ)
+    } else {
+        irqs
+    };
+
+    #[cfg(feature = "embassy")]
+    let irqs = if irqs & (1 << timer::IRQ) != 0 {
+        embassy::handle_timer();
+        irqs & !(1 << timer::IRQ)
+    } else {
+        irqs
+    };
+
+    #[cfg(all(
+        feature = "interrupts",
+        not(any(feature = "timers", feature = "embassy"))
+    ))]
+    runtime::tick(irqs);
+
+    // give the specific exception handlers a chance first
+    #[cfg(feature = "interrupts")]
+    let irqs = if irqs & (1 << exception::IRQ) != 0 && exception::handle(regs) {
+        irqs & !(1 << exception::IRQ)
+    } else {
+        irqs
+    };
+
+    #[cfg(feature = "protect")]
+    let irqs = if irqs & (1 << exception::BUS_ERROR_IRQ) != 0 && protect::handle(regs) {
+        irqs & !(1 << exception::BUS_ERROR_IRQ)
+    } else {
+        irqs
+    };
+
+    #[cfg(feature = "emulate-unaligned")]
+    let irqs = if irqs & (1 << exception::BUS_ERROR_IRQ) != 0 && emulate::unaligned(regs) {
+        irqs & !(1 << exception::BUS_ERROR_IRQ)
+    } else {
+        irqs
+    };
+
+    #[cfg(feature = "executor")]
+    let irqs = executor::wake(irqs);
+
+    if irqs != 0 {
+        unsafe {
+            // dispatch trap to handler
+            trap_handler(regs, irqs);
+        }
     }
+
+    #[cfg(feature = "priority")]
+    priority::drain(regs);
+
+    #[cfg(feature = "embassy")]
+    embassy::poll();
+
+    unsafe { __trap_exit(pending_irqs) };
+
+    #[cfg(feature = "stack-canary")]
+    stack::check_stack_canary();
+
+    #[cfg(any(feature = "embassy", feature = "priority"))]
+    exception::exit();
+
+    let frame = regs as *mut PicoRV32StoredRegisters as *mut u32;
+    #[cfg(feature = "context-switch")]
+    let frame = context::resume(frame);
+    frame
 }
 
 /// Default Trap Handler
+#[cfg(not(interrupt_table))]
 #[no_mangle]
 pub fn default_trap_handler(_irqs: u32) {}
 
+/// Default Trap Handler, dispatching through the interrupt table of the device crate
+#[cfg(all(feature = "device", not(feature = "riscv-rt-compat")))]
+#[no_mangle]
+pub extern "C" fn default_trap_handler(_regs: &mut PicoRV32StoredRegisters, irqs: u32) {
+    device::dispatch_all(irqs)
+}
+
+/// Default Trap Handler, dispatching to the riscv-rt handlers
+#[cfg(feature = "riscv-rt-compat")]
+#[no_mangle]
+pub extern "C" fn default_trap_handler(regs: &mut PicoRV32StoredRegisters, irqs: u32) {
+    riscv_rt::dispatch(regs, irqs)
+}
+
+#[cfg(interrupt_table)]
+#[doc(hidden)]
+#[no_mangle]
+pub extern "C" fn default_handler() {
+    loop {
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+#[cfg(feature = "riscv-rt-compat")]
+#[doc(hidden)]
+#[no_mangle]
+pub extern "C" fn default_riscv_rt_exception_handler(_trap_frame: &riscv_rt::TrapFrame) {
+    loop {
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+#[cfg(feature = "riscv-rt-compat")]
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_mp_hook(_hartid: usize) -> bool {
+    true
+}
+
+#[cfg(feature = "riscv-rt-compat")]
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_setup_interrupts() {}
+
 #[doc(hidden)]
 #[no_mangle]
 pub unsafe fn default_pre_init() {}
 
+#[doc(hidden)]
+#[no_mangle]
+pub unsafe fn default_memory_setup() {}
+
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_park_flush() {}
+
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_on_main_exit() {}
+
+#[cfg(feature = "stack-canary")]
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_stack_overflow() -> ! {
+    runtime::fatal()
+}
+
+#[cfg(feature = "integrity-check")]
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_integrity_failure() -> ! {
+    loop {
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_alloc_error(layout: core::alloc::Layout) {
+    crash::record(crash::Crash::OutOfMemory {
+        size: layout.size() as u32,
+        align: layout.align() as u32,
+    });
+    runtime::fatal()
+}
+
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_on_soft_reset() {}
+
+#[allow(non_upper_case_globals)]
+#[doc(hidden)]
+#[no_mangle]
+pub static default_main_masks_irqs: u8 = 0;
+
+#[allow(non_upper_case_globals)]
+#[doc(hidden)]
+#[no_mangle]
+pub static default_runtime_config: runtime::RuntimeConfig = runtime::DEFAULT_CONFIG;
+
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_boot_beacon(_stage: runtime::BootStage) {}
+
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_trap_enter(_irqs: u32) {}
+
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_trap_exit(_irqs: u32) {}
+
+#[cfg(slot_layout)]
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_select_slot() -> Option<image::Slot> {
+    image::first_intact_slot()
+}
+
+#[cfg(all(feature = "panic-handler", not(feature = "panic-console")))]
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_panic_sink(_text: &str) {}
+
+#[cfg(feature = "panic-console")]
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_panic_sink(text: &str) {
+    for byte in text.bytes() {
+        console::write_byte(byte);
+    }
+}
+
+#[cfg(feature = "syscalls")]
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_syscall_handler(_nr: u32, _args: &syscall::Args) -> u32 {
+    syscall::ENOSYS
+}
+
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_debug_trap_handler(_regs: &mut PicoRV32StoredRegisters) -> bool {
+    false
+}
+
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_custom_instruction_handler(
+    _regs: &mut PicoRV32StoredRegisters,
+    _instr: decode::DecodedInstr,
+) -> bool {
+    false
+}
+
+#[cfg(feature = "protect")]
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_protect_configure(_window: usize, _range: core::ops::Range<u32>) {}
+
+#[cfg(feature = "protect")]
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_protection_fault_handler(
+    _regs: &mut PicoRV32StoredRegisters,
+    _violation: &protect::Violation,
+) -> bool {
+    false
+}
+
+#[doc(hidden)]
+#[no_mangle]
+pub fn default_illegal_instruction_handler(
+    _regs: &mut PicoRV32StoredRegisters,
+    _instr: u32,
+) -> bool {
+    false
+}
+
 /// Usage:
 ///
 /// ```
 /// use core::sync::atomic;
 /// use core::sync::atomic::Ordering;
+/// # use picorv32_rt::picorv32_interrupts;
 ///
 /// pub fn timer(_regs: &picorv32_rt::PicoRV32StoredRegisters) {
 ///     // ...
@@ -578,33 +2113,263 @@ pub unsafe fn default_pre_init() {}
 /// }
 ///
 /// picorv32_interrupts!(
-///     0: timer,
+///     timer: timer,
 ///     1: illegal_instruction,
 ///     2: bus_error,
 ///     5: irq5,
 ///     6: irq6
 /// );
 /// ```
+///
+/// IRQ numbers must be in the range `0..32`, the timer IRQ can be given as `timer` instead of `0`,
+/// see the [`timer`](timer/index.html) module. Every line with a handler gets a marker symbol, and
+/// linking fails if one of them is marked as unconnected in `_irq_unconnected`, see the `device.x`
+/// section of the crate documentation.
+///
+/// Handlers declared with the [`interrupt`](attr.interrupt.html) attribute can keep state in
+/// `static mut` variables at the top of their body, which they get as `&mut` references:
+///
+/// ```
+/// use picorv32_rt::{interrupt, picorv32_interrupts};
+///
+/// #[interrupt]
+/// fn irq5(_regs: &picorv32_rt::PicoRV32StoredRegisters) {
+///     static mut RECEIVED: usize = 0;
+///     static mut BUFFER: [u8; 64] = [0; 64];
+///
+///     BUFFER[*RECEIVED % 64] = 0; // read the UART
+///     *RECEIVED += 1;
+/// }
+///
+/// picorv32_interrupts!(5: irq5);
+/// ```
+///
+/// Each call would hand out the references again, so such handlers are `unsafe fn` and can only be
+/// called by `picorv32_interrupts!`:
+///
+/// ``` compile_fail
+/// use picorv32_rt::interrupt;
+///
+/// #[interrupt]
+/// fn irq5(_regs: &picorv32_rt::PicoRV32StoredRegisters) {
+///     static mut RECEIVED: usize = 0;
+///
+///     *RECEIVED += 1;
+/// }
+///
+/// fn main() {
+///     let regs = unsafe { core::mem::zeroed() };
+///     irq5(&regs);
+/// }
+/// ```
 #[cfg(feature = "interrupts")]
 #[macro_export]
 macro_rules! picorv32_interrupts {
-    (@interrupt ($n:literal, $pending_irqs:expr, $regs:expr, $handler:ident)) => {
-        if $pending_irqs & (1 << $n) != 0 {
-            $handler($regs);
+    (@irq timer) => {
+        $crate::timer::IRQ
+    };
+    (@irq $n:literal) => {
+        $n
+    };
+    (@marker timer) => {
+        $crate::irq_marker!(0);
+    };
+    (@marker $n:literal) => {
+        $crate::irq_marker!($n);
+    };
+    (@interrupt ($n:tt, $pending_irqs:expr, $regs:expr, $handler:ident)) => {
+        if $pending_irqs & (1 << $crate::picorv32_interrupts!(@irq $n)) != 0 {
+            $crate::dispatch_irq($crate::picorv32_interrupts!(@irq $n), || unsafe {
+                $handler($regs)
+            });
         }
     };
-    ( $( $irq:literal : $handler:ident ),* ) => {
+    ( $( $irq:tt : $handler:ident ),* ) => {
+        // handlers declared with `#[interrupt]` are `unsafe fn`, plain ones aren't
+        #[allow(unused_unsafe)]
         #[no_mangle]
-        pub extern "C" fn trap_handler(regs: *const picorv32_rt::PicoRV32StoredRegisters, pending_irqs: u32) {
-            let regs = unsafe { regs.as_ref().unwrap() };
+        pub extern "C" fn trap_handler(regs: *mut $crate::PicoRV32StoredRegisters, pending_irqs: u32) {
+            let regs = unsafe { regs.as_mut().unwrap() };
             $(
-                picorv32_interrupts!(@interrupt($irq, pending_irqs, regs, $handler));
+                $crate::picorv32_interrupts!(@interrupt($irq, pending_irqs, regs, $handler));
             )*
         }
+
+        $(
+            $crate::picorv32_interrupts!(@marker $irq);
+        )*
+    };
+}
+
+/// Prints to the [console](console/index.html)
+#[cfg(feature = "console")]
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::console::_print(format_args!($($arg)*))
+    };
+}
+
+/// Prints to the [console](console/index.html), with a newline
+#[cfg(feature = "console")]
+#[macro_export]
+macro_rules! println {
+    () => {
+        $crate::print!("\n")
+    };
+    ($fmt:expr) => {
+        $crate::print!(concat!($fmt, "\n"))
+    };
+    ($fmt:expr, $($arg:tt)*) => {
+        $crate::print!(concat!($fmt, "\n"), $($arg)*)
+    };
+}
+
+/// Registers `fn()` `$f` as a background task with a budget of `$budget` cycles
+///
+/// See the [`background`](background/index.html) module.
+#[cfg(feature = "background")]
+#[macro_export]
+macro_rules! background_task {
+    ($f:ident, $budget:expr) => {
+        #[allow(non_snake_case)]
+        #[doc(hidden)]
+        mod $f {
+            #[link_section = ".background_tasks"]
+            #[used]
+            static mut TASK: $crate::background::Task = $crate::background::Task {
+                name: stringify!($f),
+                f: super::$f,
+                budget: $budget,
+                stats: $crate::background::NO_STATS,
+                debt: 0,
+            };
+        }
+    };
+}
+
+/// Defines the [`RuntimeConfig`](runtime/struct.RuntimeConfig.html) of the firmware
+///
+/// Takes `field: value` pairs, the fields left out keep their defaults. It can be used once per
+/// firmware, see the [`runtime`](runtime/index.html) module.
+#[macro_export]
+macro_rules! runtime_config {
+    ($($field:ident : $value:expr),* $(,)*) => {
+        #[doc(hidden)]
+        #[export_name = "__picorv32_rt_config"]
+        pub static __PICORV32_RT_CONFIG: $crate::runtime::RuntimeConfig =
+            $crate::runtime::RuntimeConfig {
+                $($field: $value,)*
+                ..$crate::runtime::DEFAULT_CONFIG
+            };
+    };
+}
+
+/// Calls the handler of IRQ line `irq`, used by `picorv32_interrupts!`
+#[doc(hidden)]
+#[inline(always)]
+pub fn dispatch_irq<F: FnOnce()>(irq: u32, handler: F) {
+    #[cfg(feature = "irq-stats")]
+    {
+        let start = runtime::cycles();
+        handler();
+        irq_stats::record(irq, runtime::cycles().wrapping_sub(start));
+    }
+    #[cfg(not(feature = "irq-stats"))]
+    {
+        let _ = irq;
+        handler();
+    }
+}
+
+/// Checks an invariant, with a cheap failure path in release builds
+///
+/// In debug builds this is `assert!`. In release builds a failure records the source location
+/// in the [crash region](crash/index.html) and restarts the firmware, the message isn't used.
+#[macro_export]
+macro_rules! rt_assert {
+    ($cond:expr) => {
+        $crate::rt_assert!($cond, concat!("assertion failed: ", stringify!($cond)))
+    };
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            if cfg!(debug_assertions) {
+                panic!($($arg)+);
+            } else {
+                $crate::crash::assertion_failed(file!(), line!());
+            }
+        }
+    };
+}
+
+/// Like [`rt_assert!`](macro.rt_assert.html), but only checked in debug builds
+#[macro_export]
+macro_rules! rt_debug_assert {
+    ($($arg:tt)+) => {
+        if cfg!(debug_assertions) {
+            $crate::rt_assert!($($arg)+);
+        }
+    };
+}
+
+/// Creates a statically allocated value and returns a `&'static mut` to it, once
+///
+/// Returns `None` if this invocation was already evaluated before, e.g. when the code it's in runs
+/// twice. The check is done in a critical section. The expression is only evaluated the first
+/// time. Handy for long-lived driver state like buffers handed to a DMA engine.
+///
+/// ```
+/// use picorv32_rt::singleton;
+///
+/// fn take_buffer() -> Option<&'static mut [u8; 256]> {
+///     singleton!(: [u8; 256] = [0; 256])
+/// }
+///
+/// let buffer = take_buffer().unwrap();
+/// buffer[0] = 1;
+/// assert!(take_buffer().is_none());
+/// ```
+#[macro_export]
+macro_rules! singleton {
+    ($name:ident: $ty:ty = $expr:expr) => {
+        $crate::interrupt::free(|_| {
+            static mut $name: Option<$ty> = None;
+
+            #[allow(unsafe_code)]
+            let used = unsafe { $name.is_some() };
+            if used {
+                None
+            } else {
+                let expr = $expr;
+
+                #[allow(unsafe_code)]
+                unsafe {
+                    $name = Some(expr);
+                    $name.as_mut()
+                }
+            }
+        })
+    };
+    (: $ty:ty = $expr:expr) => {
+        $crate::singleton!(VAR: $ty = $expr)
+    };
+}
+
+/// Triggers a breakpoint trap (`ebreak`)
+///
+/// With the `interrupts` feature this ends up in `DebugTrapHandler`, see the
+/// [`exception`](exception/index.html) module. Without interrupt support the core halts.
+#[macro_export]
+macro_rules! breakpoint {
+    () => {
+        unsafe { $crate::riscv::asm::ebreak() }
     };
 }
 
 /// sleep until an interrupt is received
 pub fn wfi() {
     let _irqs = unsafe { asm::waitirq() };
+
+    #[cfg(feature = "stack-canary")]
+    stack::check_stack_canary();
 }
@@ -0,0 +1,96 @@
+//! `malloc` family for linked C code
+//!
+//! With the `malloc-shims` feature this crate exports `malloc`, `free`, `calloc` and `realloc`
+//! symbols that forward to the application's `#[global_allocator]`. Every call is done inside a
+//! critical section, so C code allocating from interrupt handlers is fine too.
+//!
+//! Every block is preceded by a small header that records its size, since C's `free` doesn't
+//! pass it back. Blocks are aligned to 8 bytes, which is `alignof(max_align_t)` on RV32.
+//!
+//! Requires Rust 1.36 or newer (the `alloc` crate).
+
+use alloc::alloc::{self as heap, Layout};
+use core::ffi::c_void;
+use core::ptr;
+
+use crate::interrupt;
+
+/// Alignment of the blocks handed out to C code
+const ALIGN: usize = 8;
+
+/// Size of the header storing the block size, padded to keep the payload aligned
+const HEADER_SIZE: usize = ALIGN;
+
+fn layout(size: usize) -> Option<Layout> {
+    let total = size.checked_add(HEADER_SIZE)?;
+    Layout::from_size_align(total, ALIGN).ok()
+}
+
+unsafe fn to_payload(block: *mut u8, size: usize) -> *mut c_void {
+    if block.is_null() {
+        return ptr::null_mut();
+    }
+    *(block as *mut usize) = size;
+    block.add(HEADER_SIZE) as *mut c_void
+}
+
+unsafe fn to_block(payload: *mut c_void) -> (*mut u8, usize) {
+    let block = (payload as *mut u8).sub(HEADER_SIZE);
+    (block, *(block as *const usize))
+}
+
+/// Allocates `size` bytes, returns a null pointer on failure
+#[no_mangle]
+pub unsafe extern "C" fn malloc(size: usize) -> *mut c_void {
+    let layout = match layout(size) {
+        Some(layout) => layout,
+        None => return ptr::null_mut(),
+    };
+    interrupt::free(|_| to_payload(heap::alloc(layout), size))
+}
+
+/// Allocates a zeroed array of `nmemb` elements of `size` bytes each
+#[no_mangle]
+pub unsafe extern "C" fn calloc(nmemb: usize, size: usize) -> *mut c_void {
+    let size = match nmemb.checked_mul(size) {
+        Some(size) => size,
+        None => return ptr::null_mut(),
+    };
+    let layout = match layout(size) {
+        Some(layout) => layout,
+        None => return ptr::null_mut(),
+    };
+    interrupt::free(|_| to_payload(heap::alloc_zeroed(layout), size))
+}
+
+/// Resizes a block previously returned by `malloc`, `calloc` or `realloc`
+#[no_mangle]
+pub unsafe extern "C" fn realloc(payload: *mut c_void, size: usize) -> *mut c_void {
+    if payload.is_null() {
+        return malloc(size);
+    }
+    if size == 0 {
+        free(payload);
+        return ptr::null_mut();
+    }
+
+    let new_layout = match layout(size) {
+        Some(layout) => layout,
+        None => return ptr::null_mut(),
+    };
+    let (block, old_size) = to_block(payload);
+    let old_layout = Layout::from_size_align_unchecked(old_size + HEADER_SIZE, ALIGN);
+    interrupt::free(|_| to_payload(heap::realloc(block, old_layout, new_layout.size()), size))
+}
+
+/// Releases a block previously returned by `malloc`, `calloc` or `realloc`
+#[no_mangle]
+pub unsafe extern "C" fn free(payload: *mut c_void) {
+    if payload.is_null() {
+        return;
+    }
+
+    let (block, size) = to_block(payload);
+    let layout = Layout::from_size_align_unchecked(size + HEADER_SIZE, ALIGN);
+    interrupt::free(|_| heap::dealloc(block, layout))
+}
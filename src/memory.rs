@@ -0,0 +1,105 @@
+//! Memory map of the running firmware
+//!
+//! The address ranges the linker script laid the image out in, read from its symbols, so code
+//! that needs them doesn't have to declare the symbols itself. All of them are word aligned, and
+//! empty if the section is.
+//!
+//! ```
+//! use picorv32_rt::memory;
+//!
+//! fn is_static(addr: usize) -> bool {
+//!     memory::data_range().contains(&addr) || memory::bss_range().contains(&addr)
+//! }
+//! ```
+
+use core::ops::Range;
+
+pub use crate::heap::{heap2_range, heap_range};
+
+extern "C" {
+    static _sramtext: u8;
+    static _eramtext: u8;
+    static _sitext: u8;
+    static _sramfunc: u32;
+    static _eramfunc: u32;
+    static _siramfunc: u32;
+    static _sdata: u32;
+    static _edata: u32;
+    static _sidata: u32;
+    static _sbss: u32;
+    static _ebss: u32;
+    static _sdata_ram2: u32;
+    static _edata_ram2: u32;
+    static _sidata_ram2: u32;
+    static _sbss_ram2: u32;
+    static _ebss_ram2: u32;
+    static _suninit: u8;
+    static _euninit: u8;
+    static _estack: u8;
+    static _sstack: u8;
+}
+
+fn range<T>(start: &'static T, end: &'static T) -> Range<usize> {
+    start as *const T as usize..end as *const T as usize
+}
+
+/// `.text` and `.rodata`, where they run from, in RAM with the `copy-to-ram` feature
+pub fn text_range() -> Range<usize> {
+    unsafe { range(&_sramtext, &_eramtext) }
+}
+
+/// Where `.text` and `.rodata` are loaded from, the same as [`text_range`](fn.text_range.html)
+/// unless they're copied to RAM
+pub fn text_load_address() -> usize {
+    unsafe { &_sitext as *const u8 as usize }
+}
+
+/// `.ramfunc`, functions copied to RAM at startup
+pub fn ramfunc_range() -> Range<usize> {
+    unsafe { range(&_sramfunc, &_eramfunc) }
+}
+
+/// Where the initial contents of `.ramfunc` are loaded from
+pub fn ramfunc_load_address() -> usize {
+    unsafe { &_siramfunc as *const u32 as usize }
+}
+
+/// `.data`, initialized statics
+pub fn data_range() -> Range<usize> {
+    unsafe { range(&_sdata, &_edata) }
+}
+
+/// Where the initial values of `.data` are loaded from
+pub fn data_load_address() -> usize {
+    unsafe { &_sidata as *const u32 as usize }
+}
+
+/// `.bss`, zero-initialized statics
+pub fn bss_range() -> Range<usize> {
+    unsafe { range(&_sbss, &_ebss) }
+}
+
+/// `.data_RAM2`, initialized statics in the second RAM region
+pub fn data_ram2_range() -> Range<usize> {
+    unsafe { range(&_sdata_ram2, &_edata_ram2) }
+}
+
+/// Where the initial values of `.data_RAM2` are loaded from
+pub fn data_ram2_load_address() -> usize {
+    unsafe { &_sidata_ram2 as *const u32 as usize }
+}
+
+/// `.bss_RAM2`, zero-initialized statics in the second RAM region
+pub fn bss_ram2_range() -> Range<usize> {
+    unsafe { range(&_sbss_ram2, &_ebss_ram2) }
+}
+
+/// `.uninit`, statics the startup code leaves alone, see [`no_init`](../attr.no_init.html)
+pub fn uninit_range() -> Range<usize> {
+    unsafe { range(&_suninit, &_euninit) }
+}
+
+/// The stack, from its lowest address `_estack` up to `_stack_start`, which it grows down from
+pub fn stack_range() -> Range<usize> {
+    unsafe { range(&_estack, &_sstack) }
+}
@@ -0,0 +1,182 @@
+//! 64-bit monotonic cycle counter
+//!
+//! The lower 32 bits of the cycle counter wrap within a minute at typical clock speeds. With the
+//! `monotonic` feature [`now`](fn.now.html) reads all 64 bits, which don't wrap in practice. The
+//! core has to be built with `ENABLE_COUNTERS` and `ENABLE_COUNTERS64`.
+//!
+//! The counter is read without masking IRQs, so it can be used from both thread and interrupt
+//! context.
+//!
+//! ```
+//! use picorv32_rt::monotonic;
+//!
+//! fn timed<F: FnOnce()>(f: F) -> u64 {
+//!     let start = monotonic::now();
+//!     f();
+//!     monotonic::now() - start
+//! }
+//! ```
+//!
+//! # RTIC
+//!
+//! With the `rtic` feature [`CycleMonotonic`](struct.CycleMonotonic.html) implements
+//! `rtic_monotonic::Monotonic`, using the hardware timer for the compare interrupt, so RTIC
+//! applications can schedule tasks on PicoRV32. Time is counted in cycles.
+
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+
+#[cfg(feature = "rtic")]
+use crate::timer::Timer;
+
+/// Cycles since reset
+#[inline]
+pub fn now() -> u64 {
+    extern "C" {
+        fn __picorv32_rt_rdcycle64() -> u64;
+    }
+
+    unsafe { __picorv32_rt_rdcycle64() }
+}
+
+/// Busy-waits until `cycles` cycles have passed
+pub fn delay(cycles: u64) {
+    let start = now();
+    while now() - start < cycles {}
+}
+
+/// A point in time, in cycles since reset
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(pub u64);
+
+impl Instant {
+    /// The current time
+    #[inline]
+    pub fn now() -> Instant {
+        Instant(now())
+    }
+
+    /// Time passed since `self`
+    #[inline]
+    pub fn elapsed(self) -> Duration {
+        Instant::now() - self
+    }
+}
+
+/// A span of time in cycles
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Duration(pub u64);
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Instant {
+        Instant(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, rhs: Duration) {
+        self.0 += rhs.0;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, rhs: Duration) -> Instant {
+        Instant(self.0 - rhs.0)
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, rhs: Duration) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Duration {
+        Duration(self.0 - rhs.0)
+    }
+}
+
+impl Add<Duration> for Duration {
+    type Output = Duration;
+
+    fn add(self, rhs: Duration) -> Duration {
+        Duration(self.0 + rhs.0)
+    }
+}
+
+impl Sub<Duration> for Duration {
+    type Output = Duration;
+
+    fn sub(self, rhs: Duration) -> Duration {
+        Duration(self.0 - rhs.0)
+    }
+}
+
+/// RTIC monotonic on the cycle counter and the hardware timer
+///
+/// The compare interrupt is IRQ0, so the RTIC dispatcher has to be bound to it. Compare values
+/// further away than `u32::MAX` cycles cause an early interrupt, after which RTIC sets the compare
+/// value again.
+#[cfg(feature = "rtic")]
+pub struct CycleMonotonic {
+    timer: Timer,
+}
+
+#[cfg(feature = "rtic")]
+impl CycleMonotonic {
+    /// Creates the monotonic, taking over the hardware timer
+    pub fn new(timer: Timer) -> Self {
+        CycleMonotonic { timer }
+    }
+
+    /// Returns the hardware timer
+    pub fn free(self) -> Timer {
+        self.timer
+    }
+}
+
+#[cfg(feature = "rtic")]
+impl rtic_monotonic::Monotonic for CycleMonotonic {
+    type Instant = Instant;
+    type Duration = Duration;
+
+    fn now(&mut self) -> Instant {
+        Instant::now()
+    }
+
+    fn set_compare(&mut self, instant: Instant) {
+        let now = now();
+        let cycles = if instant.0 <= now {
+            // already due, fire as soon as possible
+            1
+        } else if instant.0 - now > u64::from(u32::max_value()) {
+            u32::max_value()
+        } else {
+            (instant.0 - now) as u32
+        };
+        self.timer.start_oneshot(cycles);
+    }
+
+    fn clear_compare_flag(&mut self) {
+        // the timer IRQ is acknowledged by taking it
+    }
+
+    fn zero() -> Instant {
+        Instant(0)
+    }
+
+    unsafe fn reset(&mut self) {
+        // the cycle counter can't be reset, instants stay relative to the reset of the core
+        self.timer.cancel();
+    }
+
+    fn disable_timer(&mut self) {
+        self.timer.cancel();
+    }
+}
@@ -0,0 +1,89 @@
+//! Panic handler with a register dump and backtrace
+//!
+//! With the `panic-handler` feature the crate provides the `#[panic_handler]`, so the firmware
+//! doesn't depend on `panic-halt` or a hand-written one. On a panic it:
+//!
+//! 1. masks all IRQs
+//! 2. records a [`Crash::Panic`](../crash/enum.Crash.html), and with the `panic-persist` feature
+//!    the [whole message](../panic_persist/index.html)
+//! 3. writes the message, the registers at the point of the panic and a backtrace to the
+//!    `__panic_sink` hook, which the application provides to send the text to a UART etc.
+//!    (no-op by default, the [console](../console/index.html) with the `panic-console` feature)
+//! 4. stops or restarts the firmware as the [`PanicPolicy`](../runtime/enum.PanicPolicy.html)
+//!    says
+//!
+//! The registers are dumped in the same format as trap frames. The backtrace comes from the
+//! [`backtrace`](../backtrace/index.html) module, so it needs the firmware to be built with
+//! `-C force-frame-pointers=yes`, otherwise it's cut short or empty.
+//!
+//! A panic in the sink itself skips straight to the last step. With the `test-harness` feature a
+//! panic in a test goes on with the next one instead, see the [`test`](../test/index.html) module.
+//!
+//! ``` ignore
+//! #[export_name = "__panic_sink"]
+//! fn panic_sink(text: &str) {
+//!     for byte in text.bytes() {
+//!         uart::write(byte);
+//!     }
+//! }
+//! ```
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::ptr;
+
+use crate::{backtrace, crash, interrupt, runtime};
+
+/// Set once a panic is being reported
+static mut PANICKING: bool = false;
+
+extern "C" {
+    fn __picorv32_rt_capture(x: *mut [u32; 32]);
+}
+
+extern "Rust" {
+    // This symbol can be provided by the user, otherwise it defaults to a no-op
+    fn __panic_sink(text: &str);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    interrupt::disable();
+
+    // word 0 is the address in here where the registers were captured
+    let mut x = [0; 32];
+    unsafe { __picorv32_rt_capture(&mut x) };
+
+    if unsafe { ptr::replace(&mut PANICKING, true) } {
+        runtime::fatal()
+    }
+
+    crash::record_panic(info);
+    #[cfg(feature = "panic-persist")]
+    crate::panic_persist::record(info);
+
+    let mut sink = Sink;
+    let _ = writeln!(sink, "{}", info);
+    let _ = writeln!(sink, "PC: {:08x}", x[0]);
+    let _ = crate::write_registers(&mut sink, &x, true);
+    let _ = writeln!(sink, "Backtrace:");
+    for (depth, return_address) in backtrace::from_frame_pointer(x[8]).enumerate() {
+        let _ = writeln!(sink, "#{:<2} {:08x}", depth, return_address);
+    }
+
+    // a failing test, the harness goes on with the next one
+    #[cfg(feature = "test-harness")]
+    crate::test::failed();
+
+    runtime::fatal()
+}
+
+/// Passes everything written to `__panic_sink`
+pub(crate) struct Sink;
+
+impl Write for Sink {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        unsafe { __panic_sink(s) };
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+//! Panic messages that survive a restart
+//!
+//! The [crash region](../crash/index.html) keeps only where a panic happened, so it doesn't pull
+//! in any formatting code. With the `panic-persist` feature the panic handler can keep the whole
+//! formatted message as well, in a [`CAPACITY`](constant.CAPACITY.html)-byte region in
+//! [`.uninit`](../index.html#uninit) RAM, and the next boot reads it back with
+//! [`get_persisted_panic`](fn.get_persisted_panic.html).
+//!
+//! [`record`](fn.record.html) writes the message, [`writer`](fn.writer.html) appends more to it,
+//! e.g. the trap frame of a fault with its program counter. Text that doesn't fit is cut short at
+//! a character boundary.
+//!
+//! ```
+//! use core::fmt::Write;
+//! use core::panic::PanicInfo;
+//! use picorv32_rt::{panic_persist, runtime, PicoRV32StoredRegisters};
+//!
+//! fn panic(info: &PanicInfo) -> ! {
+//!     panic_persist::record(info);
+//!     runtime::fatal()
+//! }
+//!
+//! fn bus_error(regs: &PicoRV32StoredRegisters) {
+//!     let _ = regs.write_to(&mut panic_persist::writer());
+//!     panic!("bus error");
+//! }
+//!
+//! // after the restart
+//! fn report_last_panic() {
+//!     if let Some(message) = panic_persist::get_persisted_panic() {
+//!         // send `message` home
+//!         panic_persist::clear();
+//!     }
+//! }
+//! ```
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::ptr;
+
+/// Bytes of message kept across a restart
+pub const CAPACITY: usize = 1024;
+
+/// `"PNIC"`, marks a valid message
+const MAGIC: u32 = 0x4349_4e50;
+
+/// Magic, length and text of the message
+#[repr(C)]
+struct Region {
+    magic: u32,
+    len: u32,
+    buf: [u8; CAPACITY],
+}
+
+#[link_section = ".uninit.panic_persist"]
+static mut REGION: Region = Region {
+    magic: 0,
+    len: 0,
+    buf: [0; CAPACITY],
+};
+
+/// Writes the message and location of a panic to the region, replacing any previous message,
+/// meant to be called from the panic handler
+pub fn record(info: &PanicInfo) {
+    clear();
+    let _ = writeln!(writer(), "{}", info);
+}
+
+/// Appends to the message in the region, starting a new one if there's none
+///
+/// Writes fail once the region is full.
+pub fn writer() -> Writer {
+    unsafe {
+        if ptr::read_volatile(&REGION.magic) != MAGIC || persisted_len() > CAPACITY {
+            ptr::write_volatile(&mut REGION.len, 0);
+            ptr::write_volatile(&mut REGION.magic, MAGIC);
+        }
+    }
+    Writer { _private: () }
+}
+
+/// The message left before the last restart, if any
+///
+/// The message stays until it's [`clear`](fn.clear.html)ed or replaced by the next panic.
+pub fn get_persisted_panic() -> Option<&'static str> {
+    unsafe {
+        if ptr::read_volatile(&REGION.magic) != MAGIC {
+            return None;
+        }
+        let len = persisted_len();
+        if len > CAPACITY {
+            return None;
+        }
+        // random RAM after a power cycle rarely gets this far
+        core::str::from_utf8(&REGION.buf[..len]).ok()
+    }
+}
+
+/// Removes the message from the region
+pub fn clear() {
+    unsafe { ptr::write_volatile(&mut REGION.magic, 0) };
+}
+
+/// Appends to the persisted message, see [`writer`](fn.writer.html)
+pub struct Writer {
+    _private: (),
+}
+
+impl Write for Writer {
+    /// Appends as much of `s` as fits, fails if it had to be cut short
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        unsafe {
+            let len = persisted_len();
+            let mut n = s.len().min(CAPACITY - len);
+            while !s.is_char_boundary(n) {
+                n -= 1;
+            }
+            for (i, &byte) in s.as_bytes()[..n].iter().enumerate() {
+                ptr::write_volatile(&mut REGION.buf[len + i], byte);
+            }
+            // a restart in the middle leaves the text written so far
+            ptr::write_volatile(&mut REGION.len, (len + n) as u32);
+
+            if n == s.len() {
+                Ok(())
+            } else {
+                Err(fmt::Error)
+            }
+        }
+    }
+}
+
+unsafe fn persisted_len() -> usize {
+    ptr::read_volatile(&REGION.len) as usize
+}
@@ -0,0 +1,167 @@
+//! Software priorities, resource locks and pending of IRQ lines
+//!
+//! PicoRV32 has no interrupt controller: trap handlers can't be preempted and there is no way to
+//! pend an IRQ line from software. With the `priority` feature this module emulates the pieces
+//! an RTIC-style framework needs on top of `maskirq`:
+//!
+//! - a priority for every IRQ line, [`set_priority`](fn.set_priority.html)
+//! - [`lock`](fn.lock.html), which masks all lines up to a ceiling priority while a resource
+//!   is accessed from thread mode
+//! - [`pend`](fn.pend.html), which runs the handler of a line as if it had fired, as soon as
+//!   the line isn't masked
+//!
+//! Pended lines are dispatched in order of their priority to `trap_handler`, at the end of the
+//! current trap or, in thread mode, right away. Their handlers get a zeroed frame instead of the
+//! registers of the interrupted code. The runtime's own handling of a line, e.g. restarting the
+//! tick, only happens when the hardware raises it, so lines 0 to 2, which the core raises for the
+//! timer, exceptions and bus errors, can't be pended.
+//!
+//! This API is meant for framework authors and isn't stable yet, it may change in minor releases.
+//!
+//! ```
+//! use picorv32_rt::priority;
+//!
+//! fn init() {
+//!     priority::set_priority(5, 2); // UART
+//!     priority::set_priority(6, 1); // software task
+//! }
+//!
+//! fn thread() {
+//!     // blocks both lines while the shared buffer is used
+//!     priority::lock(2, || {
+//!         // ...
+//!     });
+//!     priority::pend(6);
+//! }
+//! ```
+
+use core::mem;
+
+use crate::{exception, interrupt, PicoRV32StoredRegisters};
+
+/// Priority of every IRQ line, 0 by default, only written in critical sections
+static mut PRIORITIES: [u8; 32] = [0; 32];
+/// Lines pended by software, only accessed in critical sections and from the trap handler
+static mut PENDED: u32 = 0;
+
+/// Lowest line [`pend`](fn.pend.html) accepts, the ones below are raised by the core itself
+const FIRST_PENDABLE: u32 = 3;
+
+/// IRQ1 and IRQ2, never masked by [`lock`](fn.lock.html): the core halts on a masked one, and
+/// they're raised by the instruction being executed, so they can't preempt anything
+const NEVER_MASKED: u32 = 0b110;
+
+extern "C" {
+    fn trap_handler(regs: &mut PicoRV32StoredRegisters, irqs: u32);
+}
+
+/// Sets the priority of IRQ line `irq`, higher numbers mean higher priority
+///
+/// The priorities of lines 1 and 2 are kept, but have no effect: `lock` never masks them.
+///
+/// # Panics
+///
+/// If `irq` is not in the range `0..32`
+pub fn set_priority(irq: u32, priority: u8) {
+    interrupt::free(|_| unsafe { PRIORITIES[irq as usize] = priority });
+}
+
+/// Priority of IRQ line `irq`
+///
+/// # Panics
+///
+/// If `irq` is not in the range `0..32`
+pub fn priority(irq: u32) -> u8 {
+    unsafe { PRIORITIES[irq as usize] }
+}
+
+/// Mask of the IRQ lines with a priority of at most `ceiling`, without lines 1 and 2
+pub fn ceiling_mask(ceiling: u8) -> u32 {
+    let priorities = unsafe { &PRIORITIES };
+    let mask = priorities
+        .iter()
+        .enumerate()
+        .filter(|&(_, &priority)| priority <= ceiling)
+        .fold(0, |mask, (irq, _)| mask | 1 << irq);
+    mask & !NEVER_MASKED
+}
+
+/// Runs `f` with all IRQ lines up to priority `ceiling` masked
+///
+/// Lines pended in the meantime run after `f` returns. Inside a trap handler nothing can preempt
+/// `f` anyway, so only the mask of the interrupted code is touched.
+///
+/// Lines 1 and 2 keep the mask they had before, so `ecall`, `ebreak` and emulated instructions
+/// work in `f` as far as they did outside of it.
+pub fn lock<F, R>(ceiling: u8, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let mask = interrupt::disable();
+    unsafe { interrupt::restore(mask | ceiling_mask(ceiling)) };
+    let r = f();
+    unsafe { interrupt::restore(mask) };
+
+    dispatch();
+    r
+}
+
+/// Pends IRQ line `irq`, its handler runs as soon as the line isn't masked
+///
+/// # Panics
+///
+/// If `irq` is not in the range `3..32`
+pub fn pend(irq: u32) {
+    assert!(
+        (FIRST_PENDABLE..32).contains(&irq),
+        "IRQ lines 0 to 2 can't be pended"
+    );
+    interrupt::free(|_| unsafe { PENDED |= 1 << irq });
+    dispatch();
+}
+
+/// Takes back a pend of IRQ line `irq` that hasn't been dispatched yet
+pub fn unpend(irq: u32) {
+    interrupt::free(|_| unsafe { PENDED &= !(1 << irq) });
+}
+
+/// Whether IRQ line `irq` has been pended and not dispatched yet
+pub fn is_pending(irq: u32) -> bool {
+    interrupt::free(|_| unsafe { PENDED & 1 << irq != 0 })
+}
+
+/// Takes the pended line with the highest priority that isn't masked in `mask`
+fn take_next(mask: u32) -> Option<u32> {
+    let ready = unsafe { PENDED } & !mask;
+    let irq = (0..32)
+        .filter(|irq| ready & 1 << irq != 0)
+        .max_by_key(|&irq| priority(irq))?;
+    unsafe { PENDED &= !(1 << irq) };
+    Some(irq)
+}
+
+/// Dispatches the pended lines right away in thread mode, in a trap they're left to `drain`
+fn dispatch() {
+    if exception::in_trap() {
+        return;
+    }
+
+    // handlers pending more lines don't dispatch them again, all are masked here
+    let mask = interrupt::disable();
+    while let Some(irq) = take_next(mask) {
+        let mut regs: PicoRV32StoredRegisters = unsafe { mem::zeroed() };
+        unsafe { trap_handler(&mut regs, 1 << irq) };
+    }
+    unsafe { interrupt::restore(mask) };
+}
+
+/// Dispatches the pended lines, called at the end of the trap handler
+pub(crate) fn drain(regs: &mut PicoRV32StoredRegisters) {
+    // the mask of the interrupted code, the trap itself can't be preempted
+    let mask = interrupt::disable();
+    unsafe { interrupt::restore(mask) };
+
+    while let Some(irq) = take_next(mask) {
+        unsafe { trap_handler(regs, 1 << irq) };
+    }
+}
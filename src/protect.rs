@@ -0,0 +1,159 @@
+//! Memory protection via bus errors
+//!
+//! PicoRV32 has no MPU, but the bus fabric of some SoCs can be configured to answer accesses to
+//! certain address windows with a bus error. With the `protect` feature such windows are
+//! registered with [`forbid`](fn.forbid.html), which hands them to the `__protect_configure`
+//! hook to program the fabric. A bus error caused by a load or store into a forbidden window is
+//! decoded and reported to `ProtectionFaultHandler` with the effective address, other bus errors
+//! go through the usual path.
+//!
+//! ```
+//! use core::ops::Range;
+//! use picorv32_rt::protect::{self, Violation};
+//! use picorv32_rt::PicoRV32StoredRegisters;
+//!
+//! #[no_mangle]
+//! pub fn __protect_configure(window: usize, range: Range<u32>) {
+//!     // program the fabric, an empty range disables `window`
+//! }
+//!
+//! #[allow(non_snake_case)]
+//! #[no_mangle]
+//! pub fn ProtectionFaultHandler(regs: &mut PicoRV32StoredRegisters, violation: &Violation) -> bool {
+//!     // report `violation.pc` and `violation.addr`, then skip the access
+//!     true
+//! }
+//!
+//! fn init() {
+//!     // catch null pointer dereferences
+//!     protect::forbid(0..0x100);
+//! }
+//! ```
+//!
+//! Returning `true` from `ProtectionFaultHandler` resumes right after the offending instruction,
+//! a load leaves its destination register unchanged. Returning `false` passes the bus error on to
+//! `trap_handler`, which is what the default handler does.
+
+use core::ops::Range;
+
+use crate::decode::{self, DecodedInstr};
+use crate::{exception, interrupt, PicoRV32StoredRegisters};
+
+/// Maximum number of forbidden windows
+pub const CAPACITY: usize = 8;
+
+/// An access to a forbidden window
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    /// Address of the offending instruction
+    pub pc: u32,
+    /// Effective address of the access
+    pub addr: u32,
+    /// Width of the access in bytes
+    pub width: u32,
+    /// Whether the access is a store
+    pub write: bool,
+    /// Index of the window, as passed to `__protect_configure`
+    pub window: usize,
+}
+
+/// Window `start..end`, empty if unused
+#[derive(Copy, Clone)]
+struct Window {
+    start: u32,
+    end: u32,
+}
+
+const UNUSED: Window = Window { start: 0, end: 0 };
+
+/// Only accessed in critical sections and from the trap handler
+static mut WINDOWS: [Window; CAPACITY] = [UNUSED; CAPACITY];
+
+/// Forbids accesses to `range`, returns the index of the window or `None` if all are in use
+///
+/// # Panics
+///
+/// If `range` is empty
+pub fn forbid(range: Range<u32>) -> Option<usize> {
+    assert!(range.start < range.end);
+    interrupt::free(|_| {
+        let windows = unsafe { &mut WINDOWS };
+        let index = windows.iter().position(|w| w.start == w.end)?;
+        windows[index] = Window {
+            start: range.start,
+            end: range.end,
+        };
+        configure(index, range);
+        Some(index)
+    })
+}
+
+/// Allows accesses through window `index` again
+pub fn allow(index: usize) {
+    interrupt::free(|_| {
+        unsafe { WINDOWS[index] = UNUSED };
+        configure(index, 0..0);
+    })
+}
+
+/// The window `addr` falls into, if any
+pub fn window(addr: u32) -> Option<usize> {
+    interrupt::free(|_| find(addr, 1))
+}
+
+fn configure(index: usize, range: Range<u32>) {
+    extern "Rust" {
+        // This symbol can be provided by the user, otherwise it defaults to a no-op
+        fn __protect_configure(window: usize, range: Range<u32>);
+    }
+
+    unsafe { __protect_configure(index, range) }
+}
+
+/// The window overlapping `addr..addr + width`, if any
+fn find(addr: u32, width: u32) -> Option<usize> {
+    let end = addr.wrapping_add(width);
+    unsafe { &WINDOWS }
+        .iter()
+        .position(|w| w.start != w.end && addr < w.end && end > w.start)
+}
+
+/// Reports a bus error caused by an access to a forbidden window, returns `true` if it has been
+/// handled
+pub(crate) fn handle(regs: &mut PicoRV32StoredRegisters) -> bool {
+    extern "Rust" {
+        // This symbol can be provided by the user, otherwise it defaults to returning `false`
+        fn ProtectionFaultHandler(
+            regs: &mut PicoRV32StoredRegisters,
+            violation: &Violation,
+        ) -> bool;
+    }
+
+    let instr = unsafe { exception::faulting_instruction(regs) };
+    let (rs1, offset, width, write) = match decode::decode(instr) {
+        DecodedInstr::Load {
+            rs1, offset, width, ..
+        } => (rs1, offset, width.bytes(), false),
+        DecodedInstr::Store {
+            rs1, offset, width, ..
+        } => (rs1, offset, width.bytes(), true),
+        _ => return false,
+    };
+    let addr = match regs.reg(rs1) {
+        Some(base) => base.wrapping_add(offset as u32),
+        None => return false,
+    };
+    let window = match find(addr, width) {
+        Some(window) => window,
+        None => return false,
+    };
+
+    let violation = Violation {
+        pc: exception::instruction_address(regs),
+        addr,
+        width,
+        write,
+        window,
+    };
+    unsafe { ProtectionFaultHandler(regs, &violation) }
+}
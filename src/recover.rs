@@ -0,0 +1,110 @@
+//! Recovery from faults
+//!
+//! [`recover`](fn.recover.html) runs a closure under a checkpoint. If a trap handler decides
+//! the faulting code can't continue it calls [`unwind`](fn.unwind.html), and once the trap
+//! returns execution resumes at the innermost checkpoint, making `recover` return the error code.
+//!
+//! Unwinding doesn't run destructors of anything that lived inside the closure, it just
+//! abandons those stack frames.
+//!
+//! ```
+//! use core::num::NonZeroU32;
+//! use picorv32_rt::{picorv32_interrupts, recover, PicoRV32StoredRegisters};
+//!
+//! const NO_DEVICE: u32 = 1;
+//!
+//! pub fn bus_error(regs: &mut PicoRV32StoredRegisters) {
+//!     if !recover::unwind(regs, NonZeroU32::new(NO_DEVICE).unwrap()) {
+//!         loop {}
+//!     }
+//! }
+//!
+//! picorv32_interrupts!(2: bus_error);
+//!
+//! fn probe(addr: usize) -> bool {
+//!     recover::recover(|| unsafe { core::ptr::read_volatile(addr as *const u32) }).is_ok()
+//! }
+//! ```
+
+use core::num::NonZeroU32;
+use core::ptr;
+
+use crate::PicoRV32StoredRegisters;
+
+/// Registers saved by `__picorv32_rt_try`: `ra`, `sp` and `s0`..`s11`
+#[repr(C)]
+struct Checkpoint {
+    regs: [u32; 14],
+    prev: *mut Checkpoint,
+}
+
+/// Innermost active checkpoint
+static mut CURRENT: *mut Checkpoint = ptr::null_mut();
+
+extern "C" {
+    fn __picorv32_rt_try(
+        f: extern "C" fn(*mut u8),
+        ctx: *mut u8,
+        checkpoint: *mut Checkpoint,
+    ) -> u32;
+    fn __picorv32_rt_unwind(checkpoint: *mut Checkpoint, code: u32) -> !;
+}
+
+/// Runs `f`, returning the code passed to [`unwind`](fn.unwind.html) if a trap handler
+/// abandoned it
+pub fn recover<F, R>(f: F) -> Result<R, NonZeroU32>
+where
+    F: FnOnce() -> R,
+{
+    extern "C" fn call<F, R>(ctx: *mut u8)
+    where
+        F: FnOnce() -> R,
+    {
+        let ctx = unsafe { &mut *(ctx as *mut (Option<F>, Option<R>)) };
+        if let Some(f) = ctx.0.take() {
+            ctx.1 = Some(f());
+        }
+    }
+
+    let mut ctx: (Option<F>, Option<R>) = (Some(f), None);
+    let mut checkpoint = Checkpoint {
+        regs: [0; 14],
+        prev: unsafe { CURRENT },
+    };
+
+    let code = unsafe {
+        CURRENT = &mut checkpoint;
+        let code = __picorv32_rt_try(
+            call::<F, R>,
+            &mut ctx as *mut (Option<F>, Option<R>) as *mut u8,
+            &mut checkpoint,
+        );
+        CURRENT = checkpoint.prev;
+        code
+    };
+
+    match (NonZeroU32::new(code), ctx.1) {
+        (None, Some(r)) => Ok(r),
+        (Some(code), _) => Err(code),
+        (None, None) => unreachable!(),
+    }
+}
+
+/// Makes the trap return to the innermost checkpoint instead of the faulting code
+///
+/// The corresponding `recover` call returns `Err(code)`. Returns `false` if there is no active
+/// checkpoint, in which case the trap frame is left untouched.
+pub fn unwind(regs: &mut PicoRV32StoredRegisters, code: NonZeroU32) -> bool {
+    let checkpoint = unsafe { CURRENT };
+    if checkpoint.is_null() {
+        return false;
+    }
+
+    regs.set_x10(checkpoint as u32);
+    regs.set_x11(code.get());
+    unsafe {
+        regs.set_q0(__picorv32_rt_unwind as *const () as usize as u32);
+    }
+
+    true
+}
@@ -0,0 +1,147 @@
+//! Recording and replaying the IRQ arrival stream
+//!
+//! With the `irq-replay` feature every trap's pending IRQs and cycle stamp can be recorded into a
+//! buffer. Dumped from the device and fed to a [`Replayer`](struct.Replayer.html) in a simulation
+//! of the same firmware, the sequence reproduces timing dependent interrupt bugs seen on hardware.
+//!
+//! Recording costs a few cycles on every trap and needs a core built with `ENABLE_COUNTERS`.
+//!
+//! ```
+//! use picorv32_rt::replay::{self, Event};
+//!
+//! static mut EVENTS: [Event; 1024] = [Event { irqs: 0, cycle: 0 }; 1024];
+//!
+//! fn capture() {
+//!     unsafe { replay::start_recording(&mut EVENTS) };
+//!     // ... exercise the system
+//!     let events = replay::stop_recording();
+//!     // dump `events` over a debug link
+//! }
+//! ```
+
+use core::mem;
+
+use crate::{interrupt, runtime, PicoRV32StoredRegisters};
+
+/// IRQ lines that are replayed, the synchronous exceptions (IRQ1, IRQ2) are tied to the
+/// instruction that caused them and can't be injected
+pub const REPLAYED_IRQS: u32 = !0b110;
+
+/// A recorded trap
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct Event {
+    /// Pending IRQs
+    pub irqs: u32,
+    /// Cycles since the recording started, wrapping
+    pub cycle: u32,
+}
+
+struct Recorder {
+    events: Option<&'static mut [Event]>,
+    len: usize,
+    start: u32,
+}
+
+static mut RECORDER: Recorder = Recorder {
+    events: None,
+    len: 0,
+    start: 0,
+};
+
+/// Starts recording into `events`, recording stops once it's full
+pub fn start_recording(events: &'static mut [Event]) {
+    interrupt::free(move |_| unsafe {
+        RECORDER = Recorder {
+            events: Some(events),
+            len: 0,
+            start: runtime::cycles(),
+        };
+    });
+}
+
+/// Stops recording, returns the recorded events
+pub fn stop_recording() -> &'static [Event] {
+    interrupt::free(|_| unsafe {
+        let len = RECORDER.len;
+        match RECORDER.events.take() {
+            Some(events) => &events[..len],
+            None => &[],
+        }
+    })
+}
+
+/// Number of events recorded so far
+pub fn recorded() -> usize {
+    interrupt::free(|_| unsafe { RECORDER.len })
+}
+
+/// Records a trap, called from the trap handler only
+pub(crate) fn record(irqs: u32) {
+    let recorder = unsafe { &mut RECORDER };
+    if let Some(ref mut events) = recorder.events {
+        if recorder.len < events.len() {
+            events[recorder.len] = Event {
+                irqs,
+                cycle: runtime::cycles().wrapping_sub(recorder.start),
+            };
+            recorder.len += 1;
+        }
+    }
+}
+
+/// Re-injects a recorded sequence into the trap dispatcher
+///
+/// Meant for simulation: the harness calls [`poll`](#method.poll) regularly, e.g. from the idle
+/// loop or a simulator callback, with IRQs of the simulated peripherals masked.
+pub struct Replayer<'a> {
+    events: &'a [Event],
+    next: usize,
+    start: u32,
+    /// Frame handed to the handlers, all zeroes
+    regs: PicoRV32StoredRegisters,
+}
+
+impl<'a> Replayer<'a> {
+    /// Starts replaying `events`, with cycle 0 being now
+    pub fn new(events: &'a [Event]) -> Self {
+        Replayer {
+            events,
+            next: 0,
+            start: runtime::cycles(),
+            regs: unsafe { mem::zeroed() },
+        }
+    }
+
+    /// Dispatches all events that are due, returns how many were dispatched
+    ///
+    /// The handlers get a zeroed frame as the registers of the interrupted code.
+    pub fn poll(&mut self) -> usize {
+        let now = runtime::cycles().wrapping_sub(self.start);
+        let mut count = 0;
+        while let Some(event) = self.events.get(self.next) {
+            if event.cycle > now {
+                break;
+            }
+            self.next += 1;
+            count += 1;
+
+            let irqs = event.irqs & REPLAYED_IRQS;
+            if irqs != 0 {
+                let regs = &mut self.regs;
+                interrupt::free(|_| inject(regs, irqs));
+            }
+        }
+        count
+    }
+
+    /// Whether all events have been dispatched
+    pub fn is_done(&self) -> bool {
+        self.next == self.events.len()
+    }
+}
+
+/// Runs the trap dispatcher as if `irqs` were pending
+fn inject(regs: &mut PicoRV32StoredRegisters, irqs: u32) {
+    crate::start_trap_rust(regs as *mut PicoRV32StoredRegisters as *mut u32, irqs);
+}
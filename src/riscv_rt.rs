@@ -0,0 +1,144 @@
+//! riscv-rt compatibility
+//!
+//! HALs and PACs written for [`riscv-rt`](https://docs.rs/riscv-rt) expect its symbols:
+//! handlers named after the interrupt they serve, a vector table of external interrupts, and
+//! hooks called during startup. With the `riscv-rt-compat` feature the default `trap_handler`
+//! maps the PicoRV32 IRQ lines onto them:
+//!
+//! - IRQ0, the [timer](../timer/index.html), calls `MachineTimer`
+//! - IRQ1 and IRQ2, the [exceptions](../exception/index.html) none of the more specific handlers
+//!   dealt with, call `ExceptionHandler` with a [`TrapFrame`](struct.TrapFrame.html)
+//! - any other line `n` calls entry `n` of `__EXTERNAL_INTERRUPTS`, the table svd2rust PACs
+//!   generate for RISC-V targets
+//! - lines without a handler call `DefaultHandler`
+//!
+//! `MachineTimer`, like the handlers of a PAC's `device.x`, defaults to `DefaultHandler`. Both
+//! `DefaultHandler` and `ExceptionHandler` spin forever by default, as they do with riscv-rt.
+//!
+//! The table is looked up like with the `device` feature, `_external_interrupts_len` has to be set
+//! to the number of its entries, see the [`device`](../device/index.html) module.
+//!
+//! `_mp_hook` is called with hart ID 0 before RAM is initialized, `.bss` and `.data` are left
+//! alone if it returns `false`. `_setup_interrupts` is called right before `main`, after the
+//! static constructors. Both can be overridden like with riscv-rt, the defaults return `true` and
+//! do nothing.
+//!
+//! PicoRV32 has no `mcause`, `mepc` or `mtvec`, so handlers reading them directly don't work, and
+//! returning from `ExceptionHandler` resumes after the trapping instruction instead of at it.
+//!
+//! ```
+//! #[allow(non_snake_case)]
+//! #[no_mangle]
+//! pub extern "C" fn ExceptionHandler(trap_frame: &picorv32_rt::riscv_rt::TrapFrame) {
+//!     panic!("exception, ra = {:#x}", trap_frame.ra);
+//! }
+//! ```
+
+pub use crate::device::Vector;
+
+use crate::{device, exception, timer, PicoRV32StoredRegisters};
+
+/// Registers saved on trap entry, as riscv-rt passes them to `ExceptionHandler`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TrapFrame {
+    /// `x1`, the return address
+    pub ra: usize,
+    /// `x5`
+    pub t0: usize,
+    /// `x6`
+    pub t1: usize,
+    /// `x7`
+    pub t2: usize,
+    /// `x28`
+    pub t3: usize,
+    /// `x29`
+    pub t4: usize,
+    /// `x30`
+    pub t5: usize,
+    /// `x31`
+    pub t6: usize,
+    /// `x10`
+    pub a0: usize,
+    /// `x11`
+    pub a1: usize,
+    /// `x12`
+    pub a2: usize,
+    /// `x13`
+    pub a3: usize,
+    /// `x14`
+    pub a4: usize,
+    /// `x15`
+    pub a5: usize,
+    /// `x16`
+    pub a6: usize,
+    /// `x17`
+    pub a7: usize,
+}
+
+impl<'a> From<&'a PicoRV32StoredRegisters> for TrapFrame {
+    fn from(r: &PicoRV32StoredRegisters) -> Self {
+        TrapFrame {
+            ra: r.x1() as usize,
+            t0: r.x5() as usize,
+            t1: r.x6() as usize,
+            t2: r.x7() as usize,
+            t3: r.x28() as usize,
+            t4: r.x29() as usize,
+            t5: r.x30() as usize,
+            t6: r.x31() as usize,
+            a0: r.x10() as usize,
+            a1: r.x11() as usize,
+            a2: r.x12() as usize,
+            a3: r.x13() as usize,
+            a4: r.x14() as usize,
+            a5: r.x15() as usize,
+            a6: r.x16() as usize,
+            a7: r.x17() as usize,
+        }
+    }
+}
+
+/// Calls the riscv-rt handlers of the IRQs in `irqs`, the default `trap_handler`
+pub(crate) fn dispatch(regs: &mut PicoRV32StoredRegisters, irqs: u32) {
+    extern "C" {
+        fn MachineTimer();
+        fn ExceptionHandler(trap_frame: &TrapFrame);
+    }
+
+    let mut pending = irqs;
+    while pending != 0 {
+        let irq = pending.trailing_zeros();
+        pending &= pending - 1;
+
+        crate::dispatch_irq(irq, || unsafe {
+            match irq {
+                timer::IRQ => MachineTimer(),
+                exception::IRQ | exception::BUS_ERROR_IRQ => {
+                    ExceptionHandler(&TrapFrame::from(&*regs))
+                }
+                _ => device::dispatch(irq),
+            }
+        });
+    }
+}
+
+/// Calls `_mp_hook` for the only hart, whether RAM should be initialized
+pub(crate) fn mp_hook() -> bool {
+    extern "Rust" {
+        // This symbol can be provided by the user, otherwise it defaults to `true`
+        fn _mp_hook(hartid: usize) -> bool;
+    }
+
+    unsafe { _mp_hook(0) }
+}
+
+/// Calls `_setup_interrupts`
+pub(crate) fn setup_interrupts() {
+    extern "Rust" {
+        // This symbol can be provided by the user, otherwise it defaults to a no-op
+        fn _setup_interrupts();
+    }
+
+    unsafe { _setup_interrupts() }
+}
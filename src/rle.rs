@@ -0,0 +1,112 @@
+//! Run-length encoding of the `.data` load image
+//!
+//! The initial values of `.data` are mostly zeros and repeated patterns, which a simple
+//! run-length encoding shrinks well without needing a dictionary in RAM. With the
+//! `compressed-data` feature the startup code [decodes](fn.decode.html) the load image into RAM
+//! instead of copying it, and [`host::compress_data`](../host/fn.compress_data.html) encodes it
+//! in the binary image after linking.
+//!
+//! The encoding is a sequence of blocks, each starting with a control byte `c`:
+//!
+//! - `c < 0x80`: `c + 1` literal bytes follow
+//! - `c >= 0x80`: the next byte is repeated `c - 0x80 + 3` times
+//!
+//! ```
+//! use picorv32_rt::rle;
+//!
+//! let mut encoded = [0; 8];
+//! let mut len = 0;
+//! rle::encode(&[0; 32], |byte| {
+//!     encoded[len] = byte;
+//!     len += 1;
+//! });
+//! assert_eq!(&encoded[..len], &[0x80 + 32 - 3, 0]);
+//!
+//! let mut decoded = [0xff; 32];
+//! assert!(rle::decode(encoded[..len].iter().cloned(), &mut decoded));
+//! assert_eq!(decoded, [0; 32]);
+//! ```
+
+/// Longest literal block
+const MAX_LITERAL: usize = 128;
+/// Shortest repeated block, shorter runs are kept as literals
+const MIN_RUN: usize = 3;
+/// Longest repeated block
+const MAX_RUN: usize = 130;
+
+/// Encodes `data`, passing the encoded bytes to `out`
+pub fn encode<F: FnMut(u8)>(data: &[u8], mut out: F) {
+    let mut literal_start = 0;
+    let mut i = 0;
+
+    while i < data.len() {
+        let run = data[i..]
+            .iter()
+            .take(MAX_RUN)
+            .take_while(|&&byte| byte == data[i])
+            .count();
+
+        if run >= MIN_RUN {
+            emit_literals(&data[literal_start..i], &mut out);
+            out(0x80 + (run - MIN_RUN) as u8);
+            out(data[i]);
+            i += run;
+            literal_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    emit_literals(&data[literal_start..], &mut out);
+}
+
+fn emit_literals<F: FnMut(u8)>(literals: &[u8], out: &mut F) {
+    for chunk in literals.chunks(MAX_LITERAL) {
+        out((chunk.len() - 1) as u8);
+        for &byte in chunk {
+            out(byte);
+        }
+    }
+}
+
+/// Decodes bytes taken from `src` until `dst` is full
+///
+/// Returns `false` if `src` ended early or a block runs past the end of `dst`, `dst` is filled
+/// as far as the encoding went then.
+pub fn decode<I: Iterator<Item = u8>>(mut src: I, dst: &mut [u8]) -> bool {
+    let mut i = 0;
+
+    while i < dst.len() {
+        let control = match src.next() {
+            Some(control) => control as usize,
+            None => return false,
+        };
+
+        if control < 0x80 {
+            let end = i + control + 1;
+            if end > dst.len() {
+                return false;
+            }
+            for byte in &mut dst[i..end] {
+                *byte = match src.next() {
+                    Some(byte) => byte,
+                    None => return false,
+                };
+            }
+            i = end;
+        } else {
+            let end = i + control - 0x80 + MIN_RUN;
+            let value = match src.next() {
+                Some(value) => value,
+                None => return false,
+            };
+            if end > dst.len() {
+                return false;
+            }
+            for byte in &mut dst[i..end] {
+                *byte = value;
+            }
+            i = end;
+        }
+    }
+    true
+}
@@ -0,0 +1,384 @@
+//! Runtime state control
+//!
+//! The tunables of the runtime itself are collected in a [`RuntimeConfig`](struct.RuntimeConfig.html),
+//! which the firmware defines once with [`runtime_config!`](../macro.runtime_config.html). Fields
+//! it leaves out keep their [defaults](constant.DEFAULT_CONFIG.html), and without the macro the
+//! defaults are used throughout.
+//!
+//! It holds what the runtime reads at startup or while the firmware runs. The memory layout,
+//! e.g. `_stack_size` and `_heap_size`, is fixed at link time and stays in `memory.x`, and the
+//! code is still picked with features: a setting here only has an effect if the feature it needs
+//! is enabled.
+//!
+//! ```
+//! use picorv32_rt::runtime::{PanicPolicy, StackPolicy};
+//!
+//! picorv32_rt::runtime_config! {
+//!     clock_hz: 12_000_000,
+//!     tick_period: 12_000, // 1ms
+//!     panic: PanicPolicy::Halt,
+//!     stack: StackPolicy {
+//!         paint: false,
+//!         canary: true,
+//!     },
+//! }
+//! ```
+
+use core::ptr;
+use core::sync::atomic::{self, Ordering};
+
+use crate::interrupt;
+
+/// Parks the CPU in its lowest-activity state, for power characterization
+///
+/// In order:
+///
+/// 1. masks all IRQ lines except `wake_irq`
+/// 2. calls `__park_flush`, which can be provided by the application to drain UARTs, log
+///    buffers etc. (no-op by default)
+/// 3. zeroes `scrub`, e.g. a buffer holding key material
+/// 4. sleeps in `waitirq` forever
+///
+/// If `wake_irq` fires its handler runs as usual and the CPU goes back to sleep afterwards. A
+/// handler that needs to get out of here has to reset the system. Lines whose instructions are
+/// emulated stay unmasked, like with [`interrupt::disable`](../interrupt/fn.disable.html).
+///
+/// # Panics
+///
+/// If `wake_irq` is not in the range `0..32`
+pub fn park(wake_irq: Option<u32>, scrub: Option<&mut [u8]>) -> ! {
+    extern "Rust" {
+        // This symbol can be provided by the user, otherwise it defaults to a no-op
+        fn __park_flush();
+    }
+
+    if let Some(irq) = wake_irq {
+        assert!(irq < 32);
+    }
+
+    interrupt::disable();
+
+    unsafe { __park_flush() };
+
+    if let Some(scrub) = scrub {
+        for byte in scrub.iter_mut() {
+            unsafe { ptr::write_volatile(byte, 0) };
+        }
+        atomic::compiler_fence(Ordering::SeqCst);
+    }
+
+    #[cfg(feature = "interrupts")]
+    {
+        if let Some(irq) = wake_irq {
+            unsafe { interrupt::restore(interrupt::MASK_ALL & !(1 << irq)) };
+        }
+    }
+
+    loop {
+        #[cfg(feature = "interrupts")]
+        crate::wfi();
+        #[cfg(not(feature = "interrupts"))]
+        atomic::compiler_fence(Ordering::SeqCst);
+    }
+}
+
+/// Lower 32 bits of the cycle counter, needs a core built with `ENABLE_COUNTERS`
+#[cfg(any(
+    feature = "background",
+    feature = "event-queue",
+    feature = "irq-replay",
+    feature = "irq-stats",
+    feature = "timers"
+))]
+pub(crate) fn cycles() -> u32 {
+    extern "C" {
+        fn __picorv32_rt_rdcycle() -> u32;
+    }
+
+    unsafe { __picorv32_rt_rdcycle() }
+}
+
+/// Boot milestones reported to the `__boot_beacon` hook
+///
+/// See the [crate documentation](../index.html#__boot_beacon).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum BootStage {
+    /// Right after `_start`, before `__pre_init`
+    PreInit = 1,
+    /// After `__pre_init` and `__memory_setup`, before `.data` and `.bss` are initialized
+    RamInit = 2,
+    /// Right before `main` is called
+    Main = 3,
+    /// In the panic handler, if it calls [`crash::record_panic`](../crash/fn.record_panic.html)
+    Panic = 4,
+    /// Turn the fault LED on, while a [blink code](../blink/index.html) is shown
+    FaultLedOn = 5,
+    /// Turn the fault LED off, while a [blink code](../blink/index.html) is shown
+    FaultLedOff = 6,
+}
+
+/// Reports a boot milestone to the `__boot_beacon` hook
+#[inline]
+pub(crate) fn beacon(stage: BootStage) {
+    extern "Rust" {
+        // This symbol can be provided by the user, otherwise it defaults to a no-op
+        fn __boot_beacon(stage: BootStage);
+    }
+
+    unsafe { __boot_beacon(stage) }
+}
+
+/// Configuration of the runtime, see the [module documentation](index.html)
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RuntimeConfig {
+    /// Core clock frequency in Hz, 0 if unknown
+    ///
+    /// With the `fault-blink` feature the length of a blink is derived from it at boot, see
+    /// [`blink::set_unit`](../blink/fn.set_unit.html).
+    pub clock_hz: u32,
+    /// Period of the timer tick in cycles, 0 for none
+    ///
+    /// With the `interrupts` feature the runtime starts the [timer](../timer/index.html) before
+    /// `main` and restarts it every time IRQ0 fires, before the handler of the `timer` line is
+    /// called. The runtime owns the timer then. It's ignored with the `timers` and `embassy`
+    /// features, which own the timer themselves.
+    pub tick_period: u32,
+    /// What failed [`rt_assert!`](../macro.rt_assert.html)s and [`fatal`](fn.fatal.html) do once
+    /// the crash is recorded
+    pub panic: PanicPolicy,
+    /// Where [crashes](../crash/index.html) are reported besides the crash region
+    pub log: LogSinks,
+    /// What the runtime does with the [stack](../stack/index.html)
+    pub stack: StackPolicy,
+}
+
+/// What fatal errors do once the crash is recorded
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Restart the firmware from `_start`
+    Restart,
+    /// [`park`](fn.park.html) the CPU
+    Halt,
+}
+
+/// Where crashes are reported, each sink also needs its feature
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LogSinks {
+    /// The installed [event log](../event_log/index.html), with `event-log`
+    pub event_log: bool,
+    /// A [blink code](../blink/index.html), with `fault-blink`
+    pub blink: bool,
+}
+
+/// What the runtime does with the stack, each part also needs its feature
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StackPolicy {
+    /// Fill the free stack with [`PAINT`](../stack/constant.PAINT.html) at startup, with
+    /// `stack-paint`
+    pub paint: bool,
+    /// Put a [`CANARY`](../stack/constant.CANARY.html) at the bottom of the stack at startup and
+    /// check it in `wfi` and after every trap, with `stack-canary`
+    pub canary: bool,
+}
+
+/// Configuration used for the fields `runtime_config!` leaves out, or without it
+pub const DEFAULT_CONFIG: RuntimeConfig = RuntimeConfig {
+    clock_hz: 0,
+    tick_period: 0,
+    panic: PanicPolicy::Restart,
+    log: LogSinks {
+        event_log: true,
+        blink: true,
+    },
+    stack: StackPolicy {
+        paint: true,
+        canary: true,
+    },
+};
+
+/// The configuration of the runtime
+pub fn config() -> &'static RuntimeConfig {
+    extern "C" {
+        // Defined by `runtime_config!`, otherwise it defaults to `DEFAULT_CONFIG`
+        static __picorv32_rt_config: RuntimeConfig;
+    }
+
+    unsafe { &__picorv32_rt_config }
+}
+
+/// Stops the firmware as the [`PanicPolicy`](enum.PanicPolicy.html) says, meant to be called
+/// from the panic handler after [`crash::record_panic`](../crash/fn.record_panic.html)
+pub fn fatal() -> ! {
+    match config().panic {
+        PanicPolicy::Restart => restart(),
+        PanicPolicy::Halt => park(None, None),
+    }
+}
+
+/// Applies the configuration, called before `main`
+pub(crate) fn configure() {
+    let config = config();
+
+    #[cfg(feature = "fault-blink")]
+    {
+        if config.clock_hz != 0 {
+            // about 4 cycles per spin, so a short blink lasts about a quarter of a second
+            crate::blink::set_unit(config.clock_hz >> 4);
+        }
+    }
+
+    #[cfg(all(
+        feature = "interrupts",
+        not(any(feature = "timers", feature = "embassy"))
+    ))]
+    {
+        if config.tick_period != 0 {
+            unsafe { picorv32::asm::timer(config.tick_period) };
+        }
+    }
+
+    let _ = config;
+}
+
+/// Restarts the tick if it's IRQ0 that fired, called by the trap handler
+#[cfg(all(
+    feature = "interrupts",
+    not(any(feature = "timers", feature = "embassy"))
+))]
+#[inline]
+pub(crate) fn tick(irqs: u32) {
+    if irqs & (1 << crate::timer::IRQ) != 0 {
+        let period = config().tick_period;
+        if period != 0 {
+            unsafe { picorv32::asm::timer(period) };
+        }
+    }
+}
+
+/// Token passed to a `#[pre_init]` function declared as `fn(PreInit)`
+///
+/// It stands for the startup code running before RAM is initialized, and offers the volatile
+/// accesses to MMIO registers that are the usual business there, without `unsafe`. Pointing them
+/// at memory the program uses breaks it as much as it sounds.
+pub struct PreInit {
+    _private: (),
+}
+
+impl PreInit {
+    #[doc(hidden)]
+    pub unsafe fn new() -> PreInit {
+        PreInit { _private: () }
+    }
+
+    /// Reads the 32-bit register at `addr`
+    #[inline]
+    pub fn read(&self, addr: usize) -> u32 {
+        unsafe { ptr::read_volatile(addr as *const u32) }
+    }
+
+    /// Writes `value` to the 32-bit register at `addr`
+    #[inline]
+    pub fn write(&self, addr: usize, value: u32) {
+        unsafe { ptr::write_volatile(addr as *mut u32, value) }
+    }
+
+    /// Reads the 32-bit register at `addr`, then writes back what `f` makes of the value
+    #[inline]
+    pub fn modify<F: FnOnce(u32) -> u32>(&self, addr: usize, f: F) {
+        let value = self.read(addr);
+        self.write(addr, f(value));
+    }
+}
+
+/// Why the firmware started, see [`boot_cause`](fn.boot_cause.html)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BootCause {
+    /// RAM didn't hold the contents of a previous run, e.g. after power-on
+    PowerOn,
+    /// The firmware restarted itself, e.g. after a failed [`rt_assert!`](../macro.rt_assert.html)
+    SoftRestart,
+    /// A reset that kept RAM, e.g. by a watchdog, the reset pin or a debugger
+    Reset,
+}
+
+/// `BOOT_STATE` of a running firmware
+const BOOT_RUNNING: u32 = 0x424f_4f54;
+/// `BOOT_STATE` of a firmware restarting itself
+const BOOT_RESTARTING: u32 = 0x5253_5452;
+
+/// Survives resets that keep RAM, random after power-on
+#[link_section = ".uninit.boot_state"]
+static mut BOOT_STATE: u32 = 0;
+
+/// Set before `main`
+static mut BOOT_CAUSE: BootCause = BootCause::PowerOn;
+
+/// Why the firmware started
+///
+/// A marker in [`.uninit`](../index.html#uninit) RAM tells whether a previous run left it
+/// there. RAM that powers up with the marker in it by chance is taken for a `Reset`, which is
+/// unlikely but possible.
+pub fn boot_cause() -> BootCause {
+    unsafe { ptr::read_volatile(ptr::addr_of!(BOOT_CAUSE)) }
+}
+
+/// Tells the boot cause from the marker left by the previous run, called before `main`
+pub(crate) fn detect_boot_cause() {
+    unsafe {
+        let cause = match ptr::read_volatile(ptr::addr_of!(BOOT_STATE)) {
+            BOOT_RUNNING => BootCause::Reset,
+            BOOT_RESTARTING => BootCause::SoftRestart,
+            _ => BootCause::PowerOn,
+        };
+        ptr::write_volatile(ptr::addr_of_mut!(BOOT_CAUSE), cause);
+        ptr::write_volatile(ptr::addr_of_mut!(BOOT_STATE), BOOT_RUNNING);
+    }
+}
+
+/// Resets the firmware in software, for SoCs without a reset controller
+///
+/// In order:
+///
+/// 1. masks all IRQs
+/// 2. calls `__on_soft_reset`, which can be provided by the application to put peripherals back
+///    into a quiet state, e.g. stop DMA transfers or turn off IRQ sources (no-op by default)
+/// 3. jumps to `_start`, which resets the stack pointer to `_stack_start` and re-initializes
+///    `.data` and `.bss` on the way to `main`
+///
+/// It can be called from a trap handler too, the trap is left with `retirq` on the way to
+/// `_start`.
+///
+/// Peripherals aren't reset by this, only the CPU state the runtime sets up is. `.uninit` is
+/// left alone, and the next boot sees [`BootCause::SoftRestart`](enum.BootCause.html).
+pub fn soft_reset() -> ! {
+    extern "Rust" {
+        // This symbol can be provided by the user, otherwise it defaults to a no-op
+        fn __on_soft_reset();
+    }
+
+    interrupt::disable();
+
+    unsafe { __on_soft_reset() };
+
+    restart()
+}
+
+/// Restarts the firmware from `_start` with all IRQs masked, re-initializing `.data` and `.bss`
+///
+/// Called from a trap, it leaves the trap on the way: PicoRV32 takes no IRQ again until `retirq`.
+pub(crate) fn restart() -> ! {
+    extern "C" {
+        fn __picorv32_rt_restart() -> !;
+    }
+
+    interrupt::disable();
+    unsafe {
+        ptr::write_volatile(ptr::addr_of_mut!(BOOT_STATE), BOOT_RESTARTING);
+        __picorv32_rt_restart()
+    }
+}
@@ -0,0 +1,209 @@
+//! Cooperative scheduler
+//!
+//! With the `scheduler` feature a handful of tasks can run on their own stacks, for firmware that
+//! has outgrown a superloop but doesn't need an RTOS. Tasks are [`spawn`](fn.spawn.html)ed with a
+//! statically allocated stack and switch only when they call [`yield_now`](fn.yield_now.html) or
+//! [`park`](fn.park.html), so they never preempt each other.
+//!
+//! The code calling [`start`](fn.start.html), usually `main`, becomes the idle task. It runs
+//! whenever no task is ready and sleeps in `waitirq` until an IRQ handler
+//! [`unpark`](fn.unpark.html)s one.
+//!
+//! ```
+//! use picorv32_rt::scheduler::{self, TaskId};
+//!
+//! static mut UART_STACK: [u32; 256] = [0; 256];
+//! static mut UART_TASK: Option<TaskId> = None;
+//!
+//! fn uart() -> ! {
+//!     loop {
+//!         // wait for the RX interrupt
+//!         scheduler::park();
+//!         // read the UART
+//!     }
+//! }
+//!
+//! pub fn irq5(_regs: &picorv32_rt::PicoRV32StoredRegisters) {
+//!     if let Some(task) = unsafe { UART_TASK } {
+//!         scheduler::unpark(task);
+//!     }
+//! }
+//!
+//! // called from `main`
+//! fn run() -> ! {
+//!     unsafe { UART_TASK = scheduler::spawn(uart, &mut UART_STACK) };
+//!     scheduler::start()
+//! }
+//! ```
+
+use picorv32::asm;
+
+use crate::interrupt;
+
+/// Maximum number of tasks, not counting the idle task
+pub const CAPACITY: usize = 8;
+
+/// Words pushed by `__picorv32_rt_switch`: `ra` and `s0`..`s11`
+const SWITCH_FRAME_WORDS: usize = 13;
+
+/// Identifier of a spawned task
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TaskId(u8);
+
+impl TaskId {
+    /// Index of the task, in the order tasks were spawned
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Task {
+    /// Saved stack pointer while the task isn't running
+    sp: usize,
+    entry: fn() -> !,
+}
+
+/// Only accessed in critical sections, `READY` and `TOKENS` from the trap handler as well
+static mut TASKS: [Option<Task>; CAPACITY] = [None; CAPACITY];
+/// Tasks that haven't parked
+static mut READY: u32 = 0;
+/// Tasks unparked since they last parked
+static mut TOKENS: u32 = 0;
+/// Running task, `None` for the idle task
+static mut CURRENT: Option<usize> = None;
+/// Saved stack pointer of the idle task while a task is running
+static mut IDLE_SP: usize = 0;
+
+extern "C" {
+    fn __picorv32_rt_switch(save_sp: *mut usize, sp: usize);
+}
+
+/// Adds a task running `entry` on `stack`, returns `None` if there are
+/// [`CAPACITY`](constant.CAPACITY.html) tasks already
+///
+/// The task is ready right away and first runs when the current one yields.
+///
+/// # Panics
+///
+/// If `stack` is too small to start the task
+pub fn spawn(entry: fn() -> !, stack: &'static mut [u32]) -> Option<TaskId> {
+    let bottom = stack.as_ptr() as usize;
+    let top = (bottom + stack.len() * 4) & !15;
+    assert!(
+        top >= bottom + SWITCH_FRAME_WORDS * 4,
+        "task stack too small"
+    );
+
+    // what `__picorv32_rt_switch` pops when the task is switched to the first time
+    let sp = top - SWITCH_FRAME_WORDS * 4;
+    let frame = &mut stack[(sp - bottom) / 4..][..SWITCH_FRAME_WORDS];
+    for word in frame.iter_mut() {
+        *word = 0;
+    }
+    frame[0] = run_task as *const () as usize as u32;
+
+    interrupt::free(|_| unsafe {
+        let index = TASKS.iter().position(Option::is_none)?;
+        TASKS[index] = Some(Task { sp, entry });
+        READY |= 1 << index;
+        Some(TaskId(index as u8))
+    })
+}
+
+/// The running task, `None` for the idle task
+pub fn current() -> Option<TaskId> {
+    interrupt::free(|_| unsafe { CURRENT }.map(|index| TaskId(index as u8)))
+}
+
+/// Lets the next ready task run, returns once all the others have had their turn
+pub fn yield_now() {
+    if let Some((save_sp, sp)) = interrupt::free(|_| switch_to(next_ready())) {
+        unsafe { __picorv32_rt_switch(save_sp, sp) };
+    }
+}
+
+/// Blocks the running task until it's [`unpark`](fn.unpark.html)ed
+///
+/// Returns right away if it has been unparked since it last parked. In the idle task it's the
+/// same as [`yield_now`](fn.yield_now.html).
+pub fn park() {
+    if current().is_none() {
+        return yield_now();
+    }
+
+    // resumed only after an `unpark`, whose token is taken on the next round
+    loop {
+        let switch = interrupt::free(|_| unsafe {
+            let bit = 1 << CURRENT.unwrap();
+            if TOKENS & bit != 0 {
+                TOKENS &= !bit;
+                return None;
+            }
+            READY &= !bit;
+            switch_to(next_ready())
+        });
+
+        match switch {
+            Some((save_sp, sp)) => unsafe { __picorv32_rt_switch(save_sp, sp) },
+            None => break,
+        }
+    }
+}
+
+/// Makes `task` ready again, can be called from interrupt handlers
+pub fn unpark(task: TaskId) {
+    interrupt::free(|_| unsafe {
+        TOKENS |= 1 << task.0;
+        READY |= 1 << task.0;
+    });
+}
+
+/// Runs the tasks, the caller becomes the idle task
+pub fn start() -> ! {
+    loop {
+        let mask = interrupt::disable();
+        if unsafe { READY } == 0 {
+            // IRQs are masked, so one that arrives in between still ends the wait
+            unsafe { asm::waitirq() };
+        }
+        unsafe { interrupt::restore(mask) };
+
+        yield_now();
+    }
+}
+
+/// First ready task after the running one, round robin, `None` if no task is ready
+fn next_ready() -> Option<usize> {
+    let ready = unsafe { READY };
+    let start = unsafe { CURRENT }.map_or(0, |index| index + 1);
+    (0..CAPACITY)
+        .map(|n| (start + n) % CAPACITY)
+        .find(|&index| ready & 1 << index != 0)
+}
+
+/// Makes `next` the running task, returns where to save the stack pointer of the current one
+/// and the stack pointer to switch to, `None` if it's running already
+fn switch_to(next: Option<usize>) -> Option<(*mut usize, usize)> {
+    unsafe {
+        if next == CURRENT {
+            return None;
+        }
+        let save_sp = match CURRENT {
+            Some(index) => &mut TASKS[index].as_mut().unwrap().sp as *mut usize,
+            None => &mut IDLE_SP as *mut usize,
+        };
+        let sp = match next {
+            Some(index) => TASKS[index].unwrap().sp,
+            None => IDLE_SP,
+        };
+        CURRENT = next;
+        Some((save_sp, sp))
+    }
+}
+
+/// Where a task starts, `__picorv32_rt_switch` returns here the first time it's switched to
+extern "C" fn run_task() -> ! {
+    let entry = interrupt::free(|_| unsafe { TASKS[CURRENT.unwrap()].unwrap().entry });
+    entry()
+}
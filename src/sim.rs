@@ -0,0 +1,46 @@
+//! Ending a simulation
+//!
+//! Verilator and iverilog testbenches usually stop when the firmware writes to a magic address.
+//! With the `sim` feature [`exit`](fn.exit.html) writes the exit code there, `(code << 1) | 1` like
+//! the `tohost` convention of `riscv-tests`, so `1` is a pass and anything else a failure with
+//! code `value >> 1`. The address is `_tohost`, set in `memory.x`:
+//!
+//! ``` text
+//! _tohost = 0x10000004;
+//! ```
+//!
+//! Without `_tohost` nothing is written, and the firmware just halts. Test firmware can end the
+//! simulation from `main` or its panic handler:
+//!
+//! ```
+//! use picorv32_rt::sim;
+//!
+//! fn run_tests() -> ! {
+//!     let failures = 0;
+//!     // ...
+//!     sim::exit(failures)
+//! }
+//! ```
+
+use core::ptr;
+
+use crate::runtime;
+
+/// Ends the simulation with `code`, 0 for a pass, and halts
+///
+/// Halting parks the CPU like [`runtime::park`](../runtime/fn.park.html), for testbenches that
+/// only stop after a few more cycles or for hardware without a testbench.
+pub fn exit(code: u32) -> ! {
+    extern "C" {
+        // Defined by link.x, 1 if memory.x sets `_tohost`
+        static _has_tohost: u8;
+        static mut _tohost: u32;
+    }
+
+    unsafe {
+        if &_has_tohost as *const u8 as usize != 0 {
+            ptr::write_volatile(&mut _tohost, code << 1 | 1);
+        }
+    }
+    runtime::park(None, None)
+}
@@ -0,0 +1,163 @@
+//! Stack usage measurement and overflow detection
+//!
+//! There's no MPU or debug unit to catch a stack growing too large, so the usual way to size it
+//! is to measure. With the `stack-paint` feature the startup code fills the free part of the
+//! stack, between the `_estack` symbol and the stack pointer, with [`PAINT`](constant.PAINT.html)
+//! before anything else runs, above the canary if there is one. The deepest the stack has ever
+//! grown is where the paint ends, which [`stack_usage`](fn.stack_usage.html) and
+//! [`stack_free`](fn.stack_free.html) find by scanning up from `_estack`.
+//!
+//! Painting can be turned off in the [`StackPolicy`](../runtime/struct.StackPolicy.html) of the
+//! runtime configuration, e.g. to shorten the boot of a release build, and so can the canary
+//! below. Without paint the stack counts as fully used.
+//!
+//! The scan takes a while on a large stack, so it's best done once the firmware has been through
+//! its deepest paths, e.g. from a debug command. A frame that reserves space it doesn't write can
+//! hide below the watermark, so the result is a lower bound.
+//!
+//! ```
+//! use picorv32_rt::stack;
+//!
+//! fn report(sink: &mut impl core::fmt::Write) -> core::fmt::Result {
+//!     writeln!(
+//!         sink,
+//!         "stack: {} of {} bytes used",
+//!         stack::stack_usage(),
+//!         stack::stack_size()
+//!     )
+//! }
+//! ```
+//!
+//! # Canary
+//!
+//! With the `stack-canary` feature the startup code puts [`CANARY`](constant.CANARY.html) in the
+//! lowest word of the stack, which is overwritten once the stack overflows.
+//! [`check_stack_canary`](fn.check_stack_canary.html) calls the `StackOverflow` hook if it's gone,
+//! and it's checked automatically whenever [`wfi`](../fn.wfi.html) returns and at the end of every
+//! trap, so an overflow is noticed close to where it happened. The hook can be provided by the
+//! application, by default it stops the firmware with
+//! [`runtime::fatal`](../runtime/fn.fatal.html). The stack can't be trusted anymore when it's
+//! called, so it must not return.
+//!
+//! Called at the end of a trap, the hook still runs in it, and PicoRV32 takes no IRQ until the
+//! trap is left with `retirq`. `runtime::fatal` and
+//! [`runtime::soft_reset`](../runtime/fn.soft_reset.html) do that on the way to `_start`, so
+//! the restarted firmware gets its IRQs back. A hook that restarts by jumping to `_start` itself
+//! leaves the core unable to take IRQs, including the traps of `ebreak`, `ecall` and illegal
+//! instructions.
+//!
+//! ``` ignore
+//! #[no_mangle]
+//! pub fn StackOverflow() -> ! {
+//!     // e.g. record the overflow in a `#[no_init]` static and restart
+//!     picorv32_rt::runtime::soft_reset()
+//! }
+//! ```
+
+use core::ptr;
+
+#[cfg(any(feature = "stack-canary", feature = "stack-paint"))]
+use crate::runtime;
+
+/// Word the free part of the stack is filled with at startup
+#[cfg(feature = "stack-paint")]
+pub const PAINT: u32 = 0xcccc_cccc;
+
+extern "C" {
+    #[cfg(feature = "stack-paint")]
+    fn __picorv32_rt_stack_pointer() -> u32;
+
+    static _estack: u8;
+    static _sstack: u8;
+}
+
+/// Size of the stack in bytes, from `_estack` up to `_sstack`
+pub fn stack_size() -> usize {
+    unsafe { &_sstack as *const u8 as usize - &_estack as *const u8 as usize }
+}
+
+/// Bytes of the stack that have never been used since startup, 0 if it wasn't painted
+#[cfg(feature = "stack-paint")]
+pub fn stack_free() -> usize {
+    let bottom = paint_bottom();
+    let top = unsafe { &_sstack as *const u8 as usize };
+
+    let mut addr = bottom;
+    while addr < top && unsafe { ptr::read_volatile(addr as *const u32) } == PAINT {
+        addr += 4;
+    }
+    addr - bottom
+}
+
+/// High-watermark of the stack, the most bytes it has held since startup
+#[cfg(feature = "stack-paint")]
+pub fn stack_usage() -> usize {
+    stack_size() - stack_free()
+}
+
+/// Fills the stack below the current frame with `PAINT`, called by the startup code
+#[cfg(feature = "stack-paint")]
+pub(crate) unsafe fn paint() {
+    if !runtime::config().stack.paint {
+        return;
+    }
+
+    let bottom = paint_bottom();
+    let sp = __picorv32_rt_stack_pointer() as usize;
+
+    let mut addr = bottom;
+    while addr < sp {
+        ptr::write_volatile(addr as *mut u32, PAINT);
+        addr += 4;
+    }
+}
+
+/// Word put at the bottom of the stack at startup
+#[cfg(feature = "stack-canary")]
+pub const CANARY: u32 = 0xdead_c0de;
+
+/// Whether the canary at the bottom of the stack is still there, always `true` if the
+/// [`StackPolicy`](../runtime/struct.StackPolicy.html) turns it off
+#[cfg(feature = "stack-canary")]
+pub fn stack_canary_intact() -> bool {
+    !runtime::config().stack.canary
+        || unsafe { ptr::read_volatile(bottom() as *const u32) == CANARY }
+}
+
+/// Calls the `StackOverflow` hook if the canary at the bottom of the stack is gone
+#[cfg(feature = "stack-canary")]
+pub fn check_stack_canary() {
+    extern "Rust" {
+        // This symbol can be provided by the user, otherwise it defaults to `runtime::fatal`
+        fn StackOverflow() -> !;
+    }
+
+    if !stack_canary_intact() {
+        unsafe { StackOverflow() }
+    }
+}
+
+/// Puts the canary at the bottom of the stack, called by the startup code
+#[cfg(feature = "stack-canary")]
+pub(crate) unsafe fn place_canary() {
+    if runtime::config().stack.canary {
+        ptr::write_volatile(bottom() as *mut u32, CANARY);
+    }
+}
+
+/// Lowest word of the stack
+fn bottom() -> usize {
+    (unsafe { &_estack as *const u8 as usize } + 3) & !3
+}
+
+/// Lowest painted word of the stack, the canary takes the one below it
+#[cfg(feature = "stack-paint")]
+fn paint_bottom() -> usize {
+    #[cfg(feature = "stack-canary")]
+    {
+        if runtime::config().stack.canary {
+            return bottom() + 4;
+        }
+    }
+    bottom()
+}
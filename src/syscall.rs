@@ -0,0 +1,141 @@
+//! `ecall` based system calls
+//!
+//! With the `syscalls` feature an `ecall` traps into the runtime, which takes the syscall number
+//! from `a7` and the arguments from `a0`..`a5` of the interrupted code, calls `syscall_handler`
+//! and writes its return value back to `a0` before resuming right after the `ecall`.
+//!
+//! `syscall_handler` is declared with the [`picorv32_syscalls!`](../macro.picorv32_syscalls.html)
+//! macro, either as a list of handler functions or as a [`SyscallTable`](trait.SyscallTable.html)
+//! instance. Without it every syscall returns [`ENOSYS`](constant.ENOSYS.html).
+//!
+//! ```
+//! use picorv32_rt::picorv32_syscalls;
+//! use picorv32_rt::syscall::Args;
+//!
+//! pub fn sys_write(args: &Args) -> u32 {
+//!     // args[0]: fd, args[1]: buffer, args[2]: length
+//!     args[2]
+//! }
+//!
+//! pub fn sys_uptime(_args: &Args) -> u32 {
+//!     0
+//! }
+//!
+//! picorv32_syscalls!(
+//!     64: sys_write,
+//!     100: sys_uptime
+//! );
+//! ```
+//!
+//! Application code then issues syscalls with [`syscall`](fn.syscall.html).
+
+use crate::PicoRV32StoredRegisters;
+
+/// Syscall arguments, `a0`..`a5` of the caller
+pub type Args = [u32; 6];
+
+/// Returned for syscall numbers without a handler (`-ENOSYS`)
+pub const ENOSYS: u32 = -38i32 as u32;
+
+/// A table of syscalls
+pub trait SyscallTable: Sync {
+    /// Performs syscall `nr`
+    fn call(&self, nr: u32, args: &Args) -> u32;
+}
+
+/// Issues syscall `nr` with the given arguments and returns the result
+///
+/// # Safety
+///
+/// The syscall may do anything it likes with the arguments, e.g. dereference them.
+#[inline]
+pub unsafe fn syscall(nr: u32, args: &Args) -> u32 {
+    extern "C" {
+        fn __picorv32_rt_ecall(
+            a0: u32,
+            a1: u32,
+            a2: u32,
+            a3: u32,
+            a4: u32,
+            a5: u32,
+            nr: u32,
+        ) -> u32;
+    }
+
+    __picorv32_rt_ecall(args[0], args[1], args[2], args[3], args[4], args[5], nr)
+}
+
+/// Dispatches an `ecall` trapped via IRQ1
+pub(crate) fn handle(regs: &mut PicoRV32StoredRegisters) -> bool {
+    extern "Rust" {
+        // This symbol will be provided by the user via `picorv32_syscalls!`
+        fn syscall_handler(nr: u32, args: &Args) -> u32;
+    }
+
+    let args = [
+        regs.x10(),
+        regs.x11(),
+        regs.x12(),
+        regs.x13(),
+        regs.x14(),
+        regs.x15(),
+    ];
+    let ret = unsafe { syscall_handler(regs.x17(), &args) };
+    regs.set_x10(ret);
+
+    true
+}
+
+/// Declares the syscall handler
+///
+/// Either from a list of `number: handler` pairs, where each handler is a
+/// `fn(&picorv32_rt::syscall::Args) -> u32`:
+///
+/// ```
+/// # use picorv32_rt::picorv32_syscalls;
+/// # use picorv32_rt::syscall::Args;
+/// pub fn sys_getpid(_args: &Args) -> u32 {
+///     1
+/// }
+///
+/// picorv32_syscalls!(172: sys_getpid);
+/// ```
+///
+/// or from a `static` implementing [`SyscallTable`](syscall/trait.SyscallTable.html):
+///
+/// ```
+/// use picorv32_rt::picorv32_syscalls;
+/// use picorv32_rt::syscall::{Args, SyscallTable};
+///
+/// struct Kernel;
+///
+/// impl SyscallTable for Kernel {
+///     fn call(&self, nr: u32, args: &Args) -> u32 {
+///         nr + args[0]
+///     }
+/// }
+///
+/// static KERNEL: Kernel = Kernel;
+///
+/// picorv32_syscalls!(table: KERNEL);
+/// ```
+#[macro_export]
+macro_rules! picorv32_syscalls {
+    (table: $table:path) => {
+        #[no_mangle]
+        pub fn syscall_handler(nr: u32, args: &$crate::syscall::Args) -> u32 {
+            $crate::syscall::SyscallTable::call(&$table, nr, args)
+        }
+    };
+    ( $( $nr:literal : $handler:ident ),* ) => {
+        #[no_mangle]
+        pub fn syscall_handler(nr: u32, args: &$crate::syscall::Args) -> u32 {
+            match nr {
+                $(
+                    $nr => $handler(args),
+                )*
+                _ => $crate::syscall::ENOSYS,
+            }
+        }
+    };
+}
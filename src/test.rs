@@ -0,0 +1,126 @@
+//! On-target test harness
+//!
+//! With the `test-harness` feature functions declared with the
+//! [`picorv32_test`](../attr.picorv32_test.html) attribute are collected in a table, and
+//! [`run`](fn.run.html) runs them one after the other, e.g. in a simulation or on a board:
+//!
+//! ``` ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! use picorv32_rt::{entry, picorv32_test, test};
+//!
+//! #[picorv32_test]
+//! fn addition() {
+//!     assert_eq!(1 + 1, 2);
+//! }
+//!
+//! #[entry]
+//! fn main() -> ! {
+//!     test::run()
+//! }
+//! ```
+//!
+//! The results go to the `__panic_sink` hook, which serves as the console, in the format of
+//! `cargo test`. A test fails by panicking: the [panic handler](../panic_handler/index.html)
+//! reports the panic, marks the test as failed and restarts the firmware, and `run` goes on with
+//! the next test. The progress is kept in [`.uninit`](../index.html#uninit) across the restarts,
+//! so the order of tests is the order they're linked in, and state a failing test left behind in
+//! `.data` and `.bss` doesn't leak into the next one.
+//!
+//! At the end the simulation [exits](../sim/fn.exit.html) with the number of failed tests, 0 if
+//! all of them passed, so a `cargo` runner that starts the simulator can report the result.
+
+use core::fmt::Write;
+use core::{ptr, slice};
+
+use crate::panic_handler::Sink;
+use crate::runtime::{self, BootCause};
+use crate::sim;
+
+/// A registered test, created by `#[picorv32_test]`
+#[doc(hidden)]
+#[repr(C)]
+pub struct Test {
+    pub name: &'static str,
+    pub f: fn(),
+}
+
+/// `"TEST"`, marks a run in progress
+const MAGIC: u32 = 0x5453_4554;
+
+/// Magic, index of the running test, passed and failed tests
+#[link_section = ".uninit.test"]
+static mut STATE: [u32; 4] = [0; 4];
+
+fn tests() -> &'static [Test] {
+    extern "C" {
+        // Boundaries of the .picorv32_tests input sections
+        static __spicorv32_tests: u32;
+        static __epicorv32_tests: u32;
+    }
+
+    unsafe {
+        let start = &__spicorv32_tests as *const u32 as *const Test;
+        let end = &__epicorv32_tests as *const u32 as *const Test;
+        slice::from_raw_parts(
+            start,
+            (end as usize - start as usize) / core::mem::size_of::<Test>(),
+        )
+    }
+}
+
+fn state() -> [u32; 4] {
+    unsafe { ptr::read_volatile(&STATE) }
+}
+
+fn set_state(state: [u32; 4]) {
+    unsafe { ptr::write_volatile(&mut STATE, state) };
+}
+
+/// Runs all tests, or the rest of them after one failed, and exits the simulation
+pub fn run() -> ! {
+    let tests = tests();
+    let mut sink = Sink;
+
+    // a run is only resumed after the restart of a failed test
+    let [magic, mut next, mut passed, mut failed] = state();
+    if magic != MAGIC || runtime::boot_cause() != BootCause::SoftRestart {
+        next = 0;
+        passed = 0;
+        failed = 0;
+        let _ = writeln!(sink, "\nrunning {} tests", tests.len());
+    }
+
+    while let Some(test) = tests.get(next as usize) {
+        set_state([MAGIC, next, passed, failed]);
+        let _ = write!(sink, "test {} ... ", test.name);
+        (test.f)();
+        let _ = writeln!(sink, "ok");
+        passed += 1;
+        next += 1;
+    }
+    set_state([0; 4]);
+
+    let _ = writeln!(
+        sink,
+        "\ntest result: {}. {} passed; {} failed\n",
+        if failed == 0 { "ok" } else { "FAILED" },
+        passed,
+        failed
+    );
+    sim::exit(failed)
+}
+
+/// Marks the running test as failed and restarts the firmware to go on with the next one,
+/// returns if no test is running
+pub(crate) fn failed() {
+    let [magic, next, passed, failed] = state();
+    if magic != MAGIC {
+        return;
+    }
+
+    let _ = writeln!(Sink, "test {} ... FAILED", tests()[next as usize].name);
+    set_state([MAGIC, next + 1, passed, failed + 1]);
+    runtime::restart()
+}
@@ -0,0 +1,102 @@
+//! The PicoRV32 timer
+//!
+//! A core built with `ENABLE_IRQ_TIMER` has a countdown timer that raises IRQ0 when it reaches
+//! zero. [`Timer`](struct.Timer.html) wraps the `timer` instruction, and the handler of IRQ0 can
+//! be declared as `timer` in [`picorv32_interrupts!`](../macro.picorv32_interrupts.html).
+//!
+//! ```
+//! use picorv32_rt::timer::Timer;
+//!
+//! fn tick(_regs: &picorv32_rt::PicoRV32StoredRegisters) {
+//!     // rearm for the next tick
+//!     let mut timer = unsafe { Timer::steal() };
+//!     timer.start_oneshot(12_000_000);
+//! }
+//!
+//! picorv32_rt::picorv32_interrupts!(timer: tick);
+//!
+//! fn init() {
+//!     let mut timer = Timer::take().unwrap();
+//!     timer.start_oneshot(12_000_000);
+//! }
+//! ```
+
+use picorv32::asm;
+
+use crate::interrupt;
+
+/// IRQ line of the timer
+pub const IRQ: u32 = 0;
+
+/// Whether `Timer::take` has been called, only accessed in critical sections
+static mut TAKEN: bool = false;
+
+/// The timer
+///
+/// With the `timers` or the `embassy` feature, or a `tick_period` in the
+/// [runtime configuration](../runtime/struct.RuntimeConfig.html), the runtime owns the timer and
+/// [`take`](#method.take) always returns `None`.
+pub struct Timer {
+    _private: (),
+}
+
+impl Timer {
+    /// Takes the timer, returns `None` if it has been taken already
+    pub fn take() -> Option<Timer> {
+        if cfg!(any(feature = "timers", feature = "embassy"))
+            || crate::runtime::config().tick_period != 0
+        {
+            return None;
+        }
+        interrupt::free(|_| unsafe {
+            if TAKEN {
+                None
+            } else {
+                TAKEN = true;
+                Some(Timer { _private: () })
+            }
+        })
+    }
+
+    /// Returns the timer regardless of whether it has been taken
+    ///
+    /// # Safety
+    ///
+    /// The other owners of the timer see it changing underneath them.
+    pub unsafe fn steal() -> Timer {
+        Timer { _private: () }
+    }
+
+    /// Raises IRQ0 in `cycles` cycles, replacing the running countdown, if any
+    ///
+    /// 0 stops the timer.
+    #[inline]
+    pub fn start_oneshot(&mut self, cycles: u32) {
+        unsafe { asm::timer(cycles) };
+    }
+
+    /// Stops the timer, returns the number of cycles that were left
+    #[inline]
+    pub fn cancel(&mut self) -> u32 {
+        unsafe { asm::timer(0) }
+    }
+
+    /// Number of cycles left before IRQ0 is raised, 0 if the timer isn't running
+    ///
+    /// The only way to read the timer is to stop it and start it again, which makes the
+    /// countdown a few cycles longer.
+    pub fn remaining(&self) -> u32 {
+        interrupt::free(|_| unsafe {
+            let remaining = asm::timer(0);
+            if remaining != 0 {
+                asm::timer(remaining);
+            }
+            remaining
+        })
+    }
+
+    /// Whether the countdown is running
+    pub fn is_running(&self) -> bool {
+        self.remaining() != 0
+    }
+}
@@ -0,0 +1,165 @@
+//! Software timers multiplexed onto the PicoRV32 timer
+//!
+//! With the `timers` feature the runtime handles IRQ0 itself: it calls the callbacks of all expired
+//! timers and rearms the hardware timer for the nearest remaining deadline. IRQ0 doesn't reach
+//! `trap_handler` then, but it still has to be unmasked, and [`Timer::take`] returns `None`.
+//!
+//! [`Timer::take`]: ../timer/struct.Timer.html#method.take
+//!
+//! Deadlines are kept in cycles, so the core has to be built with `ENABLE_COUNTERS` and
+//! `ENABLE_IRQ_TIMER`. Callbacks run in the trap handler, like any other interrupt handler.
+//!
+//! ```
+//! use picorv32_rt::timers;
+//!
+//! fn blink(led: u32) {
+//!     // toggle `led`
+//! }
+//!
+//! fn init() {
+//!     // every 12M cycles, i.e. once a second at 12MHz
+//!     timers::periodic(12_000_000, blink, 0);
+//! }
+//! ```
+
+use crate::timer::{self, Timer};
+use crate::{interrupt, runtime};
+
+/// Maximum number of active timers
+pub const CAPACITY: usize = 16;
+
+/// IRQ line of the hardware timer
+pub const IRQ: u32 = timer::IRQ;
+
+/// Handle of an active timer
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TimerId {
+    slot: usize,
+    generation: u32,
+}
+
+#[derive(Copy, Clone)]
+struct Slot {
+    active: bool,
+    /// Bumped every time the slot is reused, so stale `TimerId`s don't match
+    generation: u32,
+    /// Cycle stamp
+    deadline: u32,
+    /// 0 for one-shot timers
+    period: u32,
+    f: fn(u32),
+    arg: u32,
+}
+
+fn nop(_arg: u32) {}
+
+const FREE: Slot = Slot {
+    active: false,
+    generation: 0,
+    deadline: 0,
+    period: 0,
+    f: nop,
+    arg: 0,
+};
+
+/// Only accessed in critical sections and from the trap handler
+static mut SLOTS: [Slot; CAPACITY] = [FREE; CAPACITY];
+
+/// Calls `f(arg)` once, `delay` cycles from now, returns `None` if all timers are in use
+pub fn oneshot(delay: u32, f: fn(u32), arg: u32) -> Option<TimerId> {
+    start(delay, 0, f, arg)
+}
+
+/// Calls `f(arg)` every `period` cycles, starting `period` cycles from now, returns `None` if all
+/// timers are in use
+///
+/// # Panics
+///
+/// If `period` is 0
+pub fn periodic(period: u32, f: fn(u32), arg: u32) -> Option<TimerId> {
+    assert!(period != 0);
+    start(period, period, f, arg)
+}
+
+/// Stops a timer, returns `false` if it isn't active anymore
+pub fn cancel(id: TimerId) -> bool {
+    interrupt::free(|_| {
+        let slot = unsafe { &mut SLOTS[id.slot] };
+        if !slot.active || slot.generation != id.generation {
+            return false;
+        }
+        slot.active = false;
+        rearm(runtime::cycles());
+        true
+    })
+}
+
+fn start(delay: u32, period: u32, f: fn(u32), arg: u32) -> Option<TimerId> {
+    interrupt::free(|_| {
+        let slots = unsafe { &mut SLOTS };
+        let index = slots.iter().position(|slot| !slot.active)?;
+        let now = runtime::cycles();
+        let slot = &mut slots[index];
+        *slot = Slot {
+            active: true,
+            generation: slot.generation.wrapping_add(1),
+            deadline: now.wrapping_add(delay),
+            period,
+            f,
+            arg,
+        };
+        let id = TimerId {
+            slot: index,
+            generation: slot.generation,
+        };
+        rearm(now);
+        Some(id)
+    })
+}
+
+/// Whether `deadline` is at or before `now`
+fn expired(deadline: u32, now: u32) -> bool {
+    (now.wrapping_sub(deadline) as i32) >= 0
+}
+
+/// Programs the hardware timer for the nearest deadline, or stops it
+fn rearm(now: u32) {
+    let slots = unsafe { &SLOTS };
+    let delay = slots
+        .iter()
+        .filter(|slot| slot.active)
+        .map(|slot| {
+            if expired(slot.deadline, now) {
+                1
+            } else {
+                slot.deadline.wrapping_sub(now)
+            }
+        })
+        .min()
+        .unwrap_or(0);
+    unsafe { Timer::steal() }.start_oneshot(delay);
+}
+
+/// Dispatches expired timers, called from the trap handler on IRQ0
+pub(crate) fn handle() {
+    let now = runtime::cycles();
+    for index in 0..CAPACITY {
+        let slot = unsafe { &mut SLOTS[index] };
+        if !slot.active || !expired(slot.deadline, now) {
+            continue;
+        }
+
+        let (f, arg) = (slot.f, slot.arg);
+        if slot.period == 0 {
+            slot.active = false;
+        } else {
+            slot.deadline = slot.deadline.wrapping_add(slot.period);
+            // don't try to catch up on missed periods
+            if expired(slot.deadline, now) {
+                slot.deadline = now.wrapping_add(slot.period);
+            }
+        }
+        f(arg);
+    }
+    rearm(runtime::cycles());
+}
@@ -0,0 +1,120 @@
+use picorv32_rt::build::{MemoryLayout, KIB, MIB};
+
+#[test]
+fn memory_x() {
+    let layout = MemoryLayout::new()
+        .flash(0x0010_0000, 4 * MIB)
+        .ram(0, 14 * KIB)
+        .stack_size(2 * KIB);
+    assert_eq!(
+        layout.to_memory_x(),
+        "MEMORY\n{\n  FLASH : ORIGIN = 0x00100000, LENGTH = 0x400000\n  \
+         RAM : ORIGIN = 0x00000000, LENGTH = 0x3800\n}\n_stack_size = 0x800;\n"
+    );
+}
+
+#[test]
+fn invalid_layouts() {
+    let base = MemoryLayout::new().flash(0x0010_0000, MIB).ram(0, 16 * KIB);
+    assert_eq!(base.check(), Ok(()));
+
+    assert!(MemoryLayout::new().ram(0, KIB).check().is_err());
+    assert!(base.clone().ram(0x0010_0000, KIB).check().is_err());
+    assert!(base.clone().ram2(0x0000_3ffc, KIB).check().is_err());
+    assert!(base.clone().ram(2, KIB).check().is_err());
+    assert!(base.clone().flash(0xffff_0000, MIB).check().is_err());
+    assert!(base.clone().stack_size(6).check().is_err());
+    assert!(base.clone().flash_sector_size(3 * KIB).check().is_err());
+    assert!(base
+        .clone()
+        .stack_size(8 * KIB)
+        .heap_size(8 * KIB)
+        .heap2_size(4)
+        .check()
+        .is_err());
+    assert_eq!(
+        base.clone()
+            .ram2(0x0200_0000, MIB)
+            .stack_size(8 * KIB)
+            .heap_size(8 * KIB)
+            .heap2_size(MIB)
+            .check(),
+        Ok(())
+    );
+}
+
+#[test]
+#[should_panic(expected = "the FLASH and RAM regions overlap")]
+fn overlap_panics() {
+    MemoryLayout::new()
+        .flash(0, MIB)
+        .ram(0x1000, KIB)
+        .to_memory_x();
+}
+
+#[test]
+fn device_description() {
+    let layout = MemoryLayout::from_toml(
+        "# board A\n\
+         stack-size = 0x800\n\
+         reset-vector-address = 0x0010_0000\n\
+         irq-vector-address = 0x0010_0010\n\
+         \n\
+         [flash]\n\
+         origin = 0x0010_0000 # SPI flash\n\
+         length = 4194304\n\
+         \n\
+         [ram]\n\
+         origin = 0\n\
+         length = 0x3800\n",
+    )
+    .unwrap();
+    assert_eq!(
+        layout,
+        MemoryLayout::new()
+            .flash(0x0010_0000, 4 * MIB)
+            .ram(0, 14 * KIB)
+            .stack_size(2 * KIB)
+            .reset_vector_address(0x0010_0000)
+            .irq_vector_address(0x0010_0010)
+    );
+    assert_eq!(
+        layout.to_rust_constants(),
+        "pub const FLASH_ORIGIN: u32 = 0x00100000;\npub const FLASH_LENGTH: u32 = 0x400000;\n\
+         pub const RAM_ORIGIN: u32 = 0x00000000;\npub const RAM_LENGTH: u32 = 0x3800;\n\
+         pub const STACK_SIZE: u32 = 0x800;\n\
+         pub const RESET_VECTOR_ADDRESS: u32 = 0x100000;\n\
+         pub const IRQ_VECTOR_ADDRESS: u32 = 0x100010;\n"
+    );
+
+    let manifest = "[package]\n\
+                    name = \"board\"\n\
+                    \n\
+                    [package.metadata.picorv32]\n\
+                    flash = { origin = 0x0010_0000, length = 0x40_0000 }\n\
+                    ram = { origin = 0, length = 0x3800 }\n\
+                    stack-size = 0x800\n\
+                    \n\
+                    [dependencies]\n\
+                    picorv32-rt = { version = \"0.5\", features = [\"alloc\"] }\n";
+    assert_eq!(
+        MemoryLayout::from_manifest(manifest),
+        Ok(MemoryLayout::new()
+            .flash(0x0010_0000, 4 * MIB)
+            .ram(0, 14 * KIB)
+            .stack_size(2 * KIB))
+    );
+
+    assert_eq!(
+        MemoryLayout::from_toml("stack_size = 0x800"),
+        Err(String::from("line 1: unknown key `stack_size`"))
+    );
+    assert!(MemoryLayout::from_toml("[ram]\norigin = -1").is_err());
+    assert!(MemoryLayout::from_toml("[rom]\norigin = 0").is_err());
+    assert!(MemoryLayout::new()
+        .flash(0x0010_0000, MIB)
+        .ram(0, KIB)
+        .irq_vector_address(0x0010_0000)
+        .check()
+        .is_err());
+}
@@ -0,0 +1,42 @@
+use picorv32_rt::crash::{self, Crash};
+
+picorv32_rt::runtime_config! {
+    clock_hz: 12_000_000,
+}
+
+#[test]
+fn location_code() {
+    let code = crash::location_code("src/main.rs", 42);
+    assert_eq!(code & 0xffff, 42);
+    assert_eq!(code, crash::location_code("src/main.rs", 42));
+    assert_ne!(code >> 16, crash::location_code("src/lib.rs", 42) >> 16);
+    // lines wrap into the lower half
+    assert_eq!(crash::location_code("src/main.rs", 0x1_0002) & 0xffff, 2);
+}
+
+#[test]
+fn record_survives_until_taken() {
+    let crash = Crash::Assertion {
+        location: crash::location_code("src/main.rs", 42),
+    };
+    crash::record(crash);
+    assert_eq!(crash::take(), Some(crash));
+    assert_eq!(crash::take(), None);
+
+    let crash = Crash::OutOfMemory {
+        size: 4096,
+        align: 8,
+    };
+    crash::record(crash);
+    assert_eq!(crash::take(), Some(crash));
+}
+
+#[test]
+fn runtime_config_keeps_defaults() {
+    let config = picorv32_rt::runtime::config();
+    assert_eq!(config.clock_hz, 12_000_000);
+    assert_eq!(config.tick_period, 0);
+    assert_eq!(config.panic, picorv32_rt::runtime::DEFAULT_CONFIG.panic);
+    assert_eq!(config.log, picorv32_rt::runtime::DEFAULT_CONFIG.log);
+    assert_eq!(config.stack, picorv32_rt::runtime::DEFAULT_CONFIG.stack);
+}
@@ -0,0 +1,15 @@
+use picorv32_rt::crc;
+
+#[test]
+fn check_value() {
+    assert_eq!(crc::crc32(b"123456789"), 0xcbf4_3926);
+    assert_eq!(crc::crc32(b""), 0);
+}
+
+#[test]
+fn update_in_pieces() {
+    let data = b"The quick brown fox jumps over the lazy dog";
+    let (a, b) = data.split_at(17);
+    assert_eq!(!crc::update(crc::update(!0, a), b), crc::crc32(data));
+    assert_eq!(crc::crc32(data), 0x414f_a339);
+}
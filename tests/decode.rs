@@ -0,0 +1,128 @@
+use picorv32_rt::decode::{self, AluOp, BranchOp, DecodedInstr, MulDivOp, Width};
+
+#[test]
+fn length() {
+    assert_eq!(decode::length(0xff01_0113), 4);
+    assert_eq!(decode::length(0x1141), 2);
+}
+
+#[test]
+fn compressed_expands_to_rv32i() {
+    let pairs = [
+        (0xff01_0113, 0x1141), // addi sp, sp, -16
+        (0x0081_2503, 0x4522), // lw a0, 8(sp)
+        (0x4035_5513, 0x850d), // srai a0, a0, 3
+        (0x0010_0073, 0x9002), // ebreak
+    ];
+    for &(full, compressed) in &pairs {
+        assert_eq!(decode::decode(full), decode::decode(compressed));
+    }
+}
+
+#[test]
+fn loads_and_stores() {
+    assert_eq!(
+        decode::decode(0x0081_2503),
+        DecodedInstr::Load {
+            width: Width::Word,
+            signed: true,
+            rd: 10,
+            rs1: 2,
+            offset: 8,
+        }
+    );
+    assert_eq!(
+        decode::decode(0xfeb4_2e23),
+        DecodedInstr::Store {
+            width: Width::Word,
+            rs1: 8,
+            rs2: 11,
+            offset: -4,
+        }
+    );
+    assert_eq!(
+        decode::decode(0xc14c),
+        DecodedInstr::Store {
+            width: Width::Word,
+            rs1: 10,
+            rs2: 11,
+            offset: 4,
+        }
+    );
+}
+
+#[test]
+fn control_flow() {
+    assert_eq!(
+        decode::decode(0x00b5_0863),
+        DecodedInstr::Branch {
+            op: BranchOp::Eq,
+            rs1: 10,
+            rs2: 11,
+            offset: 16,
+        }
+    );
+    assert_eq!(
+        decode::decode(0xfe62_ece3),
+        DecodedInstr::Branch {
+            op: BranchOp::Ltu,
+            rs1: 5,
+            rs2: 6,
+            offset: -8,
+        }
+    );
+    assert_eq!(
+        decode::decode(0x0010_00ef),
+        DecodedInstr::Jal {
+            rd: 1,
+            offset: 2048,
+        }
+    );
+    assert_eq!(
+        decode::decode(0xbff5),
+        DecodedInstr::Jal { rd: 0, offset: -4 }
+    );
+}
+
+#[test]
+fn arithmetic() {
+    assert_eq!(
+        decode::decode(0x1234_5537),
+        DecodedInstr::Lui {
+            rd: 10,
+            imm: 0x1234_5000,
+        }
+    );
+    assert_eq!(
+        decode::decode(0x40c5_8533),
+        DecodedInstr::Op {
+            op: AluOp::Sub,
+            rd: 10,
+            rs1: 11,
+            rs2: 12,
+        }
+    );
+    assert_eq!(
+        decode::decode(0x02c5_d533),
+        DecodedInstr::MulDiv {
+            op: MulDivOp::Divu,
+            rd: 10,
+            rs1: 11,
+            rs2: 12,
+        }
+    );
+}
+
+#[test]
+fn system() {
+    assert_eq!(
+        decode::decode(0xc000_2573),
+        DecodedInstr::Csr {
+            funct3: 2,
+            rd: 10,
+            rs1: 0,
+            csr: 0xc00,
+        }
+    );
+    assert_eq!(decode::decode(0x0000_0000), DecodedInstr::Unknown);
+}
@@ -0,0 +1,152 @@
+//! The log on top of a simulated NOR flash, which only clears bits when programmed
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use picorv32_rt::crash::Crash;
+use picorv32_rt::event_log::{self, kind, EventLog, FlashWrite, MAX_DATA_LEN};
+
+// normally the linker script provides the default
+picorv32_rt::runtime_config!();
+
+const START: u32 = 0x0020_0000;
+const SECTOR_SIZE: u32 = 512;
+const SECTORS: u32 = 3;
+
+#[derive(Clone)]
+struct Flash(Rc<RefCell<Vec<u8>>>);
+
+impl Flash {
+    fn erased() -> Flash {
+        Flash(Rc::new(RefCell::new(vec![
+            0xff;
+            (SECTOR_SIZE * SECTORS) as usize
+        ])))
+    }
+
+    fn offset(addr: u32) -> usize {
+        (addr - START) as usize
+    }
+}
+
+impl FlashWrite for Flash {
+    fn erase_sector(&mut self, addr: u32) {
+        assert_eq!((addr - START) % SECTOR_SIZE, 0);
+        let offset = Flash::offset(addr);
+        for byte in &mut self.0.borrow_mut()[offset..offset + SECTOR_SIZE as usize] {
+            *byte = 0xff;
+        }
+    }
+
+    fn write(&mut self, addr: u32, data: &[u8]) {
+        assert_eq!(addr % 4, 0);
+        assert_eq!(data.len() % 4, 0);
+        let offset = Flash::offset(addr);
+        for (byte, &new) in self.0.borrow_mut()[offset..].iter_mut().zip(data) {
+            assert_eq!(*byte & new, new, "programming a bit that isn't erased");
+            *byte = new;
+        }
+    }
+
+    fn read(&self, addr: u32, buf: &mut [u8]) {
+        let offset = Flash::offset(addr);
+        buf.copy_from_slice(&self.0.borrow()[offset..offset + buf.len()]);
+    }
+}
+
+fn records(log: &EventLog<Flash>) -> Vec<(u16, Vec<u8>)> {
+    let mut records = Vec::new();
+    log.for_each(|kind, data| records.push((kind, data.to_vec())));
+    records
+}
+
+#[test]
+fn empty() {
+    let log = EventLog::new(Flash::erased(), START, SECTOR_SIZE, SECTORS);
+    assert!(records(&log).is_empty());
+}
+
+#[test]
+fn records_survive_reopening() {
+    let flash = Flash::erased();
+    let mut log = EventLog::new(flash.clone(), START, SECTOR_SIZE, SECTORS);
+    assert!(log.append(kind::USER, b"boot"));
+    assert!(log.append(kind::USER + 1, b"odd"));
+    assert!(log.append(kind::USER, &[]));
+
+    let log = EventLog::new(flash.clone(), START, SECTOR_SIZE, SECTORS);
+    let expected = vec![
+        (kind::USER, b"boot".to_vec()),
+        (kind::USER + 1, b"odd".to_vec()),
+        (kind::USER, vec![]),
+    ];
+    assert_eq!(records(&log), expected);
+
+    // appends after the existing records
+    let mut log = log;
+    assert!(log.append(kind::USER, b"more"));
+    let log = EventLog::new(flash, START, SECTOR_SIZE, SECTORS);
+    assert_eq!(records(&log).len(), 4);
+}
+
+#[test]
+fn too_long() {
+    let mut log = EventLog::new(Flash::erased(), START, SECTOR_SIZE, SECTORS);
+    assert!(!log.append(kind::USER, &[0; MAX_DATA_LEN + 1]));
+    assert!(log.append(kind::USER, &[0; MAX_DATA_LEN]));
+}
+
+#[test]
+fn oldest_sector_is_reused() {
+    let flash = Flash::erased();
+    let mut log = EventLog::new(flash.clone(), START, SECTOR_SIZE, SECTORS);
+    // 8 byte header and 120 bytes of data, 3 records per sector
+    for i in 0..20u8 {
+        assert!(log.append(kind::USER, &[i; 120]));
+    }
+
+    let log = EventLog::new(flash, START, SECTOR_SIZE, SECTORS);
+    let firsts: Vec<u8> = records(&log).iter().map(|(_, data)| data[0]).collect();
+    // the last 2 records are alone in a fresh sector, the 2 sectors before it are full
+    assert_eq!(firsts, (12..20).collect::<Vec<_>>());
+}
+
+#[test]
+fn corrupted_record_is_skipped() {
+    let flash = Flash::erased();
+    let mut log = EventLog::new(flash.clone(), START, SECTOR_SIZE, SECTORS);
+    log.append(kind::USER, b"good");
+    log.append(kind::USER, b"torn");
+    log.append(kind::USER, b"next");
+
+    // second record: sector header, first record, its own header
+    let data = (8 + 12 + 8) as usize;
+    flash.0.borrow_mut()[data] &= 0x0f;
+
+    let log = EventLog::new(flash, START, SECTOR_SIZE, SECTORS);
+    let data: Vec<Vec<u8>> = records(&log).into_iter().map(|(_, data)| data).collect();
+    assert_eq!(data, [b"good".to_vec(), b"next".to_vec()]);
+}
+
+#[test]
+fn crash_records() {
+    let flash = Flash::erased();
+    let log = Box::leak(Box::new(EventLog::new(
+        flash.clone(),
+        START,
+        SECTOR_SIZE,
+        SECTORS,
+    )));
+    event_log::install(log);
+
+    let crash = Crash::Panic {
+        location: 0x1234_0056,
+    };
+    picorv32_rt::crash::record(crash);
+
+    let log = EventLog::new(flash, START, SECTOR_SIZE, SECTORS);
+    let records = records(&log);
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].0, kind::CRASH);
+    assert_eq!(event_log::crash(&records[0].1), Some(crash));
+}
@@ -0,0 +1,29 @@
+//! The accessors only turn linker script symbols into addresses, so the symbols are mocked here
+//! by statics at known addresses
+
+use picorv32_rt::flash;
+
+#[no_mangle]
+static _flash_sector_size: u8 = 0;
+#[no_mangle]
+static _simage: u8 = 0;
+#[no_mangle]
+static _eimage: u8 = 0;
+#[no_mangle]
+static _sconfig_sectors: u8 = 0;
+#[no_mangle]
+static _econfig_sectors: u8 = 0;
+
+fn addr(symbol: &u8) -> usize {
+    symbol as *const u8 as usize
+}
+
+#[test]
+fn accessors_read_their_symbols() {
+    assert_eq!(flash::sector_size(), addr(&_flash_sector_size));
+    assert_eq!(flash::image_sectors(), addr(&_simage)..addr(&_eimage));
+    assert_eq!(
+        flash::config_sectors(),
+        addr(&_sconfig_sectors)..addr(&_econfig_sectors)
+    );
+}
@@ -0,0 +1,41 @@
+use picorv32_rt::flash_config::{tag, Config};
+
+static BLOCK: [u8; 32] = [
+    b'P', b'R', b'C', b'F', //
+    0x01, 0x00, 0x04, 0x00, 0x00, 0x6c, 0xdc, 0x02, // CLOCK_HZ = 48 MHz
+    0x04, 0x00, 0x03, 0x00, b'e', b'v', b'k', 0xff, // BOARD_NAME = "evk", padded
+    0x00, 0x80, 0x01, 0x00, 0x01, 0xff, 0xff, 0xff, // USER = true
+    0xff, 0xff, 0xff, 0xff, // erased
+];
+
+#[test]
+fn typed_values() {
+    let config = Config::from_bytes(&BLOCK).unwrap();
+    assert_eq!(config.u32(tag::CLOCK_HZ), Some(48_000_000));
+    assert_eq!(config.str(tag::BOARD_NAME), Some("evk"));
+    assert_eq!(config.bool(tag::USER), Some(true));
+    assert_eq!(config.u32(tag::UART_BAUD), None);
+    // wrong size
+    assert_eq!(config.u16(tag::CLOCK_HZ), None);
+}
+
+#[test]
+fn entries_end_at_erased_flash() {
+    let config = Config::from_bytes(&BLOCK).unwrap();
+    let tags: Vec<u16> = config.entries().map(|(tag, _)| tag).collect();
+    assert_eq!(tags, [tag::CLOCK_HZ, tag::BOARD_NAME, tag::USER]);
+}
+
+#[test]
+fn truncated_entry_ends_the_list() {
+    static TRUNCATED: [u8; 10] = [b'P', b'R', b'C', b'F', 0x01, 0x00, 0x04, 0x00, 0x00, 0x6c];
+    let config = Config::from_bytes(&TRUNCATED).unwrap();
+    assert_eq!(config.entries().count(), 0);
+}
+
+#[test]
+fn wrong_magic() {
+    static ERASED: [u8; 8] = [0xff; 8];
+    assert!(Config::from_bytes(&ERASED).is_none());
+    assert!(Config::from_bytes(&BLOCK[..2]).is_none());
+}
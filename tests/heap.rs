@@ -0,0 +1,91 @@
+//! The allocator over a buffer on the host
+
+use std::alloc::{GlobalAlloc, Layout};
+
+use picorv32_rt::heap::{DualHeap, Heap};
+
+// normally the linker script provides the default
+picorv32_rt::runtime_config!();
+
+const SIZE: usize = 1024;
+
+fn heap(buffer: &mut Vec<u64>) -> Heap {
+    let heap = Heap::empty();
+    unsafe { heap.init(buffer.as_mut_ptr() as usize, SIZE) };
+    heap
+}
+
+#[test]
+fn empty_heap_fails() {
+    let heap = Heap::empty();
+    assert!(unsafe { heap.alloc(Layout::new::<u32>()) }.is_null());
+    assert_eq!(heap.available(), 0);
+}
+
+#[test]
+fn allocations_are_aligned_and_disjoint() {
+    let mut buffer = vec![0u64; SIZE / 8];
+    let heap = heap(&mut buffer);
+
+    let small = Layout::from_size_align(3, 1).unwrap();
+    let aligned = Layout::from_size_align(40, 64).unwrap();
+    let a = unsafe { heap.alloc(small) };
+    let b = unsafe { heap.alloc(aligned) };
+    let c = unsafe { heap.alloc(small) };
+    assert!(!a.is_null() && !b.is_null() && !c.is_null());
+    assert_eq!(b as usize % 64, 0);
+
+    let mut blocks = [(a as usize, 3), (b as usize, 40), (c as usize, 3)];
+    blocks.sort();
+    for pair in blocks.windows(2) {
+        assert!(pair[0].0 + pair[0].1 <= pair[1].0);
+    }
+
+    unsafe {
+        heap.dealloc(b, aligned);
+        heap.dealloc(a, small);
+        heap.dealloc(c, small);
+    }
+    assert_eq!(heap.available(), SIZE);
+}
+
+#[test]
+fn freed_blocks_merge() {
+    let mut buffer = vec![0u64; SIZE / 8];
+    let heap = heap(&mut buffer);
+    let quarter = Layout::from_size_align(SIZE / 4, 8).unwrap();
+
+    let blocks: Vec<_> = (0..4).map(|_| unsafe { heap.alloc(quarter) }).collect();
+    assert!(blocks.iter().all(|block| !block.is_null()));
+    assert!(unsafe { heap.alloc(Layout::new::<u8>()) }.is_null());
+
+    // freed out of order, only a merged block fits the whole heap again
+    for &i in &[1, 3, 0, 2] {
+        unsafe { heap.dealloc(blocks[i], quarter) };
+    }
+    let whole = Layout::from_size_align(SIZE, 8).unwrap();
+    let block = unsafe { heap.alloc(whole) };
+    assert_eq!(block, blocks[0]);
+}
+
+#[test]
+fn dual_heap_falls_back_to_the_slow_one() {
+    let mut fast = vec![0u64; SIZE / 8];
+    let mut slow = vec![0u64; SIZE / 8];
+    let heap = DualHeap::empty();
+    unsafe {
+        heap.fast().init(fast.as_mut_ptr() as usize, SIZE);
+        heap.slow().init(slow.as_mut_ptr() as usize, SIZE);
+    }
+    let half = Layout::from_size_align(SIZE / 2, 8).unwrap();
+
+    let blocks: Vec<_> = (0..4).map(|_| unsafe { heap.alloc(half) }).collect();
+    assert!(heap.fast().contains(blocks[0]) && heap.fast().contains(blocks[1]));
+    assert!(heap.slow().contains(blocks[2]) && heap.slow().contains(blocks[3]));
+    assert!(unsafe { heap.alloc(half) }.is_null());
+
+    // freed into the heap it came from
+    unsafe { heap.dealloc(blocks[2], half) };
+    assert_eq!(heap.slow().available(), SIZE / 2);
+    assert_eq!(heap.fast().available(), 0);
+}
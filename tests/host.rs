@@ -0,0 +1,170 @@
+use picorv32_rt::crash::Crash;
+use picorv32_rt::event_log::kind;
+use picorv32_rt::flash_config::tag;
+use picorv32_rt::host::{self, Header};
+
+const HEADER: &str = include_str!(concat!(env!("OUT_DIR"), "/picorv32_rt.h"));
+
+#[test]
+fn generated_header() {
+    let header = Header::parse(HEADER).unwrap();
+    assert_eq!(header.link_x_version(), Some(1));
+    assert_eq!(header.define("PICORV32_RT_IRQ_BUS_ERROR"), Some("2"));
+    assert_eq!(header.feature("INTERRUPTS"), cfg!(feature = "interrupts"));
+
+    let size = header.frame_size().unwrap();
+    assert_eq!(
+        size,
+        std::mem::size_of::<picorv32_rt::PicoRV32StoredRegisters>()
+    );
+    // `x3` always comes first, holding the return address without q registers
+    assert_eq!(header.frame_offset(3), Some(0));
+    assert_eq!(header.frame_offset(4), None);
+}
+
+#[test]
+fn trap_frame_dump() {
+    let header = Header::parse(HEADER).unwrap();
+    let size = header.frame_size().unwrap();
+    let dump: Vec<u8> = (0..size as u32 / 4)
+        .flat_map(|word| word.to_le_bytes().to_vec())
+        .collect();
+
+    let regs = header.trap_frame(&dump).unwrap();
+    assert_eq!(regs.len(), size / 4);
+    for &(reg, value) in &regs {
+        assert_eq!(Some(value as usize * 4), header.frame_offset(reg));
+    }
+    assert!(header.trap_frame(&dump[..size - 1]).is_none());
+}
+
+#[test]
+fn not_a_header() {
+    assert!(Header::parse("#define FOO 1\n").is_none());
+}
+
+#[test]
+fn crash_record() {
+    let record = [
+        0x43, 0x52, 0x53, 0x48, // "CRSH"
+        0x02, 0x00, 0x00, 0x00, // assertion
+        0x0a, 0x00, 0x34, 0x12, //
+        0x00, 0x00, 0x00, 0x00,
+    ];
+    assert_eq!(
+        host::crash(&record),
+        Some(Crash::Assertion {
+            location: 0x1234_000a
+        })
+    );
+    assert_eq!(host::crash(&[0; 16]), None);
+    assert_eq!(host::crash(&record[..12]), None);
+}
+
+#[test]
+fn config_block() {
+    let block = [
+        b'P', b'R', b'C', b'F', //
+        0x02, 0x00, 0x04, 0x00, 0x00, 0xc2, 0x01, 0x00, // UART_BAUD = 115200
+        0xff, 0xff, 0xff, 0xff,
+    ];
+    assert_eq!(
+        host::config(&block),
+        Some(vec![(tag::UART_BAUD, vec![0x00, 0xc2, 0x01, 0x00])])
+    );
+    assert_eq!(host::config(&[0xff; 16]), None);
+}
+
+#[test]
+fn event_log_image() {
+    let mut image = vec![0xffu8; 2 * 512];
+    // sector 1 holds sequence 7 with a crash record
+    let sector = &mut image[512..];
+    sector[..4].copy_from_slice(b"PELG");
+    sector[4..8].copy_from_slice(&7u32.to_le_bytes());
+    let crash = [
+        0x43, 0x52, 0x53, 0x48, 0x03, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ];
+    sector[8..10].copy_from_slice(&kind::CRASH.to_le_bytes());
+    sector[10..12].copy_from_slice(&16u16.to_le_bytes());
+    sector[12..16].copy_from_slice(&picorv32_rt::crc::crc32(&crash).to_le_bytes());
+    sector[16..32].copy_from_slice(&crash);
+
+    let records = host::event_log(&image, 512);
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].crash(), Some(Crash::Panic { location: 5 }));
+    assert!(host::event_log(&vec![0xff; 1024], 512).is_empty());
+}
+
+#[test]
+fn compressed_data_image() {
+    let mut binary = vec![0x13; 16];
+    binary.extend(&[0; 64]);
+    binary.extend(&0x4154_4144u32.to_le_bytes());
+    binary.extend(&64u32.to_le_bytes());
+
+    let compressed = host::compress_data(&binary).unwrap();
+    assert_eq!(&compressed[..16], &binary[..16]);
+    assert_eq!(&compressed[16..], &[0x80 + 64 - 3, 0]);
+
+    assert!(host::compress_data(&binary[..binary.len() - 1]).is_none());
+}
+
+#[test]
+fn fpga_images() {
+    // an ELF32 header followed by two program headers, `.text` and an empty `.bss`
+    let mut elf = vec![0; 0x34 + 2 * 0x20];
+    elf[..6].copy_from_slice(b"\x7fELF\x01\x01");
+    elf[0x1c..0x20].copy_from_slice(&0x34u32.to_le_bytes());
+    elf[0x2a..0x2c].copy_from_slice(&0x20u16.to_le_bytes());
+    elf[0x2c..0x2e].copy_from_slice(&2u16.to_le_bytes());
+    let text_offset = elf.len() as u32;
+    for (i, &(address, size)) in [(0x0010_fff8u32, 20u32), (0, 0)].iter().enumerate() {
+        let header = &mut elf[0x34 + i * 0x20..];
+        header[..4].copy_from_slice(&1u32.to_le_bytes());
+        header[4..8].copy_from_slice(&text_offset.to_le_bytes());
+        header[12..16].copy_from_slice(&address.to_le_bytes());
+        header[16..20].copy_from_slice(&size.to_le_bytes());
+    }
+    elf.extend((1..=20).collect::<Vec<u8>>());
+
+    let segments = host::elf_segments(&elf).unwrap();
+    assert_eq!(
+        segments,
+        vec![host::Segment {
+            address: 0x0010_fff8,
+            data: (1..=20).collect(),
+        }]
+    );
+    assert!(host::elf_segments(&elf[..0x30]).is_none());
+
+    let memory_x = "MEMORY\n{\n  FLASH (rx) : ORIGIN = 0x0010fff0, LENGTH = 4K\n  \
+                    RAM : ORIGIN = 0, LENGTH = 0x3800\n}\n";
+    assert_eq!(
+        host::memory_region(memory_x, "FLASH"),
+        Some((0x0010_fff0, 4096))
+    );
+    assert_eq!(host::memory_region(memory_x, "RAM"), Some((0, 0x3800)));
+    assert_eq!(host::memory_region(memory_x, "RAM2"), None);
+
+    let image = host::memory_image(&segments, 0x0010_fff0, 4096).unwrap();
+    assert_eq!(image.len(), 28);
+    assert_eq!(
+        host::readmemh(&image),
+        "00000000\n00000000\n04030201\n08070605\n0c0b0a09\n100f0e0d\n14131211\n"
+    );
+    assert!(host::memory_image(&segments, 0x0010_fff0, 16).is_none());
+    assert!(host::memory_image(&segments, 0x0011_0000, 4096).is_none());
+
+    // split at the 64K boundary, with an extended linear address record for each half
+    assert_eq!(
+        host::intel_hex(&segments),
+        ":020000040010EA\n\
+         :08FFF8000102030405060708DD\n\
+         :020000040011E9\n\
+         :08000000090A0B0C0D0E0F1094\n\
+         :0400080011121314AA\n\
+         :00000001FF\n"
+    );
+}
@@ -0,0 +1,62 @@
+use picorv32_rt::image::{self, ImageHeader, Slot};
+
+/// Header as emitted by `application-layout.x`, followed by `code`
+fn image(code: &[u8]) -> Vec<u8> {
+    let mut image = Vec::new();
+    for word in &[
+        image::MAGIC,
+        0x0011_007e,
+        0x0011_0020,
+        (image::HEADER_SIZE + code.len()) as u32,
+        image::NO_CRC,
+    ] {
+        image.extend_from_slice(&word.to_le_bytes());
+    }
+    image.extend_from_slice(code);
+    image
+}
+
+#[test]
+fn parse() {
+    let header = ImageHeader::parse(&image(&[1, 2, 3, 4])).unwrap();
+    assert_eq!(header.entry, 0x0011_007e);
+    assert_eq!(header.trap, 0x0011_0020);
+    assert_eq!(header.size, 24);
+    assert_eq!(header.crc, image::NO_CRC);
+
+    assert_eq!(ImageHeader::parse(&image(&[])[..16]), None);
+    assert_eq!(ImageHeader::parse(&[0xff; 32]), None);
+}
+
+#[test]
+fn filled_crc_matches() {
+    let code = [0x13, 0x00, 0x00, 0x00, 0x6f, 0x00, 0x00, 0x00];
+    let mut image = image(&code);
+    assert!(!ImageHeader::parse(&image).unwrap().crc_matches(&image));
+
+    let header = image::fill_crc(&mut image).unwrap();
+    assert_eq!(header.crc, picorv32_rt::crc::crc32(&code));
+    assert_eq!(ImageHeader::parse(&image), Some(header));
+    assert!(header.crc_matches(&image));
+
+    // trailing padding isn't covered
+    image.extend_from_slice(&[0xff; 4]);
+    assert!(header.crc_matches(&image));
+
+    image[image::HEADER_SIZE] ^= 1;
+    assert!(!header.crc_matches(&image));
+    // truncated
+    assert!(!header.crc_matches(&image[..20]));
+}
+
+#[test]
+fn fill_crc_needs_the_whole_image() {
+    let mut image = image(&[1, 2, 3, 4]);
+    assert_eq!(image::fill_crc(&mut image[..22]), None);
+}
+
+#[test]
+fn other_slot() {
+    assert_eq!(Slot::A.other(), Slot::B);
+    assert_eq!(Slot::B.other(), Slot::A);
+}
@@ -0,0 +1,33 @@
+use picorv32_rt::{crc, image, integrity};
+
+#[test]
+fn crc_is_the_last_word() {
+    let mut binary = vec![0x13, 0x00, 0x00, 0x00, 0x6f, 0x00, 0x00, 0x00];
+    binary.extend_from_slice(&[0xff; 4]);
+
+    let crc = integrity::fill_crc(&mut binary).unwrap();
+    assert_eq!(crc, crc::crc32(&binary[..8]));
+    assert_eq!(binary[8..], crc.to_le_bytes());
+}
+
+#[test]
+fn image_header_isnt_covered() {
+    let mut binary = Vec::new();
+    for word in &[image::MAGIC, 0x0011_0074, 0x0011_0020, 32, image::NO_CRC] {
+        binary.extend_from_slice(&word.to_le_bytes());
+    }
+    binary.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    binary.extend_from_slice(&[0xff; 4]);
+
+    let crc = integrity::fill_crc(&mut binary).unwrap();
+    assert_eq!(crc, crc::crc32(&binary[image::HEADER_SIZE..28]));
+    // and the header covers it
+    let header = image::fill_crc(&mut binary).unwrap();
+    assert!(header.crc_matches(&binary));
+}
+
+#[test]
+fn too_short() {
+    assert_eq!(integrity::fill_crc(&mut [0; 3]), None);
+    assert_eq!(integrity::fill_crc(&mut [0; 4]), Some(crc::crc32(&[])));
+}
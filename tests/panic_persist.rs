@@ -0,0 +1,33 @@
+use core::fmt::Write;
+
+use picorv32_rt::panic_persist;
+
+picorv32_rt::runtime_config!();
+
+#[test]
+fn message_survives_until_cleared() {
+    panic_persist::clear();
+    assert_eq!(panic_persist::get_persisted_panic(), None);
+
+    write!(panic_persist::writer(), "panicked at 'oops'").unwrap();
+    writeln!(panic_persist::writer(), ", src/main.rs:3:5").unwrap();
+    assert_eq!(
+        panic_persist::get_persisted_panic(),
+        Some("panicked at 'oops', src/main.rs:3:5\n")
+    );
+    assert!(panic_persist::get_persisted_panic().is_some());
+
+    panic_persist::clear();
+    assert_eq!(panic_persist::get_persisted_panic(), None);
+
+    // cut short at a character boundary
+    let mut writer = panic_persist::writer();
+    for _ in 0..panic_persist::CAPACITY / 2 - 1 {
+        writer.write_str("ab").unwrap();
+    }
+    assert!(writer.write_str("!é").is_err());
+    let message = panic_persist::get_persisted_panic().unwrap();
+    assert_eq!(message.len(), panic_persist::CAPACITY - 1);
+    assert!(message.ends_with("ab!"));
+    panic_persist::clear();
+}
@@ -0,0 +1,37 @@
+use picorv32_rt::rle;
+
+fn encode(data: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    rle::encode(data, |byte| encoded.push(byte));
+    encoded
+}
+
+#[test]
+fn round_trip() {
+    let mut data = vec![0; 300];
+    data.extend((0..200).map(|i| i as u8));
+    data.extend(&[7, 7, 1, 1, 1, 2]);
+
+    let encoded = encode(&data);
+    assert!(encoded.len() < data.len());
+
+    let mut decoded = vec![0xff; data.len()];
+    assert!(rle::decode(encoded.iter().cloned(), &mut decoded));
+    assert_eq!(decoded, data);
+}
+
+#[test]
+fn short_runs_stay_literal() {
+    assert_eq!(encode(&[1, 1, 2]), [2, 1, 1, 2]);
+    assert_eq!(encode(&[1, 1, 1, 2]), [0x80, 1, 0, 2]);
+    assert_eq!(encode(&[]), []);
+}
+
+#[test]
+fn truncated_input() {
+    let encoded = encode(&[5; 10]);
+    let mut decoded = [0; 10];
+    assert!(!rle::decode(encoded[..1].iter().cloned(), &mut decoded));
+    // a block running past the end of the destination
+    assert!(!rle::decode(encoded.iter().cloned(), &mut decoded[..9]));
+}
@@ -0,0 +1,31 @@
+//! The stack is mocked by a static buffer, with `_sstack` placed in `.bss` so it lies above the
+//! buffer in `.data`
+
+use picorv32_rt::stack::{self, CANARY, PAINT};
+
+// normally the linker script provides the default
+picorv32_rt::runtime_config!();
+
+const WORDS: usize = 64;
+
+#[no_mangle]
+static mut _estack: [u32; WORDS] = [1; WORDS];
+#[no_mangle]
+static mut _sstack: u8 = 0;
+
+#[test]
+fn canary_is_not_counted_as_paint() {
+    unsafe {
+        assert!((&_sstack as *const u8 as usize) > (&_estack as *const _ as usize) + WORDS * 4);
+
+        // what the startup code leaves behind once the stack has grown down to word 17
+        _estack[0] = CANARY;
+        for word in &mut _estack[1..17] {
+            *word = PAINT;
+        }
+    }
+
+    assert!(stack::stack_canary_intact());
+    assert_eq!(stack::stack_free(), 16 * 4);
+    assert_eq!(stack::stack_usage(), stack::stack_size() - 16 * 4);
+}